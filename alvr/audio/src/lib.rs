@@ -14,7 +14,7 @@ use alvr_common::{
     parking_lot::Mutex,
 };
 use alvr_session::{AudioBufferingConfig, CustomAudioDeviceConfig, MicrophoneDevicesConfig};
-use alvr_sockets::{StreamReceiver, StreamSender};
+use alvr_sockets::StreamReceiver;
 use cpal::{
     BufferSize, Host, Sample, SampleFormat, StreamConfig,
     traits::{DeviceTrait, HostTrait, StreamTrait},
@@ -247,7 +247,7 @@ fn downmix_audio(data: Vec<u8>, in_channels: u16, out_channels: u16) -> Vec<u8>
 #[allow(unused_variables)]
 pub fn record_audio_blocking(
     is_running: Arc<dyn Fn() -> bool + Send + Sync>,
-    mut sender: StreamSender<()>,
+    mut send_samples: impl FnMut(&[u8]) + Send + 'static,
     device: &Device,
     channels_count: u16,
     mute: bool,
@@ -301,7 +301,7 @@ pub fn record_audio_blocking(
                 let data = downmix_audio(data, config.channels(), channels_count);
 
                 if is_running() {
-                    sender.send_header_with_payload(&(), &data).ok();
+                    send_samples(&data);
                 } else {
                     *state.lock() = AudioRecordState::ShouldStop;
                 }
@@ -1,6 +1,6 @@
 use alvr_common::{ConnectionError, anyhow::Result, debug, error, parking_lot::Mutex};
 use alvr_session::AudioBufferingConfig;
-use alvr_sockets::{StreamReceiver, StreamSender};
+use alvr_sockets::StreamReceiver;
 
 use std::os::unix::fs::FileTypeExt;
 use std::{
@@ -80,7 +80,7 @@ static MIC_STREAMING: AtomicBool = AtomicBool::new(false);
 
 pub fn audio_loop(
     is_running: impl Fn() -> bool,
-    sender: StreamSender<()>,
+    send_samples: impl FnMut(&[u8]) + Send + 'static,
     speaker_info: Option<AudioInfo>,
     receiver: &mut StreamReceiver<()>,
     mic_info: Option<(AudioInfo, AudioBufferingConfig)>,
@@ -100,8 +100,13 @@ pub fn audio_loop(
         let mic_info = mic_info.as_ref().map(|(info, _)| *info);
 
         move || {
-            if let Err(e) = pw_main_loop(pw_receiver, sender, speaker_info, sample_queue, mic_info)
-            {
+            if let Err(e) = pw_main_loop(
+                pw_receiver,
+                send_samples,
+                speaker_info,
+                sample_queue,
+                mic_info,
+            ) {
                 error!("Unhandled pipewire audio device error, please report it on GitHub: {e}");
             }
             debug!("Pipewire audio loop exiting");
@@ -153,7 +158,7 @@ pub fn audio_loop(
 
 fn pw_main_loop(
     pw_receiver: Receiver<Terminate>,
-    audio_sender: StreamSender<()>,
+    send_samples: impl FnMut(&[u8]) + 'static,
     speaker_info: Option<AudioInfo>,
     sample_queue: Arc<Mutex<VecDeque<f32>>>,
     mic_info: Option<AudioInfo>,
@@ -173,7 +178,7 @@ fn pw_main_loop(
         debug!("Creating pw output audio stream");
         Some(create_speaker_stream(
             &pw_core,
-            audio_sender,
+            send_samples,
             info.sample_rate,
             info.channel_count,
         )?)
@@ -215,7 +220,7 @@ fn audio_info_to_vec(audio_info: AudioInfoRaw) -> Vec<u8> {
 
 fn create_speaker_stream(
     pw_core: &CoreRc,
-    mut sender: StreamSender<()>,
+    mut send_samples: impl FnMut(&[u8]) + 'static,
     sample_rate: u32,
     channel_count: u32,
 ) -> Result<(StreamRc, StreamListener<i16>), pipewire::Error> {
@@ -243,7 +248,7 @@ fn create_speaker_stream(
                 if let Some(data) = pw_buf.data() {
                     // Data is given as s16le in the correct layout by pipewire already,
                     // no need to do conversions
-                    sender.send_header_with_payload(&(), &data[0..size]).ok();
+                    send_samples(&data[0..size]);
                 }
             }
         })
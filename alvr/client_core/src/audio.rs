@@ -4,7 +4,7 @@ use alvr_common::{
     parking_lot::Mutex,
 };
 use alvr_session::AudioBufferingConfig;
-use alvr_sockets::{StreamReceiver, StreamSender};
+use alvr_sockets::StreamReceiver;
 use ndk::audio::{
     AudioCallbackResult, AudioDirection, AudioError, AudioFormat, AudioInputPreset,
     AudioPerformanceMode, AudioSharingMode, AudioStreamBuilder,
@@ -22,7 +22,7 @@ const INPUT_RECV_TIMEOUT: Duration = Duration::from_millis(20);
 #[allow(unused_variables)]
 pub fn record_audio_blocking(
     is_running: Arc<dyn Fn() -> bool + Send + Sync>,
-    mut sender: StreamSender<()>,
+    mut send_samples: impl FnMut(&[u8]) + Send + 'static,
     device: &Device,
     channels_count: u16,
     mute: bool,
@@ -76,7 +76,7 @@ pub fn record_audio_blocking(
 
     while is_running() && error.lock().is_none() {
         while let Ok(sample_buffer) = samples_receiver.recv_timeout(INPUT_RECV_TIMEOUT) {
-            sender.send_header_with_payload(&(), &sample_buffer).ok();
+            send_samples(&sample_buffer);
         }
     }
 
@@ -181,6 +181,15 @@ pub extern "C" fn alvr_protocol_id(protocol_buffer: *mut c_char) -> u64 {
     string_to_c_str(protocol_buffer, &storage::Config::load().protocol_id)
 }
 
+/// Refuse streamers with encryption disabled even if the client is not paired yet. Paired clients
+/// always refuse them.
+#[unsafe(no_mangle)]
+pub extern "C" fn alvr_set_require_encryption(require: bool) {
+    let mut config = storage::Config::load();
+    config.require_encryption = require;
+    config.store();
+}
+
 #[cfg(target_os = "android")]
 #[unsafe(no_mangle)]
 pub extern "C" fn alvr_try_get_permission(permission: *const c_char) {
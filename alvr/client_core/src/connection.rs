@@ -9,7 +9,7 @@ use crate::{
 };
 use alvr_common::{
     ALVR_VERSION, AnyhowToCon, ConResult, ConnectionError, ConnectionState, LifecycleState,
-    ViewParams, dbg_connection, debug, error, info,
+    ViewParams, con_bail, dbg_connection, debug, error, info,
     parking_lot::{Condvar, Mutex, RwLock},
     wait_rwlock, warn,
 };
@@ -21,7 +21,7 @@ use alvr_packets::{
 use alvr_session::{SocketProtocol, settings_schema::Switch};
 use alvr_sockets::{
     ControlSocketSender, KEEPALIVE_INTERVAL, KEEPALIVE_TIMEOUT, PeerType, ProtoControlSocket,
-    StreamSender, StreamSocketBuilder,
+    StreamClass, StreamPriority, StreamSender, StreamSocketBuilder,
 };
 use std::{
     collections::VecDeque,
@@ -38,7 +38,8 @@ use alvr_audio as audio;
 const INITIAL_MESSAGE: &str = concat!(
     "Searching for streamer...\n",
     "Open ALVR on your PC then click \"Trust\"\n",
-    "next to the device entry",
+    "next to the device entry if the\n",
+    "pairing key matches",
 );
 const SUCCESS_CONNECT_MESSAGE: &str = "Successful connection!\nPlease wait...";
 const STREAM_STARTING_MESSAGE: &str = "The stream will begin soon\nPlease wait...";
@@ -50,8 +51,10 @@ const SOCKET_INIT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 const CONNECTION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 const HANDSHAKE_ACTION_TIMEOUT: Duration = Duration::from_secs(2);
 const STREAMING_RECV_TIMEOUT: Duration = Duration::from_millis(500);
+const CLOCK_SYNC_INTERVAL: Duration = Duration::from_millis(500);
 
 const MAX_UNREAD_PACKETS: usize = 10; // Applies per stream
+const MAX_INTACT_VIDEO_FRAMES: usize = 32;
 
 pub type DecoderCallback = dyn FnMut(Duration, &[u8]) -> bool + Send;
 
@@ -65,14 +68,23 @@ pub struct ConnectionContext {
     pub statistics_manager: Mutex<Option<StatisticsManager>>,
     pub decoder_callback: Mutex<Option<Box<DecoderCallback>>>,
     pub global_view_params_queue: Mutex<VecDeque<(Duration, [ViewParams; 2])>>,
+    // Frames submitted to the decoder with all their references since the last IDR, and whether
+    // the decoder has output them
+    pub intact_video_frames: Mutex<VecDeque<(Duration, bool)>>,
     pub max_prediction: RwLock<Duration>,
 }
 
 fn set_hud_message(event_queue: &Mutex<VecDeque<ClientCoreEvent>>, message: &str) {
+    let config = Config::load();
+    // The user compares this with the fingerprint shown in the dashboard before trusting
+    let fingerprint = alvr_sockets::pairing_public_key(&config.pairing_key)
+        .map(|key| alvr_sockets::pairing_key_fingerprint(&key))
+        .unwrap_or_default();
+
     let message = format!(
-        "ALVR v{}\nhostname: {}\nIP: {}\n\n{message}",
+        "ALVR v{}\nhostname: {}\nIP: {}\npairing key: {fingerprint}\n\n{message}",
         *ALVR_VERSION,
-        Config::load().hostname,
+        config.hostname,
         alvr_system_info::local_ip(),
     );
 
@@ -130,7 +142,11 @@ fn connection_pipeline(
 
     let (mut proto_control_socket, server_ip) = {
         let config = Config::load();
-        let announcer_socket = AnnouncerSocket::new(&config.hostname).to_con()?;
+        let announcer_socket = AnnouncerSocket::new(
+            &config.hostname,
+            alvr_sockets::pairing_public_key(&config.pairing_key).ok(),
+        )
+        .to_con()?;
         let listener_socket =
             alvr_sockets::get_server_listener(HANDSHAKE_ACTION_TIMEOUT).to_con()?;
 
@@ -187,6 +203,26 @@ fn connection_pipeline(
             },
         )))
         .to_con()?;
+
+    dbg_connection!("connection_pipeline: Negotiate encryption");
+    let mut config = Config::load();
+    let server_pairing_key = proto_control_socket
+        .negotiate_encryption_as_client(&config.pairing_key, HANDSHAKE_ACTION_TIMEOUT)?;
+    match (server_pairing_key, &config.paired_server_key) {
+        (Some(key), Some(paired_key)) if key == *paired_key => (),
+        (Some(_), Some(_)) => con_bail!("The streamer is not the one this client is paired with"),
+        (Some(key), None) => {
+            info!("Paired with the streamer");
+            config.paired_server_key = Some(key);
+            config.store();
+        }
+        (None, Some(_)) => con_bail!("Refusing unencrypted connection, this client is paired"),
+        (None, None) if config.require_encryption => {
+            con_bail!("Refusing unencrypted connection. Enable encryption in the streamer settings")
+        }
+        (None, None) => (),
+    }
+
     let config_packet =
         proto_control_socket.recv::<StreamConfigPacket>(HANDSHAKE_ACTION_TIMEOUT)?;
     dbg_connection!("connection_pipeline: stream config received");
@@ -202,8 +238,10 @@ fn connection_pipeline(
 
     *ctx.statistics_manager.lock() = Some(StatisticsManager::new(
         settings.connection.statistics_history_size,
+        proto_control_socket.secure_channel(),
     ));
 
+    let secure_channel = proto_control_socket.secure_channel();
     let (mut control_sender, mut control_receiver) = proto_control_socket
         .split(STREAMING_RECV_TIMEOUT)
         .to_con()?;
@@ -258,22 +296,62 @@ fn connection_pipeline(
         server_ip,
         settings.connection.stream_port,
         settings.connection.packet_size as _,
+        settings
+            .connection
+            .forward_error_correction
+            .as_option()
+            .copied(),
+        secure_channel,
         HANDSHAKE_ACTION_TIMEOUT,
     )?;
 
     info!("Connected to server");
 
-    let mut video_receiver =
-        stream_socket.subscribe_to_stream::<VideoPacketHeader>(VIDEO, MAX_UNREAD_PACKETS);
-    let mut game_audio_receiver = stream_socket.subscribe_to_stream(AUDIO, MAX_UNREAD_PACKETS);
-    let tracking_sender = stream_socket.request_stream(TRACKING);
-    let mut haptics_receiver =
-        stream_socket.subscribe_to_stream::<Haptics>(HAPTICS, MAX_UNREAD_PACKETS);
-    let statistics_sender = stream_socket.request_stream(STATISTICS);
+    let mut video_receiver = stream_socket.subscribe_to_stream::<VideoPacketHeader>(
+        VIDEO,
+        alvr_sockets::video_stream_class(&settings),
+        MAX_UNREAD_PACKETS,
+    );
+    let mut game_audio_receiver =
+        stream_socket.subscribe_to_stream(AUDIO, StreamClass::Droppable, MAX_UNREAD_PACKETS);
+    let tracking_sender =
+        stream_socket.request_stream(TRACKING, StreamClass::LatestOnly, StreamPriority::High);
+    let mut haptics_receiver = stream_socket.subscribe_to_stream::<Haptics>(
+        HAPTICS,
+        StreamClass::Reliable,
+        MAX_UNREAD_PACKETS,
+    );
+    let statistics_sender =
+        stream_socket.request_stream(STATISTICS, StreamClass::Reliable, StreamPriority::Medium);
 
     let video_receive_thread = thread::spawn({
         let ctx = Arc::clone(&ctx);
         move || {
+            let reference_frame_invalidation =
+                settings.video.encoder_config.reference_frame_invalidation
+                    && !settings.connection.avoid_video_glitching;
+            let request_recovery = || {
+                let packet = if reference_frame_invalidation {
+                    // Last frame decoded without missing references
+                    let last_valid_frame_timestamp = ctx
+                        .intact_video_frames
+                        .lock()
+                        .iter()
+                        .rev()
+                        .find(|(_, decoded)| *decoded)
+                        .map(|(timestamp, _)| *timestamp);
+
+                    ClientControlPacket::VideoFrameLoss {
+                        last_valid_frame_timestamp,
+                    }
+                } else {
+                    ClientControlPacket::RequestIdr
+                };
+                if let Some(sender) = &mut *ctx.control_sender.lock() {
+                    sender.send(&packet).ok();
+                }
+            };
+
             let mut stream_corrupted = true;
             while is_streaming(&ctx) {
                 let data = match video_receiver.recv(STREAMING_RECV_TIMEOUT) {
@@ -286,17 +364,30 @@ fn connection_pipeline(
                 };
 
                 if let Some(stats) = &mut *ctx.statistics_manager.lock() {
-                    stats.report_video_packet_received(header.timestamp);
+                    stats.report_video_packet_received(
+                        header.timestamp,
+                        data.recovered_shards_count(),
+                        data.first_shard_arrival(),
+                        data.last_shard_arrival(),
+                    );
                 }
 
                 if header.is_idr {
                     stream_corrupted = false;
+                    ctx.intact_video_frames.lock().clear();
                 } else if data.had_packet_loss() {
                     stream_corrupted = true;
-                    if let Some(sender) = &mut *ctx.control_sender.lock() {
-                        sender.send(&ClientControlPacket::RequestIdr).ok();
-                    }
+                    request_recovery();
                     warn!("Network dropped video packet");
+                } else if let Some(reference) = header.recovery_reference
+                    && ctx
+                        .intact_video_frames
+                        .lock()
+                        .iter()
+                        .any(|(timestamp, _)| *timestamp == reference)
+                {
+                    // The encoder stopped referencing the frames affected by the loss
+                    stream_corrupted = false;
                 }
 
                 if !stream_corrupted || !settings.connection.avoid_video_glitching {
@@ -322,10 +413,14 @@ fn connection_pipeline(
 
                     if !submitted {
                         stream_corrupted = true;
-                        if let Some(sender) = &mut *ctx.control_sender.lock() {
-                            sender.send(&ClientControlPacket::RequestIdr).ok();
-                        }
+                        request_recovery();
                         warn!("Dropped video packet. Reason: Decoder saturation")
+                    } else if !stream_corrupted {
+                        let mut intact_frames = ctx.intact_video_frames.lock();
+                        intact_frames.push_back((header.timestamp, false));
+                        if intact_frames.len() > MAX_INTACT_VIDEO_FRAMES {
+                            intact_frames.pop_front();
+                        }
                     }
                 } else {
                     if let Some(sender) = &mut *ctx.control_sender.lock() {
@@ -361,16 +456,22 @@ fn connection_pipeline(
     let microphone_thread = if matches!(settings.audio.microphone, Switch::Enabled(_)) {
         let device = alvr_audio::new_input(None).to_con()?;
 
-        let microphone_sender = stream_socket.request_stream(AUDIO);
+        let microphone_sender =
+            stream_socket.request_stream(AUDIO, StreamClass::Droppable, StreamPriority::Medium);
 
         thread::spawn({
             let ctx = Arc::clone(&ctx);
             move || {
                 while is_streaming(&ctx) {
                     let ctx = Arc::clone(&ctx);
+                    let mut microphone_sender = microphone_sender.clone();
                     match audio::record_audio_blocking(
                         Arc::new(move || is_streaming(&ctx)),
-                        microphone_sender.clone(),
+                        move |samples| {
+                            microphone_sender
+                                .send_header_with_payload(&(), samples)
+                                .ok();
+                        },
                         &device,
                         1,
                         false,
@@ -421,6 +522,7 @@ fn connection_pipeline(
         let disconnect_notif = Arc::clone(&disconnect_notif);
         move || {
             let mut keepalive_deadline = Instant::now();
+            let mut clock_sync_deadline = Instant::now();
 
             #[cfg(target_os = "android")]
             let mut battery_deadline = Instant::now();
@@ -444,6 +546,23 @@ fn connection_pipeline(
                     keepalive_deadline = Instant::now() + KEEPALIVE_INTERVAL;
                 }
 
+                if Instant::now() > clock_sync_deadline {
+                    // The estimate is shared so the server can convert the client timestamps
+                    let request = ctx.statistics_manager.lock().as_ref().map(|stats| {
+                        ClientControlPacket::ClockSyncRequest {
+                            client_send_time: stats.clock_time(),
+                            server_clock_estimate: stats.server_clock_estimate(),
+                        }
+                    });
+                    if let Some(request) = request
+                        && let Some(sender) = &mut *ctx.control_sender.lock()
+                    {
+                        sender.send(&request).ok();
+                    }
+
+                    clock_sync_deadline = Instant::now() + CLOCK_SYNC_INTERVAL;
+                }
+
                 #[cfg(target_os = "android")]
                 if Instant::now() > battery_deadline {
                     let (gauge_value, is_plugged) = alvr_system_info::get_battery_status();
@@ -496,6 +615,19 @@ fn connection_pipeline(
                     Ok(ServerControlPacket::StartStream) => {
                         error!("Unexpected StartStream paceket");
                     }
+                    Ok(ServerControlPacket::ClockSyncResponse {
+                        client_send_time,
+                        server_receive_time,
+                        server_send_time,
+                    }) => {
+                        if let Some(stats) = &mut *ctx.statistics_manager.lock() {
+                            stats.report_clock_sync_response(
+                                client_send_time,
+                                server_receive_time,
+                                server_send_time,
+                            );
+                        }
+                    }
                     Ok(ServerControlPacket::KeepAlive) => (),
                     Ok(
                         ServerControlPacket::Reserved(_) | ServerControlPacket::ReservedBuffer(_),
@@ -266,6 +266,16 @@ impl ClientCoreContext {
         if let Some(stats) = &mut *self.connection_context.statistics_manager.lock() {
             stats.report_frame_decoded(timestamp);
         }
+
+        if let Some(frame) = self
+            .connection_context
+            .intact_video_frames
+            .lock()
+            .iter_mut()
+            .find(|(frame_timestamp, _)| *frame_timestamp == timestamp)
+        {
+            frame.1 = true;
+        }
     }
 
     pub fn report_fatal_decoder_error(&self, error: &str) {
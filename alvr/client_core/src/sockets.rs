@@ -3,16 +3,19 @@ use mdns_sd::{ServiceDaemon, ServiceInfo};
 
 pub struct AnnouncerSocket {
     hostname: String,
+    // Public key the streamer pins when the user trusts this client
+    pairing_public_key: Option<String>,
     daemon: ServiceDaemon,
 }
 
 impl AnnouncerSocket {
-    pub fn new(hostname: &str) -> Result<Self> {
+    pub fn new(hostname: &str, pairing_public_key: Option<String>) -> Result<Self> {
         let daemon = ServiceDaemon::new()?;
 
         Ok(Self {
             daemon,
             hostname: hostname.to_owned(),
+            pairing_public_key,
         })
     }
 
@@ -22,16 +25,19 @@ impl AnnouncerSocket {
             bail!("IP is unspecified");
         }
 
+        let protocol_id = alvr_common::protocol_id();
+        let mut properties = vec![(alvr_sockets::MDNS_PROTOCOL_KEY, protocol_id.as_str())];
+        if let Some(key) = &self.pairing_public_key {
+            properties.push((alvr_sockets::MDNS_PAIRING_KEY_KEY, key.as_str()));
+        }
+
         self.daemon.register(ServiceInfo::new(
             alvr_sockets::MDNS_SERVICE_TYPE,
             &format!("alvr{}", rand::random::<u16>()),
             &self.hostname,
             local_ip,
             5353,
-            &[(
-                alvr_sockets::MDNS_PROTOCOL_KEY,
-                alvr_common::protocol_id().as_str(),
-            )][..],
+            &properties[..],
         )?)?;
 
         Ok(())
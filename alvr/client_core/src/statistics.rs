@@ -1,5 +1,6 @@
-use alvr_common::SlidingWindowAverage;
+use alvr_common::{ClockEstimate, ClockSyncEstimator, SlidingWindowAverage};
 use alvr_packets::ClientStatistics;
+use alvr_sockets::SecureChannel;
 use std::{
     collections::VecDeque,
     time::{Duration, Instant},
@@ -16,10 +17,14 @@ pub struct StatisticsManager {
     max_history_size: usize,
     prev_vsync: Instant,
     total_pipeline_latency_average: SlidingWindowAverage<Duration>,
+    secure_channel: Option<SecureChannel>,
+    // Reference for the times reported to the server and for clock synchronization
+    epoch: Instant,
+    server_clock_sync: ClockSyncEstimator,
 }
 
 impl StatisticsManager {
-    pub fn new(max_history_size: usize) -> Self {
+    pub fn new(max_history_size: usize, secure_channel: Option<SecureChannel>) -> Self {
         Self {
             max_history_size,
             history_buffer: VecDeque::new(),
@@ -28,6 +33,9 @@ impl StatisticsManager {
                 Duration::ZERO,
                 max_history_size,
             ),
+            secure_channel,
+            epoch: Instant::now(),
+            server_clock_sync: ClockSyncEstimator::new(),
         }
     }
 
@@ -37,12 +45,14 @@ impl StatisticsManager {
             .iter()
             .any(|frame| frame.client_stats.target_timestamp == target_timestamp)
         {
+            let now = Instant::now();
             self.history_buffer.push_front(HistoryFrame {
-                input_acquired: Instant::now(),
+                input_acquired: now,
                 // this is just a placeholder because Instant does not have a default value
-                video_packet_received: Instant::now(),
+                video_packet_received: now,
                 client_stats: ClientStatistics {
                     target_timestamp,
+                    tracking_sent: now.saturating_duration_since(self.epoch),
                     ..Default::default()
                 },
             });
@@ -53,13 +63,24 @@ impl StatisticsManager {
         }
     }
 
-    pub fn report_video_packet_received(&mut self, target_timestamp: Duration) {
+    pub fn report_video_packet_received(
+        &mut self,
+        target_timestamp: Duration,
+        fec_recovered_shards: usize,
+        first_shard_arrival: Instant,
+        last_shard_arrival: Instant,
+    ) {
         if let Some(frame) = self
             .history_buffer
             .iter_mut()
             .find(|frame| frame.client_stats.target_timestamp == target_timestamp)
         {
             frame.video_packet_received = Instant::now();
+            frame.client_stats.fec_recovered_shards = fec_recovered_shards;
+            frame.client_stats.video_first_shard_arrival =
+                first_shard_arrival.saturating_duration_since(self.epoch);
+            frame.client_stats.video_last_shard_arrival =
+                last_shard_arrival.saturating_duration_since(self.epoch);
         }
     }
 
@@ -110,6 +131,10 @@ impl StatisticsManager {
             let vsync = now + vsync_queue;
             frame.client_stats.frame_interval = vsync.saturating_duration_since(self.prev_vsync);
             self.prev_vsync = vsync;
+
+            if let Some(channel) = &self.secure_channel {
+                frame.client_stats.encryption_time = channel.take_statistics().processing_time;
+            }
         }
     }
 
@@ -120,6 +145,29 @@ impl StatisticsManager {
             .map(|frame| frame.client_stats.clone())
     }
 
+    pub fn clock_time(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    pub fn report_clock_sync_response(
+        &mut self,
+        client_send_time: Duration,
+        server_receive_time: Duration,
+        server_send_time: Duration,
+    ) {
+        let client_receive_time = self.clock_time();
+        self.server_clock_sync.report_exchange(
+            client_send_time,
+            server_receive_time,
+            server_send_time,
+            client_receive_time,
+        );
+    }
+
+    pub fn server_clock_estimate(&self) -> Option<ClockEstimate> {
+        self.server_clock_sync.estimate()
+    }
+
     // latency used for head prediction
     pub fn average_total_pipeline_latency(&self) -> Duration {
         self.total_pipeline_latency_average.get_average()
@@ -20,6 +20,16 @@ fn config_path() -> PathBuf {
 pub struct Config {
     pub hostname: String,
     pub protocol_id: String,
+    // Private key used to authenticate this client when the connection is encrypted
+    #[serde(default)]
+    pub pairing_key: String,
+    // Public key of the streamer this client is paired with
+    #[serde(default)]
+    pub paired_server_key: Option<String>,
+    // Refuse streamers with encryption disabled even before pairing, so that the pairing cannot be
+    // skipped by a man in the middle
+    #[serde(default)]
+    pub require_encryption: bool,
 }
 
 impl Default for Config {
@@ -35,16 +45,32 @@ impl Default for Config {
                 rng.random_range(0..10),
             ),
             protocol_id: alvr_common::protocol_id(),
+            pairing_key: new_pairing_key(),
+            paired_server_key: None,
+            require_encryption: false,
         }
     }
 }
 
+fn new_pairing_key() -> String {
+    alvr_sockets::generate_pairing_key().unwrap_or_else(|e| {
+        error!("Error generating pairing key: {e}");
+        String::new()
+    })
+}
+
 impl Config {
     pub fn load() -> Self {
         if let Ok(config_string) = fs::read_to_string(config_path()) {
             // Failure happens if the Config signature changed between versions.
             // todo: recover data from mismatched Config signature. low priority
-            if let Ok(config) = serde_json::from_str(&config_string) {
+            if let Ok(mut config) = serde_json::from_str::<Config>(&config_string) {
+                // Configs from older versions don't have a pairing key
+                if config.pairing_key.is_empty() {
+                    config.pairing_key = new_pairing_key();
+                    config.store();
+                }
+
                 return config;
             } else {
                 info!("Error parsing ALVR config. Using default");
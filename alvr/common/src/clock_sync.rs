@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+
+const MAX_SAMPLES: usize = 64;
+// Samples are discarded if their round trip time is above the minimum plus this margin, since the
+// asymmetry of the queuing delay makes the offset inaccurate
+const ROUND_TRIP_TIME_MARGIN_S: f64 = 0.002;
+// Minimum time span of the samples used to estimate the drift
+const MIN_DRIFT_SPAN_S: f64 = 5.0;
+// Crystal oscillators are well within this bound
+const MAX_DRIFT: f64 = 500e-6;
+
+// Offset and drift of a remote clock relative to the local clock. Clocks are represented by the
+// duration since an arbitrary epoch chosen by each peer.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ClockEstimate {
+    pub reference_local_time: Duration,
+    pub offset_s: f64, // remote - local, at the reference time
+    pub drift: f64,    // seconds gained by the remote clock per local second
+    pub round_trip_time: Duration,
+}
+
+impl ClockEstimate {
+    pub fn offset_s_at(&self, local_time: Duration) -> f64 {
+        self.offset_s
+            + self.drift * (local_time.as_secs_f64() - self.reference_local_time.as_secs_f64())
+    }
+
+    pub fn local_to_remote(&self, local_time: Duration) -> Duration {
+        Duration::from_secs_f64((local_time.as_secs_f64() + self.offset_s_at(local_time)).max(0.0))
+    }
+
+    pub fn remote_to_local(&self, remote_time: Duration) -> Duration {
+        // The drift is tiny, evaluating it at the approximated local time is accurate enough
+        let approximate_local_time =
+            Duration::from_secs_f64((remote_time.as_secs_f64() - self.offset_s).max(0.0));
+
+        Duration::from_secs_f64(
+            (remote_time.as_secs_f64() - self.offset_s_at(approximate_local_time)).max(0.0),
+        )
+    }
+
+    // The estimate as seen by the remote peer
+    pub fn inverse(&self) -> Self {
+        Self {
+            reference_local_time: self.local_to_remote(self.reference_local_time),
+            offset_s: -self.offset_s,
+            drift: -self.drift / (1.0 + self.drift),
+            round_trip_time: self.round_trip_time,
+        }
+    }
+}
+
+struct ClockSample {
+    local_time_s: f64,
+    offset_s: f64,
+    round_trip_time_s: f64,
+}
+
+// NTP-style estimator. Each exchange is made of a request sent by the local peer and a response
+// containing the receive and send times of the remote peer.
+#[derive(Default)]
+pub struct ClockSyncEstimator {
+    samples: VecDeque<ClockSample>,
+    estimate: Option<ClockEstimate>,
+}
+
+impl ClockSyncEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn report_exchange(
+        &mut self,
+        request_sent_local: Duration,
+        request_received_remote: Duration,
+        response_sent_remote: Duration,
+        response_received_local: Duration,
+    ) {
+        let t0 = request_sent_local.as_secs_f64();
+        let t1 = request_received_remote.as_secs_f64();
+        let t2 = response_sent_remote.as_secs_f64();
+        let t3 = response_received_local.as_secs_f64();
+
+        let round_trip_time_s = (t3 - t0) - (t2 - t1);
+        if round_trip_time_s < 0.0 {
+            // Corrupted exchange
+            return;
+        }
+
+        self.samples.push_back(ClockSample {
+            local_time_s: (t0 + t3) / 2.0,
+            offset_s: ((t1 - t0) + (t2 - t3)) / 2.0,
+            round_trip_time_s,
+        });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.update_estimate();
+    }
+
+    fn update_estimate(&mut self) {
+        let min_round_trip_time_s = self
+            .samples
+            .iter()
+            .map(|s| s.round_trip_time_s)
+            .fold(f64::MAX, f64::min);
+        let good_samples = self
+            .samples
+            .iter()
+            .filter(|s| s.round_trip_time_s <= min_round_trip_time_s + ROUND_TRIP_TIME_MARGIN_S)
+            .collect::<Vec<_>>();
+
+        // Safety: there is at least one sample, the one with the minimum round trip time
+        let first = good_samples.first().unwrap();
+        let last = good_samples.last().unwrap();
+
+        let count = good_samples.len() as f64;
+        let mean_time_s = good_samples.iter().map(|s| s.local_time_s).sum::<f64>() / count;
+        let mean_offset_s = good_samples.iter().map(|s| s.offset_s).sum::<f64>() / count;
+
+        let drift = if last.local_time_s - first.local_time_s >= MIN_DRIFT_SPAN_S {
+            let (numerator, denominator) = good_samples.iter().fold((0.0, 0.0), |(num, den), s| {
+                let dt = s.local_time_s - mean_time_s;
+                (num + dt * (s.offset_s - mean_offset_s), den + dt * dt)
+            });
+
+            (numerator / denominator).clamp(-MAX_DRIFT, MAX_DRIFT)
+        } else {
+            0.0
+        };
+
+        self.estimate = Some(ClockEstimate {
+            reference_local_time: Duration::from_secs_f64(last.local_time_s.max(0.0)),
+            offset_s: mean_offset_s + drift * (last.local_time_s - mean_time_s),
+            drift,
+            round_trip_time: Duration::from_secs_f64(min_round_trip_time_s),
+        });
+    }
+
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        self.estimate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFSET_S: f64 = 3.5;
+    const PROCESSING_S: f64 = 0.001;
+
+    // Simulates an exchange starting at the given local time, with a remote clock ahead by
+    // OFFSET_S plus the accumulated drift
+    fn report_exchange(
+        estimator: &mut ClockSyncEstimator,
+        local_time_s: f64,
+        uplink_s: f64,
+        downlink_s: f64,
+        drift: f64,
+    ) {
+        let to_remote = |local_s: f64| local_s + OFFSET_S + drift * local_s;
+
+        let t0 = local_time_s;
+        let t1 = to_remote(t0 + uplink_s);
+        let t2 = t1 + PROCESSING_S;
+        let t3 = t0 + uplink_s + PROCESSING_S + downlink_s;
+
+        estimator.report_exchange(
+            Duration::from_secs_f64(t0),
+            Duration::from_secs_f64(t1),
+            Duration::from_secs_f64(t2),
+            Duration::from_secs_f64(t3),
+        );
+    }
+
+    fn assert_near(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() < tolerance,
+            "{value} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn test_symmetric_exchange() {
+        let mut estimator = ClockSyncEstimator::new();
+        assert!(estimator.estimate().is_none());
+
+        report_exchange(&mut estimator, 10.0, 0.01, 0.01, 0.0);
+
+        let estimate = estimator.estimate().unwrap();
+        assert_near(estimate.offset_s, OFFSET_S, 1e-6);
+        assert_near(estimate.round_trip_time.as_secs_f64(), 0.02, 1e-6);
+        assert_eq!(estimate.drift, 0.0);
+    }
+
+    #[test]
+    fn test_asymmetric_exchange() {
+        let mut estimator = ClockSyncEstimator::new();
+
+        // Half of the path asymmetry ends up in the offset, it cannot be observed
+        report_exchange(&mut estimator, 10.0, 0.01, 0.03, 0.0);
+
+        let estimate = estimator.estimate().unwrap();
+        assert_near(estimate.offset_s, OFFSET_S + (0.01 - 0.03) / 2.0, 1e-6);
+        assert_near(estimate.round_trip_time.as_secs_f64(), 0.04, 1e-6);
+    }
+
+    #[test]
+    fn test_queued_samples_are_discarded() {
+        let mut estimator = ClockSyncEstimator::new();
+
+        for i in 0..20 {
+            // One exchange out of two is delayed by a queue, alternating the direction
+            let queuing_s = 0.02 + i as f64 * 0.001;
+            let (uplink_s, downlink_s) = match i % 4 {
+                1 => (0.005 + queuing_s, 0.005),
+                3 => (0.005, 0.005 + queuing_s),
+                _ => (0.005, 0.005),
+            };
+            let local_time_s = 10.0 + i as f64 * 0.1;
+            report_exchange(&mut estimator, local_time_s, uplink_s, downlink_s, 0.0);
+        }
+
+        let estimate = estimator.estimate().unwrap();
+        assert_near(estimate.offset_s, OFFSET_S, 1e-6);
+        assert_near(estimate.round_trip_time.as_secs_f64(), 0.01, 1e-6);
+    }
+
+    #[test]
+    fn test_corrupted_exchange_is_ignored() {
+        let mut estimator = ClockSyncEstimator::new();
+
+        // The response is received before the request has been processed
+        estimator.report_exchange(
+            Duration::from_secs(10),
+            Duration::from_secs(20),
+            Duration::from_secs(21),
+            Duration::from_millis(10_500),
+        );
+
+        assert!(estimator.estimate().is_none());
+    }
+
+    #[test]
+    fn test_drift() {
+        let drift = 100e-6;
+        let mut estimator = ClockSyncEstimator::new();
+
+        // The drift is estimated only after MIN_DRIFT_SPAN_S
+        for i in 0..20 {
+            report_exchange(&mut estimator, 10.0 + i as f64 * 0.2, 0.005, 0.005, drift);
+        }
+        assert_eq!(estimator.estimate().unwrap().drift, 0.0);
+
+        for i in 0..40 {
+            report_exchange(&mut estimator, 14.0 + i as f64 * 0.2, 0.005, 0.005, drift);
+        }
+        let estimate = estimator.estimate().unwrap();
+        assert_near(estimate.drift, drift, 1e-6);
+
+        let local_time = Duration::from_secs(30);
+        let remote_time_s = 30.0 + OFFSET_S + drift * 30.0;
+        assert_near(
+            estimate.local_to_remote(local_time).as_secs_f64(),
+            remote_time_s,
+            1e-6,
+        );
+        assert_near(
+            estimate
+                .remote_to_local(estimate.local_to_remote(local_time))
+                .as_secs_f64(),
+            30.0,
+            1e-6,
+        );
+
+        // The inverse estimate maps remote times back to local times
+        let inverse = estimate.inverse();
+        assert_near(
+            inverse
+                .local_to_remote(Duration::from_secs_f64(remote_time_s))
+                .as_secs_f64(),
+            30.0,
+            1e-6,
+        );
+    }
+}
@@ -1,5 +1,6 @@
 mod average;
 mod c_api;
+mod clock_sync;
 mod connection_result;
 pub mod inputs;
 mod logging;
@@ -19,6 +20,7 @@ pub use settings_schema;
 
 pub use average::*;
 pub use c_api::*;
+pub use clock_sync::*;
 pub use connection_result::*;
 pub use inputs::*;
 pub use log::{debug, error, info, warn};
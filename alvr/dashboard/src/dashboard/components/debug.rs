@@ -27,5 +27,21 @@ For that, use other means of recording, for example through headset or desktop V
         }
     });
 
+    ui.add_space(10.0);
+
+    ui.label(
+        "Session recordings contain the tracking and inputs received from the headset. They are saved in the log directory and can be replayed through the server API without a headset.",
+    );
+
+    ui.columns(2, |ui| {
+        if ui[0].button("Start session recording").clicked() {
+            request = Some(ServerRequest::StartSessionRecording);
+        }
+
+        if ui[1].button("Stop session recording").clicked() {
+            request = Some(ServerRequest::StopSessionRecording);
+        }
+    });
+
     request
 }
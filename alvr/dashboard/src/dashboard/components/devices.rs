@@ -1,5 +1,6 @@
 use crate::dashboard::ServerRequest;
 use alvr_common::ConnectionState;
+use alvr_events::SpectatorStatistics;
 use alvr_gui_common::theme::{self, log_colors};
 use alvr_packets::ClientConnectionsAction;
 use alvr_session::{ClientConnectionConfig, SessionConfig};
@@ -9,6 +10,7 @@ use eframe::{
     emath::{Align, Align2},
     epaint::Color32,
 };
+use std::collections::HashMap;
 
 struct EditPopupState {
     new_devices: bool,
@@ -21,6 +23,7 @@ pub struct DevicesTab {
     trusted_devices: Option<Vec<(String, ClientConnectionConfig)>>,
     edit_popup_state: Option<EditPopupState>,
     adb_download_progress: Option<f32>,
+    spectator_statistics: HashMap<String, SpectatorStatistics>,
 }
 
 impl DevicesTab {
@@ -30,6 +33,7 @@ impl DevicesTab {
             trusted_devices: None,
             edit_popup_state: None,
             adb_download_progress: None,
+            spectator_statistics: HashMap::new(),
         }
     }
 
@@ -41,10 +45,21 @@ impl DevicesTab {
                 .into_iter()
                 .partition::<Vec<_>, _>(|(_, data)| data.trusted);
 
+        self.spectator_statistics.retain(|hostname, _| {
+            trusted_clients.iter().any(|(trusted_hostname, data)| {
+                trusted_hostname == hostname && data.connection_state == ConnectionState::Streaming
+            })
+        });
+
         self.trusted_devices = Some(trusted_clients);
         self.new_devices = Some(untrusted_clients);
     }
 
+    pub fn update_spectator_statistics(&mut self, statistics: SpectatorStatistics) {
+        self.spectator_statistics
+            .insert(statistics.hostname.clone(), statistics);
+    }
+
     pub fn update_adb_download_progress(&mut self, progress: f32) {
         self.adb_download_progress = Some(progress);
     }
@@ -99,10 +114,8 @@ impl DevicesTab {
 
             ui.add_space(theme::FRAME_PADDING);
 
-            if let Some(clients) = &self.new_devices
-                && let Some(request) = new_clients_section(ui, clients)
-            {
-                requests.push(request);
+            if let Some(clients) = &self.new_devices {
+                requests.extend(new_clients_section(ui, clients));
             }
 
             ui.add_space(theme::FRAME_PADDING);
@@ -115,6 +128,7 @@ impl DevicesTab {
                         .filter(|(hostname, _)| hostname != WIRED_CLIENT_HOSTNAME)
                         .collect::<Vec<_>>()
                         .as_slice(),
+                    &self.spectator_statistics,
                     &mut self.edit_popup_state,
                 )
             {
@@ -258,8 +272,8 @@ fn wired_client_section(
 fn new_clients_section(
     ui: &mut Ui,
     clients: &[(String, ClientConnectionConfig)],
-) -> Option<ServerRequest> {
-    let mut request = None;
+) -> Vec<ServerRequest> {
+    let mut requests = vec![];
 
     Frame::group(ui.style())
         .inner_margin(theme::FRAME_PADDING)
@@ -274,7 +288,7 @@ fn new_clients_section(
                     ui.with_layout(Layout::right_to_left(Align::Center), |_| ());
                 });
             });
-            for (hostname, _) in clients {
+            for (hostname, data) in clients {
                 Frame::group(ui.style())
                     .fill(theme::DARKER_BG)
                     .inner_margin(egui::vec2(15.0, 12.0))
@@ -285,13 +299,26 @@ fn new_clients_section(
                             .show(ui, |ui| {
                                 ui.horizontal(|ui| {
                                     ui.label(hostname);
+                                    if let Some(key) = &data.announced_pairing_key {
+                                        pairing_key_label(ui, key);
+                                    }
                                 });
                                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
                                     if ui.button("Trust").clicked() {
-                                        request = Some(ServerRequest::UpdateClientList {
+                                        requests.push(ServerRequest::UpdateClientList {
                                             hostname: hostname.clone(),
                                             action: ClientConnectionsAction::Trust,
                                         });
+                                        // Pin the key the user has seen, not the one of whichever
+                                        // device connects first
+                                        if let Some(key) = &data.announced_pairing_key {
+                                            requests.push(ServerRequest::UpdateClientList {
+                                                hostname: hostname.clone(),
+                                                action: ClientConnectionsAction::SetPairingKey(
+                                                    key.clone(),
+                                                ),
+                                            });
+                                        }
                                     };
                                 });
                                 ui.end_row();
@@ -300,12 +327,13 @@ fn new_clients_section(
             }
         });
 
-    request
+    requests
 }
 
 fn trusted_clients_section(
     ui: &mut Ui,
     clients: &[&(String, ClientConnectionConfig)],
+    spectator_statistics: &HashMap<String, SpectatorStatistics>,
     edit_popup_state: &mut Option<EditPopupState>,
 ) -> Option<ServerRequest> {
     let mut request = None;
@@ -373,6 +401,56 @@ fn trusted_clients_section(
                                         });
                                     }
                                 });
+
+                                ui.end_row();
+
+                                let mut spectator = data.spectator;
+                                if ui
+                                    .checkbox(&mut spectator, "Spectator")
+                                    .on_hover_text(
+                                        "Receive the stream of the main device. \
+                                        The tracking and input of this device are ignored.",
+                                    )
+                                    .changed()
+                                {
+                                    request = Some(ServerRequest::UpdateClientList {
+                                        hostname: hostname.clone(),
+                                        action: ClientConnectionsAction::SetSpectator(spectator),
+                                    });
+                                }
+                                if let Some(stats) = spectator_statistics.get(hostname) {
+                                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                        ui.label(format!(
+                                            "{:.1} Mbps, {}, {} dropped frames/s",
+                                            stats.video_mbits_per_sec,
+                                            stats.client_fps.map_or_else(
+                                                || "unknown FPS".into(),
+                                                |fps| format!("{fps} FPS")
+                                            ),
+                                            stats.dropped_frames_per_sec
+                                        ));
+                                    });
+                                }
+
+                                // Clients trusted before announcing their key, for example the ones
+                                // added manually, are paired once the user confirms it
+                                if data.pairing_key.is_none()
+                                    && let Some(key) = &data.announced_pairing_key
+                                {
+                                    ui.end_row();
+
+                                    pairing_key_label(ui, key);
+                                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                        if ui.button("Confirm pairing key").clicked() {
+                                            request = Some(ServerRequest::UpdateClientList {
+                                                hostname: hostname.clone(),
+                                                action: ClientConnectionsAction::SetPairingKey(
+                                                    key.clone(),
+                                                ),
+                                            });
+                                        }
+                                    });
+                                }
                             });
                     });
             }
@@ -381,6 +459,15 @@ fn trusted_clients_section(
     request
 }
 
+// The fingerprint must match the one shown by the client
+fn pairing_key_label(ui: &mut Ui, public_key: &str) {
+    ui.weak(format!(
+        "Pairing key: {}",
+        alvr_sockets::pairing_key_fingerprint(public_key)
+    ))
+    .on_hover_text("Check that the same key is shown on the device");
+}
+
 fn connection_label(ui: &mut Ui, connection_state: &ConnectionState) {
     match connection_state {
         ConnectionState::Disconnected => ui.colored_label(Color32::GRAY, "Disconnected"),
@@ -50,6 +50,13 @@ impl StatisticsTab {
                 self.draw_latency_graph(ui, available_width);
                 self.draw_fps_graph(ui, available_width);
                 self.draw_bitrate_graph(ui, available_width);
+                if self
+                    .history
+                    .back()
+                    .is_some_and(|stats| stats.bitrate_directives.congestion_signal.is_some())
+                {
+                    self.draw_congestion_graph(ui, available_width);
+                }
                 self.draw_statistics_overview(ui, stats);
             });
         } else {
@@ -282,6 +289,7 @@ impl StatisticsTab {
                 let mut encoder_latency_limiter = Vec::with_capacity(GRAPH_HISTORY_SIZE);
                 let mut max_throughput = Vec::with_capacity(GRAPH_HISTORY_SIZE);
                 let mut min_throughput = Vec::with_capacity(GRAPH_HISTORY_SIZE);
+                let mut congestion_receive_rate = Vec::with_capacity(GRAPH_HISTORY_SIZE);
                 let mut requested_bitrate = Vec::with_capacity(GRAPH_HISTORY_SIZE);
                 let mut recorded_throughput = Vec::with_capacity(GRAPH_HISTORY_SIZE);
                 let mut recorded_bitrate = Vec::with_capacity(GRAPH_HISTORY_SIZE);
@@ -306,6 +314,9 @@ impl StatisticsTab {
                     if let Some(value) = d.manual_min_throughput_bps {
                         min_throughput.push(to_screen_trans * pos2(i as f32, value / 1e6))
                     }
+                    if let Some(value) = d.congestion_receive_rate_bps {
+                        congestion_receive_rate.push(to_screen_trans * pos2(i as f32, value / 1e6))
+                    }
                     requested_bitrate
                         .push(to_screen_trans * pos2(i as f32, d.requested_bitrate_bps / 1e6));
                     recorded_throughput.push(
@@ -345,6 +356,11 @@ impl StatisticsTab {
                     min_throughput,
                     graph_colors::MIN_MAX_LATENCY_THROUGHPUT,
                 );
+                draw_lines(
+                    painter,
+                    congestion_receive_rate,
+                    graph_colors::CONGESTION_RECEIVE_RATE,
+                );
                 draw_lines(painter, requested_bitrate, graph_colors::REQUESTED_BITRATE);
                 draw_lines(
                     painter,
@@ -407,6 +423,12 @@ impl StatisticsTab {
                         td.manual_min_throughput_bps,
                         graph_colors::MIN_MAX_LATENCY_THROUGHPUT,
                     );
+                    maybe_label(
+                        ui,
+                        "Receive rate",
+                        td.congestion_receive_rate_bps,
+                        graph_colors::CONGESTION_RECEIVE_RATE,
+                    );
                     maybe_label(
                         ui,
                         "Requested bitrate",
@@ -432,6 +454,118 @@ impl StatisticsTab {
         )
     }
 
+    fn draw_congestion_graph(&self, ui: &mut Ui, available_width: f32) {
+        let mut data = statistics::Data::new(
+            self.history
+                .iter()
+                .map(|stats| {
+                    let d = &stats.bitrate_directives;
+                    [
+                        d.congestion_delay_trend_ms.map(f32::abs),
+                        d.congestion_overuse_threshold_ms,
+                        d.congestion_queuing_delay_s.map(|s| s * 1000.0),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .fold(0.0, f32::max) as f64
+                })
+                .collect::<Vec<_>>(),
+        );
+        let max = (data.quantile(UPPER_QUANTILE) * 1.2) as f32;
+
+        self.draw_graph(
+            ui,
+            available_width,
+            "Congestion Control",
+            -max..=max,
+            |painter, to_screen_trans| {
+                let mut delay_trend = Vec::with_capacity(GRAPH_HISTORY_SIZE);
+                let mut upper_threshold = Vec::with_capacity(GRAPH_HISTORY_SIZE);
+                let mut lower_threshold = Vec::with_capacity(GRAPH_HISTORY_SIZE);
+                let mut queuing_delay = Vec::with_capacity(GRAPH_HISTORY_SIZE);
+                for i in 0..GRAPH_HISTORY_SIZE {
+                    let d = &self.history[i].bitrate_directives;
+
+                    if let Some(value) = d.congestion_delay_trend_ms {
+                        delay_trend.push(to_screen_trans * pos2(i as f32, value));
+                    }
+                    if let Some(value) = d.congestion_overuse_threshold_ms {
+                        upper_threshold.push(to_screen_trans * pos2(i as f32, value));
+                        lower_threshold.push(to_screen_trans * pos2(i as f32, -value));
+                    }
+                    if let Some(value) = d.congestion_queuing_delay_s {
+                        queuing_delay.push(to_screen_trans * pos2(i as f32, value * 1000.0));
+                    }
+                }
+
+                draw_lines(
+                    painter,
+                    upper_threshold,
+                    graph_colors::CONGESTION_OVERUSE_THRESHOLD,
+                );
+                draw_lines(
+                    painter,
+                    lower_threshold,
+                    graph_colors::CONGESTION_OVERUSE_THRESHOLD,
+                );
+                draw_lines(
+                    painter,
+                    queuing_delay,
+                    graph_colors::CONGESTION_QUEUING_DELAY,
+                );
+                draw_lines(painter, delay_trend, graph_colors::CONGESTION_DELAY_TREND);
+            },
+            |ui, stats| {
+                Grid::new("congestion_tooltip")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        fn maybe_label(
+                            ui: &mut Ui,
+                            text: &str,
+                            maybe_value_ms: Option<f32>,
+                            color: Color32,
+                        ) {
+                            if let Some(value) = maybe_value_ms {
+                                ui.colored_label(color, text);
+                                ui.colored_label(color, format!("{value:.2}ms"));
+                                ui.end_row();
+                            }
+                        }
+
+                        let d = &stats.bitrate_directives;
+
+                        if let Some(signal) = d.congestion_signal {
+                            ui.label("Network state");
+                            ui.label(format!("{signal:?}"));
+                            ui.end_row();
+                        }
+                        maybe_label(
+                            ui,
+                            "Delay trend",
+                            d.congestion_delay_trend_ms,
+                            graph_colors::CONGESTION_DELAY_TREND,
+                        );
+                        maybe_label(
+                            ui,
+                            "Overuse threshold",
+                            d.congestion_overuse_threshold_ms,
+                            graph_colors::CONGESTION_OVERUSE_THRESHOLD,
+                        );
+                        maybe_label(
+                            ui,
+                            "Queuing delay",
+                            d.congestion_queuing_delay_s.map(|s| s * 1000.0),
+                            graph_colors::CONGESTION_QUEUING_DELAY,
+                        );
+                    });
+
+                ui.small(
+                    "Note: the bitrate is reduced when the delay trend is above the threshold.",
+                );
+            },
+        )
+    }
+
     fn draw_statistics_overview(&self, ui: &mut Ui, statistics: &StatisticsSummary) {
         ui.add_space(10.0);
 
@@ -448,6 +582,20 @@ impl StatisticsTab {
             ui[0].label("Bitrate:");
             ui[1].label(format!("{:.1} Mbps", statistics.video_mbits_per_sec));
 
+            ui[0].label("FEC recovered shards:");
+            ui[1].label(format!(
+                "{} shards ({} shards/s)",
+                statistics.fec_recovered_shards_total, statistics.fec_recovered_shards_per_sec
+            ));
+
+            ui[0].label("Encryption cost:");
+            ui[1].label(format!(
+                "server {:.2} ms/s, client {:.2} ms/s, {:.1} kbps",
+                statistics.server_encryption_ms_per_sec,
+                statistics.client_encryption_ms_per_sec,
+                statistics.encryption_overhead_kbits_per_sec
+            ));
+
             ui[0].label("Total latency:");
             ui[1].label(format!("{:.0} ms", statistics.total_latency_ms));
 
@@ -457,6 +605,28 @@ impl StatisticsTab {
             ui[0].label("Transport latency:");
             ui[1].label(format!("{:.2} ms", statistics.network_latency_ms));
 
+            ui[0].label("Uplink / downlink latency:");
+            ui[1].label(
+                if let (Some(uplink), Some(downlink)) =
+                    (statistics.uplink_latency_ms, statistics.downlink_latency_ms)
+                {
+                    format!("{uplink:.2} ms / {downlink:.2} ms")
+                } else {
+                    "Synchronizing clocks...".into()
+                },
+            );
+
+            ui[0].label("Clock offset:");
+            ui[1].label(
+                if let (Some(offset), Some(round_trip)) =
+                    (statistics.clock_offset_ms, statistics.clock_round_trip_ms)
+                {
+                    format!("{offset:.2} ms (round trip {round_trip:.2} ms)")
+                } else {
+                    "-".into()
+                },
+            );
+
             ui[0].label("Decoder latency:");
             ui[1].label(format!("{:.2} ms", statistics.decode_latency_ms));
 
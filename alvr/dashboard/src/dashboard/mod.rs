@@ -35,6 +35,8 @@ pub enum ServerRequest {
     InsertIdr,
     StartRecording,
     StopRecording,
+    StartSessionRecording,
+    StopSessionRecording,
     AddFirewallRules,
     RemoveFirewallRules,
     GetDriverList,
@@ -161,6 +163,9 @@ impl eframe::App for Dashboard {
                 EventType::StatisticsSummary(statistics) => {
                     self.statistics_tab.update_statistics(statistics)
                 }
+                EventType::SpectatorStatistics(statistics) => {
+                    self.connections_tab.update_spectator_statistics(statistics)
+                }
                 EventType::Session(session) => {
                     let settings = session.to_settings();
 
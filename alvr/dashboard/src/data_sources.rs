@@ -139,6 +139,17 @@ impl DataSources {
 
         let session_manager = get_local_session_source();
         let port = session_manager.settings().connection.web_server_port;
+
+        // The server loads the same tokens from disk
+        let authorization = match alvr_server_io::load_or_create_web_server_tokens(
+            &filesystem_layout.web_server_tokens(),
+        ) {
+            Ok(tokens) => format!("Bearer {}", tokens.admin),
+            Err(e) => {
+                error!("Failed to load the web server tokens: {e}");
+                String::new()
+            }
+        };
         let session_source = Arc::new(Mutex::new(SessionSource::Local(Box::new(session_manager))));
 
         let version_check_thread = thread::spawn({
@@ -210,6 +221,7 @@ impl DataSources {
             let context = context.clone();
             let session_source = Arc::clone(&session_source);
             let events_sender = events_sender.clone();
+            let authorization = authorization.clone();
             move || {
                 let base_uri = format!("http://127.0.0.1:{port}");
                 let rq: ureq::Agent = ureq::Agent::config_builder()
@@ -305,7 +317,9 @@ impl DataSources {
                                 ServerRequest::CaptureFrame
                                 | ServerRequest::InsertIdr
                                 | ServerRequest::StartRecording
-                                | ServerRequest::StopRecording => {
+                                | ServerRequest::StopRecording
+                                | ServerRequest::StartSessionRecording
+                                | ServerRequest::StopSessionRecording => {
                                     warn!(
                                         "Cannot perform action, streamer (SteamVR) is not connected."
                                     )
@@ -318,6 +332,7 @@ impl DataSources {
                             let get = |path: &str| {
                                 rq.get(format!("{base_uri}/api/{path}"))
                                     .header("X-ALVR", "true")
+                                    .header("Authorization", &authorization)
                                     .call()
                                     .ok();
                             };
@@ -325,34 +340,47 @@ impl DataSources {
                             fn post_body(
                                 rq: &ureq::Agent,
                                 base_uri: &str,
+                                authorization: &str,
                                 path: &str,
                                 body: Option<impl Serialize>,
                             ) {
                                 let builder = rq
                                     .post(format!("{base_uri}/api/{path}"))
-                                    .header("X-ALVR", "true");
+                                    .header("X-ALVR", "true")
+                                    .header("Authorization", authorization);
                                 if let Some(body) = body {
                                     builder.send_json(body).ok();
                                 } else {
                                     builder.send_empty().ok();
                                 }
                             }
-                            let post = |path: &str| post_body(&rq, &base_uri, path, None::<()>);
+                            let post = |path: &str| {
+                                post_body(&rq, &base_uri, &authorization, path, None::<()>)
+                            };
 
                             match request {
                                 ServerRequest::Log(entry) => {
-                                    post_body(&rq, &base_uri, "log", Some(entry))
+                                    post_body(&rq, &base_uri, &authorization, "log", Some(entry))
                                 }
                                 ServerRequest::GetSession => get("session"),
-                                ServerRequest::UpdateSession(session) => {
-                                    post_body(&rq, &base_uri, "session", Some(&*session))
-                                }
-                                ServerRequest::SetSessionValues(values) => {
-                                    post_body(&rq, &base_uri, "session/values", Some(values))
-                                }
+                                ServerRequest::UpdateSession(session) => post_body(
+                                    &rq,
+                                    &base_uri,
+                                    &authorization,
+                                    "session",
+                                    Some(&*session),
+                                ),
+                                ServerRequest::SetSessionValues(values) => post_body(
+                                    &rq,
+                                    &base_uri,
+                                    &authorization,
+                                    "session/values",
+                                    Some(values),
+                                ),
                                 ServerRequest::UpdateClientList { hostname, action } => post_body(
                                     &rq,
                                     &base_uri,
+                                    &authorization,
                                     "session/client-connections",
                                     Some((hostname, action)),
                                 ),
@@ -360,13 +388,23 @@ impl DataSources {
                                 ServerRequest::RemoveFirewallRules => post("firewall-rules/remove"),
                                 ServerRequest::GetDriverList => get("drivers"),
                                 ServerRequest::RegisterAlvrDriver => post("drivers/register-alvr"),
-                                ServerRequest::UnregisterDriver(path) => {
-                                    post_body(&rq, &base_uri, "drivers/unregister", Some(path))
-                                }
+                                ServerRequest::UnregisterDriver(path) => post_body(
+                                    &rq,
+                                    &base_uri,
+                                    &authorization,
+                                    "drivers/unregister",
+                                    Some(path),
+                                ),
                                 ServerRequest::CaptureFrame => post("capture-frame"),
                                 ServerRequest::InsertIdr => post("insert-idr"),
                                 ServerRequest::StartRecording => post("recording/start"),
                                 ServerRequest::StopRecording => post("recording/stop"),
+                                ServerRequest::StartSessionRecording => {
+                                    post("session-recording/start")
+                                }
+                                ServerRequest::StopSessionRecording => {
+                                    post("session-recording/stop")
+                                }
                                 ServerRequest::RestartSteamvr => post("restart-steamvr"),
                                 ServerRequest::ShutdownSteamvr => post("shutdown-steamvr"),
                             }
@@ -404,6 +442,9 @@ impl DataSources {
                     let mut req = uri.into_client_request().unwrap();
                     req.headers_mut()
                         .insert("X-ALVR", HeaderValue::from_str("true").unwrap());
+                    if let Ok(value) = HeaderValue::from_str(&authorization) {
+                        req.headers_mut().insert("Authorization", value);
+                    }
 
                     let Ok((mut ws, _)) = tungstenite::client(req, socket) else {
                         thread::sleep(Duration::from_millis(500));
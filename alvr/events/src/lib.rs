@@ -2,26 +2,58 @@ use alvr_common::{DeviceMotion, LogEntry, LogSeverity, Pose, info};
 use alvr_packets::{ButtonValue, FaceData};
 use alvr_session::SessionConfig;
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::Duration,
+};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
 pub struct StatisticsSummary {
     pub video_packets_total: usize,
     pub video_packets_per_sec: usize,
     pub video_mbytes_total: usize,
     pub video_mbits_per_sec: f32,
+    pub fec_recovered_shards_total: usize,
+    pub fec_recovered_shards_per_sec: usize,
+    pub server_encryption_ms_per_sec: f32,
+    pub client_encryption_ms_per_sec: f32,
+    pub encryption_overhead_kbits_per_sec: f32,
     pub total_latency_ms: f32,
     pub network_latency_ms: f32,
     pub encode_latency_ms: f32,
     pub decode_latency_ms: f32,
+    // One way latencies, measured with the synchronized clocks
+    pub uplink_latency_ms: Option<f32>,
+    pub downlink_latency_ms: Option<f32>,
+    pub clock_offset_ms: Option<f32>,
+    pub clock_round_trip_ms: Option<f32>,
     pub client_fps: u32,
     pub server_fps: u32,
     pub battery_hmd: u32,
     pub hmd_plugged: bool,
 }
 
-// Bitrate statistics minus the empirical output value
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SpectatorStatistics {
+    pub hostname: String,
+    pub video_packets_per_sec: usize,
+    pub video_mbits_per_sec: f32,
+    pub dropped_frames_per_sec: usize,
+    pub client_fps: Option<u32>,
+    pub decode_latency_ms: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+pub enum CongestionSignal {
+    Normal,
+    Overuse,
+    Underuse,
+}
+
+// Bitrate statistics minus the empirical output value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
 pub struct BitrateDirectives {
     pub scaled_calculated_throughput_bps: Option<f32>,
     pub decoder_latency_limiter_bps: Option<f32>,
@@ -29,16 +61,24 @@ pub struct BitrateDirectives {
     pub encoder_latency_limiter_bps: Option<f32>,
     pub manual_max_throughput_bps: Option<f32>,
     pub manual_min_throughput_bps: Option<f32>,
+    // The following fields are used only by the delay gradient controller
+    pub congestion_receive_rate_bps: Option<f32>,
+    pub congestion_delay_trend_ms: Option<f32>,
+    pub congestion_overuse_threshold_ms: Option<f32>,
+    pub congestion_queuing_delay_s: Option<f32>,
+    pub congestion_signal: Option<CongestionSignal>,
     pub requested_bitrate_bps: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
 pub struct GraphStatistics {
     pub total_pipeline_latency_s: f32,
     pub game_time_s: f32,
     pub server_compositor_s: f32,
     pub encoder_s: f32,
     pub network_s: f32,
+    pub uplink_s: Option<f32>,
+    pub downlink_s: Option<f32>,
     pub decoder_s: f32,
     pub decoder_queue_s: f32,
     pub client_compositor_s: f32,
@@ -50,6 +90,13 @@ pub struct GraphStatistics {
     pub bitrate_bps: f32,
 }
 
+// Latest statistics of the main client. Fields are null if no client is streaming.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub struct StatisticsSnapshot {
+    pub summary: Option<StatisticsSummary>,
+    pub graph: Option<GraphStatistics>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TrackingEvent {
     pub device_motions: Vec<(String, DeviceMotion)>,
@@ -84,6 +131,7 @@ pub enum EventType {
     Session(Box<SessionConfig>),
     StatisticsSummary(StatisticsSummary),
     GraphStatistics(GraphStatistics),
+    SpectatorStatistics(SpectatorStatistics),
     Tracking(Box<TrackingEvent>),
     Buttons(Vec<ButtonEvent>),
     Haptics(HapticsEvent),
@@ -93,6 +141,27 @@ pub enum EventType {
     NewVersionFound { version: String, message: String },
 }
 
+impl EventType {
+    // Same as the serialized "id" field
+    pub fn id(&self) -> &'static str {
+        match self {
+            EventType::Log(_) => "Log",
+            EventType::DebugGroup { .. } => "DebugGroup",
+            EventType::Session(_) => "Session",
+            EventType::StatisticsSummary(_) => "StatisticsSummary",
+            EventType::GraphStatistics(_) => "GraphStatistics",
+            EventType::SpectatorStatistics(_) => "SpectatorStatistics",
+            EventType::Tracking(_) => "Tracking",
+            EventType::Buttons(_) => "Buttons",
+            EventType::Haptics(_) => "Haptics",
+            EventType::DriversList(_) => "DriversList",
+            EventType::ServerRequestsSelfRestart => "ServerRequestsSelfRestart",
+            EventType::Adb(_) => "Adb",
+            EventType::NewVersionFound { .. } => "NewVersionFound",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Event {
     pub timestamp: String,
@@ -112,6 +181,7 @@ impl Event {
             EventType::Session(_) => "SESSION".to_string(),
             EventType::StatisticsSummary(_) => "STATS".to_string(),
             EventType::GraphStatistics(_) => "GRAPH".to_string(),
+            EventType::SpectatorStatistics(_) => "SPECTATOR".to_string(),
             EventType::Tracking(_) => "TRACKING".to_string(),
             EventType::Buttons(_) => "BUTTONS".to_string(),
             EventType::Haptics(_) => "HAPTICS".to_string(),
@@ -127,7 +197,9 @@ impl Event {
             EventType::Log(log_entry) => log_entry.content.clone(),
             EventType::DebugGroup { message, .. } => message.clone(),
             EventType::Session(_) => "Updated".into(),
-            EventType::StatisticsSummary(_) | EventType::GraphStatistics(_) => "".into(),
+            EventType::StatisticsSummary(_)
+            | EventType::GraphStatistics(_)
+            | EventType::SpectatorStatistics(_) => "".into(),
             EventType::Tracking(tracking) => serde_json::to_string(tracking).unwrap(),
             EventType::Buttons(buttons) => serde_json::to_string(buttons).unwrap(),
             EventType::Haptics(haptics) => serde_json::to_string(haptics).unwrap(),
@@ -139,6 +211,24 @@ impl Event {
     }
 }
 
+// Sent by clients of the events WebSocket to select the events they receive. It can be sent again
+// at any time to replace the previous subscription.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EventSubscription {
+    // Event ids, as in the "id" field. All events are received if not set
+    pub event_types: Option<HashSet<String>>,
+    // Maximum events per second by event id. Events exceeding the rate are skipped
+    #[serde(default)]
+    pub max_rates: HashMap<String, f32>,
+}
+
+// Sent by the server through the events WebSocket when the client could not keep up and some
+// events were lost
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EventsLagged {
+    pub lagged_events: u64,
+}
+
 pub fn send_event(event_type: EventType) {
     info!("{}", serde_json::to_string(&event_type).unwrap());
 }
@@ -210,6 +210,14 @@ impl Layout {
         self.config_dir.join("session.json")
     }
 
+    pub fn pairing_key(&self) -> PathBuf {
+        self.config_dir.join("pairing_key")
+    }
+
+    pub fn web_server_tokens(&self) -> PathBuf {
+        self.config_dir.join("web_server_tokens.json")
+    }
+
     pub fn session_log(&self) -> PathBuf {
         if cfg!(target_os = "linux") {
             self.log_dir.join("alvr_session_log.txt")
@@ -48,6 +48,11 @@ pub mod graph_colors {
     pub const REQUESTED_BITRATE: Color32 = Color32::GREEN;
     pub const RECORDED_THROUGHPUT: Color32 = Color32::KHAKI;
     pub const RECORDED_BITRATE: Color32 = super::FG;
+
+    pub const CONGESTION_RECEIVE_RATE: Color32 = Color32::LIGHT_BLUE;
+    pub const CONGESTION_DELAY_TREND: Color32 = Color32::GOLD;
+    pub const CONGESTION_OVERUSE_THRESHOLD: Color32 = Color32::RED;
+    pub const CONGESTION_QUEUING_DELAY: Color32 = NETWORK;
 }
 
 pub fn set_theme(ctx: &Context) {
@@ -1,5 +1,5 @@
 use alvr_common::{
-    BodySkeleton, ConnectionState, DeviceMotion, LogSeverity, Pose, ViewParams,
+    BodySkeleton, ClockEstimate, ConnectionState, DeviceMotion, LogSeverity, Pose, ViewParams,
     anyhow::Result,
     glam::{Quat, UVec2, Vec2},
     semver::Version,
@@ -16,6 +16,7 @@ use std::{
     net::IpAddr,
     time::Duration,
 };
+use utoipa::ToSchema;
 
 pub const TRACKING: u16 = 0;
 pub const HAPTICS: u16 = 1;
@@ -160,6 +161,12 @@ pub enum ServerControlPacket {
     Restarting,
     KeepAlive,
     RealTimeConfig(RealTimeConfig),
+    // Times measured with the server clock
+    ClockSyncResponse {
+        client_send_time: Duration,
+        server_receive_time: Duration,
+        server_send_time: Duration,
+    },
     Reserved(String),
     ReservedBuffer(Vec<u8>),
 }
@@ -177,7 +184,7 @@ pub enum ButtonValue {
     Scalar(f32),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ButtonEntry {
     pub path_id: u64,
     pub value: ButtonValue,
@@ -202,6 +209,17 @@ pub enum ClientControlPacket {
         message: String,
     },
     ProximityState(bool),
+    // Sent instead of RequestIdr when reference frame invalidation is enabled. Contains the
+    // timestamp of the last frame decoded without missing references, if any.
+    VideoFrameLoss {
+        last_valid_frame_timestamp: Option<Duration>,
+    },
+    // Contains the current estimate of the server clock, so the server can convert the times
+    // reported by the client
+    ClockSyncRequest {
+        client_send_time: Duration,
+        server_clock_estimate: Option<ClockEstimate>,
+    },
     Reserved(String),
     ReservedBuffer(Vec<u8>),
 }
@@ -226,7 +244,7 @@ pub struct FaceData {
     pub face_expressions: Option<FaceExpressions>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TrackingData {
     pub poll_timestamp: Duration,
     pub device_motions: Vec<(u64, DeviceMotion)>,
@@ -235,11 +253,14 @@ pub struct TrackingData {
     pub body: Option<BodySkeleton>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct VideoPacketHeader {
     pub timestamp: Duration,
     pub global_view_params: [ViewParams; 2],
     pub is_idr: bool,
+    // Set on the first frame encoded after a reference frame invalidation. The frame references
+    // only frames up to this timestamp, so the client can resume decoding if it has this one.
+    pub recovery_reference: Option<Duration>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -250,7 +271,7 @@ pub struct Haptics {
     pub amplitude: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub enum PathSegment {
     Name(String),
     Index(usize),
@@ -300,6 +321,9 @@ pub enum ClientConnectionsAction {
     RemoveEntry,
     UpdateCurrentIp(Option<IpAddr>),
     SetConnectionState(ConnectionState),
+    SetPairingKey(String),
+    SetAnnouncedPairingKey(String),
+    SetSpectator(bool),
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -311,14 +335,57 @@ pub struct ClientStatistics {
     pub rendering: Duration,
     pub vsync_queue: Duration,
     pub total_pipeline_latency: Duration,
+    pub fec_recovered_shards: usize, // video shards rebuilt with forward error correction
+    pub encryption_time: Duration,   // time spent encrypting and decrypting since the last report
+    // Arrival time of the first and last shard of the video frame, measured with the client clock
+    // from the start of the stream. The server maps them to its own clock with the clock offset
+    // estimated by the client
+    pub video_first_shard_arrival: Duration,
+    pub video_last_shard_arrival: Duration,
+    pub tracking_sent: Duration, // measured with the same clock as the video shard arrival
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 pub struct PathValuePair {
     pub path: Vec<PathSegment>,
+    #[schema(value_type = Value)]
     pub value: json::Value,
 }
 
+// Returned by the web server API with any status code other than success
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct ApiError {
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct ClientListEntry {
+    pub hostname: String,
+    pub display_name: String,
+    #[schema(value_type = Option<String>)]
+    pub current_ip: Option<IpAddr>,
+    #[schema(value_type = Vec<String>)]
+    pub manual_ips: Vec<IpAddr>,
+    pub trusted: bool,
+    #[schema(value_type = String, example = "Streaming")]
+    pub connection_state: ConnectionState,
+    pub spectator: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct InputProfileStatus {
+    // Process name
+    pub foreground_application: Option<String>,
+    pub selected_profile: Option<String>,
+    pub active_profile: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct InputProfileSelectionRequest {
+    // None: use the profile of the foreground application
+    pub profile: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum FirewallRulesAction {
     Add,
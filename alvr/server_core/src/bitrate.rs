@@ -1,3 +1,4 @@
+use crate::congestion_control::{DelayGradientController, RateControlParams};
 use alvr_common::SlidingWindowAverage;
 use alvr_events::BitrateDirectives;
 use alvr_session::{
@@ -31,6 +32,8 @@ pub struct BitrateManager {
     dynamic_decoder_max_bytes_per_frame: f32,
     previous_config: Option<BitrateConfig>,
     update_needed: bool,
+    // Used only with the delay gradient mode
+    delay_gradient_controller: Option<DelayGradientController>,
 }
 
 impl BitrateManager {
@@ -57,6 +60,7 @@ impl BitrateManager {
             dynamic_decoder_max_bytes_per_frame: f32::MAX,
             previous_config: None,
             update_needed: true,
+            delay_gradient_controller: None,
         }
     }
 
@@ -96,6 +100,51 @@ impl BitrateManager {
         self.packet_bytes_history.push_back((timestamp, size_bytes));
     }
 
+    pub fn report_frame_sent(
+        &mut self,
+        timestamp: Duration,
+        last_shard_send_instant: Instant,
+        size_bytes: usize,
+    ) {
+        if let Some(controller) = &mut self.delay_gradient_controller {
+            controller.report_frame_sent(timestamp, last_shard_send_instant, size_bytes);
+        }
+    }
+
+    // The arrival times are measured by the client clock
+    pub fn report_frame_arrival(
+        &mut self,
+        config: &BitrateMode,
+        timestamp: Duration,
+        first_shard_arrival: Duration,
+        last_shard_arrival: Duration,
+    ) {
+        if let BitrateMode::DelayGradient {
+            max_bitrate_mbps,
+            min_bitrate_mbps,
+            decrease_multiplier,
+            increase_rate_per_s,
+            ..
+        } = config
+            && let Some(controller) = &mut self.delay_gradient_controller
+        {
+            let params = RateControlParams {
+                min_bitrate_bps: *min_bitrate_mbps as f32 * 1e6,
+                max_bitrate_bps: *max_bitrate_mbps as f32 * 1e6,
+                decrease_multiplier: *decrease_multiplier,
+                increase_rate_per_s: *increase_rate_per_s,
+            };
+
+            // React to congestion without waiting for the next periodic update
+            self.update_needed |= controller.report_frame_arrival(
+                timestamp,
+                first_shard_arrival,
+                last_shard_arrival,
+                &params,
+            );
+        }
+    }
+
     // decoder_latency is used to learn a suitable maximum bitrate bound to avoid decoder runaway
     // latency
     pub fn report_frame_latencies(
@@ -153,11 +202,40 @@ impl BitrateManager {
     ) -> Option<(DynamicEncoderParams, BitrateDirectives)> {
         let now = Instant::now();
 
+        let update_interval = if let BitrateMode::DelayGradient {
+            update_interval_ms, ..
+        } = &config.mode
+        {
+            Duration::from_millis(*update_interval_ms)
+        } else {
+            UPDATE_INTERVAL
+        };
+
         if self.previous_config.as_ref() != Some(config) {
+            if let BitrateMode::DelayGradient {
+                initial_bitrate_mbps,
+                initial_overuse_threshold_ms,
+                ..
+            } = &config.mode
+            {
+                // Start over only when switching mode, not when tweaking the parameters
+                if !matches!(
+                    self.previous_config.as_ref().map(|c| &c.mode),
+                    Some(BitrateMode::DelayGradient { .. })
+                ) {
+                    self.delay_gradient_controller = Some(DelayGradientController::new(
+                        *initial_bitrate_mbps as f32 * 1e6,
+                        *initial_overuse_threshold_ms,
+                    ));
+                }
+            } else {
+                self.delay_gradient_controller = None;
+            }
+
             self.previous_config = Some(config.clone());
             // Continue method. Always update bitrate in this case
         } else if !self.update_needed
-            && (now < self.last_update_instant + UPDATE_INTERVAL
+            && (now < self.last_update_instant + update_interval
                 || matches!(config.mode, BitrateMode::ConstantMbps(_)))
         {
             return None;
@@ -239,6 +317,25 @@ impl BitrateManager {
                 // to the throughput.
                 throughput_bps
             }
+            BitrateMode::DelayGradient {
+                initial_bitrate_mbps,
+                max_bitrate_mbps,
+                min_bitrate_mbps,
+                ..
+            } => {
+                let max_bps = *max_bitrate_mbps as f32 * 1e6;
+                let min_bps = *min_bitrate_mbps as f32 * 1e6;
+                bitrate_directives.manual_max_throughput_bps = Some(max_bps);
+                bitrate_directives.manual_min_throughput_bps = Some(min_bps);
+
+                if let Some(controller) = &self.delay_gradient_controller {
+                    controller.fill_directives(&mut bitrate_directives);
+
+                    f32::min(f32::max(controller.bitrate_bps(), min_bps), max_bps)
+                } else {
+                    *initial_bitrate_mbps as f32 * 1e6
+                }
+            }
         };
 
         bitrate_directives.requested_bitrate_bps = bitrate_bps;
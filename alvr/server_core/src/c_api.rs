@@ -3,7 +3,7 @@
 
 use crate::{
     SESSION_MANAGER, ServerCoreContext, ServerCoreEvent, ServerNegotiatedStreamingConfig,
-    logging_backend, tracking::HandType,
+    logging_backend, metrics::METRICS, tracking::HandType,
 };
 use alvr_common::{
     AlvrCodecType, AlvrPose, AlvrViewParams, log,
@@ -74,6 +74,7 @@ pub enum AlvrEvent {
     RestartPending,
     ShutdownPending,
     ProximityState(bool),
+    InvalidateReferenceFrames { last_valid_frame_timestamp_ns: u64 },
 }
 
 #[repr(C)]
@@ -310,7 +311,17 @@ pub unsafe extern "C" fn alvr_poll_event(out_event: *mut AlvrEvent, timeout_ns:
                 BUTTONS_QUEUE.lock().push_back(entries);
                 unsafe { *out_event = AlvrEvent::ButtonsUpdated };
             }
-            ServerCoreEvent::RequestIDR => unsafe { *out_event = AlvrEvent::RequestIDR },
+            ServerCoreEvent::RequestIDR => {
+                METRICS.lock().report_idr_request();
+                unsafe { *out_event = AlvrEvent::RequestIDR };
+            }
+            ServerCoreEvent::InvalidateReferenceFrames {
+                last_valid_frame_timestamp,
+            } => unsafe {
+                *out_event = AlvrEvent::InvalidateReferenceFrames {
+                    last_valid_frame_timestamp_ns: last_valid_frame_timestamp.as_nanos() as u64,
+                };
+            },
             ServerCoreEvent::CaptureFrame => unsafe { *out_event = AlvrEvent::CaptureFrame },
             ServerCoreEvent::RestartPending => unsafe {
                 *out_event = AlvrEvent::RestartPending;
@@ -509,6 +520,21 @@ pub unsafe extern "C" fn alvr_send_video_nal(
     }
 }
 
+/// Call before alvr_send_video_nal() for the first frame encoded without the frames invalidated
+/// after an InvalidateReferenceFrames event. Do not call if an IDR frame was inserted instead.
+#[unsafe(no_mangle)]
+pub extern "C" fn alvr_report_recovery_frame(
+    timestamp_ns: u64,
+    last_valid_frame_timestamp_ns: u64,
+) {
+    if let Some(context) = &*SERVER_CORE_CONTEXT.read() {
+        context.report_recovery_frame(
+            Duration::from_nanos(timestamp_ns),
+            Duration::from_nanos(last_valid_frame_timestamp_ns),
+        );
+    }
+}
+
 /// Returns true if updated
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn alvr_get_dynamic_encoder_params(
@@ -0,0 +1,480 @@
+// Delay gradient congestion controller, modeled after the trendline estimator and the AIMD rate
+// controller of Google Congestion Control.
+// The client and server clocks are not synchronized, so the one-way delay of a frame cannot be
+// measured directly. Instead, the difference between the arrival interval and the send interval of
+// consecutive frames tells if the network queues are growing (positive gradient) or draining
+// (negative gradient).
+
+use alvr_events::{BitrateDirectives, CongestionSignal};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const TREND_WINDOW_SIZE: usize = 20;
+const DELAY_SMOOTHING_COEFF: f64 = 0.9;
+const TREND_GAIN: f32 = 4.0;
+const MAX_TREND_DELTAS: usize = 60;
+const THRESHOLD_GAIN_UP: f32 = 0.0087;
+const THRESHOLD_GAIN_DOWN: f32 = 0.039;
+const MAX_THRESHOLD_STEP_MS: f32 = 100.0;
+const THRESHOLD_OUTLIER_MS: f32 = 15.0;
+const MIN_THRESHOLD_MS: f32 = 6.0;
+const MAX_THRESHOLD_MS: f32 = 600.0;
+const OVERUSE_MIN_DURATION: Duration = Duration::from_millis(10);
+const MIN_DECREASE_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_INCREASE_STEP: Duration = Duration::from_secs(1);
+const RECEIVE_RATE_WINDOW: Duration = Duration::from_millis(500);
+const BASE_DELAY_WINDOW: Duration = Duration::from_secs(10);
+const MAX_PENDING_FRAMES: usize = 256;
+
+pub struct RateControlParams {
+    pub min_bitrate_bps: f32,
+    pub max_bitrate_bps: f32,
+    pub decrease_multiplier: f32,
+    pub increase_rate_per_s: f32,
+}
+
+struct SentFrame {
+    timestamp: Duration,
+    send_instant: Instant,
+    size_bytes: usize,
+}
+
+struct ReceivedFrame {
+    first_shard_arrival: Duration,
+    last_shard_arrival: Duration,
+    size_bytes: usize,
+}
+
+pub struct DelayGradientController {
+    epoch: Instant,
+    // Frames waiting for the arrival report of the client
+    sent_frames: VecDeque<SentFrame>,
+    received_frames: VecDeque<ReceivedFrame>,
+    previous_frame: Option<(Instant, Duration)>,
+    first_arrival: Option<Duration>,
+    accumulated_delay_ms: f64,
+    smoothed_delay_ms: f64,
+    deltas_count: usize,
+    trend_samples: VecDeque<(f64, f64)>,
+    trend_ms: f32,
+    previous_trend_ms: f32,
+    threshold_ms: f32,
+    last_threshold_update: Option<Duration>,
+    overuse_start: Option<Duration>,
+    overuse_count: usize,
+    signal: CongestionSignal,
+    // Monotonic queue of the relative one-way delays, used to find the minimum in the window
+    base_delays: VecDeque<(Duration, f64)>,
+    queuing_delay_s: f32,
+    bitrate_bps: f32,
+    holding: bool,
+    last_increase: Option<Duration>,
+    last_decrease: Option<Duration>,
+}
+
+impl DelayGradientController {
+    pub fn new(initial_bitrate_bps: f32, initial_threshold_ms: f32) -> Self {
+        Self {
+            epoch: Instant::now(),
+            sent_frames: VecDeque::new(),
+            received_frames: VecDeque::new(),
+            previous_frame: None,
+            first_arrival: None,
+            accumulated_delay_ms: 0.0,
+            smoothed_delay_ms: 0.0,
+            deltas_count: 0,
+            trend_samples: VecDeque::new(),
+            trend_ms: 0.0,
+            previous_trend_ms: 0.0,
+            threshold_ms: initial_threshold_ms,
+            last_threshold_update: None,
+            overuse_start: None,
+            overuse_count: 0,
+            signal: CongestionSignal::Normal,
+            base_delays: VecDeque::new(),
+            queuing_delay_s: 0.0,
+            bitrate_bps: initial_bitrate_bps,
+            holding: false,
+            last_increase: None,
+            last_decrease: None,
+        }
+    }
+
+    // send_instant refers to the last shard of the frame
+    pub fn report_frame_sent(
+        &mut self,
+        timestamp: Duration,
+        send_instant: Instant,
+        size_bytes: usize,
+    ) {
+        self.sent_frames.push_back(SentFrame {
+            timestamp,
+            send_instant,
+            size_bytes,
+        });
+        if self.sent_frames.len() > MAX_PENDING_FRAMES {
+            self.sent_frames.pop_front();
+        }
+    }
+
+    // Arrivals are measured with the client clock. Returns true if the bitrate has been decreased
+    pub fn report_frame_arrival(
+        &mut self,
+        timestamp: Duration,
+        first_shard_arrival: Duration,
+        last_shard_arrival: Duration,
+        params: &RateControlParams,
+    ) -> bool {
+        // Frames are reported in order. Older frames have been lost or not displayed
+        let frame = loop {
+            match self.sent_frames.pop_front() {
+                Some(frame) if frame.timestamp == timestamp => break frame,
+                Some(frame) if frame.timestamp < timestamp => continue,
+                Some(frame) => {
+                    self.sent_frames.push_front(frame);
+                    return false;
+                }
+                None => return false,
+            }
+        };
+
+        if last_shard_arrival.is_zero() {
+            // The client did not record the arrival of this frame
+            return false;
+        }
+
+        self.update_receive_rate(first_shard_arrival, last_shard_arrival, frame.size_bytes);
+        self.update_queuing_delay(frame.send_instant, last_shard_arrival);
+
+        if let Some((previous_send_instant, previous_arrival)) = self.previous_frame
+            && last_shard_arrival > previous_arrival
+        {
+            let send_delta_ms = frame
+                .send_instant
+                .saturating_duration_since(previous_send_instant)
+                .as_secs_f64()
+                * 1000.0;
+            let arrival_delta_ms = (last_shard_arrival - previous_arrival).as_secs_f64() * 1000.0;
+
+            self.update_trend(arrival_delta_ms - send_delta_ms, last_shard_arrival);
+            self.detect_overuse(last_shard_arrival);
+        }
+        self.previous_frame = Some((frame.send_instant, last_shard_arrival));
+
+        self.update_bitrate(last_shard_arrival, params)
+    }
+
+    fn update_receive_rate(
+        &mut self,
+        first_shard_arrival: Duration,
+        last_shard_arrival: Duration,
+        size_bytes: usize,
+    ) {
+        self.received_frames.push_back(ReceivedFrame {
+            first_shard_arrival,
+            last_shard_arrival,
+            size_bytes,
+        });
+        while let Some(frame) = self.received_frames.front()
+            && frame.last_shard_arrival + RECEIVE_RATE_WINDOW < last_shard_arrival
+        {
+            self.received_frames.pop_front();
+        }
+    }
+
+    fn receive_rate_bps(&self) -> Option<f32> {
+        let first = self.received_frames.front()?;
+        let last = self.received_frames.back()?;
+
+        let interval_s = last
+            .last_shard_arrival
+            .saturating_sub(first.first_shard_arrival)
+            .as_secs_f32();
+        if interval_s < RECEIVE_RATE_WINDOW.as_secs_f32() / 4.0 {
+            // Not enough samples
+            return None;
+        }
+
+        let bytes = self
+            .received_frames
+            .iter()
+            .map(|frame| frame.size_bytes)
+            .sum::<usize>();
+
+        Some(bytes as f32 * 8.0 / interval_s)
+    }
+
+    fn update_queuing_delay(&mut self, send_instant: Instant, arrival: Duration) {
+        // This includes the unknown clock offset, which cancels out with the minimum
+        let relative_delay_s = arrival.as_secs_f64()
+            - send_instant
+                .saturating_duration_since(self.epoch)
+                .as_secs_f64();
+
+        while let Some(&(_, delay)) = self.base_delays.back()
+            && delay >= relative_delay_s
+        {
+            self.base_delays.pop_back();
+        }
+        self.base_delays.push_back((arrival, relative_delay_s));
+        while let Some(&(sample_arrival, _)) = self.base_delays.front()
+            && sample_arrival + BASE_DELAY_WINDOW < arrival
+        {
+            self.base_delays.pop_front();
+        }
+
+        // Safety: a sample has just been pushed
+        self.queuing_delay_s = (relative_delay_s - self.base_delays.front().unwrap().1) as f32;
+    }
+
+    fn update_trend(&mut self, delay_delta_ms: f64, arrival: Duration) {
+        let first_arrival = *self.first_arrival.get_or_insert(arrival);
+
+        self.deltas_count = usize::min(self.deltas_count + 1, MAX_TREND_DELTAS);
+        self.accumulated_delay_ms += delay_delta_ms;
+        self.smoothed_delay_ms = DELAY_SMOOTHING_COEFF * self.smoothed_delay_ms
+            + (1.0 - DELAY_SMOOTHING_COEFF) * self.accumulated_delay_ms;
+
+        self.trend_samples.push_back((
+            (arrival - first_arrival).as_secs_f64() * 1000.0,
+            self.smoothed_delay_ms,
+        ));
+        if self.trend_samples.len() > TREND_WINDOW_SIZE {
+            self.trend_samples.pop_front();
+        }
+
+        if self.trend_samples.len() == TREND_WINDOW_SIZE {
+            // Least squares fit of the smoothed delay over the arrival time
+            let count = self.trend_samples.len() as f64;
+            let mean_x = self.trend_samples.iter().map(|(x, _)| x).sum::<f64>() / count;
+            let mean_y = self.trend_samples.iter().map(|(_, y)| y).sum::<f64>() / count;
+
+            let (numerator, denominator) =
+                self.trend_samples
+                    .iter()
+                    .fold((0.0, 0.0), |(numerator, denominator), (x, y)| {
+                        (
+                            numerator + (x - mean_x) * (y - mean_y),
+                            denominator + (x - mean_x) * (x - mean_x),
+                        )
+                    });
+
+            if denominator > 0.0 {
+                let slope = numerator / denominator;
+                self.previous_trend_ms = self.trend_ms;
+                self.trend_ms = (slope * self.deltas_count as f64) as f32 * TREND_GAIN;
+            }
+        }
+    }
+
+    fn detect_overuse(&mut self, arrival: Duration) {
+        if self.trend_ms > self.threshold_ms {
+            let overuse_start = *self.overuse_start.get_or_insert(arrival);
+            self.overuse_count += 1;
+
+            if arrival - overuse_start > OVERUSE_MIN_DURATION
+                && self.overuse_count > 1
+                && self.trend_ms >= self.previous_trend_ms
+            {
+                self.signal = CongestionSignal::Overuse;
+                self.overuse_start = None;
+                self.overuse_count = 0;
+            }
+        } else if self.trend_ms < -self.threshold_ms {
+            self.overuse_start = None;
+            self.overuse_count = 0;
+            self.signal = CongestionSignal::Underuse;
+        } else {
+            self.overuse_start = None;
+            self.overuse_count = 0;
+            self.signal = CongestionSignal::Normal;
+        }
+
+        // The threshold adapts to the network jitter. Sudden spikes are ignored
+        let trend_magnitude_ms = self.trend_ms.abs();
+        if trend_magnitude_ms <= self.threshold_ms + THRESHOLD_OUTLIER_MS {
+            let step_ms = self.last_threshold_update.map_or(0.0, |last_update| {
+                f32::min(
+                    (arrival - last_update).as_secs_f32() * 1000.0,
+                    MAX_THRESHOLD_STEP_MS,
+                )
+            });
+            let gain = if trend_magnitude_ms < self.threshold_ms {
+                THRESHOLD_GAIN_DOWN
+            } else {
+                THRESHOLD_GAIN_UP
+            };
+
+            self.threshold_ms += gain * (trend_magnitude_ms - self.threshold_ms) * step_ms;
+            self.threshold_ms = self.threshold_ms.clamp(MIN_THRESHOLD_MS, MAX_THRESHOLD_MS);
+        }
+        self.last_threshold_update = Some(arrival);
+    }
+
+    fn update_bitrate(&mut self, arrival: Duration, params: &RateControlParams) -> bool {
+        let receive_rate_bps = self.receive_rate_bps();
+        let mut decreased = false;
+
+        match self.signal {
+            CongestionSignal::Overuse => {
+                if self
+                    .last_decrease
+                    .is_none_or(|last_decrease| arrival > last_decrease + MIN_DECREASE_INTERVAL)
+                {
+                    let reference_bps = receive_rate_bps.unwrap_or(self.bitrate_bps);
+                    self.bitrate_bps =
+                        f32::min(self.bitrate_bps, reference_bps * params.decrease_multiplier);
+                    self.last_decrease = Some(arrival);
+                    decreased = true;
+                }
+                self.holding = true;
+            }
+            // The queues are draining, wait for them to be empty
+            CongestionSignal::Underuse => self.holding = true,
+            CongestionSignal::Normal => {
+                if self.holding {
+                    self.holding = false;
+                } else if let Some(last_increase) = self.last_increase {
+                    let step_s =
+                        Duration::min(arrival.saturating_sub(last_increase), MAX_INCREASE_STEP)
+                            .as_secs_f32();
+                    self.bitrate_bps *= f32::powf(1.0 + params.increase_rate_per_s, step_s);
+
+                    // Do not grow indefinitely if the encoder does not use the whole bitrate
+                    if let Some(receive_rate_bps) = receive_rate_bps {
+                        self.bitrate_bps = f32::min(self.bitrate_bps, receive_rate_bps * 1.5 + 1e6);
+                    }
+                }
+                self.last_increase = Some(arrival);
+            }
+        }
+
+        // Note: not using clamp() since the limits could be misconfigured
+        self.bitrate_bps = f32::min(
+            f32::max(self.bitrate_bps, params.min_bitrate_bps),
+            params.max_bitrate_bps,
+        );
+
+        decreased
+    }
+
+    pub fn bitrate_bps(&self) -> f32 {
+        self.bitrate_bps
+    }
+
+    pub fn fill_directives(&self, directives: &mut BitrateDirectives) {
+        directives.congestion_receive_rate_bps = self.receive_rate_bps();
+        directives.congestion_delay_trend_ms = Some(self.trend_ms);
+        directives.congestion_overuse_threshold_ms = Some(self.threshold_ms);
+        directives.congestion_queuing_delay_s = Some(self.queuing_delay_s);
+        directives.congestion_signal = Some(self.signal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: RateControlParams = RateControlParams {
+        min_bitrate_bps: 1e6,
+        max_bitrate_bps: 100e6,
+        decrease_multiplier: 0.85,
+        increase_rate_per_s: 0.08,
+    };
+    const FRAME_INTERVAL_MS: u64 = 10;
+    const FRAME_SIZE_BYTES: usize = 30_000;
+
+    fn signal(controller: &DelayGradientController) -> CongestionSignal {
+        let mut directives = BitrateDirectives::default();
+        controller.fill_directives(&mut directives);
+
+        directives.congestion_signal.unwrap()
+    }
+
+    // Frames are sent every 10 ms. The arrival interval is 10 ms plus the given extra delay, which
+    // is positive when the network queues grow and negative when they drain. Returns the number of
+    // bitrate decreases.
+    fn stream_frames(
+        controller: &mut DelayGradientController,
+        epoch: Instant,
+        first_index: u64,
+        count: u64,
+        extra_delay_ms: i64,
+        arrival_ms: &mut i64,
+    ) -> usize {
+        let mut decreases = 0;
+        for index in first_index..first_index + count {
+            let timestamp = Duration::from_millis(index * FRAME_INTERVAL_MS);
+            controller.report_frame_sent(timestamp, epoch + timestamp, FRAME_SIZE_BYTES);
+
+            *arrival_ms += FRAME_INTERVAL_MS as i64 + extra_delay_ms;
+            let arrival = Duration::from_millis(*arrival_ms as u64);
+            if controller.report_frame_arrival(timestamp, arrival, arrival, &PARAMS) {
+                decreases += 1;
+            }
+        }
+
+        decreases
+    }
+
+    #[test]
+    fn test_normal_increases_bitrate() {
+        let mut controller = DelayGradientController::new(10e6, 12.5);
+        let epoch = Instant::now();
+        let mut arrival_ms = 100;
+
+        let decreases = stream_frames(&mut controller, epoch, 0, 100, 0, &mut arrival_ms);
+
+        assert_eq!(decreases, 0);
+        assert_eq!(signal(&controller), CongestionSignal::Normal);
+        assert!(controller.bitrate_bps() > 10e6);
+    }
+
+    #[test]
+    fn test_overuse_decreases_bitrate() {
+        // Above the 24 Mbps sent by the encoder, the decrease is relative to the receive rate
+        let mut controller = DelayGradientController::new(30e6, 12.5);
+        let epoch = Instant::now();
+        let mut arrival_ms = 100;
+
+        stream_frames(&mut controller, epoch, 0, 50, 0, &mut arrival_ms);
+        let bitrate_before_bps = controller.bitrate_bps();
+
+        // Each frame is queued 5 ms longer than the previous one
+        let decreases = stream_frames(&mut controller, epoch, 50, 50, 5, &mut arrival_ms);
+
+        assert!(decreases > 0);
+        assert_eq!(signal(&controller), CongestionSignal::Overuse);
+        assert!(controller.bitrate_bps() < bitrate_before_bps);
+        assert!(controller.bitrate_bps() < 24e6 * PARAMS.decrease_multiplier);
+
+        // The overuse lasts 750 ms and decreases are spaced by at least MIN_DECREASE_INTERVAL
+        assert!(decreases <= 4);
+    }
+
+    #[test]
+    fn test_underuse_holds_bitrate() {
+        let mut controller = DelayGradientController::new(10e6, 12.5);
+        let epoch = Instant::now();
+        let mut arrival_ms = 100;
+
+        stream_frames(&mut controller, epoch, 0, 50, 5, &mut arrival_ms);
+        assert_eq!(signal(&controller), CongestionSignal::Overuse);
+
+        // The queues drain, frames arrive 4 ms closer than they were sent
+        stream_frames(&mut controller, epoch, 50, 60, -4, &mut arrival_ms);
+        assert_eq!(signal(&controller), CongestionSignal::Underuse);
+
+        let bitrate_bps = controller.bitrate_bps();
+        stream_frames(&mut controller, epoch, 110, 5, -4, &mut arrival_ms);
+        assert_eq!(signal(&controller), CongestionSignal::Underuse);
+        assert_eq!(controller.bitrate_bps(), bitrate_bps);
+
+        // Once the queues are empty the bitrate is increased again, after one held update
+        stream_frames(&mut controller, epoch, 115, 100, 0, &mut arrival_ms);
+        assert_eq!(signal(&controller), CongestionSignal::Normal);
+        assert!(controller.bitrate_bps() > bitrate_bps);
+    }
+}
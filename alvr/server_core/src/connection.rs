@@ -3,14 +3,19 @@ use crate::{
     ServerNegotiatedStreamingConfig,
     bitrate::BitrateManager,
     input_mapping::ButtonMappingManager,
+    metrics::METRICS,
+    session_recording::{self, SessionRecordEntry},
     sockets::WelcomeSocket,
+    spectators::{self, SharedStreamConfig},
     statistics::StatisticsManager,
+    statistics_timeline::StatisticsTimeline,
     tracking::{self, TrackingManager},
 };
 use alvr_adb::{WiredConnection, WiredConnectionStatus};
 use alvr_common::{
-    AnyhowToCon, BUTTON_INFO, CONTROLLER_PROFILE_INFO, ConResult, ConnectionError, ConnectionState,
-    LifecycleState, QUEST_CONTROLLER_PROFILE_PATH, con_bail, dbg_connection, debug, error,
+    AnyhowToCon, BUTTON_INFO, ConResult, ConnectionError, ConnectionState, LifecycleState,
+    anyhow::Result,
+    con_bail, dbg_connection, debug, error,
     glam::{UVec2, Vec2},
     info,
     parking_lot::{Condvar, Mutex, RwLock},
@@ -19,46 +24,67 @@ use alvr_common::{
 };
 use alvr_events::{AdbEvent, ButtonEvent, EventType};
 use alvr_packets::{
-    AUDIO, ClientConnectionResult, ClientConnectionsAction, ClientControlPacket,
+    AUDIO, ButtonEntry, ClientConnectionResult, ClientConnectionsAction, ClientControlPacket,
     ClientNegotiatedStreamingConfig, ClientStatistics, HAPTICS, NegotiatedStreamingConfigExt,
     RealTimeConfig, STATISTICS, ServerControlPacket, StreamConfigPacket, TRACKING, TrackingData,
     VIDEO, VideoPacketHeader,
 };
 use alvr_session::{
-    BodyTrackingSinkConfig, CodecType, ControllersEmulationMode, FrameSize, H264Profile, Settings,
-    SocketProtocol, SteamvrHmdInitConfig,
+    BodyTrackingSinkConfig, CodecType, ControllersConfig, ControllersEmulationMode, FrameSize,
+    H264Profile, InputMappingProfile, Settings, SocketProtocol, SteamvrHmdInitConfig,
 };
 use alvr_sockets::{
     CONTROL_PORT, KEEPALIVE_INTERVAL, KEEPALIVE_TIMEOUT, ProtoControlSocket, SocketConnection,
-    StreamSocketConfig, WIRED_CLIENT_HOSTNAME,
+    SocketStatistics, StreamClass, StreamPriority, StreamSocketConfig, WIRED_CLIENT_HOSTNAME,
 };
 use std::{
-    collections::{HashMap, hash_map::DefaultHasher},
+    collections::{HashMap, HashSet, hash_map::DefaultHasher},
+    fs,
     hash::{Hash, Hasher},
     net::{IpAddr, Ipv4Addr},
     process::Command,
-    sync::{Arc, mpsc::RecvTimeoutError},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::RecvTimeoutError,
+    },
     thread,
     time::{Duration, Instant},
 };
 
 const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
-const HANDSHAKE_ACTION_TIMEOUT: Duration = Duration::from_secs(2);
+pub const HANDSHAKE_ACTION_TIMEOUT: Duration = Duration::from_secs(2);
 pub const STREAMING_RECV_TIMEOUT: Duration = Duration::from_millis(500);
 const REAL_TIME_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
 
-const MAX_UNREAD_PACKETS: usize = 10; // Applies per stream
+pub const MAX_UNREAD_PACKETS: usize = 10; // Applies per stream
 
 pub struct VideoPacket {
     pub header: VideoPacketHeader,
     pub payload: Vec<u8>,
 }
 
+// The private pairing key is stored outside of the session, which is exposed by the web server
+fn load_or_create_pairing_key() -> Result<String> {
+    let path = FILESYSTEM_LAYOUT.get().unwrap().pairing_key();
+
+    if let Ok(key) = fs::read_to_string(&path) {
+        alvr_server_io::restrict_permissions(&path)?;
+
+        return Ok(key.trim().to_owned());
+    }
+
+    let key = alvr_sockets::generate_pairing_key()?;
+    alvr_server_io::write_private_file(&path, &key)?;
+
+    Ok(key)
+}
+
 fn align32(value: f32) -> u32 {
     ((value / 32.).floor() * 32.) as u32
 }
 
-fn is_streaming(client_hostname: &str) -> bool {
+pub fn is_streaming(client_hostname: &str) -> bool {
     SESSION_MANAGER
         .read()
         .client_list()
@@ -66,6 +92,31 @@ fn is_streaming(client_hostname: &str) -> bool {
         .is_some_and(|c| c.connection_state == ConnectionState::Streaming)
 }
 
+pub fn stream_socket_config(settings: &Settings, wired: bool) -> StreamSocketConfig {
+    StreamSocketConfig {
+        protocol: if wired {
+            SocketProtocol::Tcp
+        } else {
+            settings.connection.stream_protocol
+        },
+        port: settings.connection.stream_port,
+        buffer_config: settings.connection.server_buffer_config,
+        max_packet_size: settings.connection.packet_size as _,
+        dscp: settings.connection.dscp,
+        fec_config: settings
+            .connection
+            .forward_error_correction
+            .as_option()
+            .copied(),
+        network_impairment: settings
+            .connection
+            .debug
+            .network_impairment
+            .as_option()
+            .copied(),
+    }
+}
+
 // Compute a hash over all steamvr-restart settings and client-negotiated values.
 // The small SteamvrHmdInitConfig carries the negotiated resolution/fps; everything else comes from
 // Settings directly, using the same derivation as the old full SteamvrHmdInitConfig did.
@@ -419,7 +470,7 @@ pub fn handshake_loop(ctx: Arc<ConnectionContext>, lifecycle_state: Arc<RwLock<L
                 continue;
             }
 
-            for (client_hostname, client_ip) in clients {
+            for (client_hostname, (client_ip, pairing_key)) in clients {
                 let trusted = {
                     let mut session_manager = SESSION_MANAGER.write();
 
@@ -431,6 +482,14 @@ pub fn handshake_loop(ctx: Arc<ConnectionContext>, lifecycle_state: Arc<RwLock<L
                         },
                     );
 
+                    // Shown in the dashboard, it is pinned only once confirmed by the user
+                    if let Some(key) = pairing_key {
+                        session_manager.update_client_connections(
+                            client_hostname.clone(),
+                            ClientConnectionsAction::SetAnnouncedPairingKey(key),
+                        );
+                    }
+
                     if config.auto_trust_clients {
                         session_manager.update_client_connections(
                             client_hostname.clone(),
@@ -527,10 +586,172 @@ fn try_connect(
     Ok(())
 }
 
+// Maps the buttons received from the client. The mapping manager is recreated when the interaction
+// profile of the client changes, or when the active input profile or the mapping settings change.
+pub struct ClientButtonMapping {
+    manager: Option<ButtonMappingManager>,
+    // Without input IDs, the buttons of the default interaction profile are assumed
+    input_ids: Option<HashSet<u64>>,
+    input_profile_name: Option<String>,
+    // The active profile is resolved again only when the selection or the settings change
+    settings_revision: Arc<AtomicUsize>,
+    input_profile_revisions: Option<(usize, usize)>,
+    // Settings used to create the manager. It is recreated only when they change, because it holds
+    // the state of the buttons
+    mapping_settings: Option<serde_json::Value>,
+}
+
+impl ClientButtonMapping {
+    // Profiles are applied when the first buttons are received
+    pub fn new(settings: &Settings, settings_revision: Arc<AtomicUsize>) -> Self {
+        let config = settings.headset.controllers.as_option();
+
+        Self {
+            manager: config.map(ButtonMappingManager::new_default),
+            input_ids: None,
+            input_profile_name: None,
+            settings_revision,
+            input_profile_revisions: None,
+            mapping_settings: config.map(|config| button_mapping_settings(config, None)),
+        }
+    }
+
+    pub fn set_input_ids(&mut self, ctx: &ConnectionContext, input_ids: HashSet<u64>) {
+        self.input_ids = Some(input_ids);
+        self.refresh(ctx, true);
+    }
+
+    // Logs the buttons received from the client and forwards them after mapping
+    pub fn process_buttons(
+        &mut self,
+        ctx: &ConnectionContext,
+        entries: &[ButtonEntry],
+        now: Instant,
+    ) {
+        if SESSION_MANAGER
+            .read()
+            .settings()
+            .extra
+            .logging
+            .log_button_presses
+        {
+            alvr_events::send_event(EventType::Buttons(
+                entries
+                    .iter()
+                    .map(|e| ButtonEvent {
+                        path: BUTTON_INFO.get(&e.path_id).map_or_else(
+                            || format!("Unknown (ID: {:#16x})", e.path_id),
+                            |info| info.path.to_owned(),
+                        ),
+                        value: e.value,
+                    })
+                    .collect(),
+            ));
+        }
+
+        self.refresh(ctx, false);
+
+        if let Some(manager) = &mut self.manager {
+            let button_entries = entries
+                .iter()
+                .flat_map(|entry| manager.map_button_at(entry, now))
+                .collect::<Vec<_>>();
+
+            send_buttons(ctx, button_entries);
+        }
+    }
+
+    // Advance the time based mappings
+    pub fn update(&mut self, ctx: &ConnectionContext, now: Instant) {
+        if let Some(manager) = &mut self.manager {
+            send_buttons(ctx, manager.update_at(now));
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.manager
+            .as_ref()
+            .and_then(|manager| manager.next_deadline())
+    }
+
+    fn refresh(&mut self, ctx: &ConnectionContext, input_ids_changed: bool) {
+        // Read before resolving the profile, so that a concurrent change is picked up later
+        let revisions = (
+            ctx.input_profile_selection.read().revision(),
+            self.settings_revision.load(Ordering::Relaxed),
+        );
+        if !input_ids_changed && self.input_profile_revisions == Some(revisions) {
+            return;
+        }
+        self.input_profile_revisions = Some(revisions);
+
+        let session_manager_lock = SESSION_MANAGER.read();
+        let config = session_manager_lock
+            .settings()
+            .headset
+            .controllers
+            .as_option();
+        let input_profile = config.and_then(|config| {
+            ctx.input_profile_selection
+                .read()
+                .active_profile(config)
+                .map(|(name, profile)| (name.clone(), profile))
+        });
+
+        let input_profile_name = input_profile.as_ref().map(|(name, _)| name.clone());
+        if input_profile_name != self.input_profile_name {
+            info!(
+                "Using input profile: {}",
+                input_profile_name.as_deref().unwrap_or("default")
+            );
+            self.input_profile_name = input_profile_name;
+        }
+
+        let input_profile = input_profile.map(|(_, profile)| profile);
+        let mapping_settings = config.map(|config| button_mapping_settings(config, input_profile));
+        if !input_ids_changed && mapping_settings == self.mapping_settings {
+            return;
+        }
+        self.mapping_settings = mapping_settings;
+
+        // The buttons held with the previous mappings are released, otherwise they would stay
+        // pressed
+        if let Some(manager) = &mut self.manager {
+            send_buttons(ctx, manager.release_all());
+        }
+
+        self.manager = config.map(|config| match (input_profile, &self.input_ids) {
+            (Some(profile), _) => ButtonMappingManager::new_manual(&profile.button_mappings),
+            (None, Some(input_ids)) => ButtonMappingManager::new_from_config(config, input_ids),
+            (None, None) => ButtonMappingManager::new_default(config),
+        });
+    }
+}
+
+fn button_mapping_settings(
+    config: &ControllersConfig,
+    input_profile: Option<&InputMappingProfile>,
+) -> serde_json::Value {
+    serde_json::json!([
+        config.emulation_mode,
+        config.button_mapping_config,
+        config.button_mappings,
+        input_profile.map(|profile| &profile.button_mappings),
+    ])
+}
+
+fn send_buttons(ctx: &ConnectionContext, entries: Vec<ButtonEntry>) {
+    if !entries.is_empty() {
+        ctx.events_sender
+            .send(ServerCoreEvent::Buttons(entries))
+            .ok();
+    }
+}
+
 fn connection_pipeline(
     ctx: Arc<ConnectionContext>,
     lifecycle_state: Arc<RwLock<LifecycleState>>,
-    socket: ProtoControlSocket,
+    mut socket: ProtoControlSocket,
     connection_result: ClientConnectionResult,
     client_hostname: String,
     client_ip: IpAddr,
@@ -581,6 +802,91 @@ fn connection_pipeline(
 
     let initial_settings = session_manager_lock.settings().clone();
 
+    dbg_connection!("connection_pipeline: Negotiating encryption");
+    let server_pairing_key = if initial_settings.connection.encryption {
+        Some(load_or_create_pairing_key().to_con()?)
+    } else {
+        None
+    };
+    if let Some(client_pairing_key) = socket
+        .negotiate_encryption_as_server(server_pairing_key.as_deref(), HANDSHAKE_ACTION_TIMEOUT)?
+    {
+        let pinned_key = session_manager_lock
+            .client_list()
+            .get(&client_hostname)
+            .and_then(|client| client.pairing_key.clone());
+
+        // Without a key confirmed by the user, the first peer to connect would be trusted. This is
+        // allowed only for wired clients and when the user opted into trusting any client.
+        let trust_on_first_use = client_hostname == WIRED_CLIENT_HOSTNAME
+            || initial_settings
+                .connection
+                .client_discovery
+                .as_option()
+                .is_some_and(|config| config.auto_trust_clients);
+
+        match pinned_key {
+            Some(key) if key == client_pairing_key => (),
+            Some(_) => con_bail!(
+                "The pairing key of {client_hostname} changed! Remove the client and trust it again to pair it"
+            ),
+            None if trust_on_first_use => {
+                info!("Paired with {client_hostname}");
+
+                session_manager_lock.update_client_connections(
+                    client_hostname.clone(),
+                    ClientConnectionsAction::SetPairingKey(client_pairing_key),
+                );
+            }
+            None => {
+                let fingerprint = alvr_sockets::pairing_key_fingerprint(&client_pairing_key);
+
+                session_manager_lock.update_client_connections(
+                    client_hostname.clone(),
+                    ClientConnectionsAction::SetAnnouncedPairingKey(client_pairing_key),
+                );
+
+                con_bail!(
+                    "Confirm the pairing key of {client_hostname} in the dashboard. It must match the key shown by the client: {fingerprint}"
+                );
+            }
+        }
+    }
+
+    if session_manager_lock
+        .client_list()
+        .get(&client_hostname)
+        .is_some_and(|c| c.spectator)
+    {
+        return spectators::spectator_pipeline(
+            ctx,
+            socket,
+            session_manager_lock,
+            streaming_caps,
+            client_hostname,
+            client_ip,
+        );
+    }
+
+    // Only one client can drive the stream, the others must be spectators
+    if let Some((main_hostname, _)) =
+        session_manager_lock
+            .client_list()
+            .iter()
+            .find(|(hostname, info)| {
+                **hostname != client_hostname
+                    && !info.spectator
+                    && info.connection_state != ConnectionState::Disconnected
+            })
+    {
+        warn!(
+            "{client_hostname} cannot stream because {main_hostname} is already connected. \
+            Mark it as spectator to receive the same stream"
+        );
+
+        return Ok(());
+    }
+
     fn get_view_res(config: FrameSize, default_res: UVec2) -> UVec2 {
         let res = match config {
             FrameSize::Scale(scale) => default_res.as_vec2() * scale,
@@ -808,30 +1114,31 @@ fn connection_pipeline(
         return Ok(());
     }
 
-    let stream_protocol = if wired {
-        SocketProtocol::Tcp
-    } else {
-        initial_settings.connection.stream_protocol
-    };
-
     dbg_connection!("connection_pipeline: Finishing handshake");
     let mut socket = SocketConnection::from_client_connection(
         socket,
         HANDSHAKE_ACTION_TIMEOUT,
         stream_config_packet,
-        StreamSocketConfig {
-            protocol: stream_protocol,
-            port: initial_settings.connection.stream_port,
-            buffer_config: initial_settings.connection.server_buffer_config,
-            max_packet_size: initial_settings.connection.packet_size as _,
-            dscp: initial_settings.connection.dscp,
-        },
+        stream_socket_config(&initial_settings, wired),
     )?;
 
     dbg_connection!("connection_pipeline: Handshake successful, spawning threads");
 
     let disconnect_notif = Arc::new(Condvar::new());
 
+    let statistics_timeline =
+        if let Switch::Enabled(config) = &initial_settings.extra.capture.statistics_timeline {
+            StatisticsTimeline::new(
+                &client_hostname,
+                &initial_settings,
+                config.max_file_size_mb * 1_000_000,
+            )
+            .inspect_err(|e| error!("Failed to create statistics timeline: {e}"))
+            .ok()
+        } else {
+            None
+        };
+
     *ctx.statistics_manager.write() = Some(StatisticsManager::new(
         initial_settings.connection.statistics_history_size,
         Duration::from_secs_f32(1.0 / fps),
@@ -840,6 +1147,7 @@ fn connection_pipeline(
         } else {
             0.0
         },
+        statistics_timeline,
     ));
     *ctx.bitrate_manager.lock() =
         BitrateManager::new(initial_settings.video.bitrate.history_size, fps);
@@ -847,17 +1155,29 @@ fn connection_pipeline(
         TrackingManager::new(initial_settings.connection.statistics_history_size);
 
     let control_sender = Arc::new(Mutex::new(socket.request_reliable_stream()?));
-    let mut video_sender = socket.request_unreliable_stream(VIDEO);
-    let game_audio_sender: alvr_sockets::StreamSender<()> = socket.request_unreliable_stream(AUDIO);
-    let haptics_sender = socket.request_unreliable_stream(HAPTICS);
+    let mut video_sender = socket.request_stream(
+        VIDEO,
+        alvr_sockets::video_stream_class(&initial_settings),
+        StreamPriority::Low,
+    );
+    let game_audio_sender: alvr_sockets::StreamSender<()> =
+        socket.request_stream(AUDIO, StreamClass::Droppable, StreamPriority::Medium);
+    let haptics_sender =
+        socket.request_stream(HAPTICS, StreamClass::Reliable, StreamPriority::High);
 
     let mut control_receiver = socket.subscribe_to_reliable_stream()?;
     let mut microphone_receiver: alvr_sockets::StreamReceiver<()> =
-        socket.subscribe_to_unreliable_stream(AUDIO, MAX_UNREAD_PACKETS);
-    let tracking_receiver =
-        socket.subscribe_to_unreliable_stream::<TrackingData>(TRACKING, MAX_UNREAD_PACKETS);
-    let mut statics_receiver =
-        socket.subscribe_to_unreliable_stream::<ClientStatistics>(STATISTICS, MAX_UNREAD_PACKETS);
+        socket.subscribe_to_stream(AUDIO, StreamClass::Droppable, MAX_UNREAD_PACKETS);
+    let tracking_receiver = socket.subscribe_to_stream::<TrackingData>(
+        TRACKING,
+        StreamClass::LatestOnly,
+        MAX_UNREAD_PACKETS,
+    );
+    let mut statics_receiver = socket.subscribe_to_stream::<ClientStatistics>(
+        STATISTICS,
+        StreamClass::Reliable,
+        MAX_UNREAD_PACKETS,
+    );
 
     let (video_channel_sender, video_channel_receiver) =
         std::sync::mpsc::sync_channel(initial_settings.connection.max_queued_server_video_frames);
@@ -883,9 +1203,20 @@ fn connection_pipeline(
                     .unrecenter_view_params(&mut header.global_view_params);
 
                 // todo: use get_buffer and make encoder write to socket buffers directly to avoid copy
-                video_sender
+                if video_sender
                     .send_header_with_payload(&header, &payload)
-                    .ok();
+                    .is_ok()
+                    && let Some((_, last_shard_instant)) = video_sender.last_send_instants()
+                {
+                    ctx.bitrate_manager.lock().report_frame_sent(
+                        header.timestamp,
+                        last_shard_instant,
+                        payload.len(),
+                    );
+                    if let Some(stats) = &mut *ctx.statistics_manager.write() {
+                        stats.report_frame_sent(header.timestamp, last_shard_instant);
+                    }
+                }
             }
         }
     });
@@ -894,7 +1225,7 @@ fn connection_pipeline(
     let game_audio_thread = if let Switch::Enabled(config) =
         initial_settings.audio.game_audio.clone()
     {
-        #[cfg(windows)]
+        #[cfg(not(target_os = "linux"))]
         let ctx = Arc::clone(&ctx);
 
         let client_hostname = client_hostname.clone();
@@ -932,7 +1263,7 @@ fn connection_pipeline(
                             let client_hostname = client_hostname.clone();
                             move || is_streaming(&client_hostname)
                         }),
-                        game_audio_sender.clone(),
+                        spectators::game_audio_fan_out(&ctx, game_audio_sender.clone()),
                         &device,
                         2,
                         config.mute_when_streaming,
@@ -1025,6 +1356,7 @@ fn connection_pipeline(
             });
 
         if mic.is_some() || audio_info.is_some() {
+            let ctx = Arc::clone(&ctx);
             let client_hostname = client_hostname.clone();
             thread::spawn(move || {
                 linux::audio_loop(
@@ -1032,7 +1364,7 @@ fn connection_pipeline(
                         let client_hostname = client_hostname.clone();
                         move || is_streaming(&client_hostname)
                     },
-                    game_audio_sender,
+                    spectators::game_audio_fan_out(&ctx, game_audio_sender),
                     audio_info,
                     &mut microphone_receiver,
                     mic,
@@ -1057,6 +1389,7 @@ fn connection_pipeline(
     let statistics_thread = thread::spawn({
         let ctx = Arc::clone(&ctx);
         let client_hostname = client_hostname.clone();
+        let secure_channel = socket.secure_channel();
         move || {
             while is_streaming(&client_hostname) {
                 let data = match statics_receiver.recv(STREAMING_RECV_TIMEOUT) {
@@ -1069,8 +1402,18 @@ fn connection_pipeline(
                 };
 
                 if let Some(stats) = &mut *ctx.statistics_manager.write() {
+                    if let Some(channel) = &secure_channel {
+                        let encryption_stats = channel.take_statistics();
+                        stats.report_encryption(
+                            encryption_stats.processing_time,
+                            encryption_stats.overhead_bytes,
+                        );
+                    }
+
                     let timestamp = client_stats.target_timestamp;
                     let decoder_latency = client_stats.video_decode;
+                    let first_shard_arrival = client_stats.video_first_shard_arrival;
+                    let last_shard_arrival = client_stats.video_last_shard_arrival;
                     let (network_latency, game_latency) = stats.report_statistics(client_stats);
 
                     ctx.events_sender
@@ -1078,12 +1421,20 @@ fn connection_pipeline(
                         .ok();
 
                     let session_manager_lock = SESSION_MANAGER.read();
-                    ctx.bitrate_manager.lock().report_frame_latencies(
-                        &session_manager_lock.settings().video.bitrate.mode,
+                    let bitrate_mode = &session_manager_lock.settings().video.bitrate.mode;
+                    let mut bitrate_manager_lock = ctx.bitrate_manager.lock();
+                    bitrate_manager_lock.report_frame_latencies(
+                        bitrate_mode,
                         timestamp,
                         network_latency,
                         decoder_latency,
                     );
+                    bitrate_manager_lock.report_frame_arrival(
+                        bitrate_mode,
+                        timestamp,
+                        first_shard_arrival,
+                        last_shard_arrival,
+                    );
                 }
             }
         }
@@ -1139,27 +1490,10 @@ fn connection_pipeline(
     let control_receive_thread = thread::spawn({
         let ctx = Arc::clone(&ctx);
 
-        let controllers_config = session_manager_lock
-            .settings()
-            .headset
-            .controllers
-            .as_option();
-        let mut controller_button_mapping_manager = controllers_config.map(|config| {
-            if let Some(mappings) = &config.button_mappings {
-                ButtonMappingManager::new_manual(mappings)
-            } else {
-                ButtonMappingManager::new_automatic(
-                    &CONTROLLER_PROFILE_INFO
-                        .get(&alvr_common::hash_string(QUEST_CONTROLLER_PROFILE_PATH))
-                        .unwrap()
-                        .button_set,
-                    &config.emulation_mode,
-                    &config.button_mapping_config,
-                )
-            }
-        });
-        let controllers_emulation_mode =
-            controllers_config.map(|config| config.emulation_mode.clone());
+        let mut button_mapping = ClientButtonMapping::new(
+            session_manager_lock.settings(),
+            session_manager_lock.settings_revision(),
+        );
 
         let disconnect_notif = Arc::clone(&disconnect_notif);
         let control_sender = Arc::clone(&control_sender);
@@ -1167,7 +1501,19 @@ fn connection_pipeline(
         move || {
             let mut disconnection_deadline = Instant::now() + KEEPALIVE_TIMEOUT;
             while is_streaming(&client_hostname) {
-                let packet = match control_receiver.recv(STREAMING_RECV_TIMEOUT) {
+                button_mapping.update(&ctx, Instant::now());
+
+                // Wake up in time for the time based button mappings
+                let timeout =
+                    button_mapping
+                        .next_deadline()
+                        .map_or(STREAMING_RECV_TIMEOUT, |deadline| {
+                            deadline
+                                .saturating_duration_since(Instant::now())
+                                .clamp(Duration::from_millis(1), STREAMING_RECV_TIMEOUT)
+                        });
+
+                let packet = match control_receiver.recv(timeout) {
                     Ok(packet) => packet,
                     Err(ConnectionError::TryAgain(_)) => {
                         if Instant::now() > disconnection_deadline {
@@ -1183,6 +1529,14 @@ fn connection_pipeline(
                     }
                 };
 
+                // Used for clock synchronization
+                let packet_received_time = ctx
+                    .statistics_manager
+                    .read()
+                    .as_ref()
+                    .map(|stats| stats.clock_time())
+                    .unwrap_or_default();
+
                 match packet {
                     ClientControlPacket::PlayspaceSync(packet) => {
                         if !initial_settings.headset.tracking_ref_only {
@@ -1216,12 +1570,64 @@ fn connection_pipeline(
                         }
                         ctx.events_sender.send(ServerCoreEvent::RequestIDR).ok();
                     }
+                    ClientControlPacket::VideoFrameLoss {
+                        last_valid_frame_timestamp,
+                    } => {
+                        METRICS.lock().report_client_frame_loss();
+
+                        let last_valid_frame_timestamp = ctx
+                            .reference_frame_tracker
+                            .lock()
+                            .report_frame_loss(last_valid_frame_timestamp);
+
+                        if let Some(last_valid_frame_timestamp) = last_valid_frame_timestamp {
+                            ctx.events_sender
+                                .send(ServerCoreEvent::InvalidateReferenceFrames {
+                                    last_valid_frame_timestamp,
+                                })
+                                .ok();
+                        } else {
+                            ctx.events_sender.send(ServerCoreEvent::RequestIDR).ok();
+                        }
+                    }
+                    ClientControlPacket::ClockSyncRequest {
+                        client_send_time,
+                        server_clock_estimate,
+                    } => {
+                        let server_send_time =
+                            ctx.statistics_manager.write().as_mut().map(|stats| {
+                                if let Some(estimate) = server_clock_estimate {
+                                    stats.report_client_clock_estimate(estimate);
+                                }
+
+                                stats.clock_time()
+                            });
+
+                        if let Some(server_send_time) = server_send_time {
+                            control_sender
+                                .lock()
+                                .send(&ServerControlPacket::ClockSyncResponse {
+                                    client_send_time,
+                                    server_receive_time: packet_received_time,
+                                    server_send_time,
+                                })
+                                .ok();
+                        }
+                    }
                     ClientControlPacket::LocalViewParams(params) => {
+                        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+                            recorder.record(SessionRecordEntry::LocalViewParams(params));
+                        }
+
                         ctx.events_sender
                             .send(ServerCoreEvent::LocalViewParams(params))
                             .ok();
                     }
                     ClientControlPacket::Battery(packet) => {
+                        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+                            recorder.record(SessionRecordEntry::Battery(packet.clone()));
+                        }
+
                         ctx.events_sender
                             .send(ServerCoreEvent::Battery(packet.clone()))
                             .ok();
@@ -1235,66 +1641,33 @@ fn connection_pipeline(
                         }
                     }
                     ClientControlPacket::Buttons(entries) => {
-                        {
-                            let session_manager_lock = SESSION_MANAGER.read();
-                            if session_manager_lock
-                                .settings()
-                                .extra
-                                .logging
-                                .log_button_presses
-                            {
-                                alvr_events::send_event(EventType::Buttons(
-                                    entries
-                                        .iter()
-                                        .map(|e| ButtonEvent {
-                                            path: BUTTON_INFO.get(&e.path_id).map_or_else(
-                                                || format!("Unknown (ID: {:#16x})", e.path_id),
-                                                |info| info.path.to_owned(),
-                                            ),
-                                            value: e.value,
-                                        })
-                                        .collect(),
-                                ));
-                            }
+                        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+                            recorder.record(SessionRecordEntry::Buttons(entries.clone()));
                         }
 
-                        if let Some(manager) = &mut controller_button_mapping_manager {
-                            let button_entries = entries
-                                .iter()
-                                .flat_map(|entry| manager.map_button(entry))
-                                .collect::<Vec<_>>();
-
-                            if !button_entries.is_empty() {
-                                ctx.events_sender
-                                    .send(ServerCoreEvent::Buttons(button_entries))
-                                    .ok();
-                            }
-                        };
+                        button_mapping.process_buttons(&ctx, &entries, Instant::now());
                     }
-                    ClientControlPacket::ActiveInteractionProfile { input_ids, .. } => {
-                        controller_button_mapping_manager = if let Switch::Enabled(config) =
-                            &SESSION_MANAGER.read().settings().headset.controllers
-                        {
-                            if let Some(mappings) = &config.button_mappings {
-                                Some(ButtonMappingManager::new_manual(mappings))
-                            } else {
-                                controllers_emulation_mode.as_ref().map(|emulation_mode| {
-                                    ButtonMappingManager::new_automatic(
-                                        &input_ids,
-                                        emulation_mode,
-                                        &config.button_mapping_config,
-                                    )
-                                })
-                            }
-                        } else {
-                            None
-                        };
+                    ClientControlPacket::ActiveInteractionProfile {
+                        input_ids: new_input_ids,
+                        ..
+                    } => {
+                        button_mapping.set_input_ids(&ctx, new_input_ids.clone());
+
+                        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+                            recorder.record(SessionRecordEntry::ActiveInteractionProfile {
+                                input_ids: new_input_ids,
+                            });
+                        }
                     }
                     ClientControlPacket::Log { level, message } => {
                         info!("Client {client_hostname}: [{level:?}] {message}")
                     }
                     ClientControlPacket::KeepAlive | ClientControlPacket::StreamReady => (),
                     ClientControlPacket::ProximityState(headset_is_worn) => {
+                        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+                            recorder.record(SessionRecordEntry::ProximityState(headset_is_worn));
+                        }
+
                         ctx.events_sender
                             .send(ServerCoreEvent::ProximityState(headset_is_worn))
                             .ok();
@@ -1315,7 +1688,12 @@ fn connection_pipeline(
         move || {
             while is_streaming(&client_hostname) {
                 match socket.recv_poll() {
-                    Ok(()) => (),
+                    Ok(()) => {
+                        let statistics = socket.take_statistics();
+                        if statistics != SocketStatistics::default() {
+                            METRICS.lock().report_socket_statistics(statistics);
+                        }
+                    }
                     Err(ConnectionError::TryAgain(_)) => continue,
                     Err(e) => {
                         info!("Client disconnected. Cause: {e}");
@@ -1360,9 +1738,9 @@ fn connection_pipeline(
             warn!("Failed to run connect script: {e}");
         }
     }
-    if initial_settings.extra.capture.startup_video_recording {
-        info!("Creating recording file");
-        crate::create_recording_file(&ctx, session_manager_lock.settings());
+    if initial_settings.extra.capture.startup_session_recording {
+        info!("Creating session recording file");
+        session_recording::start_session_recording(&ctx);
     }
 
     session_manager_lock.update_client_connections(
@@ -1370,20 +1748,30 @@ fn connection_pipeline(
         ClientConnectionsAction::SetConnectionState(ConnectionState::Streaming),
     );
 
+    let negotiated_config = ServerNegotiatedStreamingConfig {
+        transcoding_view_resolution,
+        emulated_headset_view_resolution: transcoding_view_resolution,
+        refresh_rate: fps as _,
+        enable_foveated_encoding,
+        codec,
+        h264_profile: encoder_profile,
+        use_10bit_encoder: enable_10_bits_encoding,
+        encoding_gamma,
+        enable_hdr,
+    };
+    *ctx.main_stream_config.lock() = Some(SharedStreamConfig {
+        negotiated: negotiated_config.clone(),
+        game_audio_sample_rate,
+    });
+
+    // The game audio format is known only after the stream config is set
+    if initial_settings.extra.capture.startup_video_recording {
+        info!("Creating recording file");
+        crate::create_recording_file(&ctx, session_manager_lock.settings());
+    }
+
     ctx.events_sender
-        .send(ServerCoreEvent::ClientConnected(
-            ServerNegotiatedStreamingConfig {
-                transcoding_view_resolution,
-                emulated_headset_view_resolution: transcoding_view_resolution,
-                refresh_rate: fps as _,
-                enable_foveated_encoding,
-                codec,
-                h264_profile: encoder_profile,
-                use_10bit_encoder: enable_10_bits_encoding,
-                encoding_gamma,
-                enable_hdr,
-            },
-        ))
+        .send(ServerCoreEvent::ClientConnected(negotiated_config))
         .ok();
 
     dbg_connection!("connection_pipeline: Threads initialized; unlocking streams");
@@ -1394,7 +1782,18 @@ fn connection_pipeline(
     *ctx.video_channel_sender.lock() = None;
     *ctx.haptics_sender.lock() = None;
 
-    *ctx.video_recording_file.lock() = None;
+    // Spectators cannot outlive the main client
+    *ctx.main_stream_config.lock() = None;
+    let spectator_hostnames = ctx.spectators.lock().keys().cloned().collect::<Vec<_>>();
+    for hostname in spectator_hostnames {
+        session_manager_lock.update_client_connections(
+            hostname,
+            ClientConnectionsAction::SetConnectionState(ConnectionState::Disconnecting),
+        );
+    }
+
+    *ctx.video_recorder.lock() = None;
+    *ctx.session_recorder.lock() = None;
 
     session_manager_lock.update_client_connections(
         client_hostname,
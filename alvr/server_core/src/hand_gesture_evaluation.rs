@@ -0,0 +1,345 @@
+// Offline evaluation of the hand gestures on recorded hand skeletons, to tune the hand tracking
+// interaction settings without a headset
+use crate::{
+    hand_gestures::{self, HandGestureManager},
+    session_recording::{SessionRecord, SessionRecordEntry},
+};
+use alvr_common::{HAND_LEFT_ID, HAND_RIGHT_ID, Pose, anyhow::Result};
+use alvr_session::HandTrackingInteractionConfig;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    fn device_id(self) -> u64 {
+        match self {
+            Hand::Left => *HAND_LEFT_ID,
+            Hand::Right => *HAND_RIGHT_ID,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HandRecordFrame {
+    pub time: Duration, // Since the start of the recording
+    pub hand_skeletons: [Option<[Pose; 26]>; 2],
+}
+
+// Hand recordings contain only the hand skeletons, which makes them compact enough to be used as
+// test fixtures. Session recordings are accepted too, their tracking records are converted.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecordingLine {
+    Hands(Box<HandRecordFrame>),
+    Session(SessionRecord),
+}
+
+pub fn parse_hand_recording(text: &str) -> Result<Vec<HandRecordFrame>> {
+    let mut frames = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str(line)? {
+            RecordingLine::Hands(frame) => frames.push(*frame),
+            RecordingLine::Session(SessionRecord {
+                time,
+                entry: SessionRecordEntry::Tracking(tracking),
+            }) => frames.push(HandRecordFrame {
+                time,
+                hand_skeletons: tracking.hand_skeletons,
+            }),
+            RecordingLine::Session(_) => (),
+        }
+    }
+
+    Ok(frames)
+}
+
+pub fn load_hand_recording(path: &Path) -> Result<Vec<HandRecordFrame>> {
+    parse_hand_recording(&fs::read_to_string(path)?)
+}
+
+// Interval in which the gesture is intended to be performed
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GestureLabel {
+    pub hand: Hand,
+    pub gesture: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct GestureActivation {
+    pub start: Duration,
+    pub end: Duration, // Time of the last frame if the gesture is still active at the end
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GestureEvaluation {
+    pub hand: Hand,
+    // Name of a built-in gesture (e.g. ThumbIndexPinch) or of a custom gesture
+    pub gesture: String,
+    pub activations: Vec<GestureActivation>,
+    // Activations that don't overlap any label of this gesture
+    pub false_triggers: usize,
+    // Labels that don't overlap any activation
+    pub missed: usize,
+}
+
+// Replays the frames through a new hand gesture manager. Without labels, every activation counts
+// as a false trigger, which is useful for recordings of hands that should not trigger anything.
+pub fn evaluate_hand_gestures(
+    frames: &[HandRecordFrame],
+    config: &HandTrackingInteractionConfig,
+    labels: &[GestureLabel],
+) -> Vec<GestureEvaluation> {
+    let mut manager = HandGestureManager::new();
+
+    // The end of the last activation is None while the gesture is active
+    let mut timelines = BTreeMap::<(Hand, String), Vec<(Duration, Option<Duration>)>>::new();
+
+    for frame in frames {
+        let time_millis = frame.time.as_millis();
+
+        for (hand, skeleton) in [Hand::Left, Hand::Right]
+            .into_iter()
+            .zip(frame.hand_skeletons)
+        {
+            let Some(skeleton) = skeleton else {
+                continue;
+            };
+            let device_id = hand.device_id();
+
+            let mut states = manager
+                .get_active_gestures_at(&skeleton, config, device_id, time_millis)
+                .into_iter()
+                .map(|gesture| (format!("{:?}", gesture.id), gesture.active))
+                .collect::<Vec<_>>();
+            for (name, gesture) in &config.custom_gestures {
+                if hand_gestures::custom_gesture_binding(gesture, device_id).is_some() {
+                    let active = manager.is_custom_gesture_active(
+                        name,
+                        gesture,
+                        &skeleton,
+                        config,
+                        device_id,
+                        time_millis,
+                    );
+                    states.push((name.clone(), active));
+                }
+            }
+
+            for (name, active) in states {
+                let activations = timelines.entry((hand, name)).or_default();
+                let is_active = activations.last().is_some_and(|(_, end)| end.is_none());
+
+                if active && !is_active {
+                    activations.push((frame.time, None));
+                } else if !active && is_active {
+                    activations.last_mut().unwrap().1 = Some(frame.time);
+                }
+            }
+        }
+    }
+
+    // Labeled gestures are reported even if they never activated
+    for label in labels {
+        timelines
+            .entry((label.hand, label.gesture.clone()))
+            .or_default();
+    }
+
+    let end_time = frames.last().map(|frame| frame.time).unwrap_or_default();
+    let overlaps = |activation: &GestureActivation, label: &GestureLabel| {
+        activation.start <= label.end && label.start <= activation.end
+    };
+
+    timelines
+        .into_iter()
+        .map(|((hand, gesture), activations)| {
+            let activations = activations
+                .into_iter()
+                .map(|(start, end)| GestureActivation {
+                    start,
+                    end: end.unwrap_or(end_time),
+                })
+                .collect::<Vec<_>>();
+            let labels = labels
+                .iter()
+                .filter(|label| label.hand == hand && label.gesture == gesture)
+                .collect::<Vec<_>>();
+
+            let false_triggers = activations
+                .iter()
+                .filter(|activation| !labels.iter().any(|label| overlaps(activation, label)))
+                .count();
+            let missed = labels
+                .iter()
+                .filter(|label| {
+                    !activations
+                        .iter()
+                        .any(|activation| overlaps(activation, label))
+                })
+                .count();
+
+            GestureEvaluation {
+                hand,
+                gesture,
+                activations,
+                false_triggers,
+                missed,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_packets::{FaceData, TrackingData};
+    use alvr_session::{
+        CustomHandGesture, HandJoint, HandJointPredicate, ThresholdComparison,
+        settings_schema::Switch,
+    };
+
+    // Synthetic recordings of a left hand at 25 Hz. In pinch.jsonl the thumb pinches the index
+    // finger twice, in pinch_hover.jsonl it hovers around the pinch distance, in range for two
+    // frames out of four.
+    const PINCH_RECORDING: &str = include_str!("../resources/hand_gestures/pinch.jsonl");
+    const PINCH_HOVER_RECORDING: &str =
+        include_str!("../resources/hand_gestures/pinch_hover.jsonl");
+
+    fn config(
+        activation_delay: u32,
+        deactivation_delay: u32,
+        repeat_delay: u32,
+    ) -> HandTrackingInteractionConfig {
+        HandTrackingInteractionConfig {
+            only_touch: false,
+            pinch_touch_distance: 0.0,
+            pinch_trigger_distance: 0.25,
+            curl_touch_distance: 2.0,
+            curl_trigger_distance: 2.5,
+            joystick_deadzone: 40.0,
+            joystick_offset_horizontal: 0.0,
+            joystick_offset_vertical: 0.0,
+            joystick_range: 1.0,
+            activation_delay,
+            deactivation_delay,
+            repeat_delay,
+            custom_gestures: vec![(
+                "Pinch".into(),
+                CustomHandGesture {
+                    predicates: vec![HandJointPredicate::Distance {
+                        first_joint: HandJoint::ThumbTip,
+                        second_joint: HandJoint::IndexTip,
+                        comparison: ThresholdComparison::Below,
+                        threshold: 2.0,
+                    }],
+                    left_hand_button: Switch::Enabled("/user/hand/left/input/menu/click".into()),
+                    right_hand_button: Switch::Disabled,
+                },
+            )],
+        }
+    }
+
+    fn label(gesture: &str, start_ms: u64, end_ms: u64) -> GestureLabel {
+        GestureLabel {
+            hand: Hand::Left,
+            gesture: gesture.into(),
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+        }
+    }
+
+    fn evaluation<'a>(
+        evaluations: &'a [GestureEvaluation],
+        gesture: &str,
+    ) -> &'a GestureEvaluation {
+        evaluations
+            .iter()
+            .find(|evaluation| evaluation.hand == Hand::Left && evaluation.gesture == gesture)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pinch_recording() {
+        let frames = parse_hand_recording(PINCH_RECORDING).unwrap();
+        let labels = [
+            label("ThumbIndexPinch", 600, 1400),
+            label("ThumbIndexPinch", 2000, 2700),
+            label("Pinch", 600, 1400),
+            label("Pinch", 2000, 2700),
+            label("ThumbMiddlePinch", 2000, 2700),
+        ];
+
+        let evaluations = evaluate_hand_gestures(&frames, &config(50, 100, 100), &labels);
+
+        for gesture in ["ThumbIndexPinch", "Pinch"] {
+            let evaluation = evaluation(&evaluations, gesture);
+            assert_eq!(evaluation.activations.len(), 2);
+            assert_eq!(evaluation.false_triggers, 0);
+            assert_eq!(evaluation.missed, 0);
+        }
+
+        let middle_pinch = evaluation(&evaluations, "ThumbMiddlePinch");
+        assert!(middle_pinch.activations.is_empty());
+        assert_eq!(middle_pinch.missed, 1);
+
+        // The activation delay postpones the activation
+        let pinch = &evaluation(&evaluations, "ThumbIndexPinch").activations[0];
+        let undelayed_evaluations = evaluate_hand_gestures(&frames, &config(0, 0, 0), &labels);
+        let undelayed_pinch = &evaluation(&undelayed_evaluations, "ThumbIndexPinch").activations[0];
+        assert!(pinch.start > undelayed_pinch.start);
+        assert!(pinch.end > undelayed_pinch.end);
+    }
+
+    #[test]
+    fn test_pinch_hover_recording() {
+        let frames = parse_hand_recording(PINCH_HOVER_RECORDING).unwrap();
+
+        let evaluations = evaluate_hand_gestures(&frames, &config(50, 100, 100), &[]);
+        let pinch = evaluation(&evaluations, "ThumbIndexPinch");
+        assert!(pinch.activations.is_empty());
+        assert_eq!(pinch.false_triggers, 0);
+
+        // Without delays, every time the thumb gets close enough is a false trigger
+        let evaluations = evaluate_hand_gestures(&frames, &config(0, 0, 0), &[]);
+        assert!(evaluation(&evaluations, "ThumbIndexPinch").false_triggers >= 5);
+    }
+
+    #[test]
+    fn test_session_recording() {
+        let records = [
+            SessionRecord {
+                time: Duration::from_millis(10),
+                entry: SessionRecordEntry::ProximityState(true),
+            },
+            SessionRecord {
+                time: Duration::from_millis(20),
+                entry: SessionRecordEntry::Tracking(Box::new(TrackingData {
+                    poll_timestamp: Duration::from_millis(20),
+                    device_motions: vec![],
+                    hand_skeletons: [None, Some([Pose::IDENTITY; 26])],
+                    face: FaceData::default(),
+                    body: None,
+                })),
+            },
+        ];
+        let text = records
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let frames = parse_hand_recording(&text).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, Duration::from_millis(20));
+        assert!(frames[0].hand_skeletons[0].is_none());
+        assert!(frames[0].hand_skeletons[1].is_some());
+    }
+}
@@ -4,7 +4,10 @@ use alvr_common::{
     *,
 };
 use alvr_packets::{ButtonEntry, ButtonValue};
-use alvr_session::HandTrackingInteractionConfig;
+use alvr_session::{
+    CustomHandGesture, HandJoint, HandJointPredicate, HandTrackingInteractionConfig,
+    ThresholdComparison, settings_schema::Switch,
+};
 use std::{
     collections::{HashMap, HashSet},
     hash::Hash,
@@ -12,6 +15,13 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_millis(0))
+        .as_millis()
+}
+
 fn lerp_pose(a: Pose, b: Pose, fac: f32) -> Pose {
     Pose {
         orientation: a.orientation.lerp(b.orientation, fac),
@@ -84,11 +94,75 @@ pub enum HandGestureId {
     // Complex
     JoystickX,
     JoystickY,
+    // User defined, identified by the hash of the name
+    Custom(u64),
+}
+
+fn predicate_holds(hand_skeleton: &[Pose; 26], predicate: &HandJointPredicate) -> bool {
+    let position = |joint: HandJoint| hand_skeleton[joint as usize].position;
+
+    let (value, comparison, threshold) = match predicate {
+        HandJointPredicate::Distance {
+            first_joint,
+            second_joint,
+            comparison,
+            threshold,
+        } => (
+            position(*first_joint).distance(position(*second_joint)),
+            comparison,
+            threshold * 0.01,
+        ),
+        HandJointPredicate::Angle {
+            first_joint,
+            middle_joint,
+            last_joint,
+            comparison,
+            threshold,
+        } => {
+            let middle = position(*middle_joint);
+            (
+                (position(*first_joint) - middle).angle_between(position(*last_joint) - middle),
+                comparison,
+                threshold.to_radians(),
+            )
+        }
+    };
+
+    match comparison {
+        ThresholdComparison::Below => value < threshold,
+        ThresholdComparison::Above => value > threshold,
+    }
+}
+
+// Button path a custom gesture drives on the given hand, if the gesture is used on that hand
+pub fn custom_gesture_binding(gesture: &CustomHandGesture, device_id: u64) -> Option<&String> {
+    let binding = if device_id == *HAND_LEFT_ID {
+        &gesture.left_hand_button
+    } else {
+        &gesture.right_hand_button
+    };
+
+    match binding {
+        Switch::Enabled(path) => Some(path),
+        Switch::Disabled => None,
+    }
+}
+
+fn custom_gesture_button_entry(path_id: u64, pressed: bool) -> ButtonEntry {
+    let value = match BUTTON_INFO.get(&path_id).map(|info| &info.button_type) {
+        Some(ButtonType::Scalar) => ButtonValue::Scalar(if pressed { 1.0 } else { 0.0 }),
+        _ => ButtonValue::Binary(pressed),
+    };
+
+    ButtonEntry { path_id, value }
 }
 
 pub struct HandGestureManager {
     gesture_data_left: HashMap<HandGestureId, GestureAction>,
     gesture_data_right: HashMap<HandGestureId, GestureAction>,
+    custom_button_states_left: HashMap<u64, bool>,
+    custom_button_states_right: HashMap<u64, bool>,
+    reported_unknown_paths: HashSet<String>,
 }
 
 impl HandGestureManager {
@@ -96,14 +170,117 @@ impl HandGestureManager {
         Self {
             gesture_data_left: HashMap::new(),
             gesture_data_right: HashMap::new(),
+            custom_button_states_left: HashMap::new(),
+            custom_button_states_right: HashMap::new(),
+            reported_unknown_paths: HashSet::new(),
         }
     }
 
+    // Evaluates the gestures defined in the settings and returns the changes of the buttons they
+    // are bound to. The buttons are not remapped, since the user chose them explicitly.
+    pub fn get_custom_gesture_entries(
+        &mut self,
+        hand_skeleton: &[Pose; 26],
+        config: &HandTrackingInteractionConfig,
+        device_id: u64,
+    ) -> Vec<ButtonEntry> {
+        // A button bound to multiple gestures is pressed if any of them is active
+        let mut button_states = HashMap::<u64, bool>::new();
+
+        let time_millis = now_millis();
+
+        for (name, gesture) in &config.custom_gestures {
+            let Some(path) = custom_gesture_binding(gesture, device_id) else {
+                continue;
+            };
+
+            let path_id = hash_string(path);
+            if !BUTTON_INFO.contains_key(&path_id) {
+                if self.reported_unknown_paths.insert(path.clone()) {
+                    warn!("Hand gesture \"{name}\" is bound to unknown button path {path}");
+                }
+
+                continue;
+            }
+
+            let active = self.is_custom_gesture_active(
+                name,
+                gesture,
+                hand_skeleton,
+                config,
+                device_id,
+                time_millis,
+            );
+
+            *button_states.entry(path_id).or_default() |= active;
+        }
+
+        let previous_states = if device_id == *HAND_LEFT_ID {
+            &mut self.custom_button_states_left
+        } else {
+            &mut self.custom_button_states_right
+        };
+
+        // Release the buttons of gestures that have been removed or rebound
+        let mut entries = previous_states
+            .iter()
+            .filter(|(path_id, pressed)| **pressed && !button_states.contains_key(path_id))
+            .map(|(path_id, _)| custom_gesture_button_entry(*path_id, false))
+            .collect::<Vec<_>>();
+
+        for (path_id, pressed) in &button_states {
+            if previous_states.get(path_id) != Some(pressed) {
+                entries.push(custom_gesture_button_entry(*path_id, *pressed));
+            }
+        }
+
+        *previous_states = button_states;
+
+        entries
+    }
+
+    pub fn is_custom_gesture_active(
+        &mut self,
+        name: &str,
+        gesture: &CustomHandGesture,
+        hand_skeleton: &[Pose; 26],
+        config: &HandTrackingInteractionConfig,
+        device_id: u64,
+        time_millis: u128,
+    ) -> bool {
+        let in_range = !gesture.predicates.is_empty()
+            && gesture
+                .predicates
+                .iter()
+                .all(|predicate| predicate_holds(hand_skeleton, predicate));
+
+        self.update_gesture_action(
+            HandGestureId::Custom(hash_string(name)),
+            in_range,
+            config.repeat_delay,
+            config.activation_delay,
+            config.deactivation_delay,
+            device_id,
+            time_millis,
+        )
+    }
+
     pub fn get_active_gestures(
         &mut self,
         hand_skeleton: &[Pose; 26],
         config: &HandTrackingInteractionConfig,
         device_id: u64,
+    ) -> Vec<HandGesture> {
+        self.get_active_gestures_at(hand_skeleton, config, device_id, now_millis())
+    }
+
+    // Time is in milliseconds, in any time base as long as it is consistent between calls
+    pub fn get_active_gestures_at(
+        &mut self,
+        hand_skeleton: &[Pose; 26],
+        config: &HandTrackingInteractionConfig,
+        device_id: u64,
+        time_millis: u128,
     ) -> Vec<HandGesture> {
         // global joints
         let gj = hand_skeleton;
@@ -155,6 +332,7 @@ impl HandGestureManager {
                     config.activation_delay,
                     config.deactivation_delay,
                     device_id,
+                    time_millis,
                 ),
                 clicked: self
                     .test_gesture_dist(thumb_tip, thumb_rad, index_tip, index_rad, pinch_min),
@@ -178,6 +356,7 @@ impl HandGestureManager {
                     config.activation_delay,
                     config.deactivation_delay,
                     device_id,
+                    time_millis,
                 ),
                 clicked: self
                     .test_gesture_dist(thumb_tip, thumb_rad, middle_tip, middle_rad, pinch_min),
@@ -201,6 +380,7 @@ impl HandGestureManager {
                     config.activation_delay,
                     config.deactivation_delay,
                     device_id,
+                    time_millis,
                 ),
                 clicked: self
                     .test_gesture_dist(thumb_tip, thumb_rad, ring_tip, ring_rad, pinch_min),
@@ -224,6 +404,7 @@ impl HandGestureManager {
                     config.activation_delay,
                     config.deactivation_delay,
                     device_id,
+                    time_millis,
                 ),
                 clicked: self
                     .test_gesture_dist(thumb_tip, thumb_rad, little_tip, little_rad, pinch_min),
@@ -375,10 +556,34 @@ impl HandGestureManager {
         in_delay: u32,
         out_delay: u32,
         device_id: u64,
+        time_millis: u128,
     ) -> bool {
         let in_range = first_anchor.position.distance(second_anchor.position)
             < (activation_dist + first_radius + second_radius);
 
+        self.update_gesture_action(
+            gesture_id,
+            in_range,
+            repeat_delay,
+            in_delay,
+            out_delay,
+            device_id,
+            time_millis,
+        )
+    }
+
+    // Applies the activation, deactivation and repeat delays to the raw state of a gesture
+    #[allow(clippy::too_many_arguments)]
+    fn update_gesture_action(
+        &mut self,
+        gesture_id: HandGestureId,
+        in_range: bool,
+        repeat_delay: u32,
+        in_delay: u32,
+        out_delay: u32,
+        device_id: u64,
+        time_millis: u128,
+    ) -> bool {
         let gesture_data = if device_id == *HAND_LEFT_ID {
             &mut self.gesture_data_left
         } else {
@@ -403,19 +608,13 @@ impl HandGestureManager {
             g.entering = false;
         }
 
-        // Get current time, for comparison
-        let time_millis = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or(Duration::from_millis(0))
-            .as_millis();
-
         // Transitioning from inactive to active
         if in_range && !g.active {
             // Don't transition state unless the duration of repeat_delay has passed since last deactivation
-            if g.last_deactivated < time_millis - u128::from(repeat_delay) {
+            if g.last_deactivated + u128::from(repeat_delay) < time_millis {
                 if g.entering {
                     // Don't transition state unless gesture has been in range for the duration of in_delay
-                    if g.entering_since < time_millis - u128::from(in_delay) {
+                    if g.entering_since + u128::from(in_delay) < time_millis {
                         g.last_activated = time_millis;
                         g.entering = false;
                         g.active = true;
@@ -432,7 +631,7 @@ impl HandGestureManager {
         if !in_range && g.active {
             if g.exiting {
                 // Don't transition state unless gesture has been out of range for the duration of out_delay
-                if g.exiting_since < time_millis - u128::from(out_delay) {
+                if g.exiting_since + u128::from(out_delay) < time_millis {
                     g.last_deactivated = time_millis;
                     g.exiting = false;
                     g.active = false;
@@ -597,3 +796,177 @@ pub fn trigger_hand_gesture_actions(
 
     button_entries
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn config(custom_gestures: Vec<(String, CustomHandGesture)>) -> HandTrackingInteractionConfig {
+        HandTrackingInteractionConfig {
+            only_touch: false,
+            pinch_touch_distance: 0.0,
+            pinch_trigger_distance: 0.25,
+            curl_touch_distance: 2.0,
+            curl_trigger_distance: 2.5,
+            joystick_deadzone: 40.0,
+            joystick_offset_horizontal: 0.0,
+            joystick_offset_vertical: 0.0,
+            joystick_range: 1.0,
+            activation_delay: 0,
+            deactivation_delay: 0,
+            repeat_delay: 0,
+            custom_gestures,
+        }
+    }
+
+    fn pinch_gesture(left_hand_button: &str) -> CustomHandGesture {
+        CustomHandGesture {
+            predicates: vec![HandJointPredicate::Distance {
+                first_joint: HandJoint::ThumbTip,
+                second_joint: HandJoint::IndexTip,
+                comparison: ThresholdComparison::Below,
+                threshold: 2.0,
+            }],
+            left_hand_button: Switch::Enabled(left_hand_button.into()),
+            right_hand_button: Switch::Disabled,
+        }
+    }
+
+    // Thumb and index tips 1.5cm apart, index finger straight along -Z
+    fn skeleton() -> [Pose; 26] {
+        let mut skeleton = [Pose::IDENTITY; 26];
+        skeleton[HandJoint::ThumbTip as usize].position = Vec3::new(0.015, 0.0, -0.09);
+        skeleton[HandJoint::IndexProximal as usize].position = Vec3::new(0.0, 0.0, -0.03);
+        skeleton[HandJoint::IndexIntermediate as usize].position = Vec3::new(0.0, 0.0, -0.06);
+        skeleton[HandJoint::IndexTip as usize].position = Vec3::new(0.0, 0.0, -0.09);
+
+        skeleton
+    }
+
+    fn distance(comparison: ThresholdComparison, threshold: f32) -> HandJointPredicate {
+        HandJointPredicate::Distance {
+            first_joint: HandJoint::ThumbTip,
+            second_joint: HandJoint::IndexTip,
+            comparison,
+            threshold,
+        }
+    }
+
+    fn index_angle(comparison: ThresholdComparison, threshold: f32) -> HandJointPredicate {
+        HandJointPredicate::Angle {
+            first_joint: HandJoint::IndexProximal,
+            middle_joint: HandJoint::IndexIntermediate,
+            last_joint: HandJoint::IndexTip,
+            comparison,
+            threshold,
+        }
+    }
+
+    // Returns the pressed state of each entry, in the order of the given paths
+    fn entry_states(entries: &[ButtonEntry], paths: &[&str]) -> Vec<Option<bool>> {
+        paths
+            .iter()
+            .map(|path| {
+                entries
+                    .iter()
+                    .find(|entry| entry.path_id == hash_string(path))
+                    .map(|entry| matches!(entry.value, ButtonValue::Binary(true)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_distance_predicate() {
+        let skeleton = skeleton();
+
+        // The threshold is in centimeters
+        assert!(predicate_holds(
+            &skeleton,
+            &distance(ThresholdComparison::Below, 2.0)
+        ));
+        assert!(!predicate_holds(
+            &skeleton,
+            &distance(ThresholdComparison::Below, 1.0)
+        ));
+        assert!(predicate_holds(
+            &skeleton,
+            &distance(ThresholdComparison::Above, 1.0)
+        ));
+        assert!(!predicate_holds(
+            &skeleton,
+            &distance(ThresholdComparison::Above, 2.0)
+        ));
+    }
+
+    #[test]
+    fn test_angle_predicate() {
+        let mut skeleton = skeleton();
+
+        // The threshold is in degrees, a straight finger is at 180°
+        assert!(predicate_holds(
+            &skeleton,
+            &index_angle(ThresholdComparison::Above, 170.0)
+        ));
+        assert!(!predicate_holds(
+            &skeleton,
+            &index_angle(ThresholdComparison::Below, 170.0)
+        ));
+
+        // Bent at 90°
+        skeleton[HandJoint::IndexTip as usize].position = Vec3::new(0.0, -0.03, -0.06);
+        assert!(predicate_holds(
+            &skeleton,
+            &index_angle(ThresholdComparison::Below, 100.0)
+        ));
+        assert!(predicate_holds(
+            &skeleton,
+            &index_angle(ThresholdComparison::Above, 80.0)
+        ));
+    }
+
+    #[test]
+    fn test_empty_predicates() {
+        let mut gesture = pinch_gesture(LEFT_MENU_CLICK_PATH);
+        gesture.predicates.clear();
+        let config = config(vec![]);
+        let mut manager = HandGestureManager::new();
+
+        for time_millis in 0..10 {
+            assert!(!manager.is_custom_gesture_active(
+                "Empty",
+                &gesture,
+                &skeleton(),
+                &config,
+                *HAND_LEFT_ID,
+                time_millis,
+            ));
+        }
+    }
+
+    #[test]
+    fn test_custom_gesture_button_release() {
+        let paths = [LEFT_MENU_CLICK_PATH, LEFT_X_CLICK_PATH];
+        let mut manager = HandGestureManager::new();
+
+        // The gesture activates on the second evaluation, in a later millisecond
+        let mut update = |config: &HandTrackingInteractionConfig| {
+            thread::sleep(Duration::from_millis(2));
+            let entries = manager.get_custom_gesture_entries(&skeleton(), config, *HAND_LEFT_ID);
+
+            entry_states(&entries, &paths)
+        };
+
+        let menu_config = config(vec![("Pinch".into(), pinch_gesture(LEFT_MENU_CLICK_PATH))]);
+        update(&menu_config);
+        assert_eq!(update(&menu_config), [Some(true), None]);
+
+        // Rebinding releases the previous button
+        let x_config = config(vec![("Pinch".into(), pinch_gesture(LEFT_X_CLICK_PATH))]);
+        assert_eq!(update(&x_config), [Some(false), Some(true)]);
+        assert_eq!(update(&x_config), [None, None]);
+
+        // Removing the gesture releases its button
+        assert_eq!(update(&config(vec![])), [None, Some(false)]);
+    }
+}
@@ -1,10 +1,17 @@
-use alvr_common::*;
+use alvr_common::{glam::Vec2, *};
 use alvr_packets::{ButtonEntry, ButtonValue};
 use alvr_session::{
-    AutomaticButtonMappingConfig, BinaryToScalarStates, ButtonBindingTarget, ButtonMappingType,
-    ControllersEmulationMode, HysteresisThreshold, Range,
+    AutomaticButtonMappingConfig, AxisTransform, BinaryToScalarStates, ButtonBindingTarget,
+    ButtonMappingType, ControllersConfig, ControllersEmulationMode, HysteresisThreshold,
+    InputMappingProfile, Range, ResponseCurve, ThumbstickAxisTransform, ThumbstickDirection,
+    ThumbstickDirectionConfig,
 };
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+const SNAP_TURN_PULSE_DURATION: Duration = Duration::from_millis(50);
 
 pub fn registered_button_set(
     controllers_emulation_mode: &ControllersEmulationMode,
@@ -51,6 +58,9 @@ pub struct BindingTarget {
     destination: u64,
     mapping_type: ButtonMappingType,
     binary_conditions: Vec<u64>,
+    chord_buttons: Vec<u64>,
+    // Other axis of the thumbstick, for thumbstick mappings
+    paired_axis: Option<u64>,
 }
 
 // Inputs relative to the same physical button
@@ -112,6 +122,8 @@ fn passthrough(target: u64) -> BindingTarget {
         destination: target,
         mapping_type: ButtonMappingType::Passthrough,
         binary_conditions: vec![],
+        chord_buttons: vec![],
+        paired_axis: None,
     }
 }
 
@@ -120,6 +132,8 @@ fn binary_to_scalar(target: u64, map: BinaryToScalarStates) -> BindingTarget {
         destination: target,
         mapping_type: ButtonMappingType::BinaryToScalar(map),
         binary_conditions: vec![],
+        chord_buttons: vec![],
+        paired_axis: None,
     }
 }
 
@@ -128,6 +142,8 @@ fn hysteresis_threshold(target: u64, map: HysteresisThreshold) -> BindingTarget
         destination: target,
         mapping_type: ButtonMappingType::HysteresisThreshold(map),
         binary_conditions: vec![],
+        chord_buttons: vec![],
+        paired_axis: None,
     }
 }
 
@@ -136,6 +152,8 @@ fn remap(target: u64, map: Range) -> BindingTarget {
         destination: target,
         mapping_type: ButtonMappingType::Remap(map),
         binary_conditions: vec![],
+        chord_buttons: vec![],
+        paired_axis: None,
     }
 }
 
@@ -592,10 +610,242 @@ pub fn automatic_bindings(
     bindings
 }
 
+// Inputs used to pick the mapping profile. A profile selected manually takes precedence over the
+// profile of the foreground application.
+#[derive(Default)]
+pub struct InputProfileSelection {
+    foreground_application: Option<String>,
+    selected_profile: Option<String>,
+    // Incremented on every change, so the active profile is resolved again only when needed
+    revision: usize,
+}
+
+impl InputProfileSelection {
+    pub fn foreground_application(&self) -> Option<&String> {
+        self.foreground_application.as_ref()
+    }
+
+    pub fn selected_profile(&self) -> Option<&String> {
+        self.selected_profile.as_ref()
+    }
+
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    pub fn set_foreground_application(&mut self, application: Option<String>) {
+        if application != self.foreground_application {
+            self.foreground_application = application;
+            self.revision += 1;
+        }
+    }
+
+    pub fn select_profile(&mut self, profile: Option<String>) {
+        if profile != self.selected_profile {
+            self.selected_profile = profile;
+            self.revision += 1;
+        }
+    }
+
+    pub fn active_profile<'a>(
+        &self,
+        config: &'a ControllersConfig,
+    ) -> Option<&'a (String, InputMappingProfile)> {
+        if let Some(selected) = &self.selected_profile {
+            return config
+                .input_profiles
+                .iter()
+                .find(|(name, _)| name == selected);
+        }
+
+        let application = self.foreground_application.as_ref()?;
+        config.input_profiles.iter().find(|(_, profile)| {
+            profile
+                .applications
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(application))
+        })
+    }
+}
+
+// Stretch the range above the deadzone to 0..1
+fn apply_deadzone(magnitude: f32, deadzone: f32) -> f32 {
+    if magnitude <= deadzone {
+        0.0
+    } else {
+        ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0)
+    }
+}
+
+fn apply_curve(curve: &ResponseCurve, magnitude: f32) -> f32 {
+    match curve {
+        ResponseCurve::Linear => magnitude,
+        ResponseCurve::Exponent(exponent) => magnitude.powf(*exponent),
+        ResponseCurve::Piecewise(points) => {
+            let mut points = points
+                .iter()
+                .map(|point| Vec2::new(point.input, point.output))
+                .collect::<Vec<_>>();
+            points.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+            let mut previous = Vec2::ZERO;
+            for point in points.into_iter().chain([Vec2::ONE]) {
+                if magnitude <= point.x {
+                    if point.x <= previous.x {
+                        return point.y;
+                    }
+
+                    let factor = (magnitude - previous.x) / (point.x - previous.x);
+                    return previous.lerp(point, factor).y;
+                }
+
+                previous = point;
+            }
+
+            previous.y
+        }
+    }
+}
+
+// The sign of the value is preserved, the deadzone and curve are applied to its magnitude
+fn transform_axis(transform: &AxisTransform, value: f32) -> f32 {
+    let magnitude = apply_curve(
+        &transform.curve,
+        apply_deadzone(value.abs(), transform.deadzone),
+    );
+    let value = magnitude.copysign(value);
+
+    if transform.inverted { -value } else { value }
+}
+
+fn transform_thumbstick_axis(
+    transform: &ThumbstickAxisTransform,
+    value: f32,
+    paired_value: f32,
+) -> f32 {
+    let distance = Vec2::new(value, paired_value).length();
+    let value = if distance > 0.0 {
+        value * apply_deadzone(distance, transform.radial_deadzone) / distance
+    } else {
+        0.0
+    };
+
+    transform_axis(&transform.transform, value)
+}
+
+fn thumbstick_points_to(config: &ThumbstickDirectionConfig, x: f32, y: f32) -> bool {
+    if Vec2::new(x, y).length() < config.threshold {
+        return false;
+    }
+
+    match config.direction {
+        ThumbstickDirection::Up => y >= x.abs(),
+        ThumbstickDirection::Down => -y >= x.abs(),
+        ThumbstickDirection::Left => -x >= y.abs(),
+        ThumbstickDirection::Right => x >= y.abs(),
+    }
+}
+
+// State of the time based and stateful mappings
+#[derive(Default)]
+struct ActionState {
+    active: bool,
+    // Long press activation, next turbo repetition or end of the snap turn pulse
+    deadline: Option<Instant>,
+    // Used for double tap
+    last_press: Option<Instant>,
+    // Used for snap turn, until the thumbstick stops pointing in the direction
+    latched: bool,
+}
+
+impl ActionState {
+    // Returns the new destination value if changed
+    fn set_active(&mut self, active: bool) -> Option<bool> {
+        (self.active != active).then(|| {
+            self.active = active;
+            active
+        })
+    }
+
+    fn source_changed(
+        &mut self,
+        mapping_type: &ButtonMappingType,
+        pressed: bool,
+        now: Instant,
+    ) -> Option<bool> {
+        match mapping_type {
+            ButtonMappingType::Toggle => {
+                if pressed {
+                    self.set_active(!self.active)
+                } else {
+                    None
+                }
+            }
+            ButtonMappingType::LongPress(duration_ms) => {
+                self.deadline = pressed.then(|| now + Duration::from_millis(*duration_ms));
+                if pressed {
+                    None
+                } else {
+                    self.set_active(false)
+                }
+            }
+            ButtonMappingType::DoubleTap(interval_ms) => {
+                if !pressed {
+                    self.set_active(false)
+                } else if self.last_press.is_some_and(|last_press| {
+                    now - last_press <= Duration::from_millis(*interval_ms)
+                }) {
+                    self.last_press = None;
+                    self.set_active(true)
+                } else {
+                    self.last_press = Some(now);
+                    None
+                }
+            }
+            ButtonMappingType::Turbo(interval_ms) => {
+                self.deadline = pressed.then(|| now + Duration::from_millis(*interval_ms));
+                self.set_active(pressed)
+            }
+            _ => None,
+        }
+    }
+
+    fn deadline_reached(&mut self, mapping_type: &ButtonMappingType, now: Instant) -> Option<bool> {
+        self.deadline.filter(|deadline| *deadline <= now)?;
+
+        match mapping_type {
+            ButtonMappingType::LongPress(_) => {
+                self.deadline = None;
+                self.set_active(true)
+            }
+            ButtonMappingType::Turbo(interval_ms) => {
+                self.deadline = Some(now + Duration::from_millis(*interval_ms));
+                self.set_active(!self.active)
+            }
+            ButtonMappingType::SnapTurn(_) => {
+                self.deadline = None;
+                self.set_active(false)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn binary_conditions_met(binary_source_states: &HashMap<u64, bool>, conditions: &[u64]) -> bool {
+    conditions
+        .iter()
+        .all(|id| binary_source_states.get(id).copied().unwrap_or(false))
+}
+
 pub struct ButtonMappingManager {
     mappings: HashMap<u64, Vec<BindingTarget>>,
     binary_source_states: HashMap<u64, bool>,
+    scalar_source_states: HashMap<u64, f32>,
     hysteresis_states: HashMap<u64, HashMap<u64, bool>>,
+    // Indexed by source and destination
+    action_states: HashMap<(u64, u64), ActionState>,
+    // Last value sent for each destination
+    destination_values: HashMap<u64, ButtonValue>,
 }
 
 impl ButtonMappingManager {
@@ -608,10 +858,37 @@ impl ButtonMappingManager {
         Self {
             mappings: automatic_bindings(source, &button_set, button_mapping_config),
             binary_source_states: HashMap::new(),
+            scalar_source_states: HashMap::new(),
             hysteresis_states: HashMap::new(),
+            action_states: HashMap::new(),
+            destination_values: HashMap::new(),
+        }
+    }
+
+    // Manual mappings take precedence over the automatic bindings of the source buttons
+    pub fn new_from_config(config: &ControllersConfig, source: &HashSet<u64>) -> Self {
+        if let Some(mappings) = &config.button_mappings {
+            Self::new_manual(mappings)
+        } else {
+            Self::new_automatic(
+                source,
+                &config.emulation_mode,
+                &config.button_mapping_config,
+            )
         }
     }
 
+    // Used until the client reports its active interaction profile
+    pub fn new_default(config: &ControllersConfig) -> Self {
+        Self::new_from_config(
+            config,
+            &CONTROLLER_PROFILE_INFO
+                .get(&QUEST_CONTROLLER_PROFILE_ID)
+                .unwrap()
+                .button_set,
+        )
+    }
+
     pub fn new_manual(mappings: &[(String, Vec<ButtonBindingTarget>)]) -> Self {
         let mappings = mappings
             .iter()
@@ -628,6 +905,23 @@ impl ButtonMappingManager {
                                 .iter()
                                 .map(|c| alvr_common::hash_string(c))
                                 .collect(),
+                            chord_buttons: match &b.mapping_type {
+                                ButtonMappingType::Chord(buttons) => buttons
+                                    .iter()
+                                    .map(|c| alvr_common::hash_string(c))
+                                    .collect(),
+                                _ => vec![],
+                            },
+                            paired_axis: match &b.mapping_type {
+                                ButtonMappingType::ThumbstickAxis(transform) => {
+                                    Some(alvr_common::hash_string(&transform.paired_axis))
+                                }
+                                ButtonMappingType::Dpad(config)
+                                | ButtonMappingType::SnapTurn(config) => {
+                                    Some(alvr_common::hash_string(&config.y_axis))
+                                }
+                                _ => None,
+                            },
                         })
                         .collect(),
                 )
@@ -637,12 +931,51 @@ impl ButtonMappingManager {
         Self {
             mappings,
             binary_source_states: HashMap::new(),
+            scalar_source_states: HashMap::new(),
             hysteresis_states: HashMap::new(),
+            action_states: HashMap::new(),
+            destination_values: HashMap::new(),
         }
     }
 
     // Apply any button changes that are mapped to this specific button
     pub fn map_button(&mut self, source_button: &ButtonEntry) -> Vec<ButtonEntry> {
+        self.map_button_at(source_button, Instant::now())
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.action_states
+            .values()
+            .filter_map(|state| state.deadline)
+            .min()
+    }
+
+    // Reset the destinations that are pressed or deflected. Must be called before replacing the
+    // manager, otherwise they would stay in that state.
+    pub fn release_all(&mut self) -> Vec<ButtonEntry> {
+        self.destination_values
+            .drain()
+            .filter_map(|(path_id, value)| {
+                let value = match value {
+                    ButtonValue::Binary(true) => ButtonValue::Binary(false),
+                    ButtonValue::Scalar(value) if value != 0.0 => ButtonValue::Scalar(0.0),
+                    _ => return None,
+                };
+
+                Some(ButtonEntry { path_id, value })
+            })
+            .collect()
+    }
+
+    fn record_destinations(&mut self, destination_buttons: Vec<ButtonEntry>) -> Vec<ButtonEntry> {
+        for entry in &destination_buttons {
+            self.destination_values.insert(entry.path_id, entry.value);
+        }
+
+        destination_buttons
+    }
+
+    pub fn map_button_at(&mut self, source_button: &ButtonEntry, now: Instant) -> Vec<ButtonEntry> {
         if let ButtonValue::Binary(value) = source_button.value {
             let val_ref = self
                 .binary_source_states
@@ -656,11 +989,15 @@ impl ButtonMappingManager {
             // NB: Update value
             *val_ref = value;
         }
+        if let ButtonValue::Scalar(value) = source_button.value {
+            self.scalar_source_states
+                .insert(source_button.path_id, value);
+        }
 
         let mut destination_buttons = vec![];
 
         if let Some(mappings) = self.mappings.get(&source_button.path_id) {
-            'mapping: for mapping in mappings {
+            for mapping in mappings {
                 let destination_value = match (&mapping.mapping_type, source_button.value) {
                     (ButtonMappingType::Passthrough, value) => value,
                     (
@@ -696,21 +1033,63 @@ impl ButtonMappingManager {
                         let value = (value - range.min) / (range.max - range.min);
                         ButtonValue::Scalar(value.clamp(0.0, 1.0))
                     }
+                    (
+                        ButtonMappingType::Toggle
+                        | ButtonMappingType::LongPress(_)
+                        | ButtonMappingType::DoubleTap(_)
+                        | ButtonMappingType::Turbo(_),
+                        ButtonValue::Binary(pressed),
+                    ) => {
+                        // Conditions are checked only on press, so that the destination is never
+                        // left pressed
+                        if pressed
+                            && !binary_conditions_met(
+                                &self.binary_source_states,
+                                &mapping.binary_conditions,
+                            )
+                        {
+                            continue;
+                        }
+
+                        let state = self
+                            .action_states
+                            .entry((source_button.path_id, mapping.destination))
+                            .or_default();
+                        if let Some(value) =
+                            state.source_changed(&mapping.mapping_type, pressed, now)
+                        {
+                            destination_buttons.push(ButtonEntry {
+                                path_id: mapping.destination,
+                                value: ButtonValue::Binary(value),
+                            });
+                        }
+
+                        continue;
+                    }
+                    (ButtonMappingType::Chord(_), ButtonValue::Binary(_)) => {
+                        // Handled below, together with the changes of the other chord buttons
+                        continue;
+                    }
+                    (ButtonMappingType::Axis(transform), ButtonValue::Scalar(value)) => {
+                        ButtonValue::Scalar(transform_axis(transform, value))
+                    }
+                    (
+                        ButtonMappingType::ThumbstickAxis(_)
+                        | ButtonMappingType::Dpad(_)
+                        | ButtonMappingType::SnapTurn(_),
+                        ButtonValue::Scalar(_),
+                    ) => {
+                        // Handled below, together with the changes of the paired axis
+                        continue;
+                    }
                     _ => {
                         error!("Failed to map button!");
                         continue;
                     }
                 };
 
-                for source_id in &mapping.binary_conditions {
-                    if !self
-                        .binary_source_states
-                        .get(source_id)
-                        .copied()
-                        .unwrap_or(false)
-                    {
-                        continue 'mapping;
-                    }
+                if !binary_conditions_met(&self.binary_source_states, &mapping.binary_conditions) {
+                    continue;
                 }
 
                 destination_buttons.push(ButtonEntry {
@@ -718,13 +1097,462 @@ impl ButtonMappingManager {
                     value: destination_value,
                 });
             }
-        } else {
+        } else if !self.is_secondary_source(source_button.path_id) {
             let button_name = BUTTON_INFO
                 .get(&source_button.path_id)
                 .map_or("Unknown", |info| info.path);
             info!("Received button not mapped: {button_name}");
         }
 
+        match source_button.value {
+            ButtonValue::Binary(_) => {
+                destination_buttons.append(&mut self.update_chords(source_button.path_id))
+            }
+            ButtonValue::Scalar(_) => {
+                destination_buttons.append(&mut self.update_thumbsticks(source_button.path_id, now))
+            }
+        }
+
+        self.record_destinations(destination_buttons)
+    }
+
+    // Advance time based mappings. Must be called periodically, at least when reaching
+    // next_deadline()
+    pub fn update_at(&mut self, now: Instant) -> Vec<ButtonEntry> {
+        let mut destination_buttons = vec![];
+
+        for (source_id, mappings) in &self.mappings {
+            for mapping in mappings {
+                if let Some(state) = self
+                    .action_states
+                    .get_mut(&(*source_id, mapping.destination))
+                    && let Some(value) = state.deadline_reached(&mapping.mapping_type, now)
+                {
+                    destination_buttons.push(ButtonEntry {
+                        path_id: mapping.destination,
+                        value: ButtonValue::Binary(value),
+                    });
+                }
+            }
+        }
+
+        self.record_destinations(destination_buttons)
+    }
+
+    // Chord buttons and paired axes don't need their own mapping
+    fn is_secondary_source(&self, button_id: u64) -> bool {
+        self.mappings.values().flatten().any(|mapping| {
+            mapping.chord_buttons.contains(&button_id) || mapping.paired_axis == Some(button_id)
+        })
+    }
+
+    // Reevaluate the thumbstick mappings that include the changed axis
+    fn update_thumbsticks(&mut self, changed_axis_id: u64, now: Instant) -> Vec<ButtonEntry> {
+        let mut destination_buttons = vec![];
+
+        for (source_id, mappings) in &self.mappings {
+            for mapping in mappings {
+                if *source_id != changed_axis_id && mapping.paired_axis != Some(changed_axis_id) {
+                    continue;
+                }
+
+                let axis_value = |id| self.scalar_source_states.get(&id).copied().unwrap_or(0.0);
+                let x = axis_value(*source_id);
+                let y = mapping.paired_axis.map(axis_value).unwrap_or(0.0);
+                let conditions_met =
+                    binary_conditions_met(&self.binary_source_states, &mapping.binary_conditions);
+
+                let value = match &mapping.mapping_type {
+                    ButtonMappingType::ThumbstickAxis(transform) => {
+                        if !conditions_met {
+                            continue;
+                        }
+
+                        ButtonValue::Scalar(transform_thumbstick_axis(transform, x, y))
+                    }
+                    ButtonMappingType::Dpad(config) => {
+                        let state = self
+                            .action_states
+                            .entry((*source_id, mapping.destination))
+                            .or_default();
+
+                        // As for the other stateful mappings, conditions are checked only on press
+                        let active =
+                            thumbstick_points_to(config, x, y) && (state.active || conditions_met);
+                        let Some(active) = state.set_active(active) else {
+                            continue;
+                        };
+
+                        ButtonValue::Binary(active)
+                    }
+                    ButtonMappingType::SnapTurn(config) => {
+                        let state = self
+                            .action_states
+                            .entry((*source_id, mapping.destination))
+                            .or_default();
+
+                        if !thumbstick_points_to(config, x, y) {
+                            state.latched = false;
+                            continue;
+                        } else if state.latched || !conditions_met {
+                            continue;
+                        }
+
+                        // The pulse is released by update()
+                        state.latched = true;
+                        state.deadline = Some(now + SNAP_TURN_PULSE_DURATION);
+                        let Some(active) = state.set_active(true) else {
+                            continue;
+                        };
+
+                        ButtonValue::Binary(active)
+                    }
+                    _ => continue,
+                };
+
+                destination_buttons.push(ButtonEntry {
+                    path_id: mapping.destination,
+                    value,
+                });
+            }
+        }
+
         destination_buttons
     }
+
+    // Reevaluate the chords that include the changed button, either as source or as chord button
+    fn update_chords(&mut self, changed_button_id: u64) -> Vec<ButtonEntry> {
+        let mut destination_buttons = vec![];
+
+        for (source_id, mappings) in &self.mappings {
+            for mapping in mappings {
+                if !matches!(mapping.mapping_type, ButtonMappingType::Chord(_))
+                    || (*source_id != changed_button_id
+                        && !mapping.chord_buttons.contains(&changed_button_id))
+                {
+                    continue;
+                }
+
+                let state = self
+                    .action_states
+                    .entry((*source_id, mapping.destination))
+                    .or_default();
+
+                let chord_held = binary_conditions_met(&self.binary_source_states, &[*source_id])
+                    && binary_conditions_met(&self.binary_source_states, &mapping.chord_buttons);
+                // As for the other stateful mappings, conditions are checked only on press
+                let active = chord_held
+                    && (state.active
+                        || binary_conditions_met(
+                            &self.binary_source_states,
+                            &mapping.binary_conditions,
+                        ));
+
+                if let Some(value) = state.set_active(active) {
+                    destination_buttons.push(ButtonEntry {
+                        path_id: mapping.destination,
+                        value: ButtonValue::Binary(value),
+                    });
+                }
+            }
+        }
+
+        destination_buttons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_session::CurvePoint;
+
+    const SOURCE: &str = "/user/hand/right/input/a/click";
+    const DESTINATION: &str = "/user/hand/right/input/b/click";
+    const CHORD_BUTTON: &str = "/user/hand/right/input/trigger/click";
+
+    fn manager(mapping_type: ButtonMappingType) -> ButtonMappingManager {
+        ButtonMappingManager::new_manual(&[(
+            SOURCE.into(),
+            vec![ButtonBindingTarget {
+                destination: DESTINATION.into(),
+                mapping_type,
+                binary_conditions: vec![],
+            }],
+        )])
+    }
+
+    fn press(
+        manager: &mut ButtonMappingManager,
+        button: &str,
+        pressed: bool,
+        now: Instant,
+    ) -> Vec<bool> {
+        let entry = ButtonEntry {
+            path_id: alvr_common::hash_string(button),
+            value: ButtonValue::Binary(pressed),
+        };
+
+        destination_values(manager.map_button_at(&entry, now))
+    }
+
+    fn destination_values(entries: Vec<ButtonEntry>) -> Vec<bool> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                assert_eq!(entry.path_id, alvr_common::hash_string(DESTINATION));
+                matches!(entry.value, ButtonValue::Binary(true))
+            })
+            .collect()
+    }
+
+    fn ms(start: Instant, value: u64) -> Instant {
+        start + Duration::from_millis(value)
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut manager = manager(ButtonMappingType::Toggle);
+        let now = Instant::now();
+
+        assert_eq!(press(&mut manager, SOURCE, true, now), [true]);
+        assert!(press(&mut manager, SOURCE, false, now).is_empty());
+        assert_eq!(press(&mut manager, SOURCE, true, now), [false]);
+        assert!(press(&mut manager, SOURCE, false, now).is_empty());
+    }
+
+    #[test]
+    fn test_long_press() {
+        let mut manager = manager(ButtonMappingType::LongPress(500));
+        let start = Instant::now();
+
+        // Released too early
+        assert!(press(&mut manager, SOURCE, true, start).is_empty());
+        assert!(press(&mut manager, SOURCE, false, ms(start, 200)).is_empty());
+        assert!(manager.next_deadline().is_none());
+
+        assert!(press(&mut manager, SOURCE, true, ms(start, 1000)).is_empty());
+        assert_eq!(manager.next_deadline(), Some(ms(start, 1500)));
+        assert!(destination_values(manager.update_at(ms(start, 1400))).is_empty());
+        assert_eq!(
+            destination_values(manager.update_at(ms(start, 1500))),
+            [true]
+        );
+        assert_eq!(press(&mut manager, SOURCE, false, ms(start, 2000)), [false]);
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let mut manager = manager(ButtonMappingType::DoubleTap(300));
+        let start = Instant::now();
+
+        assert!(press(&mut manager, SOURCE, true, start).is_empty());
+        assert!(press(&mut manager, SOURCE, false, ms(start, 100)).is_empty());
+        assert_eq!(press(&mut manager, SOURCE, true, ms(start, 200)), [true]);
+        assert_eq!(press(&mut manager, SOURCE, false, ms(start, 300)), [false]);
+
+        // Too slow
+        assert!(press(&mut manager, SOURCE, true, ms(start, 1000)).is_empty());
+        assert!(press(&mut manager, SOURCE, false, ms(start, 1100)).is_empty());
+        assert!(press(&mut manager, SOURCE, true, ms(start, 1500)).is_empty());
+    }
+
+    #[test]
+    fn test_turbo() {
+        let mut manager = manager(ButtonMappingType::Turbo(100));
+        let start = Instant::now();
+
+        assert_eq!(press(&mut manager, SOURCE, true, start), [true]);
+        assert_eq!(
+            destination_values(manager.update_at(ms(start, 100))),
+            [false]
+        );
+        assert_eq!(
+            destination_values(manager.update_at(ms(start, 200))),
+            [true]
+        );
+        assert_eq!(press(&mut manager, SOURCE, false, ms(start, 250)), [false]);
+        assert!(destination_values(manager.update_at(ms(start, 400))).is_empty());
+    }
+
+    #[test]
+    fn test_chord() {
+        let mut manager = manager(ButtonMappingType::Chord(vec![CHORD_BUTTON.into()]));
+        let now = Instant::now();
+
+        // Any order
+        assert!(press(&mut manager, CHORD_BUTTON, true, now).is_empty());
+        assert_eq!(press(&mut manager, SOURCE, true, now), [true]);
+        assert_eq!(press(&mut manager, CHORD_BUTTON, false, now), [false]);
+        assert_eq!(press(&mut manager, CHORD_BUTTON, true, now), [true]);
+        assert_eq!(press(&mut manager, SOURCE, false, now), [false]);
+    }
+
+    const X_AXIS: &str = "/user/hand/right/input/thumbstick/x";
+    const Y_AXIS: &str = "/user/hand/right/input/thumbstick/y";
+
+    fn thumbstick_manager(mapping_type: ButtonMappingType) -> ButtonMappingManager {
+        ButtonMappingManager::new_manual(&[(
+            X_AXIS.into(),
+            vec![ButtonBindingTarget {
+                destination: DESTINATION.into(),
+                mapping_type,
+                binary_conditions: vec![],
+            }],
+        )])
+    }
+
+    fn move_axis(
+        manager: &mut ButtonMappingManager,
+        axis: &str,
+        value: f32,
+        now: Instant,
+    ) -> Vec<ButtonValue> {
+        let entry = ButtonEntry {
+            path_id: alvr_common::hash_string(axis),
+            value: ButtonValue::Scalar(value),
+        };
+
+        manager
+            .map_button_at(&entry, now)
+            .into_iter()
+            .map(|entry| entry.value)
+            .collect()
+    }
+
+    fn scalar(values: &[ButtonValue]) -> f32 {
+        match values {
+            [ButtonValue::Scalar(value)] => *value,
+            _ => panic!("expected a single scalar"),
+        }
+    }
+
+    #[test]
+    fn test_axis_transform() {
+        let transform = |curve| AxisTransform {
+            deadzone: 0.2,
+            curve,
+            inverted: false,
+        };
+
+        let linear = transform(ResponseCurve::Linear);
+        assert_eq!(transform_axis(&linear, 0.1), 0.0);
+        assert!((transform_axis(&linear, 0.6) - 0.5).abs() < 1e-6);
+        assert!((transform_axis(&linear, -0.6) + 0.5).abs() < 1e-6);
+        assert_eq!(transform_axis(&linear, 1.0), 1.0);
+
+        let squared = transform(ResponseCurve::Exponent(2.0));
+        assert!((transform_axis(&squared, -0.6) + 0.25).abs() < 1e-6);
+
+        let piecewise = AxisTransform {
+            inverted: true,
+            ..transform(ResponseCurve::Piecewise(vec![CurvePoint {
+                input: 0.5,
+                output: 0.2,
+            }]))
+        };
+        assert!((transform_axis(&piecewise, 0.4) + 0.1).abs() < 1e-6);
+        assert!((transform_axis(&piecewise, 0.8) + 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_thumbstick_radial_deadzone() {
+        let mut manager =
+            thumbstick_manager(ButtonMappingType::ThumbstickAxis(ThumbstickAxisTransform {
+                paired_axis: Y_AXIS.into(),
+                radial_deadzone: 0.5,
+                transform: AxisTransform {
+                    deadzone: 0.0,
+                    curve: ResponseCurve::Linear,
+                    inverted: false,
+                },
+            }));
+        let now = Instant::now();
+
+        assert_eq!(scalar(&move_axis(&mut manager, X_AXIS, 0.3, now)), 0.0);
+        // Moving the other axis pushes the thumbstick out of the deadzone
+        assert_eq!(scalar(&move_axis(&mut manager, Y_AXIS, 0.3, now)), 0.0);
+        let value = scalar(&move_axis(&mut manager, Y_AXIS, 0.8, now));
+        let distance = Vec2::new(0.3, 0.8).length();
+        assert!((value - 0.3 * (distance - 0.5) / 0.5 / distance).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dpad() {
+        let mut manager = thumbstick_manager(ButtonMappingType::Dpad(ThumbstickDirectionConfig {
+            y_axis: Y_AXIS.into(),
+            direction: ThumbstickDirection::Up,
+            threshold: 0.5,
+        }));
+        let now = Instant::now();
+
+        assert!(move_axis(&mut manager, Y_AXIS, 0.4, now).is_empty());
+        assert!(matches!(
+            move_axis(&mut manager, Y_AXIS, 0.7, now)[..],
+            [ButtonValue::Binary(true)]
+        ));
+        // Now pointing right
+        assert!(matches!(
+            move_axis(&mut manager, X_AXIS, 0.9, now)[..],
+            [ButtonValue::Binary(false)]
+        ));
+    }
+
+    #[test]
+    fn test_snap_turn() {
+        let mut manager =
+            thumbstick_manager(ButtonMappingType::SnapTurn(ThumbstickDirectionConfig {
+                y_axis: Y_AXIS.into(),
+                direction: ThumbstickDirection::Right,
+                threshold: 0.7,
+            }));
+        let start = Instant::now();
+
+        assert!(matches!(
+            move_axis(&mut manager, X_AXIS, 0.9, start)[..],
+            [ButtonValue::Binary(true)]
+        ));
+        // Holding the thumbstick doesn't repeat the turn
+        assert!(move_axis(&mut manager, X_AXIS, 1.0, ms(start, 10)).is_empty());
+        assert!(matches!(
+            manager.update_at(ms(start, 50))[..],
+            [ButtonEntry {
+                value: ButtonValue::Binary(false),
+                ..
+            }]
+        ));
+        assert!(move_axis(&mut manager, X_AXIS, 1.0, ms(start, 100)).is_empty());
+
+        assert!(move_axis(&mut manager, X_AXIS, 0.0, ms(start, 200)).is_empty());
+        assert!(matches!(
+            move_axis(&mut manager, X_AXIS, 0.8, ms(start, 300))[..],
+            [ButtonValue::Binary(true)]
+        ));
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut manager = manager(ButtonMappingType::Toggle);
+        let now = Instant::now();
+
+        assert_eq!(press(&mut manager, SOURCE, true, now), [true]);
+        assert_eq!(destination_values(manager.release_all()), [false]);
+        // Released destinations are not released again
+        assert!(manager.release_all().is_empty());
+    }
+
+    #[test]
+    fn test_input_profile_selection_revision() {
+        let mut selection = InputProfileSelection::default();
+
+        selection.set_foreground_application(Some("vrchat.exe".into()));
+        assert_eq!(selection.revision(), 1);
+        // Unchanged values don't invalidate the active profile
+        selection.set_foreground_application(Some("vrchat.exe".into()));
+        assert_eq!(selection.revision(), 1);
+
+        selection.select_profile(Some("Profile".into()));
+        assert_eq!(selection.revision(), 2);
+        selection.select_profile(None);
+        assert_eq!(selection.revision(), 3);
+    }
 }
@@ -1,13 +1,23 @@
 mod bitrate;
 mod c_api;
+mod congestion_control;
 mod connection;
+mod hand_gesture_evaluation;
 mod hand_gestures;
 mod haptics;
 mod input_mapping;
 mod logging_backend;
+mod metrics;
+mod recording;
+mod reference_frames;
+mod session_recording;
 mod sockets;
+mod spectators;
 mod statistics;
+mod statistics_timeline;
 mod tracking;
+mod video_mirror;
+mod web_api;
 mod web_server;
 
 pub use c_api::*;
@@ -32,14 +42,19 @@ use alvr_packets::{
 use alvr_server_io::ServerSessionManager;
 use alvr_session::{CodecType, H264Profile, OpenvrProperty, Settings, SteamvrHmdInitConfig};
 use alvr_sockets::StreamSender;
+use axum::body::Bytes;
 use bitrate::{BitrateManager, DynamicEncoderParams};
+use input_mapping::InputProfileSelection;
+use metrics::{FrameDropReason, METRICS};
+use recording::VideoRecorder;
+use reference_frames::ReferenceFrameTracker;
+use session_recording::SessionRecorder;
+use spectators::{SecondaryIdrRequests, SharedStreamConfig, Spectator};
 use statistics::StatisticsManager;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     env,
     ffi::OsStr,
-    fs::File,
-    io::Write,
     sync::{
         Arc, LazyLock, OnceLock,
         atomic::{AtomicBool, Ordering},
@@ -70,6 +85,7 @@ pub fn initialize_environment(layout: afs::Layout) {
     SESSION_MANAGER.write().session_mut();
 }
 
+#[derive(Clone)]
 pub struct ServerNegotiatedStreamingConfig {
     pub transcoding_view_resolution: UVec2,
     pub emulated_headset_view_resolution: UVec2,
@@ -97,6 +113,11 @@ pub enum ServerCoreEvent {
     },
     Buttons(Vec<ButtonEntry>), // Note: this is after mapping
     RequestIDR,
+    // The encoder should stop referencing the frames sent after this timestamp. If it succeeds, it
+    // must call report_recovery_frame() for the first frame encoded without them
+    InvalidateReferenceFrames {
+        last_valid_frame_timestamp: Duration,
+    },
     CaptureFrame,
     GameRenderLatencyFeedback(Duration), // only used for SteamVR
     ShutdownPending,
@@ -109,45 +130,90 @@ pub struct ConnectionContext {
     statistics_manager: RwLock<Option<StatisticsManager>>,
     bitrate_manager: Mutex<BitrateManager>,
     tracking_manager: RwLock<TrackingManager>,
+    input_profile_selection: RwLock<InputProfileSelection>,
     decoder_config: Mutex<Option<DecoderInitializationConfig>>,
-    video_mirror_sender: Mutex<Option<broadcast::Sender<Vec<u8>>>>,
-    video_recording_file: Mutex<Option<File>>,
+    reference_frame_tracker: Mutex<ReferenceFrameTracker>,
+    video_mirror_sender: Mutex<Option<broadcast::Sender<Bytes>>>,
+    video_recorder: Mutex<Option<VideoRecorder>>,
+    session_recorder: Mutex<Option<SessionRecorder>>,
     connection_threads: Mutex<Vec<JoinHandle<()>>>,
     clients_to_be_removed: Mutex<HashSet<String>>,
     video_channel_sender: Mutex<Option<SyncSender<VideoPacket>>>,
     haptics_sender: Mutex<Option<StreamSender<Haptics>>>,
+    main_stream_config: Mutex<Option<SharedStreamConfig>>,
+    spectators: Mutex<HashMap<String, Spectator>>,
+    secondary_idr_requests: Mutex<SecondaryIdrRequests>,
 }
 
-pub fn create_recording_file(connection_context: &ConnectionContext, settings: &Settings) {
-    let codec = settings.video.preferred_codec;
-    let ext = match codec {
-        CodecType::H264 => "h264",
-        CodecType::Hevc => "h265",
-        CodecType::AV1 => "av1",
-    };
+impl ConnectionContext {
+    fn new(events_sender: mpsc::Sender<ServerCoreEvent>, initial_settings: &Settings) -> Self {
+        // Create a temporary StatisticsManager until a headset connects
+        let stats = StatisticsManager::new(
+            initial_settings.connection.statistics_history_size,
+            Duration::from_secs_f32(1.0 / 90.0),
+            if let Switch::Enabled(config) = &initial_settings.headset.controllers {
+                config.steamvr_pipeline_frames
+            } else {
+                0.0
+            },
+            None,
+        );
 
+        Self {
+            events_sender,
+            statistics_manager: RwLock::new(Some(stats)),
+            bitrate_manager: Mutex::new(BitrateManager::new(256, 60.0)),
+            tracking_manager: RwLock::new(TrackingManager::new(
+                initial_settings.connection.statistics_history_size,
+            )),
+            input_profile_selection: RwLock::new(InputProfileSelection::default()),
+            decoder_config: Mutex::new(None),
+            reference_frame_tracker: Mutex::new(ReferenceFrameTracker::default()),
+            video_mirror_sender: Mutex::new(None),
+            video_recorder: Mutex::new(None),
+            session_recorder: Mutex::new(None),
+            connection_threads: Mutex::new(Vec::new()),
+            clients_to_be_removed: Mutex::new(HashSet::new()),
+            video_channel_sender: Mutex::new(None),
+            haptics_sender: Mutex::new(None),
+            main_stream_config: Mutex::new(None),
+            spectators: Mutex::new(HashMap::new()),
+            secondary_idr_requests: Mutex::new(SecondaryIdrRequests::default()),
+        }
+    }
+}
+
+pub fn create_recording_file(connection_context: &ConnectionContext, settings: &Settings) {
     let path = FILESYSTEM_LAYOUT.get().unwrap().log_dir.join(format!(
-        "recording.{}.{ext}",
+        "recording.{}.mkv",
         chrono::Local::now().format("%F.%H-%M-%S")
     ));
 
-    match File::create(path) {
-        Ok(mut file) => {
-            if let Some(config) = &*connection_context.decoder_config.lock() {
-                file.write_all(&config.config_buffer).ok();
-            }
-
-            *connection_context.video_recording_file.lock() = Some(file);
-
+    let game_audio_sample_rate = settings
+        .audio
+        .game_audio
+        .enabled()
+        .then(|| {
             connection_context
-                .events_sender
-                .send(ServerCoreEvent::RequestIDR)
-                .ok();
-        }
-        Err(e) => {
-            error!("Failed to record video on disk: {e}");
-        }
-    }
+                .main_stream_config
+                .lock()
+                .as_ref()
+                .map(|config| config.game_audio_sample_rate)
+        })
+        .flatten();
+
+    // The file is created when the first IDR frame is received
+    *connection_context.video_recorder.lock() = Some(VideoRecorder::new(
+        path,
+        connection_context.decoder_config.lock().clone(),
+        game_audio_sample_rate,
+        settings.extra.capture.record_view_params,
+    ));
+
+    connection_context
+        .events_sender
+        .send(ServerCoreEvent::RequestIDR)
+        .ok();
 }
 
 pub fn notify_restart_driver() {
@@ -208,33 +274,8 @@ impl ServerCoreContext {
 
         let (events_sender, events_receiver) = mpsc::channel();
 
-        // Create a temporary StatisticsManager until a headset connects
         let initial_settings = SESSION_MANAGER.read().settings().clone();
-        let stats = StatisticsManager::new(
-            initial_settings.connection.statistics_history_size,
-            Duration::from_secs_f32(1.0 / 90.0),
-            if let Switch::Enabled(config) = &initial_settings.headset.controllers {
-                config.steamvr_pipeline_frames
-            } else {
-                0.0
-            },
-        );
-
-        let connection_context = Arc::new(ConnectionContext {
-            events_sender,
-            statistics_manager: RwLock::new(Some(stats)),
-            bitrate_manager: Mutex::new(BitrateManager::new(256, 60.0)),
-            tracking_manager: RwLock::new(TrackingManager::new(
-                initial_settings.connection.statistics_history_size,
-            )),
-            decoder_config: Mutex::new(None),
-            video_mirror_sender: Mutex::new(None),
-            video_recording_file: Mutex::new(None),
-            connection_threads: Mutex::new(Vec::new()),
-            clients_to_be_removed: Mutex::new(HashSet::new()),
-            video_channel_sender: Mutex::new(None),
-            haptics_sender: Mutex::new(None),
-        });
+        let connection_context = Arc::new(ConnectionContext::new(events_sender, &initial_settings));
 
         let webserver_runtime = Runtime::new().unwrap();
         webserver_runtime.spawn({
@@ -290,7 +331,6 @@ impl ServerCoreContext {
             .tracking_manager
             .read()
             .get_hand_skeleton(hand_type, timestamp)
-            .copied()
     }
 
     pub fn get_motion_to_photon_latency(&self) -> Duration {
@@ -327,6 +367,17 @@ impl ServerCoreContext {
             .unwrap_or_default()
     }
 
+    // Used to switch the input mapping profile. The application is identified by its process name.
+    // SteamVR app keys are not supported, drivers cannot query them from the process ID.
+    pub fn set_foreground_application(&self, application: Option<String>) {
+        dbg_server_core!("set_foreground_application: {application:?}");
+
+        self.connection_context
+            .input_profile_selection
+            .write()
+            .set_foreground_application(application);
+    }
+
     pub fn send_haptics(&self, haptics: Haptics) {
         dbg_server_core!("send_haptics");
 
@@ -366,19 +417,17 @@ impl ServerCoreContext {
     pub fn set_video_config_nals(&self, config_buffer: Vec<u8>, codec: CodecType) {
         dbg_server_core!("set_video_config_nals");
 
-        if let Some(sender) = &*self.connection_context.video_mirror_sender.lock() {
-            sender.send(config_buffer.clone()).ok();
-        }
-
-        if let Some(file) = &mut *self.connection_context.video_recording_file.lock() {
-            file.write_all(&config_buffer).ok();
-        }
-
-        *self.connection_context.decoder_config.lock() = Some(DecoderInitializationConfig {
+        let config = DecoderInitializationConfig {
             codec,
             config_buffer,
             ext_str: String::new(),
-        });
+        };
+
+        if let Some(recorder) = &mut *self.connection_context.video_recorder.lock() {
+            recorder.set_decoder_config(config.clone());
+        }
+
+        *self.connection_context.decoder_config.lock() = Some(config);
     }
 
     pub fn send_video_nal(
@@ -432,31 +481,91 @@ impl ServerCoreContext {
                     .connection
                     .avoid_video_glitching
             {
-                if let Some(sender) = &*self.connection_context.video_mirror_sender.lock() {
-                    sender.send(nal_buffer.clone()).ok();
+                video_mirror::publish_frame(
+                    &self.connection_context,
+                    timestamp,
+                    is_idr,
+                    &nal_buffer,
+                );
+
+                let mut reference_frame_tracker =
+                    self.connection_context.reference_frame_tracker.lock();
+
+                let header = VideoPacketHeader {
+                    timestamp,
+                    global_view_params,
+                    is_idr,
+                    recovery_reference: reference_frame_tracker
+                        .take_recovery_reference(timestamp, is_idr),
+                };
+
+                if let Some(recorder) = &mut *self.connection_context.video_recorder.lock() {
+                    recorder.write_video(&header, &nal_buffer);
+                }
+
+                let request_deferred_idr = {
+                    let mut secondary_idr_requests =
+                        self.connection_context.secondary_idr_requests.lock();
+                    if is_idr {
+                        secondary_idr_requests.report_idr();
+                    }
+
+                    secondary_idr_requests.poll()
+                };
+                if request_deferred_idr {
+                    self.connection_context
+                        .events_sender
+                        .send(ServerCoreEvent::RequestIDR)
+                        .ok();
                 }
 
-                if let Some(file) = &mut *self.connection_context.video_recording_file.lock() {
-                    file.write_all(&nal_buffer).ok();
+                for spectator in self.connection_context.spectators.lock().values_mut() {
+                    if spectator.send_video(VideoPacket {
+                        header: header.clone(),
+                        payload: nal_buffer.clone(),
+                    }) {
+                        // The spectator needs a new IDR to recover
+                        spectators::request_secondary_idr(&self.connection_context);
+                    }
                 }
 
                 let sender_result = sender.try_send(VideoPacket {
-                    header: VideoPacketHeader {
-                        timestamp,
-                        global_view_params,
-                        is_idr,
-                    },
+                    header,
                     payload: nal_buffer,
                 });
                 if matches!(sender_result, Err(TrySendError::Full(_))) {
-                    STREAM_CORRUPTED.store(true, Ordering::SeqCst);
-                    self.connection_context
-                        .events_sender
-                        .send(ServerCoreEvent::RequestIDR)
-                        .ok();
+                    let settings = SESSION_MANAGER.read().settings().clone();
+                    let last_valid_frame_timestamp =
+                        (settings.video.encoder_config.reference_frame_invalidation
+                            && !settings.connection.avoid_video_glitching)
+                            .then(|| reference_frame_tracker.report_frame_dropped())
+                            .flatten();
+
+                    if let Some(last_valid_frame_timestamp) = last_valid_frame_timestamp {
+                        self.connection_context
+                            .events_sender
+                            .send(ServerCoreEvent::InvalidateReferenceFrames {
+                                last_valid_frame_timestamp,
+                            })
+                            .ok();
+                    } else {
+                        STREAM_CORRUPTED.store(true, Ordering::SeqCst);
+                        self.connection_context
+                            .events_sender
+                            .send(ServerCoreEvent::RequestIDR)
+                            .ok();
+                    }
+                    METRICS
+                        .lock()
+                        .report_frame_dropped(FrameDropReason::NetworkQueueFull);
                     warn!("Dropping video packet. Reason: Can't push to network");
+                } else {
+                    reference_frame_tracker.report_frame_sent(timestamp, is_idr);
                 }
             } else {
+                METRICS
+                    .lock()
+                    .report_frame_dropped(FrameDropReason::WaitingForIdr);
                 warn!("Dropping video packet. Reason: Waiting for IDR frame");
             }
 
@@ -490,6 +599,21 @@ impl ServerCoreContext {
         })
     }
 
+    // Called by the encoder for the first frame that doesn't reference the frames invalidated after
+    // an InvalidateReferenceFrames event, before sending it
+    pub fn report_recovery_frame(
+        &self,
+        target_timestamp: Duration,
+        last_valid_frame_timestamp: Duration,
+    ) {
+        dbg_server_core!("report_recovery_frame");
+
+        self.connection_context
+            .reference_frame_tracker
+            .lock()
+            .report_recovery_frame(target_timestamp, last_valid_frame_timestamp);
+    }
+
     pub fn report_composed(&self, target_timestamp: Duration, offset: Duration) {
         dbg_server_core!("report_composed");
 
@@ -0,0 +1,360 @@
+// Streaming metrics in the Prometheus text format, served by the web server at /metrics. Counters
+// and histograms are accumulated for the whole lifetime of the server, gauges show the latest
+// statistics of the main client.
+
+use alvr_common::parking_lot::Mutex;
+use alvr_events::{GraphStatistics, StatisticsSnapshot};
+use alvr_sockets::SocketStatistics;
+use std::{fmt::Write, sync::LazyLock};
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(|| Mutex::new(Metrics::new()));
+
+// In seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.002, 0.005, 0.01, 0.015, 0.02, 0.03, 0.05, 0.075, 0.1, 0.2, 0.5, 1.0,
+];
+
+#[derive(Clone, Copy)]
+pub enum FrameDropReason {
+    NetworkQueueFull,
+    WaitingForIdr,
+}
+
+struct Histogram {
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            bucket_counts: vec![0; LATENCY_BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&mut self.bucket_counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+// Pipeline stages, as reported by the graph statistics
+const STAGES: &[&str] = &[
+    "total",
+    "game",
+    "server_compositor",
+    "encoder",
+    "network",
+    "uplink",
+    "downlink",
+    "decoder",
+    "decoder_queue",
+    "client_compositor",
+    "vsync_queue",
+];
+
+pub struct Metrics {
+    stage_latencies: Vec<Histogram>, // same order as STAGES
+    idr_requests: u64,
+    client_frame_losses: u64,
+    network_queue_dropped_frames: u64,
+    waiting_for_idr_dropped_frames: u64,
+    spectator_dropped_frames: u64,
+    nacked_shards: u64,
+    retransmitted_shards: u64,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            stage_latencies: STAGES.iter().map(|_| Histogram::new()).collect(),
+            idr_requests: 0,
+            client_frame_losses: 0,
+            network_queue_dropped_frames: 0,
+            waiting_for_idr_dropped_frames: 0,
+            spectator_dropped_frames: 0,
+            nacked_shards: 0,
+            retransmitted_shards: 0,
+        }
+    }
+
+    pub fn report_latencies(&mut self, graph: &GraphStatistics) {
+        let latencies = [
+            Some(graph.total_pipeline_latency_s),
+            Some(graph.game_time_s),
+            Some(graph.server_compositor_s),
+            Some(graph.encoder_s),
+            Some(graph.network_s),
+            graph.uplink_s,
+            graph.downlink_s,
+            Some(graph.decoder_s),
+            Some(graph.decoder_queue_s),
+            Some(graph.client_compositor_s),
+            Some(graph.vsync_queue_s),
+        ];
+
+        for (histogram, latency) in self.stage_latencies.iter_mut().zip(latencies) {
+            if let Some(latency) = latency {
+                histogram.observe(latency as f64);
+            }
+        }
+    }
+
+    pub fn report_idr_request(&mut self) {
+        self.idr_requests += 1;
+    }
+
+    pub fn report_client_frame_loss(&mut self) {
+        self.client_frame_losses += 1;
+    }
+
+    pub fn report_frame_dropped(&mut self, reason: FrameDropReason) {
+        match reason {
+            FrameDropReason::NetworkQueueFull => self.network_queue_dropped_frames += 1,
+            FrameDropReason::WaitingForIdr => self.waiting_for_idr_dropped_frames += 1,
+        }
+    }
+
+    pub fn report_spectator_frame_dropped(&mut self) {
+        self.spectator_dropped_frames += 1;
+    }
+
+    pub fn report_socket_statistics(&mut self, statistics: SocketStatistics) {
+        self.nacked_shards += statistics.nacked_shards as u64;
+        self.retransmitted_shards += statistics.retransmitted_shards as u64;
+    }
+
+    pub fn encode(&self, snapshot: &StatisticsSnapshot) -> String {
+        let mut encoder = Encoder(String::new());
+
+        encoder.header(
+            "alvr_stage_latency_seconds",
+            "histogram",
+            "Latency of each stage of the video pipeline",
+        );
+        for (stage, histogram) in STAGES.iter().zip(&self.stage_latencies) {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.bucket_counts) {
+                encoder.sample(
+                    "alvr_stage_latency_seconds_bucket",
+                    &format!("stage=\"{stage}\",le=\"{bound}\""),
+                    *count as f64,
+                );
+            }
+            encoder.sample(
+                "alvr_stage_latency_seconds_bucket",
+                &format!("stage=\"{stage}\",le=\"+Inf\""),
+                histogram.count as f64,
+            );
+            encoder.sample(
+                "alvr_stage_latency_seconds_sum",
+                &format!("stage=\"{stage}\""),
+                histogram.sum,
+            );
+            encoder.sample(
+                "alvr_stage_latency_seconds_count",
+                &format!("stage=\"{stage}\""),
+                histogram.count as f64,
+            );
+        }
+
+        encoder.counter(
+            "alvr_idr_requests_total",
+            "IDR frames requested to the encoder",
+            self.idr_requests,
+        );
+        encoder.counter(
+            "alvr_client_frame_losses_total",
+            "Video frame losses reported by the main client",
+            self.client_frame_losses,
+        );
+
+        encoder.header(
+            "alvr_dropped_frames_total",
+            "counter",
+            "Encoded frames not sent to the main client",
+        );
+        encoder.sample(
+            "alvr_dropped_frames_total",
+            "reason=\"network_queue_full\"",
+            self.network_queue_dropped_frames as f64,
+        );
+        encoder.sample(
+            "alvr_dropped_frames_total",
+            "reason=\"waiting_for_idr\"",
+            self.waiting_for_idr_dropped_frames as f64,
+        );
+
+        encoder.counter(
+            "alvr_spectator_dropped_frames_total",
+            "Encoded frames not sent to spectators",
+            self.spectator_dropped_frames,
+        );
+
+        encoder.counter(
+            "alvr_nacked_shards_total",
+            "Shards reported as lost by the main client",
+            self.nacked_shards,
+        );
+        encoder.counter(
+            "alvr_retransmitted_shards_total",
+            "Shards sent again to the main client",
+            self.retransmitted_shards,
+        );
+
+        encoder.gauge(
+            "alvr_streaming",
+            "Whether the main client is streaming",
+            Some(if snapshot.summary.is_some() { 1.0 } else { 0.0 }),
+        );
+
+        let summary = snapshot.summary.as_ref();
+        encoder.gauge(
+            "alvr_client_fps",
+            "Frame rate of the main client",
+            summary.map(|s| s.client_fps as f64),
+        );
+        encoder.gauge(
+            "alvr_server_fps",
+            "Frame rate of the server",
+            summary.map(|s| s.server_fps as f64),
+        );
+        encoder.gauge(
+            "alvr_video_bitrate_bits_per_second",
+            "Bitrate of the video sent to the main client",
+            summary.map(|s| s.video_mbits_per_sec as f64 * 1e6),
+        );
+        encoder.gauge(
+            "alvr_video_packets_per_second",
+            "Video packets sent to the main client per second",
+            summary.map(|s| s.video_packets_per_sec as f64),
+        );
+        encoder.gauge(
+            "alvr_fec_recovered_shards_per_second",
+            "Video shards rebuilt by the main client with forward error correction",
+            summary.map(|s| s.fec_recovered_shards_per_sec as f64),
+        );
+        encoder.gauge(
+            "alvr_clock_offset_seconds",
+            "Offset of the client clock relative to the server clock",
+            summary
+                .and_then(|s| s.clock_offset_ms)
+                .map(|ms| ms as f64 / 1e3),
+        );
+        encoder.gauge(
+            "alvr_hmd_battery_ratio",
+            "Battery level of the headset",
+            summary.map(|s| s.battery_hmd as f64 / 100.0),
+        );
+        encoder.gauge(
+            "alvr_hmd_plugged",
+            "Whether the headset is charging",
+            summary.map(|s| if s.hmd_plugged { 1.0 } else { 0.0 }),
+        );
+
+        let graph = snapshot.graph.as_ref();
+        encoder.gauge(
+            "alvr_requested_bitrate_bits_per_second",
+            "Bitrate requested to the encoder",
+            graph.map(|g| g.bitrate_directives.requested_bitrate_bps as f64),
+        );
+        encoder.gauge(
+            "alvr_throughput_bits_per_second",
+            "Network throughput measured for the last frame",
+            graph.map(|g| g.throughput_bps as f64),
+        );
+
+        encoder.0
+    }
+}
+
+struct Encoder(String);
+
+impl Encoder {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.0, "# HELP {name} {help}").ok();
+        writeln!(self.0, "# TYPE {name} {kind}").ok();
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: f64) {
+        if labels.is_empty() {
+            writeln!(self.0, "{name} {value}").ok();
+        } else {
+            writeln!(self.0, "{name}{{{labels}}} {value}").ok();
+        }
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, "counter", help);
+        self.sample(name, "", value as f64);
+    }
+
+    // Gauges without a value are omitted
+    fn gauge(&mut self, name: &str, help: &str, value: Option<f64>) {
+        if let Some(value) = value {
+            self.header(name, "gauge", help);
+            self.sample(name, "", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut metrics = Metrics::new();
+        metrics.report_idr_request();
+        metrics.report_frame_dropped(FrameDropReason::WaitingForIdr);
+        metrics.report_socket_statistics(SocketStatistics {
+            nacked_shards: 3,
+            retransmitted_shards: 2,
+        });
+        metrics.report_socket_statistics(SocketStatistics {
+            nacked_shards: 1,
+            retransmitted_shards: 1,
+        });
+
+        let text = metrics.encode(&StatisticsSnapshot::default());
+        let lines = text.lines().collect::<Vec<_>>();
+
+        for expected_line in [
+            "# HELP alvr_idr_requests_total IDR frames requested to the encoder",
+            "# TYPE alvr_idr_requests_total counter",
+            "alvr_idr_requests_total 1",
+            "alvr_dropped_frames_total{reason=\"network_queue_full\"} 0",
+            "alvr_dropped_frames_total{reason=\"waiting_for_idr\"} 1",
+            "# TYPE alvr_nacked_shards_total counter",
+            "alvr_nacked_shards_total 4",
+            "alvr_retransmitted_shards_total 3",
+            "alvr_stage_latency_seconds_bucket{stage=\"total\",le=\"+Inf\"} 0",
+            "alvr_streaming 0",
+        ] {
+            assert!(lines.contains(&expected_line), "{expected_line}");
+        }
+
+        // Gauges without a value are omitted
+        assert!(!text.contains("alvr_client_fps"));
+
+        // Every sample belongs to a declared metric
+        for line in lines.iter().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let family = ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|suffix| name.strip_suffix(suffix))
+                .filter(|family| text.contains(&format!("# TYPE {family} histogram")))
+                .unwrap_or(name);
+            assert!(text.contains(&format!("# TYPE {family} ")), "{line}");
+        }
+    }
+}
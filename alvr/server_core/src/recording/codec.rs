@@ -0,0 +1,897 @@
+// Conversion of the encoder output to the Matroska codec mappings. H264 and HEVC are converted
+// from Annex B to length prefixed NALs, with the parameter sets stored in the codec private data.
+// AV1 temporal units are stored without temporal delimiters, with the sequence header stored in
+// the codec private data.
+
+use alvr_common::anyhow::{Result, anyhow, bail};
+use alvr_session::CodecType;
+
+const H264_NAL_TYPE_SPS: u8 = 7;
+const H264_NAL_TYPE_PPS: u8 = 8;
+const HEVC_NAL_TYPE_VPS: u8 = 32;
+const HEVC_NAL_TYPE_SPS: u8 = 33;
+const HEVC_NAL_TYPE_PPS: u8 = 34;
+const AV1_OBU_SEQUENCE_HEADER: u8 = 1;
+const AV1_OBU_TEMPORAL_DELIMITER: u8 = 2;
+
+pub struct VideoTrackConfig {
+    pub codec_id: &'static str,
+    pub codec_private: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or_else(|| anyhow!("Truncated bitstream"))?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+
+        Ok(bit as u32)
+    }
+
+    fn flag(&mut self) -> Result<bool> {
+        Ok(self.bit()? == 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0_u64;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()? as u64;
+        }
+
+        Ok(value as u32)
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    // Exp-Golomb code
+    fn ue(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                bail!("Invalid Exp-Golomb code");
+            }
+        }
+
+        Ok(((1_u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64) as u32)
+    }
+
+    fn se(&mut self) -> Result<i32> {
+        let value = self.ue()? as i64;
+
+        Ok(if value % 2 == 1 {
+            ((value + 1) / 2) as i32
+        } else {
+            -(value / 2) as i32
+        })
+    }
+
+    // AV1 variable length code
+    fn uvlc(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros >= 32 {
+                return Ok(u32::MAX);
+            }
+        }
+
+        Ok(((1_u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64) as u32)
+    }
+}
+
+fn annex_b_nals(buffer: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut nal_start = None;
+
+    let mut idx = 0;
+    while idx + 2 < buffer.len() {
+        if buffer[idx] == 0 && buffer[idx + 1] == 0 && buffer[idx + 2] == 1 {
+            if let Some(start) = nal_start {
+                nals.push(&buffer[start..idx]);
+            }
+            idx += 3;
+            nal_start = Some(idx);
+        } else {
+            idx += 1;
+        }
+    }
+    if let Some(start) = nal_start {
+        nals.push(&buffer[start..]);
+    }
+
+    // The leading zero of 4 byte start codes and the trailing zeros are not part of the NALs
+    nals.into_iter()
+        .map(|nal| {
+            let end = nal.iter().rposition(|b| *b != 0).map_or(0, |pos| pos + 1);
+            &nal[..end]
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+// Removes the emulation prevention bytes
+fn nal_to_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zero_count = 0;
+    for &byte in nal {
+        if zero_count >= 2 && byte == 3 {
+            zero_count = 0;
+            continue;
+        }
+
+        zero_count = if byte == 0 { zero_count + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+fn h264_nal_type(nal: &[u8]) -> u8 {
+    nal[0] & 0x1f
+}
+
+fn hevc_nal_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3f
+}
+
+struct H264SpsInfo {
+    chroma_format_idc: u32,
+    bit_depth_luma_minus8: u32,
+    bit_depth_chroma_minus8: u32,
+    width: u32,
+    height: u32,
+}
+
+fn parse_h264_sps(sps: &[u8]) -> Result<H264SpsInfo> {
+    let rbsp = nal_to_rbsp(sps);
+    let mut reader = BitReader::new(rbsp.get(1..).unwrap_or_default());
+
+    let profile_idc = reader.bits(8)?;
+    reader.skip(16); // constraint flags and level
+    reader.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    let mut bit_depth_luma_minus8 = 0;
+    let mut bit_depth_chroma_minus8 = 0;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = reader.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = reader.flag()?;
+        }
+        bit_depth_luma_minus8 = reader.ue()?;
+        bit_depth_chroma_minus8 = reader.ue()?;
+        reader.skip(1); // qpprime_y_zero_transform_bypass_flag
+
+        if reader.flag()? {
+            let list_count = if chroma_format_idc != 3 { 8 } else { 12 };
+            for list_idx in 0..list_count {
+                if reader.flag()? {
+                    let size = if list_idx < 6 { 16 } else { 64 };
+                    let mut last_scale = 8;
+                    let mut next_scale = 8;
+                    for _ in 0..size {
+                        if next_scale != 0 {
+                            next_scale = (last_scale + reader.se()? + 256) % 256;
+                        }
+                        if next_scale != 0 {
+                            last_scale = next_scale;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    reader.ue()?; // log2_max_frame_num_minus4
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.skip(1); // delta_pic_order_always_zero_flag
+            reader.se()?; // offset_for_non_ref_pic
+            reader.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..reader.ue()? {
+                reader.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => (),
+    }
+    reader.ue()?; // max_num_ref_frames
+    reader.skip(1); // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = reader.ue()? + 1;
+    let height_in_map_units = reader.ue()? + 1;
+    let frame_mbs_only = reader.flag()?;
+    if !frame_mbs_only {
+        reader.skip(1); // mb_adaptive_frame_field_flag
+    }
+    reader.skip(1); // direct_8x8_inference_flag
+
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let mut width = width_in_mbs * 16;
+    let mut height = height_in_map_units * 16 * field_factor;
+
+    if reader.flag()? {
+        let left = reader.ue()?;
+        let right = reader.ue()?;
+        let top = reader.ue()?;
+        let bottom = reader.ue()?;
+
+        let (crop_unit_x, crop_unit_y) = if separate_colour_plane || chroma_format_idc == 0 {
+            (1, field_factor)
+        } else {
+            let sub_width = if chroma_format_idc == 3 { 1 } else { 2 };
+            let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+            (sub_width, sub_height * field_factor)
+        };
+
+        width = width.saturating_sub((left + right) * crop_unit_x);
+        height = height.saturating_sub((top + bottom) * crop_unit_y);
+    }
+
+    Ok(H264SpsInfo {
+        chroma_format_idc,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+        width,
+        height,
+    })
+}
+
+// AVCDecoderConfigurationRecord, ISO/IEC 14496-15
+fn h264_track_config(buffer: &[u8]) -> Result<VideoTrackConfig> {
+    let nals = annex_b_nals(buffer);
+    let sps_list = nals
+        .iter()
+        .filter(|nal| h264_nal_type(nal) == H264_NAL_TYPE_SPS)
+        .collect::<Vec<_>>();
+    let pps_list = nals
+        .iter()
+        .filter(|nal| h264_nal_type(nal) == H264_NAL_TYPE_PPS)
+        .collect::<Vec<_>>();
+
+    let Some(sps) = sps_list.first().filter(|sps| sps.len() >= 4) else {
+        bail!("Missing H264 SPS");
+    };
+    let info = parse_h264_sps(sps)?;
+
+    let mut record = vec![
+        1,
+        sps[1],
+        sps[2],
+        sps[3],
+        0xfc | 3, // 4 bytes NAL length
+        0xe0 | sps_list.len() as u8,
+    ];
+    for nal in &sps_list {
+        record.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        record.extend_from_slice(nal);
+    }
+    record.push(pps_list.len() as u8);
+    for nal in &pps_list {
+        record.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+        record.extend_from_slice(nal);
+    }
+    if !matches!(sps[1], 66 | 77 | 88) {
+        record.push(0xfc | info.chroma_format_idc as u8);
+        record.push(0xf8 | info.bit_depth_luma_minus8 as u8);
+        record.push(0xf8 | info.bit_depth_chroma_minus8 as u8);
+        record.push(0); // numOfSequenceParameterSetExt
+    }
+
+    Ok(VideoTrackConfig {
+        codec_id: "V_MPEG4/ISO/AVC",
+        codec_private: record,
+        width: info.width,
+        height: info.height,
+    })
+}
+
+struct HevcSpsInfo {
+    max_sub_layers_minus1: u32,
+    temporal_id_nesting: bool,
+    general_profile_tier_level: [u8; 12],
+    chroma_format_idc: u32,
+    bit_depth_luma_minus8: u32,
+    bit_depth_chroma_minus8: u32,
+    width: u32,
+    height: u32,
+}
+
+fn parse_hevc_sps(sps: &[u8]) -> Result<HevcSpsInfo> {
+    let rbsp = nal_to_rbsp(sps);
+    let mut reader = BitReader::new(rbsp.get(2..).unwrap_or_default());
+
+    reader.skip(4); // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = reader.bits(3)?;
+    let temporal_id_nesting = reader.flag()?;
+
+    let mut general_profile_tier_level = [0; 12];
+    for byte in &mut general_profile_tier_level {
+        *byte = reader.bits(8)? as u8;
+    }
+
+    let mut sub_layer_flags = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = reader.flag()?;
+        let level_present = reader.flag()?;
+        sub_layer_flags.push((profile_present, level_present));
+    }
+    if max_sub_layers_minus1 > 0 {
+        reader.skip(2 * (8 - max_sub_layers_minus1 as usize));
+    }
+    for (profile_present, level_present) in sub_layer_flags {
+        if profile_present {
+            reader.skip(88);
+        }
+        if level_present {
+            reader.skip(8);
+        }
+    }
+
+    reader.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = reader.ue()?;
+    if chroma_format_idc == 3 {
+        reader.skip(1); // separate_colour_plane_flag
+    }
+    let mut width = reader.ue()?;
+    let mut height = reader.ue()?;
+    if reader.flag()? {
+        let left = reader.ue()?;
+        let right = reader.ue()?;
+        let top = reader.ue()?;
+        let bottom = reader.ue()?;
+
+        let sub_width = if chroma_format_idc == 1 || chroma_format_idc == 2 {
+            2
+        } else {
+            1
+        };
+        let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+
+        width = width.saturating_sub((left + right) * sub_width);
+        height = height.saturating_sub((top + bottom) * sub_height);
+    }
+    let bit_depth_luma_minus8 = reader.ue()?;
+    let bit_depth_chroma_minus8 = reader.ue()?;
+
+    Ok(HevcSpsInfo {
+        max_sub_layers_minus1,
+        temporal_id_nesting,
+        general_profile_tier_level,
+        chroma_format_idc,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+        width,
+        height,
+    })
+}
+
+// HEVCDecoderConfigurationRecord, ISO/IEC 14496-15
+fn hevc_track_config(buffer: &[u8]) -> Result<VideoTrackConfig> {
+    let nals = annex_b_nals(buffer);
+    let nals_of_type = |nal_type| {
+        nals.iter()
+            .filter(|nal| nal.len() >= 2 && hevc_nal_type(nal) == nal_type)
+            .collect::<Vec<_>>()
+    };
+    let vps_list = nals_of_type(HEVC_NAL_TYPE_VPS);
+    let sps_list = nals_of_type(HEVC_NAL_TYPE_SPS);
+    let pps_list = nals_of_type(HEVC_NAL_TYPE_PPS);
+
+    let Some(sps) = sps_list.first() else {
+        bail!("Missing HEVC SPS");
+    };
+    let info = parse_hevc_sps(sps)?;
+
+    let mut record = vec![1];
+    record.extend_from_slice(&info.general_profile_tier_level);
+    record.extend_from_slice(&0xf000_u16.to_be_bytes()); // min_spatial_segmentation_idc
+    record.push(0xfc); // parallelismType
+    record.push(0xfc | info.chroma_format_idc as u8);
+    record.push(0xf8 | info.bit_depth_luma_minus8 as u8);
+    record.push(0xf8 | info.bit_depth_chroma_minus8 as u8);
+    record.extend_from_slice(&0_u16.to_be_bytes()); // avgFrameRate
+    record.push(
+        (((info.max_sub_layers_minus1 + 1) as u8) << 3)
+            | ((info.temporal_id_nesting as u8) << 2)
+            | 3, // 4 bytes NAL length
+    );
+
+    let arrays = [
+        (HEVC_NAL_TYPE_VPS, vps_list),
+        (HEVC_NAL_TYPE_SPS, sps_list),
+        (HEVC_NAL_TYPE_PPS, pps_list),
+    ]
+    .into_iter()
+    .filter(|(_, list)| !list.is_empty())
+    .collect::<Vec<_>>();
+    record.push(arrays.len() as u8);
+    for (nal_type, list) in arrays {
+        record.push(0x80 | nal_type); // array_completeness
+        record.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        for nal in list {
+            record.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            record.extend_from_slice(nal);
+        }
+    }
+
+    Ok(VideoTrackConfig {
+        codec_id: "V_MPEGH/ISO/HEVC",
+        codec_private: record,
+        width: info.width,
+        height: info.height,
+    })
+}
+
+struct Obu<'a> {
+    obu_type: u8,
+    header: &'a [u8],
+    payload: &'a [u8],
+}
+
+fn read_leb128(data: &[u8]) -> Result<(usize, usize)> {
+    let mut value = 0;
+    for (idx, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * idx);
+        if byte & 0x80 == 0 {
+            return Ok((value, idx + 1));
+        }
+    }
+
+    bail!("Invalid leb128 value")
+}
+
+fn write_leb128(buffer: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+fn av1_obus(buffer: &[u8]) -> Result<Vec<Obu<'_>>> {
+    let mut obus = Vec::new();
+
+    let mut position = 0;
+    while position < buffer.len() {
+        let header_byte = buffer[position];
+        let header_size = if header_byte & 0x04 != 0 { 2 } else { 1 };
+        let header = buffer
+            .get(position..position + header_size)
+            .ok_or_else(|| anyhow!("Truncated OBU header"))?;
+
+        let (payload_start, payload_size) = if header_byte & 0x02 != 0 {
+            let (size, size_length) = read_leb128(&buffer[position + header_size..])?;
+            (position + header_size + size_length, size)
+        } else {
+            let start = position + header_size;
+            (start, buffer.len() - start)
+        };
+        let payload = buffer
+            .get(payload_start..payload_start + payload_size)
+            .ok_or_else(|| anyhow!("Truncated OBU"))?;
+
+        obus.push(Obu {
+            obu_type: (header_byte >> 3) & 0x0f,
+            header,
+            payload,
+        });
+
+        position = payload_start + payload_size;
+    }
+
+    Ok(obus)
+}
+
+// Writes the OBU with the size field set
+fn write_obu(buffer: &mut Vec<u8>, obu: &Obu) {
+    buffer.push(obu.header[0] | 0x02);
+    buffer.extend_from_slice(&obu.header[1..]);
+    write_leb128(buffer, obu.payload.len());
+    buffer.extend_from_slice(obu.payload);
+}
+
+// AV1CodecConfigurationRecord, from the AV1 ISOBMFF binding
+fn av1_track_config(buffer: &[u8]) -> Result<VideoTrackConfig> {
+    let obus = av1_obus(buffer)?;
+    let Some(sequence_header) = obus
+        .iter()
+        .find(|obu| obu.obu_type == AV1_OBU_SEQUENCE_HEADER)
+    else {
+        bail!("Missing AV1 sequence header");
+    };
+
+    let mut reader = BitReader::new(sequence_header.payload);
+
+    let seq_profile = reader.bits(3)?;
+    reader.skip(1); // still_picture
+    let reduced_still_picture_header = reader.flag()?;
+
+    let seq_level_idx;
+    let mut seq_tier = 0;
+    if reduced_still_picture_header {
+        seq_level_idx = reader.bits(5)?;
+    } else {
+        let mut decoder_model_info_present = false;
+        let mut buffer_delay_length = 0;
+        if reader.flag()? {
+            // timing_info
+            reader.skip(64); // num_units_in_display_tick and time_scale
+            if reader.flag()? {
+                reader.uvlc()?; // num_ticks_per_picture_minus_1
+            }
+
+            decoder_model_info_present = reader.flag()?;
+            if decoder_model_info_present {
+                buffer_delay_length = reader.bits(5)? as usize + 1;
+                reader.skip(32 + 5 + 5); // decoding tick, removal and presentation time lengths
+            }
+        }
+        let initial_display_delay_present = reader.flag()?;
+
+        let operating_points_count = reader.bits(5)? + 1;
+        let mut first_level_and_tier = None;
+        for _ in 0..operating_points_count {
+            reader.skip(12); // operating_point_idc
+            let level = reader.bits(5)?;
+            let tier = if level > 7 { reader.bit()? } else { 0 };
+            first_level_and_tier.get_or_insert((level, tier));
+
+            if decoder_model_info_present && reader.flag()? {
+                reader.skip(2 * buffer_delay_length + 1);
+            }
+            if initial_display_delay_present && reader.flag()? {
+                reader.skip(4);
+            }
+        }
+        // Safety: there is at least one operating point
+        (seq_level_idx, seq_tier) = first_level_and_tier.unwrap();
+    }
+
+    let frame_width_bits = reader.bits(4)? + 1;
+    let frame_height_bits = reader.bits(4)? + 1;
+    let width = reader.bits(frame_width_bits)? + 1;
+    let height = reader.bits(frame_height_bits)? + 1;
+
+    if !reduced_still_picture_header && reader.flag()? {
+        reader.skip(4 + 3); // frame id lengths
+    }
+    reader.skip(3); // superblock size, filter intra and intra edge filter
+    if !reduced_still_picture_header {
+        reader.skip(4); // compound modes, warped motion and dual filter
+        let enable_order_hint = reader.flag()?;
+        if enable_order_hint {
+            reader.skip(2); // jnt comp and ref frame mvs
+        }
+
+        let seq_force_screen_content_tools = if reader.flag()? {
+            2 // SELECT_SCREEN_CONTENT_TOOLS
+        } else {
+            reader.bit()?
+        };
+        if seq_force_screen_content_tools > 0 && !reader.flag()? {
+            reader.skip(1); // seq_force_integer_mv
+        }
+
+        if enable_order_hint {
+            reader.skip(3); // order_hint_bits_minus_1
+        }
+    }
+    reader.skip(3); // superres, cdef and restoration
+
+    // color_config
+    let high_bitdepth = reader.flag()?;
+    let twelve_bit = seq_profile == 2 && high_bitdepth && reader.flag()?;
+    let mono_chrome = seq_profile != 1 && reader.flag()?;
+    let (color_primaries, transfer_characteristics, matrix_coefficients) = if reader.flag()? {
+        (reader.bits(8)?, reader.bits(8)?, reader.bits(8)?)
+    } else {
+        (2, 2, 2) // unspecified
+    };
+
+    let mut subsampling_x = true;
+    let mut subsampling_y = true;
+    let mut chroma_sample_position = 0;
+    if mono_chrome {
+        reader.skip(1); // color_range
+    } else if color_primaries == 1 && transfer_characteristics == 13 && matrix_coefficients == 0 {
+        // sRGB
+        subsampling_x = false;
+        subsampling_y = false;
+    } else {
+        reader.skip(1); // color_range
+        match seq_profile {
+            0 => (),
+            1 => {
+                subsampling_x = false;
+                subsampling_y = false;
+            }
+            _ => {
+                if twelve_bit {
+                    subsampling_x = reader.flag()?;
+                    subsampling_y = subsampling_x && reader.flag()?;
+                } else {
+                    subsampling_y = false;
+                }
+            }
+        }
+        if subsampling_x && subsampling_y {
+            chroma_sample_position = reader.bits(2)?;
+        }
+    }
+
+    let mut record = vec![
+        0x81, // marker and version
+        ((seq_profile << 5) | seq_level_idx) as u8,
+        ((seq_tier << 7)
+            | ((high_bitdepth as u32) << 6)
+            | ((twelve_bit as u32) << 5)
+            | ((mono_chrome as u32) << 4)
+            | ((subsampling_x as u32) << 3)
+            | ((subsampling_y as u32) << 2)
+            | chroma_sample_position) as u8,
+        0, // no initial presentation delay
+    ];
+    write_obu(&mut record, sequence_header);
+
+    Ok(VideoTrackConfig {
+        codec_id: "V_AV1",
+        codec_private: record,
+        width,
+        height,
+    })
+}
+
+// The buffer must contain the codec parameter sets, either from the decoder config or inline
+pub fn video_track_config(codec: CodecType, buffer: &[u8]) -> Result<VideoTrackConfig> {
+    match codec {
+        CodecType::H264 => h264_track_config(buffer),
+        CodecType::Hevc => hevc_track_config(buffer),
+        CodecType::AV1 => av1_track_config(buffer),
+    }
+}
+
+pub fn frame_to_block(codec: CodecType, buffer: &[u8]) -> Result<Vec<u8>> {
+    let mut block = Vec::with_capacity(buffer.len() + 16);
+    match codec {
+        CodecType::H264 | CodecType::Hevc => {
+            for nal in annex_b_nals(buffer) {
+                block.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                block.extend_from_slice(nal);
+            }
+        }
+        CodecType::AV1 => {
+            for obu in av1_obus(buffer)? {
+                if obu.obu_type != AV1_OBU_TEMPORAL_DELIMITER {
+                    write_obu(&mut block, &obu);
+                }
+            }
+        }
+    }
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        bit_count: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, count: u32, value: u32) {
+            for idx in (0..count).rev() {
+                if self.bit_count.is_multiple_of(8) {
+                    self.data.push(0);
+                }
+                *self.data.last_mut().unwrap() |=
+                    (((value >> idx) & 1) as u8) << (7 - self.bit_count % 8);
+                self.bit_count += 1;
+            }
+        }
+
+        fn ue(&mut self, value: u32) {
+            let code = value + 1;
+            let length = u32::BITS - code.leading_zeros();
+            self.bits(length - 1, 0);
+            self.bits(length, code);
+        }
+
+        // Adds the rbsp_trailing_bits and the emulation prevention bytes
+        fn into_nal(mut self, header: &[u8]) -> Vec<u8> {
+            self.bits(1, 1);
+
+            let mut nal = header.to_vec();
+            let mut zero_count = 0;
+            for byte in self.data {
+                if zero_count >= 2 && byte <= 3 {
+                    nal.push(3);
+                    zero_count = 0;
+                }
+                zero_count = if byte == 0 { zero_count + 1 } else { 0 };
+                nal.push(byte);
+            }
+
+            nal
+        }
+    }
+
+    // High profile, 1920x1088 coded size cropped to 1080 lines
+    fn h264_sps() -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.bits(8, 100); // profile_idc
+        writer.bits(16, 40); // constraint flags and level
+        writer.ue(0); // seq_parameter_set_id
+        writer.ue(1); // chroma_format_idc
+        writer.ue(0); // bit_depth_luma_minus8
+        writer.ue(0); // bit_depth_chroma_minus8
+        writer.bits(2, 0); // qpprime_y_zero_transform_bypass_flag and scaling matrix
+        writer.ue(0); // log2_max_frame_num_minus4
+        writer.ue(2); // pic_order_cnt_type
+        writer.ue(1); // max_num_ref_frames
+        writer.bits(1, 0); // gaps_in_frame_num_value_allowed_flag
+        writer.ue(119); // pic_width_in_mbs_minus1
+        writer.ue(67); // pic_height_in_map_units_minus1
+        writer.bits(2, 0b11); // frame_mbs_only_flag and direct_8x8_inference_flag
+        writer.bits(1, 1); // frame_cropping_flag
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(4); // 8 lines with 4:2:0 chroma subsampling
+        writer.bits(1, 0); // vui_parameters_present_flag
+
+        writer.into_nal(&[0x67])
+    }
+
+    // Main profile, 1920x1088 cropped to 1080 lines
+    fn hevc_sps() -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.bits(4, 0); // sps_video_parameter_set_id
+        writer.bits(3, 0); // sps_max_sub_layers_minus1
+        writer.bits(1, 1); // sps_temporal_id_nesting_flag
+        writer.bits(8, 0x01); // general profile space, tier and profile idc
+        writer.bits(32, 0x6000_0000); // general_profile_compatibility_flags
+        writer.bits(32, 0x9000_0000); // general constraint flags
+        writer.bits(16, 0);
+        writer.bits(8, 120); // general_level_idc
+        writer.ue(0); // sps_seq_parameter_set_id
+        writer.ue(1); // chroma_format_idc
+        writer.ue(1920);
+        writer.ue(1088);
+        writer.bits(1, 1); // conformance_window_flag
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(0);
+        writer.ue(4);
+        writer.ue(2); // bit_depth_luma_minus8
+        writer.ue(2); // bit_depth_chroma_minus8
+
+        writer.into_nal(&[HEVC_NAL_TYPE_SPS << 1, 1])
+    }
+
+    fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| [&[0, 0, 0, 1], *nal].concat())
+            .collect()
+    }
+
+    #[test]
+    fn test_h264_sps_dimensions() {
+        let sps = h264_sps();
+        let info = parse_h264_sps(&sps).unwrap();
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!(info.chroma_format_idc, 1);
+
+        let pps = [0x68, 0xee, 0x3c, 0x80];
+        let config = video_track_config(CodecType::H264, &annex_b(&[&sps, &pps])).unwrap();
+        assert_eq!(config.codec_id, "V_MPEG4/ISO/AVC");
+        assert_eq!((config.width, config.height), (1920, 1080));
+        // Version, profile, compatibility, level, NAL length size and SPS count
+        assert_eq!(config.codec_private[..6], [1, 100, 0, 40, 0xff, 0xe1]);
+    }
+
+    #[test]
+    fn test_hevc_sps_dimensions() {
+        let sps = hevc_sps();
+        let info = parse_hevc_sps(&sps).unwrap();
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!(info.bit_depth_luma_minus8, 2);
+        assert_eq!(info.general_profile_tier_level[0], 0x01);
+        assert_eq!(info.general_profile_tier_level[11], 120);
+
+        let vps = [HEVC_NAL_TYPE_VPS << 1, 1, 0x0c, 0x01];
+        let config = video_track_config(CodecType::Hevc, &annex_b(&[&vps, &sps])).unwrap();
+        assert_eq!((config.width, config.height), (1920, 1080));
+        // VPS and SPS arrays
+        assert_eq!(config.codec_private[22], 2);
+    }
+
+    #[test]
+    fn test_nal_to_rbsp() {
+        assert_eq!(
+            nal_to_rbsp(&[0x67, 0, 0, 3, 1, 0, 0, 3, 0, 0, 3]),
+            [0x67, 0, 0, 1, 0, 0, 0, 0]
+        );
+        // Only the byte after two zeros is an emulation prevention byte
+        assert_eq!(nal_to_rbsp(&[0, 3, 0, 0, 4, 3]), [0, 3, 0, 0, 4, 3]);
+    }
+
+    #[test]
+    fn test_annex_b_to_length_prefixed() {
+        let first_nal = [0x65, 0x88, 0x84];
+        // Contains emulation prevention bytes, which must be kept
+        let second_nal = [0x41, 0x9a, 0, 0, 3, 1, 0, 0, 3, 0, 0x80];
+        let buffer = [
+            &[0, 0, 0, 1][..],
+            &first_nal,
+            &[0, 0, 1],
+            &second_nal,
+            &[0, 0], // trailing zeros
+        ]
+        .concat();
+
+        assert_eq!(annex_b_nals(&buffer), [&first_nal[..], &second_nal[..]]);
+
+        let block = frame_to_block(CodecType::H264, &buffer).unwrap();
+        let expected = [
+            &3_u32.to_be_bytes()[..],
+            &first_nal,
+            &11_u32.to_be_bytes(),
+            &second_nal,
+        ]
+        .concat();
+        assert_eq!(block, expected);
+    }
+
+    #[test]
+    fn test_av1_obus() {
+        let mut buffer = vec![];
+        for value in [0, 127, 128, 300_000] {
+            buffer.clear();
+            write_leb128(&mut buffer, value);
+            assert_eq!(read_leb128(&buffer).unwrap(), (value, buffer.len()));
+        }
+
+        // Temporal delimiter, then a frame OBU without the size field
+        let temporal_unit = [
+            (AV1_OBU_TEMPORAL_DELIMITER << 3) | 0x02,
+            0,
+            6 << 3,
+            0xaa,
+            0xbb,
+        ];
+        let block = frame_to_block(CodecType::AV1, &temporal_unit).unwrap();
+        assert_eq!(block, [(6 << 3) | 0x02, 2, 0xaa, 0xbb]);
+    }
+}
@@ -0,0 +1,598 @@
+// Minimal Matroska muxer. Clusters are buffered in memory and written with a known size, while the
+// segment size, the duration and the cues are written when the file is finished.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const EBML: u32 = 0x1a45_dfa3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42f7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42f2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42f3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114d_9b74;
+const SEEK: u32 = 0x4dbb;
+const SEEK_ID: u32 = 0x53ab;
+const SEEK_POSITION: u32 = 0x53ac;
+const INFO: u32 = 0x1549_a966;
+const TIMESTAMP_SCALE: u32 = 0x2a_d7b1;
+const DURATION: u32 = 0x4489;
+const MUXING_APP: u32 = 0x4d80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_NUMBER: u32 = 0xd7;
+const TRACK_UID: u32 = 0x73c5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_DEFAULT: u32 = 0x88;
+const FLAG_LACING: u32 = 0x9c;
+const NAME: u32 = 0x536e;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63a2;
+const VIDEO: u32 = 0xe0;
+const PIXEL_WIDTH: u32 = 0xb0;
+const PIXEL_HEIGHT: u32 = 0xba;
+const AUDIO: u32 = 0xe1;
+const SAMPLING_FREQUENCY: u32 = 0xb5;
+const CHANNELS: u32 = 0x9f;
+const BIT_DEPTH: u32 = 0x6264;
+const CLUSTER: u32 = 0x1f43_b675;
+const TIMESTAMP: u32 = 0xe7;
+const SIMPLE_BLOCK: u32 = 0xa3;
+const CUES: u32 = 0x1c53_bb6b;
+const CUE_POINT: u32 = 0xbb;
+const CUE_TIME: u32 = 0xb3;
+const CUE_TRACK_POSITIONS: u32 = 0xb7;
+const CUE_TRACK: u32 = 0xf7;
+const CUE_CLUSTER_POSITION: u32 = 0xf1;
+const VOID: u32 = 0xec;
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+const TRACK_TYPE_AUDIO: u64 = 2;
+const TRACK_TYPE_SUBTITLE: u64 = 0x11;
+
+// Block timestamps are in milliseconds
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+const MAX_CLUSTER_DURATION_MS: i64 = 5000;
+
+// Size of a Seek element with a 4 bytes ID and a 8 bytes position
+const SEEK_ENTRY_SIZE: usize = 21;
+
+fn write_id(buffer: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+    buffer.extend_from_slice(&bytes[leading_zeros..]);
+}
+
+fn write_size(buffer: &mut Vec<u8>, size: u64) {
+    // The value with all bits set is reserved for unknown sizes
+    let length = (1..=8).find(|len| size < (1 << (7 * len)) - 1).unwrap();
+    let value = size | (1 << (7 * length));
+    buffer.extend_from_slice(&value.to_be_bytes()[8 - length..]);
+}
+
+fn write_element(buffer: &mut Vec<u8>, id: u32, payload: &[u8]) {
+    write_id(buffer, id);
+    write_size(buffer, payload.len() as u64);
+    buffer.extend_from_slice(payload);
+}
+
+fn write_uint(buffer: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let leading_zeros = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    write_element(buffer, id, &bytes[leading_zeros..]);
+}
+
+fn write_float(buffer: &mut Vec<u8>, id: u32, value: f64) {
+    write_element(buffer, id, &value.to_be_bytes());
+}
+
+fn write_string(buffer: &mut Vec<u8>, id: u32, value: &str) {
+    write_element(buffer, id, value.as_bytes());
+}
+
+fn write_void(buffer: &mut Vec<u8>, total_size: usize) {
+    write_id(buffer, VOID);
+    write_size(buffer, total_size as u64 - 2);
+    buffer.resize(buffer.len() + total_size - 2, 0);
+}
+
+// Position values have a fixed size so they can be patched after they are written
+fn write_seek_entry(buffer: &mut Vec<u8>, id: u32, position: u64) {
+    let mut id_bytes = vec![];
+    write_id(&mut id_bytes, id);
+
+    let mut seek = vec![];
+    write_element(&mut seek, SEEK_ID, &id_bytes);
+    write_element(&mut seek, SEEK_POSITION, &position.to_be_bytes());
+    write_element(buffer, SEEK, &seek);
+}
+
+pub enum TrackKind {
+    Video {
+        width: u32,
+        height: u32,
+    },
+    Audio {
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: u32,
+    },
+    Subtitle,
+}
+
+pub struct Track {
+    pub number: u64, // Must be lower than 127
+    pub name: &'static str,
+    pub kind: TrackKind,
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    pub default: bool,
+}
+
+fn write_track_entry(buffer: &mut Vec<u8>, track: &Track) {
+    let mut entry = vec![];
+    write_uint(&mut entry, TRACK_NUMBER, track.number);
+    write_uint(&mut entry, TRACK_UID, track.number);
+    write_string(&mut entry, NAME, track.name);
+    write_uint(&mut entry, FLAG_DEFAULT, track.default as u64);
+    write_uint(&mut entry, FLAG_LACING, 0);
+    write_string(&mut entry, CODEC_ID, track.codec_id);
+    if let Some(codec_private) = &track.codec_private {
+        write_element(&mut entry, CODEC_PRIVATE, codec_private);
+    }
+
+    match &track.kind {
+        TrackKind::Video { width, height } => {
+            write_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_VIDEO);
+
+            let mut video = vec![];
+            write_uint(&mut video, PIXEL_WIDTH, *width as u64);
+            write_uint(&mut video, PIXEL_HEIGHT, *height as u64);
+            write_element(&mut entry, VIDEO, &video);
+        }
+        TrackKind::Audio {
+            sample_rate,
+            channels,
+            bit_depth,
+        } => {
+            write_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_AUDIO);
+
+            let mut audio = vec![];
+            write_float(&mut audio, SAMPLING_FREQUENCY, *sample_rate as f64);
+            write_uint(&mut audio, CHANNELS, *channels as u64);
+            write_uint(&mut audio, BIT_DEPTH, *bit_depth as u64);
+            write_element(&mut entry, AUDIO, &audio);
+        }
+        TrackKind::Subtitle => write_uint(&mut entry, TRACK_TYPE, TRACK_TYPE_SUBTITLE),
+    }
+
+    write_element(buffer, TRACK_ENTRY, &entry);
+}
+
+struct Cluster {
+    timestamp_ms: i64,
+    blocks: Vec<u8>,
+    has_cue: bool,
+}
+
+struct CuePoint {
+    time_ms: u64,
+    cluster_position: u64,
+}
+
+pub struct MatroskaWriter<W: Write + Seek> {
+    writer: W,
+    segment_size_position: u64,
+    segment_data_position: u64,
+    cues_seek_entry_position: u64,
+    duration_position: u64,
+    cue_track: Option<u64>,
+    cluster: Option<Cluster>,
+    cues: Vec<CuePoint>,
+    duration_ms: i64,
+    finished: bool,
+}
+
+impl<W: Write + Seek> MatroskaWriter<W> {
+    pub fn new(mut writer: W, tracks: &[Track]) -> io::Result<Self> {
+        let start_position = writer.stream_position()?;
+
+        let mut ebml_header = vec![];
+        write_uint(&mut ebml_header, EBML_VERSION, 1);
+        write_uint(&mut ebml_header, EBML_READ_VERSION, 1);
+        write_uint(&mut ebml_header, EBML_MAX_ID_LENGTH, 4);
+        write_uint(&mut ebml_header, EBML_MAX_SIZE_LENGTH, 8);
+        write_string(&mut ebml_header, DOC_TYPE, "matroska");
+        write_uint(&mut ebml_header, DOC_TYPE_VERSION, 4);
+        write_uint(&mut ebml_header, DOC_TYPE_READ_VERSION, 2);
+
+        let mut header = vec![];
+        write_element(&mut header, EBML, &ebml_header);
+        write_id(&mut header, SEGMENT);
+        let segment_size_position = header.len();
+        // Unknown size until finished
+        header.extend_from_slice(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        let segment_data_position = header.len();
+
+        let mut info_payload = vec![];
+        write_uint(&mut info_payload, TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+        write_float(&mut info_payload, DURATION, 0.0);
+        let duration_offset = info_payload.len() - 8;
+        write_string(&mut info_payload, MUXING_APP, "ALVR");
+        write_string(&mut info_payload, WRITING_APP, "ALVR");
+        let mut info = vec![];
+        write_element(&mut info, INFO, &info_payload);
+        let duration_offset = info.len() - info_payload.len() + duration_offset;
+
+        let mut tracks_payload = vec![];
+        for track in tracks {
+            write_track_entry(&mut tracks_payload, track);
+        }
+        let mut tracks_element = vec![];
+        write_element(&mut tracks_element, TRACKS, &tracks_payload);
+
+        // The seek head has a fixed size, its content depends only on its own size
+        let seek_head_size = 4 + 1 + 3 * SEEK_ENTRY_SIZE;
+        let info_position = seek_head_size as u64;
+        let tracks_position = info_position + info.len() as u64;
+
+        let mut seek_head_payload = vec![];
+        write_seek_entry(&mut seek_head_payload, INFO, info_position);
+        write_seek_entry(&mut seek_head_payload, TRACKS, tracks_position);
+        // Placeholder for the cues entry
+        write_void(&mut seek_head_payload, SEEK_ENTRY_SIZE);
+        let mut seek_head = vec![];
+        write_element(&mut seek_head, SEEK_HEAD, &seek_head_payload);
+        assert_eq!(seek_head.len(), seek_head_size);
+        let cues_seek_entry_offset = seek_head.len() - SEEK_ENTRY_SIZE;
+
+        header.extend_from_slice(&seek_head);
+        header.extend_from_slice(&info);
+        header.extend_from_slice(&tracks_element);
+
+        writer.write_all(&header)?;
+
+        let segment_data_position = start_position + segment_data_position as u64;
+
+        Ok(Self {
+            writer,
+            segment_size_position: start_position + segment_size_position as u64,
+            segment_data_position,
+            cues_seek_entry_position: segment_data_position + cues_seek_entry_offset as u64,
+            duration_position: segment_data_position + info_position + duration_offset as u64,
+            cue_track: tracks
+                .iter()
+                .find(|t| matches!(t.kind, TrackKind::Video { .. }))
+                .map(|t| t.number),
+            cluster: None,
+            cues: vec![],
+            duration_ms: 0,
+            finished: false,
+        })
+    }
+
+    fn flush_cluster(&mut self) -> io::Result<()> {
+        let Some(cluster) = self.cluster.take() else {
+            return Ok(());
+        };
+
+        let cluster_position = self.writer.stream_position()? - self.segment_data_position;
+
+        let timestamp_ms = cluster.timestamp_ms.max(0) as u64;
+        let mut payload = vec![];
+        write_uint(&mut payload, TIMESTAMP, timestamp_ms);
+
+        let mut header = vec![];
+        write_id(&mut header, CLUSTER);
+        write_size(&mut header, (payload.len() + cluster.blocks.len()) as u64);
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&payload)?;
+        self.writer.write_all(&cluster.blocks)?;
+
+        if cluster.has_cue {
+            self.cues.push(CuePoint {
+                time_ms: timestamp_ms,
+                cluster_position,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Video keyframes start a new cluster, so the player can seek to them
+    pub fn write_block(
+        &mut self,
+        track: u64,
+        timestamp_ms: i64,
+        keyframe: bool,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let is_cue = keyframe && self.cue_track == Some(track);
+
+        let needs_new_cluster = self.cluster.as_ref().is_none_or(|cluster| {
+            let relative_timestamp = timestamp_ms - cluster.timestamp_ms;
+
+            is_cue
+                || relative_timestamp < i16::MIN as i64
+                || relative_timestamp > MAX_CLUSTER_DURATION_MS
+        });
+        if needs_new_cluster {
+            self.flush_cluster()?;
+            self.cluster = Some(Cluster {
+                timestamp_ms: timestamp_ms.max(0),
+                blocks: vec![],
+                has_cue: is_cue,
+            });
+        }
+        // Safety: the cluster has been created above
+        let cluster = self.cluster.as_mut().unwrap();
+
+        let mut block_header = vec![];
+        write_size(&mut block_header, track);
+        block_header
+            .extend_from_slice(&((timestamp_ms - cluster.timestamp_ms) as i16).to_be_bytes());
+        block_header.push(if keyframe { 0x80 } else { 0 });
+
+        write_id(&mut cluster.blocks, SIMPLE_BLOCK);
+        write_size(
+            &mut cluster.blocks,
+            (block_header.len() + data.len()) as u64,
+        );
+        cluster.blocks.extend_from_slice(&block_header);
+        cluster.blocks.extend_from_slice(data);
+
+        self.duration_ms = self.duration_ms.max(timestamp_ms);
+
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.flush_cluster()?;
+
+        if !self.cues.is_empty() {
+            let cues_position = self.writer.stream_position()? - self.segment_data_position;
+
+            let mut cues_payload = vec![];
+            for cue in &self.cues {
+                let mut positions = vec![];
+                write_uint(&mut positions, CUE_TRACK, self.cue_track.unwrap_or(1));
+                write_uint(&mut positions, CUE_CLUSTER_POSITION, cue.cluster_position);
+
+                let mut point = vec![];
+                write_uint(&mut point, CUE_TIME, cue.time_ms);
+                write_element(&mut point, CUE_TRACK_POSITIONS, &positions);
+
+                write_element(&mut cues_payload, CUE_POINT, &point);
+            }
+            let mut cues = vec![];
+            write_element(&mut cues, CUES, &cues_payload);
+            self.writer.write_all(&cues)?;
+
+            let mut seek_entry = vec![];
+            write_seek_entry(&mut seek_entry, CUES, cues_position);
+            let end_position = self.writer.stream_position()?;
+            self.writer
+                .seek(SeekFrom::Start(self.cues_seek_entry_position))?;
+            self.writer.write_all(&seek_entry)?;
+            self.writer.seek(SeekFrom::Start(end_position))?;
+        }
+
+        let end_position = self.writer.stream_position()?;
+        let segment_size = end_position - self.segment_data_position;
+
+        self.writer
+            .seek(SeekFrom::Start(self.segment_size_position))?;
+        self.writer
+            .write_all(&(segment_size | (1 << 56)).to_be_bytes())?;
+
+        self.writer.seek(SeekFrom::Start(self.duration_position))?;
+        self.writer
+            .write_all(&(self.duration_ms as f64).to_be_bytes())?;
+
+        self.writer.seek(SeekFrom::Start(end_position))?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for MatroskaWriter<W> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Returns the value with the length marker and the length
+    fn read_vint(data: &[u8]) -> (u64, usize) {
+        let length = data[0].leading_zeros() as usize + 1;
+        let value = data[..length]
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64);
+
+        (value, length)
+    }
+
+    // Returns the ID and the payload of the consecutive elements
+    fn parse_elements(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut elements = vec![];
+        while !data.is_empty() {
+            let (id, id_length) = read_vint(data);
+            let (size, size_length) = read_vint(&data[id_length..]);
+            let size = (size & !(1 << (7 * size_length))) as usize;
+            let payload_start = id_length + size_length;
+
+            elements.push((id as u32, &data[payload_start..payload_start + size]));
+            data = &data[payload_start + size..];
+        }
+
+        elements
+    }
+
+    fn child<'a>(elements: &[(u32, &'a [u8])], id: u32) -> &'a [u8] {
+        elements
+            .iter()
+            .find(|(element_id, _)| *element_id == id)
+            .unwrap()
+            .1
+    }
+
+    fn read_uint(payload: &[u8]) -> u64 {
+        payload
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64)
+    }
+
+    #[test]
+    fn test_ebml_encoding() {
+        let encode_size = |size| {
+            let mut buffer = vec![];
+            write_size(&mut buffer, size);
+            buffer
+        };
+        assert_eq!(encode_size(0), [0x80]);
+        assert_eq!(encode_size(126), [0xfe]);
+        // All ones is reserved for unknown sizes
+        assert_eq!(encode_size(127), [0x40, 0x7f]);
+        assert_eq!(encode_size(16382), [0x7f, 0xfe]);
+        assert_eq!(encode_size(16383), [0x20, 0x3f, 0xff]);
+
+        let encode_id = |id| {
+            let mut buffer = vec![];
+            write_id(&mut buffer, id);
+            buffer
+        };
+        assert_eq!(encode_id(SIMPLE_BLOCK), [0xa3]);
+        assert_eq!(encode_id(EBML_VERSION), [0x42, 0x86]);
+        assert_eq!(encode_id(TIMESTAMP_SCALE), [0x2a, 0xd7, 0xb1]);
+        assert_eq!(encode_id(SEGMENT), [0x18, 0x53, 0x80, 0x67]);
+
+        // Unsigned integers use at least one byte
+        let mut buffer = vec![];
+        write_uint(&mut buffer, TRACK_NUMBER, 0);
+        write_uint(&mut buffer, TRACK_UID, 0x1234);
+        assert_eq!(buffer, [0xd7, 0x81, 0, 0x73, 0xc5, 0x82, 0x12, 0x34]);
+
+        let mut buffer = vec![];
+        write_void(&mut buffer, SEEK_ENTRY_SIZE);
+        assert_eq!(buffer.len(), SEEK_ENTRY_SIZE);
+        assert_eq!(parse_elements(&buffer)[0].0, VOID);
+    }
+
+    #[test]
+    fn test_mux_round_trip() {
+        let tracks = [
+            Track {
+                number: 1,
+                name: "Video",
+                kind: TrackKind::Video {
+                    width: 1920,
+                    height: 1080,
+                },
+                codec_id: "V_MPEG4/ISO/AVC",
+                codec_private: Some(vec![1, 2, 3]),
+                default: true,
+            },
+            Track {
+                number: 2,
+                name: "Audio",
+                kind: TrackKind::Audio {
+                    sample_rate: 48000,
+                    channels: 2,
+                    bit_depth: 16,
+                },
+                codec_id: "A_PCM/INT/LIT",
+                codec_private: None,
+                default: true,
+            },
+        ];
+
+        let mut file = vec![];
+        {
+            let mut writer = MatroskaWriter::new(Cursor::new(&mut file), &tracks).unwrap();
+            writer.write_block(1, 0, true, &[0xaa; 10]).unwrap();
+            writer.write_block(2, 5, true, &[0xbb; 4]).unwrap();
+            writer.write_block(1, 16, false, &[0xcc; 3]).unwrap();
+            writer.write_block(1, 33, true, &[0xdd; 5]).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let elements = parse_elements(&file);
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].0, EBML);
+        assert_eq!(child(&parse_elements(elements[0].1), DOC_TYPE), b"matroska");
+
+        // The segment size has been patched with the actual size
+        assert_eq!(elements[1].0, SEGMENT);
+        let segment = elements[1].1;
+        let segment_elements = parse_elements(segment);
+
+        let info = parse_elements(child(&segment_elements, INFO));
+        assert_eq!(read_uint(child(&info, TIMESTAMP_SCALE)), TIMESTAMP_SCALE_NS);
+        let duration = f64::from_be_bytes(child(&info, DURATION).try_into().unwrap());
+        assert_eq!(duration, 33.0);
+
+        let track_entries = parse_elements(child(&segment_elements, TRACKS));
+        assert_eq!(track_entries.len(), 2);
+        let video_entry = parse_elements(track_entries[0].1);
+        assert_eq!(child(&video_entry, CODEC_ID), b"V_MPEG4/ISO/AVC");
+        assert_eq!(child(&video_entry, CODEC_PRIVATE), [1, 2, 3]);
+        let video = parse_elements(child(&video_entry, VIDEO));
+        assert_eq!(read_uint(child(&video, PIXEL_WIDTH)), 1920);
+        assert_eq!(read_uint(child(&video, PIXEL_HEIGHT)), 1080);
+
+        // Each video keyframe starts a cluster
+        let clusters = segment_elements
+            .iter()
+            .filter(|(id, _)| *id == CLUSTER)
+            .map(|(_, payload)| parse_elements(payload))
+            .collect::<Vec<_>>();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(read_uint(child(&clusters[0], TIMESTAMP)), 0);
+        assert_eq!(read_uint(child(&clusters[1], TIMESTAMP)), 33);
+
+        // Track number, relative timestamp, flags and data
+        let blocks = clusters[0]
+            .iter()
+            .filter(|(id, _)| *id == SIMPLE_BLOCK)
+            .map(|(_, payload)| *payload)
+            .collect::<Vec<_>>();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0], [&[0x81, 0, 0, 0x80][..], &[0xaa; 10]].concat());
+        assert_eq!(blocks[1], [&[0x82, 0, 5, 0x80][..], &[0xbb; 4]].concat());
+        assert_eq!(blocks[2], [&[0x81, 0, 16, 0][..], &[0xcc; 3]].concat());
+
+        // The seek head points to the cues, which point to the clusters
+        let seek_entries = parse_elements(child(&segment_elements, SEEK_HEAD))
+            .into_iter()
+            .map(|(_, payload)| parse_elements(payload))
+            .collect::<Vec<_>>();
+        assert_eq!(seek_entries.len(), 3);
+        let cues_position = read_uint(child(&seek_entries[2], SEEK_POSITION)) as usize;
+        assert_eq!(child(&seek_entries[2], SEEK_ID), [0x1c, 0x53, 0xbb, 0x6b]);
+
+        let cues = parse_elements(&segment[cues_position..]);
+        assert_eq!(cues[0].0, CUES);
+        let cue_points = parse_elements(cues[0].1);
+        assert_eq!(cue_points.len(), 2);
+        for (cue_point, time_ms) in cue_points.iter().zip([0, 33]) {
+            let cue_point = parse_elements(cue_point.1);
+            assert_eq!(read_uint(child(&cue_point, CUE_TIME)), time_ms);
+
+            let positions = parse_elements(child(&cue_point, CUE_TRACK_POSITIONS));
+            let cluster_position = read_uint(child(&positions, CUE_CLUSTER_POSITION)) as usize;
+            assert_eq!(parse_elements(&segment[cluster_position..])[0].0, CLUSTER);
+        }
+    }
+}
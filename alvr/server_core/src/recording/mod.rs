@@ -0,0 +1,251 @@
+mod codec;
+mod matroska;
+
+use alvr_common::{
+    anyhow::{Result, bail},
+    error, info,
+};
+use alvr_packets::{DecoderInitializationConfig, VideoPacketHeader};
+use matroska::{MatroskaWriter, Track, TrackKind};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const VIDEO_TRACK: u64 = 1;
+const GAME_AUDIO_TRACK: u64 = 2;
+const VIEW_PARAMS_TRACK: u64 = 3;
+
+// Game audio is always recorded as 16 bit stereo
+const GAME_AUDIO_CHANNELS: u32 = 2;
+const GAME_AUDIO_BYTES_PER_FRAME: usize = 4;
+
+// The audio timeline follows the sample count, unless it drifts too far from the wall clock
+const MAX_AUDIO_DRIFT: Duration = Duration::from_millis(200);
+
+struct ActiveRecording {
+    writer: MatroskaWriter<BufWriter<File>>,
+    first_frame_timestamp: Duration,
+    start_instant: Instant,
+    audio_start: Option<Duration>,
+    audio_frames_written: u64,
+}
+
+// Records the video stream with the game audio to a Matroska file. The file is created when the
+// first IDR frame is received, so the recording can always be decoded from the beginning. A file
+// can contain a single codec configuration, if it changes the recording continues in a new file.
+pub struct VideoRecorder {
+    path: PathBuf,
+    file_index: usize,
+    decoder_config: Option<DecoderInitializationConfig>,
+    game_audio_sample_rate: Option<u32>,
+    record_view_params: bool,
+    recording: Option<ActiveRecording>,
+    failed: bool,
+}
+
+impl VideoRecorder {
+    pub fn new(
+        path: PathBuf,
+        decoder_config: Option<DecoderInitializationConfig>,
+        game_audio_sample_rate: Option<u32>,
+        record_view_params: bool,
+    ) -> Self {
+        Self {
+            path,
+            file_index: 0,
+            decoder_config,
+            game_audio_sample_rate,
+            record_view_params,
+            recording: None,
+            failed: false,
+        }
+    }
+
+    // The encoder sends the configuration before every IDR frame
+    pub fn set_decoder_config(&mut self, config: DecoderInitializationConfig) {
+        if self.recording.is_some()
+            && self.decoder_config.as_ref().is_some_and(|current| {
+                current.codec != config.codec || current.config_buffer != config.config_buffer
+            })
+        {
+            info!("Video codec configuration changed, continuing the recording in a new file");
+
+            // Dropping the writer finalizes the file. The next one is created on the next IDR.
+            self.recording = None;
+            self.file_index += 1;
+        }
+
+        self.decoder_config = Some(config);
+    }
+
+    // The first file uses the requested path, the next ones are numbered starting from 2
+    fn file_path(&self) -> PathBuf {
+        let mut path = self.path.clone();
+        if self.file_index > 0 {
+            let extension = self.path.extension().unwrap_or_default().to_string_lossy();
+            path.set_extension(format!("{}.{extension}", self.file_index + 1));
+        }
+
+        path
+    }
+
+    fn start(&mut self, header: &VideoPacketHeader, nal_buffer: &[u8]) -> Result<()> {
+        let Some(decoder_config) = &self.decoder_config else {
+            bail!("Missing video codec configuration");
+        };
+
+        // Parameter sets can also be included in the IDR frame
+        let video_config = codec::video_track_config(
+            decoder_config.codec,
+            &[&decoder_config.config_buffer, nal_buffer].concat(),
+        )?;
+
+        let mut tracks = vec![Track {
+            number: VIDEO_TRACK,
+            name: "Video",
+            kind: TrackKind::Video {
+                width: video_config.width,
+                height: video_config.height,
+            },
+            codec_id: video_config.codec_id,
+            codec_private: Some(video_config.codec_private),
+            default: true,
+        }];
+        if let Some(sample_rate) = self.game_audio_sample_rate {
+            tracks.push(Track {
+                number: GAME_AUDIO_TRACK,
+                name: "Game audio",
+                kind: TrackKind::Audio {
+                    sample_rate,
+                    channels: GAME_AUDIO_CHANNELS,
+                    bit_depth: 16,
+                },
+                codec_id: "A_PCM/INT/LIT",
+                codec_private: None,
+                default: true,
+            });
+        }
+        if self.record_view_params {
+            // Each block contains the JSON encoded global view params of the frame
+            tracks.push(Track {
+                number: VIEW_PARAMS_TRACK,
+                name: "View params",
+                kind: TrackKind::Subtitle,
+                codec_id: "S_TEXT/UTF8",
+                codec_private: None,
+                default: false,
+            });
+        }
+
+        let path = self.file_path();
+        let writer = MatroskaWriter::new(BufWriter::new(File::create(&path)?), &tracks)?;
+
+        info!("Recording video to {}", path.display());
+
+        self.recording = Some(ActiveRecording {
+            writer,
+            first_frame_timestamp: header.timestamp,
+            start_instant: Instant::now(),
+            audio_start: None,
+            audio_frames_written: 0,
+        });
+
+        Ok(())
+    }
+
+    fn stop_with_error(&mut self, error: impl std::fmt::Display) {
+        error!("Failed to record video on disk: {error}");
+
+        // Dropping the writer finalizes the file
+        self.recording = None;
+        self.failed = true;
+    }
+
+    fn write_video_blocks(&mut self, header: &VideoPacketHeader, nal_buffer: &[u8]) -> Result<()> {
+        if self.recording.is_none() {
+            self.start(header, nal_buffer)?;
+        }
+        // Safety: the recording and the decoder config are set when starting
+        let recording = self.recording.as_mut().unwrap();
+        let codec = self.decoder_config.as_ref().unwrap().codec;
+
+        let timestamp_ms = header
+            .timestamp
+            .saturating_sub(recording.first_frame_timestamp)
+            .as_millis() as i64;
+
+        recording.writer.write_block(
+            VIDEO_TRACK,
+            timestamp_ms,
+            header.is_idr,
+            &codec::frame_to_block(codec, nal_buffer)?,
+        )?;
+
+        if self.record_view_params {
+            recording.writer.write_block(
+                VIEW_PARAMS_TRACK,
+                timestamp_ms,
+                true,
+                serde_json::to_string(&header.global_view_params)?.as_bytes(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_video(&mut self, header: &VideoPacketHeader, nal_buffer: &[u8]) {
+        // Wait for an IDR frame to start
+        if self.failed || (self.recording.is_none() && !header.is_idr) {
+            return;
+        }
+
+        if let Err(e) = self.write_video_blocks(header, nal_buffer) {
+            self.stop_with_error(e);
+        }
+    }
+
+    // Samples are interleaved 16 bit stereo
+    pub fn write_game_audio(&mut self, samples: &[u8]) {
+        let (Some(sample_rate), Some(recording)) =
+            (self.game_audio_sample_rate, &mut self.recording)
+        else {
+            return;
+        };
+
+        let frame_count = (samples.len() / GAME_AUDIO_BYTES_PER_FRAME) as u64;
+        let frames_duration =
+            |count: u64| Duration::from_secs_f64(count as f64 / sample_rate as f64);
+
+        // The samples have been captured before they are received
+        let wall_clock_start = recording
+            .start_instant
+            .elapsed()
+            .saturating_sub(frames_duration(frame_count));
+
+        let timestamp = recording
+            .audio_start
+            .map(|start| start + frames_duration(recording.audio_frames_written))
+            .filter(|timestamp| timestamp.abs_diff(wall_clock_start) < MAX_AUDIO_DRIFT)
+            .unwrap_or_else(|| {
+                recording.audio_start = Some(wall_clock_start);
+                recording.audio_frames_written = 0;
+
+                wall_clock_start
+            });
+
+        if let Err(e) = recording.writer.write_block(
+            GAME_AUDIO_TRACK,
+            timestamp.as_millis() as i64,
+            true,
+            samples,
+        ) {
+            self.stop_with_error(e);
+            return;
+        }
+
+        recording.audio_frames_written += frame_count;
+    }
+}
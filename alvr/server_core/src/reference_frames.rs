@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, time::Duration};
+
+// Frames older than this are assumed to be evicted from the encoder reference buffer, so they
+// cannot be used to recover from a frame loss.
+const MAX_REFERENCE_FRAMES: usize = 16;
+
+// Keeps track of the frames sent to the client since the last IDR frame. When the client reports
+// a frame loss, this decides if the encoder can recover by invalidating the frames after the last
+// one the client decoded, or if a new IDR frame is needed.
+#[derive(Default)]
+pub struct ReferenceFrameTracker {
+    sent_frames: VecDeque<Duration>,
+    // Timestamp of the first frame encoded after an invalidation, as reported by the encoder, and
+    // timestamp of the newest frame it references
+    recovery_frame: Option<(Duration, Duration)>,
+}
+
+impl ReferenceFrameTracker {
+    pub fn report_frame_sent(&mut self, timestamp: Duration, is_idr: bool) {
+        if is_idr {
+            self.sent_frames.clear();
+            self.recovery_frame = None;
+        } else if self.sent_frames.is_empty() {
+            // The frame references frames that the client never received
+            return;
+        }
+
+        self.sent_frames.push_back(timestamp);
+        if self.sent_frames.len() > MAX_REFERENCE_FRAMES {
+            self.sent_frames.pop_front();
+        }
+    }
+
+    // Returns the timestamp of the newest frame that the encoder should keep as reference, or None
+    // if a IDR frame is needed.
+    pub fn report_frame_loss(
+        &mut self,
+        last_valid_frame_timestamp: Option<Duration>,
+    ) -> Option<Duration> {
+        let position = last_valid_frame_timestamp
+            .and_then(|timestamp| self.sent_frames.iter().position(|t| *t == timestamp))?;
+
+        // The frames after the last valid one will not be used as references anymore
+        self.sent_frames.truncate(position + 1);
+
+        self.sent_frames.back().copied()
+    }
+
+    // A frame could not be sent. Returns the timestamp of the newest frame that the encoder should
+    // keep as reference, or None if a IDR frame is needed.
+    pub fn report_frame_dropped(&self) -> Option<Duration> {
+        self.sent_frames.back().copied()
+    }
+
+    // The invalidation is applied asynchronously by the encoder, which may also fall back to an IDR
+    // frame. Only the encoder knows which frame is the first one that doesn't reference the
+    // invalidated frames.
+    pub fn report_recovery_frame(
+        &mut self,
+        timestamp: Duration,
+        last_valid_frame_timestamp: Duration,
+    ) {
+        self.recovery_frame = Some((timestamp, last_valid_frame_timestamp));
+    }
+
+    // Reference to put in the header of the frame, if the encoder reported it as a recovery frame
+    pub fn take_recovery_reference(
+        &mut self,
+        timestamp: Duration,
+        is_idr: bool,
+    ) -> Option<Duration> {
+        if is_idr {
+            self.recovery_frame = None;
+
+            return None;
+        }
+
+        let (recovery_timestamp, reference) = self.recovery_frame?;
+        if recovery_timestamp == timestamp {
+            self.recovery_frame = None;
+
+            Some(reference)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_reference() {
+        let ms = Duration::from_millis;
+        let mut tracker = ReferenceFrameTracker::default();
+
+        tracker.report_frame_sent(ms(0), true);
+        tracker.report_frame_sent(ms(10), false);
+        tracker.report_frame_sent(ms(20), false);
+
+        assert_eq!(tracker.report_frame_loss(Some(ms(10))), Some(ms(10)));
+        // Frames encoded before the encoder applies the invalidation are not marked
+        assert_eq!(tracker.take_recovery_reference(ms(30), false), None);
+        tracker.report_frame_sent(ms(30), false);
+
+        tracker.report_recovery_frame(ms(40), ms(10));
+        assert_eq!(tracker.take_recovery_reference(ms(40), false), Some(ms(10)));
+        tracker.report_frame_sent(ms(40), false);
+        assert_eq!(tracker.take_recovery_reference(ms(50), false), None);
+
+        // Frames unknown to the tracker need an IDR
+        assert_eq!(tracker.report_frame_loss(Some(ms(20))), None);
+        assert_eq!(tracker.report_frame_loss(None), None);
+    }
+
+    #[test]
+    fn test_idr_fallback() {
+        let ms = Duration::from_millis;
+        let mut tracker = ReferenceFrameTracker::default();
+
+        tracker.report_frame_sent(ms(0), true);
+        tracker.report_frame_sent(ms(10), false);
+        assert_eq!(tracker.report_frame_loss(Some(ms(0))), Some(ms(0)));
+
+        // The encoder inserted an IDR frame instead of invalidating
+        assert_eq!(tracker.take_recovery_reference(ms(20), true), None);
+        tracker.report_frame_sent(ms(20), true);
+        assert_eq!(tracker.take_recovery_reference(ms(30), false), None);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    ConnectionContext, FILESYSTEM_LAYOUT, SESSION_MANAGER, ServerCoreEvent,
+    connection::{ClientButtonMapping, STREAMING_RECV_TIMEOUT},
+    tracking::{TrackingManager, TrackingProcessor},
+};
+use alvr_common::{
+    ConnectionState, ViewParams,
+    anyhow::{Result, bail},
+    error, info,
+};
+use alvr_packets::{BatteryInfo, ButtonEntry, TrackingData};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Set while a replay is running. Clearing it stops the replay.
+static REPLAY_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Client inputs, as received by the server before any processing
+#[derive(Serialize, Deserialize)]
+pub enum SessionRecordEntry {
+    Tracking(Box<TrackingData>),
+    Buttons(Vec<ButtonEntry>),
+    ActiveInteractionProfile { input_ids: HashSet<u64> },
+    LocalViewParams([ViewParams; 2]),
+    Battery(BatteryInfo),
+    ProximityState(bool),
+}
+
+// Each line of a session recording file is a JSON encoded record
+#[derive(Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub time: Duration, // Since the start of the recording
+    pub entry: SessionRecordEntry,
+}
+
+pub struct SessionRecorder {
+    writer: BufWriter<File>,
+    start_instant: Instant,
+}
+
+impl SessionRecorder {
+    pub fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start_instant: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, entry: SessionRecordEntry) {
+        let record = SessionRecord {
+            time: self.start_instant.elapsed(),
+            entry,
+        };
+
+        if let Ok(line) = serde_json::to_string(&record) {
+            writeln!(self.writer, "{line}").ok();
+        }
+    }
+}
+
+impl Drop for SessionRecorder {
+    fn drop(&mut self) {
+        self.writer.flush().ok();
+    }
+}
+
+pub fn start_session_recording(ctx: &ConnectionContext) {
+    let path = FILESYSTEM_LAYOUT.get().unwrap().log_dir.join(format!(
+        "session.{}.jsonl",
+        chrono::Local::now().format("%F.%H-%M-%S")
+    ));
+
+    match SessionRecorder::new(&path) {
+        Ok(recorder) => {
+            info!("Recording session to {}", path.display());
+            *ctx.session_recorder.lock() = Some(recorder);
+        }
+        Err(e) => error!("Failed to record session on disk: {e}"),
+    }
+}
+
+pub fn stop_session_recording(ctx: &ConnectionContext) {
+    *ctx.session_recorder.lock() = None;
+}
+
+fn is_client_streaming() -> bool {
+    SESSION_MANAGER
+        .read()
+        .client_list()
+        .values()
+        .any(|info| info.connection_state == ConnectionState::Streaming)
+}
+
+pub fn load_session_records(path: &Path) -> Result<Vec<SessionRecord>> {
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(records)
+}
+
+// Feeds a session recording to the tracking manager and to the driver, as if the inputs were
+// received from a client. If not in real time, records are replayed as fast as possible, which
+// gives a deterministic sequence of events.
+pub fn replay_session(ctx: &ConnectionContext, path: &Path, real_time: bool) -> Result<()> {
+    if is_client_streaming() {
+        bail!("Cannot replay a session while a client is streaming");
+    }
+
+    let records = load_session_records(path)?;
+
+    if REPLAY_RUNNING.swap(true, Ordering::SeqCst) {
+        bail!("Another session replay is running");
+    }
+
+    info!(
+        "Replaying {} session records from {}",
+        records.len(),
+        path.display()
+    );
+
+    let settings = SESSION_MANAGER.read().settings().clone();
+
+    *ctx.tracking_manager.write() =
+        TrackingManager::new(settings.connection.statistics_history_size);
+
+    let mut tracking_processor = TrackingProcessor::new(settings.clone());
+    let mut button_mapping =
+        ClientButtonMapping::new(&settings, SESSION_MANAGER.read().settings_revision());
+
+    let start_instant = Instant::now();
+    for record in records {
+        if !REPLAY_RUNNING.load(Ordering::SeqCst) {
+            info!("Session replay stopped");
+            return Ok(());
+        }
+
+        if is_client_streaming() {
+            REPLAY_RUNNING.store(false, Ordering::SeqCst);
+            bail!("Session replay interrupted by a client connection");
+        }
+
+        // The time based button mappings follow the recorded time, also when not in real time
+        let record_instant = start_instant + record.time;
+
+        if real_time {
+            // Sleep in small steps so the replay can be stopped promptly
+            while let Some(delay) = record_instant.checked_duration_since(Instant::now())
+                && REPLAY_RUNNING.load(Ordering::SeqCst)
+            {
+                thread::sleep(Duration::min(delay, STREAMING_RECV_TIMEOUT));
+            }
+        }
+
+        button_mapping.update(ctx, record_instant);
+
+        match record.entry {
+            SessionRecordEntry::Tracking(tracking) => tracking_processor.process(ctx, *tracking),
+            SessionRecordEntry::Buttons(entries) => {
+                button_mapping.process_buttons(ctx, &entries, record_instant)
+            }
+            SessionRecordEntry::ActiveInteractionProfile { input_ids } => {
+                button_mapping.set_input_ids(ctx, input_ids)
+            }
+            SessionRecordEntry::LocalViewParams(params) => {
+                ctx.events_sender
+                    .send(ServerCoreEvent::LocalViewParams(params))
+                    .ok();
+            }
+            SessionRecordEntry::Battery(packet) => {
+                ctx.events_sender
+                    .send(ServerCoreEvent::Battery(packet))
+                    .ok();
+            }
+            SessionRecordEntry::ProximityState(headset_is_worn) => {
+                ctx.events_sender
+                    .send(ServerCoreEvent::ProximityState(headset_is_worn))
+                    .ok();
+            }
+        }
+    }
+
+    REPLAY_RUNNING.store(false, Ordering::SeqCst);
+    info!("Session replay finished");
+
+    Ok(())
+}
+
+pub fn stop_session_replay() {
+    REPLAY_RUNNING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::LEFT_X_CLICK_ID;
+    use alvr_packets::{ButtonValue, FaceData};
+    use std::{env, fs, process, sync::mpsc};
+
+    fn tracking(poll_timestamp: Duration) -> SessionRecordEntry {
+        SessionRecordEntry::Tracking(Box::new(TrackingData {
+            poll_timestamp,
+            device_motions: vec![],
+            hand_skeletons: [None, None],
+            face: FaceData::default(),
+            body: None,
+        }))
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = env::temp_dir().join(format!("alvr_session_replay.{}.jsonl", process::id()));
+
+        {
+            let mut recorder = SessionRecorder::new(&path).unwrap();
+            for index in 0..3 {
+                recorder.record(tracking(Duration::from_millis(10 * index)));
+            }
+            recorder.record(SessionRecordEntry::Buttons(vec![ButtonEntry {
+                path_id: *LEFT_X_CLICK_ID,
+                value: ButtonValue::Binary(true),
+            }]));
+            recorder.record(SessionRecordEntry::ProximityState(true));
+        }
+
+        let settings = SESSION_MANAGER.read().settings().clone();
+        let (events_sender, events_receiver) = mpsc::channel();
+        let ctx = ConnectionContext::new(events_sender, &settings);
+
+        let res = replay_session(&ctx, &path, false);
+        fs::remove_file(&path).ok();
+        res.unwrap();
+
+        let events = events_receiver.try_iter().collect::<Vec<_>>();
+
+        let poll_timestamps = events
+            .iter()
+            .filter_map(|event| match event {
+                ServerCoreEvent::Tracking { poll_timestamp } => Some(*poll_timestamp),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(poll_timestamps, [0, 10, 20].map(Duration::from_millis));
+
+        // The buttons go through the default mapping, which keeps the X button
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ServerCoreEvent::Buttons(entries) if entries.iter().any(|entry| {
+                entry.path_id == *LEFT_X_CLICK_ID && matches!(entry.value, ButtonValue::Binary(true))
+            })
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(ServerCoreEvent::ProximityState(true))
+        ));
+    }
+}
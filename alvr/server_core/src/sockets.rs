@@ -18,8 +18,8 @@ impl WelcomeSocket {
         Ok(Self { mdns_receiver })
     }
 
-    // Returns: client IP, client hostname
-    pub fn recv_all(&self) -> Result<HashMap<String, IpAddr>> {
+    // Returns: client hostname, client IP and announced pairing key
+    pub fn recv_all(&self) -> Result<HashMap<String, (IpAddr, Option<String>)>> {
         let mut clients = HashMap::new();
 
         loop {
@@ -54,7 +54,11 @@ impl WelcomeSocket {
                             warn!("Found incompatible client {hostname}! {reason}\n{protocols}");
                         }
 
-                        clients.insert(hostname.into(), address.to_ip_addr());
+                        let pairing_key = info
+                            .get_property_val_str(alvr_sockets::MDNS_PAIRING_KEY_KEY)
+                            .map(str::to_owned);
+
+                        clients.insert(hostname.into(), (address.to_ip_addr(), pairing_key));
                     }
                 }
                 Err(TryRecvError::Empty) => break,
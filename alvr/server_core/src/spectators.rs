@@ -0,0 +1,426 @@
+use crate::{
+    ConnectionContext, SESSION_MANAGER, ServerCoreEvent, ServerNegotiatedStreamingConfig,
+    connection::{
+        self, HANDSHAKE_ACTION_TIMEOUT, MAX_UNREAD_PACKETS, STREAMING_RECV_TIMEOUT, VideoPacket,
+    },
+    metrics::METRICS,
+    statistics::SpectatorStatisticsManager,
+};
+use alvr_common::{
+    AnyhowToCon, ConResult, ConnectionError, ConnectionState, con_bail, dbg_connection, debug,
+    info,
+    parking_lot::{Condvar, Mutex, RwLockWriteGuard},
+};
+use alvr_packets::{
+    AUDIO, ClientConnectionsAction, ClientControlPacket, ClientNegotiatedStreamingConfig,
+    ClientStatistics, NegotiatedStreamingConfigExt, STATISTICS, ServerControlPacket,
+    StreamConfigPacket, VIDEO, VideoStreamingCapabilities,
+};
+use alvr_server_io::ServerSessionManager;
+use alvr_session::{CodecType, H264Profile};
+use alvr_sockets::{
+    KEEPALIVE_INTERVAL, KEEPALIVE_TIMEOUT, ProtoControlSocket, SocketConnection, StreamClass,
+    StreamPriority, StreamSender,
+};
+use std::{
+    net::IpAddr,
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// IDRs are expensive for the main client, so the ones needed only by spectators and video mirror
+// subscribers are coalesced and sent at most once per interval
+const SECONDARY_IDR_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+// Stream parameters negotiated with the main client. Spectators must decode the same stream.
+#[derive(Clone)]
+pub struct SharedStreamConfig {
+    pub negotiated: ServerNegotiatedStreamingConfig,
+    pub game_audio_sample_rate: u32,
+}
+
+#[derive(Default)]
+pub struct SecondaryIdrRequests {
+    last_request: Option<Instant>,
+    pending: bool,
+}
+
+impl SecondaryIdrRequests {
+    // Returns true if the IDR must be requested now, otherwise it is deferred to a later poll()
+    pub fn request(&mut self) -> bool {
+        self.pending = true;
+
+        self.poll()
+    }
+
+    // Must be called for every encoded frame
+    pub fn poll(&mut self) -> bool {
+        if self.pending
+            && self
+                .last_request
+                .is_none_or(|instant| instant.elapsed() >= SECONDARY_IDR_REQUEST_INTERVAL)
+        {
+            self.pending = false;
+            self.last_request = Some(Instant::now());
+
+            true
+        } else {
+            false
+        }
+    }
+
+    // Any IDR satisfies the deferred requests
+    pub fn report_idr(&mut self) {
+        self.pending = false;
+    }
+}
+
+pub fn request_secondary_idr(ctx: &ConnectionContext) {
+    if ctx.secondary_idr_requests.lock().request() {
+        ctx.events_sender.send(ServerCoreEvent::RequestIDR).ok();
+    }
+}
+
+pub struct Spectator {
+    video_sender: SyncSender<VideoPacket>,
+    game_audio_sender: StreamSender<()>,
+    statistics: Arc<Mutex<SpectatorStatisticsManager>>,
+    // After a dropped frame the next frames cannot be decoded until an IDR is received
+    waiting_for_idr: bool,
+}
+
+impl Spectator {
+    // Returns true if a new IDR is needed
+    pub fn send_video(&mut self, packet: VideoPacket) -> bool {
+        if packet.header.is_idr {
+            self.waiting_for_idr = false;
+        } else if self.waiting_for_idr {
+            return false;
+        }
+
+        if let Err(TrySendError::Full(_)) = self.video_sender.try_send(packet) {
+            self.statistics.lock().report_frame_dropped();
+            METRICS.lock().report_spectator_frame_dropped();
+            self.waiting_for_idr = true;
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Sends the game audio to the main client, to all spectators and to the video recording
+pub fn game_audio_fan_out(
+    ctx: &Arc<ConnectionContext>,
+    mut sender: StreamSender<()>,
+) -> impl FnMut(&[u8]) + Send + 'static {
+    let ctx = Arc::clone(ctx);
+    move |samples| {
+        sender.send_header_with_payload(&(), samples).ok();
+
+        for spectator in ctx.spectators.lock().values_mut() {
+            spectator
+                .game_audio_sender
+                .send_header_with_payload(&(), samples)
+                .ok();
+        }
+
+        if let Some(recorder) = &mut *ctx.video_recorder.lock() {
+            recorder.write_game_audio(samples);
+        }
+    }
+}
+
+// Spectators receive the stream of the main client. Their tracking and input are ignored.
+pub fn spectator_pipeline(
+    ctx: Arc<ConnectionContext>,
+    socket: ProtoControlSocket,
+    session_manager_lock: RwLockWriteGuard<'_, ServerSessionManager>,
+    streaming_caps: VideoStreamingCapabilities,
+    client_hostname: String,
+    client_ip: IpAddr,
+) -> ConResult {
+    dbg_connection!("spectator_pipeline: Begin");
+
+    let Some(config) = ctx.main_stream_config.lock().clone() else {
+        debug!("Spectator {client_hostname} is waiting for the main client to connect");
+        return Ok(());
+    };
+    let negotiated = &config.negotiated;
+
+    if negotiated.transcoding_view_resolution.x > streaming_caps.max_view_resolution.x
+        || negotiated.transcoding_view_resolution.y > streaming_caps.max_view_resolution.y
+    {
+        con_bail!(
+            "Spectator {client_hostname} does not support the stream resolution {}x{}",
+            negotiated.transcoding_view_resolution.x,
+            negotiated.transcoding_view_resolution.y
+        );
+    }
+    if (negotiated.codec == CodecType::AV1 && !streaming_caps.encoder_av1)
+        || (negotiated.codec == CodecType::H264
+            && negotiated.h264_profile == H264Profile::High
+            && !streaming_caps.encoder_high_profile)
+        || (negotiated.use_10bit_encoder && !streaming_caps.encoder_10_bits)
+    {
+        con_bail!("Spectator {client_hostname} does not support the stream encoding");
+    }
+
+    let settings = session_manager_lock.settings().clone();
+    let wired = client_ip.is_loopback();
+
+    dbg_connection!("spectator_pipeline: send streaming config");
+    let stream_config_packet = StreamConfigPacket::new(
+        session_manager_lock.session(),
+        ClientNegotiatedStreamingConfig {
+            view_resolution: negotiated.transcoding_view_resolution,
+            refresh_rate_hint: negotiated.refresh_rate,
+            game_audio_sample_rate: config.game_audio_sample_rate,
+            enable_foveated_encoding: negotiated.enable_foveated_encoding,
+            encoding_gamma: negotiated.encoding_gamma,
+            enable_hdr: negotiated.enable_hdr,
+            wired,
+            ext_str: String::new(),
+        }
+        .with_ext(NegotiatedStreamingConfigExt {}),
+    )
+    .to_con()?;
+
+    // The handshake of a spectator must not block the session for the main client
+    drop(session_manager_lock);
+
+    let mut socket = SocketConnection::from_client_connection(
+        socket,
+        HANDSHAKE_ACTION_TIMEOUT,
+        stream_config_packet,
+        connection::stream_socket_config(&settings, wired),
+    )?;
+
+    dbg_connection!("spectator_pipeline: Handshake successful, spawning threads");
+
+    // The threads wait for the lock to be released before checking the connection state
+    let mut session_manager_lock = SESSION_MANAGER.write();
+
+    let disconnect_notif = Arc::new(Condvar::new());
+
+    let control_sender = Arc::new(Mutex::new(socket.request_reliable_stream()?));
+    let mut video_sender = socket.request_stream(
+        VIDEO,
+        alvr_sockets::video_stream_class(&settings),
+        StreamPriority::Low,
+    );
+    let game_audio_sender =
+        socket.request_stream(AUDIO, StreamClass::Droppable, StreamPriority::Medium);
+
+    let mut control_receiver = socket.subscribe_to_reliable_stream()?;
+    let mut statistics_receiver = socket.subscribe_to_stream::<ClientStatistics>(
+        STATISTICS,
+        StreamClass::Reliable,
+        MAX_UNREAD_PACKETS,
+    );
+
+    let statistics = Arc::new(Mutex::new(SpectatorStatisticsManager::new(
+        client_hostname.clone(),
+    )));
+
+    let (video_channel_sender, video_channel_receiver) =
+        mpsc::sync_channel(settings.connection.max_queued_server_video_frames);
+    ctx.spectators.lock().insert(
+        client_hostname.clone(),
+        Spectator {
+            video_sender: video_channel_sender,
+            game_audio_sender,
+            statistics: Arc::clone(&statistics),
+            waiting_for_idr: true,
+        },
+    );
+
+    let video_send_thread = thread::spawn({
+        let ctx = Arc::clone(&ctx);
+        let statistics = Arc::clone(&statistics);
+        let client_hostname = client_hostname.clone();
+        move || {
+            while connection::is_streaming(&client_hostname) {
+                let VideoPacket {
+                    mut header,
+                    payload,
+                } = match video_channel_receiver.recv_timeout(STREAMING_RECV_TIMEOUT) {
+                    Ok(packet) => packet,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                };
+
+                ctx.tracking_manager
+                    .read()
+                    .unrecenter_view_params(&mut header.global_view_params);
+
+                if video_sender
+                    .send_header_with_payload(&header, &payload)
+                    .is_ok()
+                {
+                    statistics.lock().report_frame_sent(payload.len());
+                }
+            }
+        }
+    });
+
+    let statistics_thread = thread::spawn({
+        let client_hostname = client_hostname.clone();
+        move || {
+            while connection::is_streaming(&client_hostname) {
+                let data = match statistics_receiver.recv(STREAMING_RECV_TIMEOUT) {
+                    Ok(stats) => stats,
+                    Err(ConnectionError::TryAgain(_)) => continue,
+                    Err(ConnectionError::Other(_)) => return,
+                };
+                let Ok(client_stats) = data.get_header() else {
+                    return;
+                };
+
+                statistics.lock().report_client_statistics(client_stats);
+            }
+        }
+    });
+
+    let keepalive_thread = thread::spawn({
+        let control_sender = Arc::clone(&control_sender);
+        let disconnect_notif = Arc::clone(&disconnect_notif);
+        let client_hostname = client_hostname.clone();
+        move || {
+            while connection::is_streaming(&client_hostname) {
+                if let Err(e) = control_sender.lock().send(&ServerControlPacket::KeepAlive) {
+                    info!("Spectator disconnected. Cause: {e:?}");
+
+                    disconnect_notif.notify_one();
+
+                    return;
+                }
+
+                thread::sleep(KEEPALIVE_INTERVAL);
+            }
+        }
+    });
+
+    let control_receive_thread = thread::spawn({
+        let ctx = Arc::clone(&ctx);
+        let control_sender = Arc::clone(&control_sender);
+        let disconnect_notif = Arc::clone(&disconnect_notif);
+        let client_hostname = client_hostname.clone();
+        move || {
+            let mut disconnection_deadline = Instant::now() + KEEPALIVE_TIMEOUT;
+            while connection::is_streaming(&client_hostname) {
+                let packet = match control_receiver.recv(STREAMING_RECV_TIMEOUT) {
+                    Ok(packet) => packet,
+                    Err(ConnectionError::TryAgain(_)) => {
+                        if Instant::now() > disconnection_deadline {
+                            info!("Spectator disconnected. Timeout");
+                            break;
+                        } else {
+                            continue;
+                        }
+                    }
+                    Err(e) => {
+                        info!("Spectator disconnected. Cause: {e}");
+                        break;
+                    }
+                };
+
+                match packet {
+                    ClientControlPacket::RequestIdr => {
+                        if let Some(config) = ctx.decoder_config.lock().clone() {
+                            control_sender
+                                .lock()
+                                .send(&ServerControlPacket::DecoderConfig(config))
+                                .ok();
+                        }
+                        request_secondary_idr(&ctx);
+                    }
+                    // Reference frame invalidation is driven by the main client only
+                    ClientControlPacket::VideoFrameLoss { .. } => request_secondary_idr(&ctx),
+                    ClientControlPacket::Log { level, message } => {
+                        info!("Spectator {client_hostname}: [{level:?}] {message}")
+                    }
+                    // Spectators cannot affect the stream or the SteamVR devices
+                    _ => (),
+                }
+
+                disconnection_deadline = Instant::now() + KEEPALIVE_TIMEOUT;
+            }
+
+            disconnect_notif.notify_one()
+        }
+    });
+
+    let stream_receive_thread = thread::spawn({
+        let disconnect_notif = Arc::clone(&disconnect_notif);
+        let client_hostname = client_hostname.clone();
+        move || {
+            while connection::is_streaming(&client_hostname) {
+                match socket.recv_poll() {
+                    Ok(()) => (),
+                    Err(ConnectionError::TryAgain(_)) => continue,
+                    Err(e) => {
+                        info!("Spectator disconnected. Cause: {e}");
+
+                        disconnect_notif.notify_one();
+
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    // The spectator is disconnected together with the main client or when the server shuts down
+    let state_check_thread = thread::spawn({
+        let disconnect_notif = Arc::clone(&disconnect_notif);
+        let client_hostname = client_hostname.clone();
+        move || {
+            while connection::is_streaming(&client_hostname) {
+                thread::sleep(STREAMING_RECV_TIMEOUT);
+            }
+
+            disconnect_notif.notify_one()
+        }
+    });
+
+    session_manager_lock.update_client_connections(
+        client_hostname.clone(),
+        ClientConnectionsAction::SetConnectionState(ConnectionState::Streaming),
+    );
+
+    // The spectator can start decoding only from an IDR
+    request_secondary_idr(&ctx);
+
+    dbg_connection!("spectator_pipeline: Threads initialized; unlocking streams");
+    alvr_common::wait_rwlock(&disconnect_notif, &mut session_manager_lock);
+    dbg_connection!("spectator_pipeline: Begin connection shutdown");
+
+    // This requests shutdown from the video thread
+    ctx.spectators.lock().remove(&client_hostname);
+
+    session_manager_lock.update_client_connections(
+        client_hostname,
+        ClientConnectionsAction::SetConnectionState(ConnectionState::Disconnecting),
+    );
+
+    // Allow threads to shutdown correctly
+    drop(session_manager_lock);
+
+    dbg_connection!("spectator_pipeline: Shutdown threads");
+    video_send_thread.join().ok();
+    statistics_thread.join().ok();
+    keepalive_thread.join().ok();
+    control_receive_thread.join().ok();
+    stream_receive_thread.join().ok();
+    state_check_thread.join().ok();
+
+    dbg_connection!("spectator_pipeline: End");
+
+    Ok(())
+}
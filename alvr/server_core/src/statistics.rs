@@ -1,5 +1,9 @@
-use alvr_common::{HEAD_ID, SlidingWindowAverage};
-use alvr_events::{BitrateDirectives, EventType, GraphStatistics, StatisticsSummary};
+use crate::{metrics::METRICS, statistics_timeline::StatisticsTimeline};
+use alvr_common::{ClockEstimate, HEAD_ID, SlidingWindowAverage, error};
+use alvr_events::{
+    BitrateDirectives, EventType, GraphStatistics, SpectatorStatistics, StatisticsSnapshot,
+    StatisticsSummary,
+};
 use alvr_packets::ClientStatistics;
 use std::{
     collections::{HashMap, VecDeque},
@@ -15,6 +19,7 @@ pub struct HistoryFrame {
     frame_present: Instant,
     frame_composed: Instant,
     frame_encoded: Instant,
+    video_sent: Option<Instant>,
     video_packet_bytes: usize,
     total_pipeline_latency: Duration,
 }
@@ -28,6 +33,7 @@ impl Default for HistoryFrame {
             frame_present: now,
             frame_composed: now,
             frame_encoded: now,
+            video_sent: None,
             video_packet_bytes: 0,
             total_pipeline_latency: Duration::ZERO,
         }
@@ -50,12 +56,26 @@ pub struct StatisticsManager {
     video_packets_partial_sum: usize,
     video_bytes_total: usize,
     video_bytes_partial_sum: usize,
+    fec_recovered_shards_total: usize,
+    fec_recovered_shards_partial_sum: usize,
+    server_encryption_time_partial_sum: Duration,
+    client_encryption_time_partial_sum: Duration,
+    encryption_overhead_bytes_partial_sum: usize,
     battery_gauges: HashMap<u64, BatteryData>,
     steamvr_pipeline_latency: Duration,
     motion_to_photon_latency_average: SlidingWindowAverage<Duration>,
     last_vsync_time: Instant,
     frame_interval: Duration,
     last_throughput_directives: BitrateDirectives,
+    // Reference for the clock synchronization with the client
+    epoch: Instant,
+    // Maps the client clock to the local clock
+    client_clock: Option<ClockEstimate>,
+    uplink_latency_average: SlidingWindowAverage<Duration>,
+    downlink_latency_average: SlidingWindowAverage<Duration>,
+    // Last reported statistics, served by the web server API
+    snapshot: StatisticsSnapshot,
+    timeline: Option<StatisticsTimeline>,
 }
 
 impl StatisticsManager {
@@ -64,6 +84,7 @@ impl StatisticsManager {
         max_history_size: usize,
         nominal_server_frame_interval: Duration,
         steamvr_pipeline_frames: f32,
+        timeline: Option<StatisticsTimeline>,
     ) -> Self {
         Self {
             history_buffer: VecDeque::new(),
@@ -75,6 +96,11 @@ impl StatisticsManager {
             video_packets_partial_sum: 0,
             video_bytes_total: 0,
             video_bytes_partial_sum: 0,
+            fec_recovered_shards_total: 0,
+            fec_recovered_shards_partial_sum: 0,
+            server_encryption_time_partial_sum: Duration::ZERO,
+            client_encryption_time_partial_sum: Duration::ZERO,
+            encryption_overhead_bytes_partial_sum: 0,
             battery_gauges: HashMap::new(),
             steamvr_pipeline_latency: Duration::from_secs_f32(
                 steamvr_pipeline_frames * nominal_server_frame_interval.as_secs_f32(),
@@ -86,6 +112,12 @@ impl StatisticsManager {
             last_vsync_time: Instant::now(),
             frame_interval: nominal_server_frame_interval,
             last_throughput_directives: BitrateDirectives::default(),
+            epoch: Instant::now(),
+            client_clock: None,
+            uplink_latency_average: SlidingWindowAverage::new(Duration::ZERO, max_history_size),
+            downlink_latency_average: SlidingWindowAverage::new(Duration::ZERO, max_history_size),
+            snapshot: StatisticsSnapshot::default(),
+            timeline,
         }
     }
 
@@ -161,6 +193,25 @@ impl StatisticsManager {
         }
     }
 
+    pub fn report_frame_sent(&mut self, target_timestamp: Duration, last_shard_sent: Instant) {
+        if let Some(frame) = self
+            .history_buffer
+            .iter_mut()
+            .find(|frame| frame.target_timestamp == target_timestamp)
+        {
+            frame.video_sent = Some(last_shard_sent);
+        }
+    }
+
+    pub fn clock_time(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    // The client estimates the server clock, the inverse is used to map the client clock
+    pub fn report_client_clock_estimate(&mut self, server_clock_estimate: ClockEstimate) {
+        self.client_clock = Some(server_clock_estimate.inverse());
+    }
+
     pub fn report_battery(&mut self, device_id: u64, gauge_value: f32, is_plugged: bool) {
         *self.battery_gauges.entry(device_id).or_default() = BatteryData {
             gauge_value,
@@ -168,6 +219,12 @@ impl StatisticsManager {
         };
     }
 
+    // Cost of the encryption on the server side, since the last call
+    pub fn report_encryption(&mut self, processing_time: Duration, overhead_bytes: usize) {
+        self.server_encryption_time_partial_sum += processing_time;
+        self.encryption_overhead_bytes_partial_sum += overhead_bytes;
+    }
+
     pub fn report_throughput_stats(&mut self, stats: BitrateDirectives) {
         self.last_throughput_directives = stats;
     }
@@ -178,6 +235,10 @@ impl StatisticsManager {
         self.motion_to_photon_latency_average
             .submit_sample(client_stats.total_pipeline_latency);
 
+        self.fec_recovered_shards_total += client_stats.fec_recovered_shards;
+        self.fec_recovered_shards_partial_sum += client_stats.fec_recovered_shards;
+        self.client_encryption_time_partial_sum += client_stats.encryption_time;
+
         if let Some(frame) = self
             .history_buffer
             .iter_mut()
@@ -213,6 +274,29 @@ impl StatisticsManager {
                     + client_stats.vsync_queue,
             );
 
+            // One way latencies, available only when the clocks are synchronized
+            let mut uplink_latency = None;
+            let mut downlink_latency = None;
+            if let Some(client_clock) = &self.client_clock {
+                let tracking_sent =
+                    self.epoch + client_clock.remote_to_local(client_stats.tracking_sent);
+                let latency = frame
+                    .tracking_received
+                    .saturating_duration_since(tracking_sent);
+                self.uplink_latency_average.submit_sample(latency);
+                uplink_latency = Some(latency);
+
+                if let Some(video_sent) = frame.video_sent
+                    && client_stats.video_last_shard_arrival != Duration::ZERO
+                {
+                    let video_received = self.epoch
+                        + client_clock.remote_to_local(client_stats.video_last_shard_arrival);
+                    let latency = video_received.saturating_duration_since(video_sent);
+                    self.downlink_latency_average.submit_sample(latency);
+                    downlink_latency = Some(latency);
+                }
+            }
+
             let client_fps =
                 1.0 / Duration::max(client_stats.frame_interval, EPS_INTERVAL).as_secs_f32();
             let server_fps =
@@ -223,7 +307,7 @@ impl StatisticsManager {
 
                 let interval_secs = FULL_REPORT_INTERVAL.as_secs_f32();
 
-                alvr_events::send_event(EventType::StatisticsSummary(StatisticsSummary {
+                let summary = StatisticsSummary {
                     video_packets_total: self.video_packets_total,
                     video_packets_per_sec: (self.video_packets_partial_sum as f32 / interval_secs)
                         as _,
@@ -231,10 +315,38 @@ impl StatisticsManager {
                     video_mbits_per_sec: self.video_bytes_partial_sum as f32 * 8.
                         / 1e6
                         / interval_secs,
+                    fec_recovered_shards_total: self.fec_recovered_shards_total,
+                    fec_recovered_shards_per_sec: (self.fec_recovered_shards_partial_sum as f32
+                        / interval_secs) as _,
+                    server_encryption_ms_per_sec: self
+                        .server_encryption_time_partial_sum
+                        .as_secs_f32()
+                        * 1000.
+                        / interval_secs,
+                    client_encryption_ms_per_sec: self
+                        .client_encryption_time_partial_sum
+                        .as_secs_f32()
+                        * 1000.
+                        / interval_secs,
+                    encryption_overhead_kbits_per_sec: self.encryption_overhead_bytes_partial_sum
+                        as f32
+                        * 8.
+                        / 1e3
+                        / interval_secs,
                     total_latency_ms: client_stats.total_pipeline_latency.as_secs_f32() * 1000.,
                     network_latency_ms: network_latency.as_secs_f32() * 1000.,
                     encode_latency_ms: encoder_latency.as_secs_f32() * 1000.,
                     decode_latency_ms: client_stats.video_decode.as_secs_f32() * 1000.,
+                    uplink_latency_ms: uplink_latency
+                        .map(|_| self.uplink_latency_average.get_average().as_secs_f32() * 1000.),
+                    downlink_latency_ms: downlink_latency
+                        .map(|_| self.downlink_latency_average.get_average().as_secs_f32() * 1000.),
+                    clock_offset_ms: self
+                        .client_clock
+                        .map(|clock| clock.offset_s_at(self.epoch.elapsed()) as f32 * 1000.),
+                    clock_round_trip_ms: self
+                        .client_clock
+                        .map(|clock| clock.round_trip_time.as_secs_f32() * 1000.),
                     client_fps: client_fps as _,
                     server_fps: server_fps as _,
                     battery_hmd: (self
@@ -250,10 +362,16 @@ impl StatisticsManager {
                         .cloned()
                         .unwrap_or_default()
                         .is_plugged,
-                }));
+                };
+                self.snapshot.summary = Some(summary.clone());
+                alvr_events::send_event(EventType::StatisticsSummary(summary));
 
                 self.video_packets_partial_sum = 0;
                 self.video_bytes_partial_sum = 0;
+                self.fec_recovered_shards_partial_sum = 0;
+                self.server_encryption_time_partial_sum = Duration::ZERO;
+                self.client_encryption_time_partial_sum = Duration::ZERO;
+                self.encryption_overhead_bytes_partial_sum = 0;
             }
 
             let packet_bits = frame.video_packet_bytes as f32 * 8.0;
@@ -264,12 +382,14 @@ impl StatisticsManager {
 
             // todo: use target timestamp in nanoseconds. the dashboard needs to use the first
             // timestamp as the graph time origin.
-            alvr_events::send_event(EventType::GraphStatistics(GraphStatistics {
+            let graph_statistics = GraphStatistics {
                 total_pipeline_latency_s: client_stats.total_pipeline_latency.as_secs_f32(),
                 game_time_s: game_time_latency.as_secs_f32(),
                 server_compositor_s: server_compositor_latency.as_secs_f32(),
                 encoder_s: encoder_latency.as_secs_f32(),
                 network_s: network_latency.as_secs_f32(),
+                uplink_s: uplink_latency.map(|latency| latency.as_secs_f32()),
+                downlink_s: downlink_latency.map(|latency| latency.as_secs_f32()),
                 decoder_s: client_stats.video_decode.as_secs_f32(),
                 decoder_queue_s: client_stats.video_decoder_queue.as_secs_f32(),
                 client_compositor_s: client_stats.rendering.as_secs_f32(),
@@ -279,7 +399,16 @@ impl StatisticsManager {
                 bitrate_directives: self.last_throughput_directives.clone(),
                 throughput_bps,
                 bitrate_bps,
-            }));
+            };
+            METRICS.lock().report_latencies(&graph_statistics);
+            if let Some(timeline) = &mut self.timeline
+                && let Err(e) = timeline.write_frame(&graph_statistics, &client_stats)
+            {
+                error!("Failed to write statistics timeline: {e}");
+                self.timeline = None;
+            }
+            self.snapshot.graph = Some(graph_statistics.clone());
+            alvr_events::send_event(EventType::GraphStatistics(graph_statistics));
 
             (network_latency, game_time_latency)
         } else {
@@ -287,6 +416,10 @@ impl StatisticsManager {
         }
     }
 
+    pub fn snapshot(&self) -> StatisticsSnapshot {
+        self.snapshot.clone()
+    }
+
     pub fn motion_to_photon_latency_average(&self) -> Duration {
         self.motion_to_photon_latency_average.get_average()
     }
@@ -308,3 +441,68 @@ impl StatisticsManager {
         (self.last_vsync_time + self.frame_interval).saturating_duration_since(now)
     }
 }
+
+// Spectators are not part of the latency pipeline, only the transmission is monitored
+pub struct SpectatorStatisticsManager {
+    hostname: String,
+    last_report_instant: Instant,
+    video_packets_partial_sum: usize,
+    video_bytes_partial_sum: usize,
+    dropped_frames_partial_sum: usize,
+    last_client_stats: Option<ClientStatistics>,
+}
+
+impl SpectatorStatisticsManager {
+    pub fn new(hostname: String) -> Self {
+        Self {
+            hostname,
+            last_report_instant: Instant::now(),
+            video_packets_partial_sum: 0,
+            video_bytes_partial_sum: 0,
+            dropped_frames_partial_sum: 0,
+            last_client_stats: None,
+        }
+    }
+
+    pub fn report_frame_sent(&mut self, bytes_count: usize) {
+        self.video_packets_partial_sum += 1;
+        self.video_bytes_partial_sum += bytes_count;
+
+        self.try_report();
+    }
+
+    pub fn report_frame_dropped(&mut self) {
+        self.dropped_frames_partial_sum += 1;
+    }
+
+    pub fn report_client_statistics(&mut self, client_stats: ClientStatistics) {
+        self.last_client_stats = Some(client_stats);
+    }
+
+    fn try_report(&mut self) {
+        let now = Instant::now();
+        let interval_secs = (now - self.last_report_instant).as_secs_f32();
+        if interval_secs < FULL_REPORT_INTERVAL.as_secs_f32() {
+            return;
+        }
+        self.last_report_instant = now;
+
+        let client_stats = self.last_client_stats.take();
+        alvr_events::send_event(EventType::SpectatorStatistics(SpectatorStatistics {
+            hostname: self.hostname.clone(),
+            video_packets_per_sec: (self.video_packets_partial_sum as f32 / interval_secs) as _,
+            video_mbits_per_sec: self.video_bytes_partial_sum as f32 * 8. / 1e6 / interval_secs,
+            dropped_frames_per_sec: (self.dropped_frames_partial_sum as f32 / interval_secs) as _,
+            client_fps: client_stats.as_ref().map(|stats| {
+                (1.0 / Duration::max(stats.frame_interval, EPS_INTERVAL).as_secs_f32()) as _
+            }),
+            decode_latency_ms: client_stats
+                .as_ref()
+                .map(|stats| stats.video_decode.as_secs_f32() * 1000.),
+        }));
+
+        self.video_packets_partial_sum = 0;
+        self.video_bytes_partial_sum = 0;
+        self.dropped_frames_partial_sum = 0;
+    }
+}
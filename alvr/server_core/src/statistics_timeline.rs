@@ -0,0 +1,146 @@
+use crate::FILESYSTEM_LAYOUT;
+use alvr_common::{ALVR_VERSION, anyhow::Result};
+use alvr_events::GraphStatistics;
+use alvr_packets::ClientStatistics;
+use alvr_session::Settings;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+const COLUMNS: &[&str] = &[
+    "time_s",
+    "target_timestamp_s",
+    "total_pipeline_latency_s",
+    "game_time_s",
+    "server_compositor_s",
+    "encoder_s",
+    "network_s",
+    "uplink_s",
+    "downlink_s",
+    "decoder_s",
+    "decoder_queue_s",
+    "client_compositor_s",
+    "vsync_queue_s",
+    "client_fps",
+    "server_fps",
+    "throughput_bps",
+    "bitrate_bps",
+    "requested_bitrate_bps",
+    "client_frame_interval_s",
+    "client_fec_recovered_shards",
+    "client_encryption_time_s",
+    "client_video_first_shard_arrival_s",
+    "client_video_last_shard_arrival_s",
+    "client_tracking_sent_s",
+];
+
+// Writes the statistics of every frame to CSV files in the log directory. A new file is started
+// when the current one exceeds the maximum size. Each file starts with comment lines identifying
+// the streaming session and the settings used, so that files can be compared offline.
+pub struct StatisticsTimeline {
+    writer: BufWriter<File>,
+    file_prefix: String,
+    header: String,
+    file_index: usize,
+    bytes_written: u64,
+    max_file_size: u64,
+    start_instant: Instant,
+}
+
+impl StatisticsTimeline {
+    pub fn new(client_hostname: &str, settings: &Settings, max_file_size: u64) -> Result<Self> {
+        let start_time = chrono::Local::now().format("%F.%H-%M-%S");
+        let session_id = format!("{client_hostname}.{start_time}");
+        let settings_hash = alvr_common::hash_string(&serde_json::to_string(settings)?);
+
+        let header = format!(
+            "# session_id={session_id}\n# settings_hash={settings_hash:016x}\n# alvr_version={}\n{}\n",
+            *ALVR_VERSION,
+            COLUMNS.join(",")
+        );
+
+        let file_prefix = format!("statistics.{start_time}");
+        let mut writer = create_file(&file_prefix, 0)?;
+        writer.write_all(header.as_bytes())?;
+
+        Ok(Self {
+            writer,
+            file_prefix,
+            bytes_written: header.len() as u64,
+            header,
+            file_index: 0,
+            max_file_size,
+            start_instant: Instant::now(),
+        })
+    }
+
+    pub fn write_frame(
+        &mut self,
+        graph_statistics: &GraphStatistics,
+        client_stats: &ClientStatistics,
+    ) -> Result<()> {
+        if self.bytes_written >= self.max_file_size {
+            self.writer.flush()?;
+
+            self.file_index += 1;
+            self.writer = create_file(&self.file_prefix, self.file_index)?;
+            self.writer.write_all(self.header.as_bytes())?;
+            self.bytes_written = self.header.len() as u64;
+        }
+
+        let secs = |duration: Duration| duration.as_secs_f32().to_string();
+        let optional = |value: Option<f32>| value.map(|v| v.to_string()).unwrap_or_default();
+
+        let g = graph_statistics;
+        let row = [
+            secs(self.start_instant.elapsed()),
+            secs(client_stats.target_timestamp),
+            g.total_pipeline_latency_s.to_string(),
+            g.game_time_s.to_string(),
+            g.server_compositor_s.to_string(),
+            g.encoder_s.to_string(),
+            g.network_s.to_string(),
+            optional(g.uplink_s),
+            optional(g.downlink_s),
+            g.decoder_s.to_string(),
+            g.decoder_queue_s.to_string(),
+            g.client_compositor_s.to_string(),
+            g.vsync_queue_s.to_string(),
+            g.client_fps.to_string(),
+            g.server_fps.to_string(),
+            g.throughput_bps.to_string(),
+            g.bitrate_bps.to_string(),
+            g.bitrate_directives.requested_bitrate_bps.to_string(),
+            secs(client_stats.frame_interval),
+            client_stats.fec_recovered_shards.to_string(),
+            secs(client_stats.encryption_time),
+            secs(client_stats.video_first_shard_arrival),
+            secs(client_stats.video_last_shard_arrival),
+            secs(client_stats.tracking_sent),
+        ]
+        .join(",");
+
+        writeln!(self.writer, "{row}")?;
+        self.bytes_written += row.len() as u64 + 1;
+
+        Ok(())
+    }
+}
+
+impl Drop for StatisticsTimeline {
+    fn drop(&mut self) {
+        self.writer.flush().ok();
+    }
+}
+
+fn create_file(prefix: &str, index: usize) -> Result<BufWriter<File>> {
+    let path = FILESYSTEM_LAYOUT
+        .get()
+        .unwrap()
+        .log_dir
+        .join(format!("{prefix}.{index}.csv"));
+
+    Ok(BufWriter::new(File::create(path)?))
+}
@@ -0,0 +1,325 @@
+use alvr_common::{DeviceMotion, Pose, glam::Vec3, inputs as inp};
+use alvr_session::{
+    OneEuroFilterConfig, OutlierRejectionConfig, PoseFilterConfig, PoseFiltersConfig,
+    PoseSmoothingConfig, settings_schema::Switch,
+};
+use std::{f32::consts::PI, time::Duration};
+
+const DEG_TO_RAD: f32 = PI / 180.0;
+
+// Blending factor of a first order low pass filter
+fn smoothing_factor(delta_time_s: f32, cutoff_hz: f32) -> f32 {
+    let time_constant = 1.0 / (2.0 * PI * cutoff_hz);
+
+    1.0 / (1.0 + time_constant / delta_time_s)
+}
+
+fn blend_pose(
+    previous: Pose,
+    current: Pose,
+    orientation_factor: f32,
+    position_factor: f32,
+) -> Pose {
+    Pose {
+        orientation: previous
+            .orientation
+            .slerp(current.orientation, orientation_factor),
+        position: previous.position.lerp(current.position, position_factor),
+    }
+}
+
+// Speeds of the movement from one pose to another
+fn pose_speeds(from: Pose, to: Pose, delta_time_s: f32) -> (Vec3, Vec3) {
+    let mut delta_orientation = to.orientation * from.orientation.inverse();
+    if delta_orientation.w < 0.0 {
+        delta_orientation = -delta_orientation;
+    }
+
+    (
+        (to.position - from.position) / delta_time_s,
+        delta_orientation.to_scaled_axis() / delta_time_s,
+    )
+}
+
+pub fn device_filter_config(
+    config: &PoseFiltersConfig,
+    device_id: u64,
+) -> Option<&PoseFilterConfig> {
+    if device_id == *inp::HEAD_ID {
+        Some(&config.head)
+    } else if [
+        *inp::HAND_LEFT_ID,
+        *inp::HAND_RIGHT_ID,
+        *inp::DETACHED_CONTROLLER_LEFT_ID,
+        *inp::DETACHED_CONTROLLER_RIGHT_ID,
+    ]
+    .contains(&device_id)
+    {
+        Some(&config.controllers)
+    } else if [
+        *inp::BODY_CHEST_ID,
+        *inp::BODY_HIPS_ID,
+        *inp::BODY_LEFT_ELBOW_ID,
+        *inp::BODY_RIGHT_ELBOW_ID,
+        *inp::BODY_LEFT_KNEE_ID,
+        *inp::BODY_LEFT_FOOT_ID,
+        *inp::BODY_RIGHT_KNEE_ID,
+        *inp::BODY_RIGHT_FOOT_ID,
+    ]
+    .contains(&device_id)
+    {
+        Some(&config.body_trackers)
+    } else if [
+        *inp::GENERIC_TRACKER_1_ID,
+        *inp::GENERIC_TRACKER_2_ID,
+        *inp::GENERIC_TRACKER_3_ID,
+    ]
+    .contains(&device_id)
+    {
+        Some(&config.generic_trackers)
+    } else {
+        None
+    }
+}
+
+struct OneEuroState {
+    linear_speed: Vec3,
+    angular_speed: Vec3,
+}
+
+struct FilterState {
+    timestamp: Duration,
+    // Last accepted sample, before smoothing
+    raw_pose: Pose,
+    filtered_pose: Pose,
+    one_euro: OneEuroState,
+    consecutive_rejections: usize,
+}
+
+// Filter pipeline for the poses of a single device: outlier rejection, then smoothing. The
+// configuration is passed on each sample so that settings can change at runtime. Velocities are
+// passed through unchanged.
+#[derive(Default)]
+pub struct PoseFilter {
+    state: Option<FilterState>,
+}
+
+impl PoseFilter {
+    pub fn filter(
+        &mut self,
+        config: &PoseFilterConfig,
+        timestamp: Duration,
+        motion: DeviceMotion,
+    ) -> DeviceMotion {
+        let Some(state) = self
+            .state
+            .as_mut()
+            .filter(|state| timestamp >= state.timestamp)
+        else {
+            // First sample, or timestamps went back in time (reconnection)
+            self.state = Some(FilterState {
+                timestamp,
+                raw_pose: motion.pose,
+                filtered_pose: motion.pose,
+                one_euro: OneEuroState {
+                    linear_speed: Vec3::ZERO,
+                    angular_speed: Vec3::ZERO,
+                },
+                consecutive_rejections: 0,
+            });
+
+            return motion;
+        };
+
+        // The same sample can be received more than once, it must not advance the filter
+        if timestamp == state.timestamp {
+            return DeviceMotion {
+                pose: state.filtered_pose,
+                ..motion
+            };
+        }
+
+        let delta_time_s = (timestamp - state.timestamp).as_secs_f32();
+        let (linear_speed, angular_speed) = pose_speeds(state.raw_pose, motion.pose, delta_time_s);
+
+        if let Switch::Enabled(config) = &config.outlier_rejection
+            && is_outlier(config, linear_speed, angular_speed)
+            && state.consecutive_rejections < config.max_consecutive_rejections
+        {
+            state.consecutive_rejections += 1;
+
+            return DeviceMotion {
+                pose: state.filtered_pose,
+                ..motion
+            };
+        }
+
+        state.consecutive_rejections = 0;
+        state.timestamp = timestamp;
+        state.raw_pose = motion.pose;
+
+        state.filtered_pose = match &config.smoothing {
+            Switch::Enabled(PoseSmoothingConfig::OneEuro(config)) => one_euro(
+                config,
+                &mut state.one_euro,
+                state.filtered_pose,
+                motion.pose,
+                (linear_speed, angular_speed),
+                delta_time_s,
+            ),
+            Switch::Enabled(PoseSmoothingConfig::Exponential { smoothing }) => {
+                let factor = 1.0 - smoothing;
+                blend_pose(state.filtered_pose, motion.pose, factor, factor)
+            }
+            Switch::Disabled => motion.pose,
+        };
+
+        DeviceMotion {
+            pose: state.filtered_pose,
+            ..motion
+        }
+    }
+}
+
+fn is_outlier(config: &OutlierRejectionConfig, linear_speed: Vec3, angular_speed: Vec3) -> bool {
+    linear_speed.length() > config.max_linear_speed
+        || angular_speed.length() > config.max_angular_speed_deg * DEG_TO_RAD
+}
+
+// https://gery.casiez.net/1euro/
+// Position and orientation are filtered separately, each using the magnitude of its own speed.
+fn one_euro(
+    config: &OneEuroFilterConfig,
+    state: &mut OneEuroState,
+    previous: Pose,
+    current: Pose,
+    (linear_speed, angular_speed): (Vec3, Vec3),
+    delta_time_s: f32,
+) -> Pose {
+    let speed_factor = smoothing_factor(delta_time_s, config.derivative_cutoff_hz);
+    state.linear_speed = state.linear_speed.lerp(linear_speed, speed_factor);
+    state.angular_speed = state.angular_speed.lerp(angular_speed, speed_factor);
+
+    let factor = |speed: Vec3| {
+        smoothing_factor(
+            delta_time_s,
+            config.min_cutoff_hz + config.beta * speed.length(),
+        )
+    };
+
+    blend_pose(
+        previous,
+        current,
+        factor(state.angular_speed),
+        factor(state.linear_speed),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motion_at(position: Vec3) -> DeviceMotion {
+        DeviceMotion {
+            pose: Pose {
+                position,
+                ..Pose::IDENTITY
+            },
+            ..DeviceMotion::IDENTITY
+        }
+    }
+
+    #[test]
+    fn test_outlier_rejection() {
+        let config = PoseFilterConfig {
+            outlier_rejection: Switch::Enabled(OutlierRejectionConfig {
+                max_linear_speed: 10.0,
+                max_angular_speed_deg: 1800.0,
+                max_consecutive_rejections: 2,
+            }),
+            smoothing: Switch::Disabled,
+        };
+        let mut filter = PoseFilter::default();
+        let ms = Duration::from_millis;
+
+        filter.filter(&config, ms(0), motion_at(Vec3::ZERO));
+
+        // 1 m in 10 ms
+        let jump = motion_at(Vec3::X);
+        assert_eq!(
+            filter.filter(&config, ms(10), jump).pose.position,
+            Vec3::ZERO
+        );
+        assert_eq!(
+            filter.filter(&config, ms(20), jump).pose.position,
+            Vec3::ZERO
+        );
+        // Too many rejections, the jump is real
+        assert_eq!(filter.filter(&config, ms(30), jump).pose.position, Vec3::X);
+
+        let small_step = motion_at(Vec3::new(1.05, 0.0, 0.0));
+        assert_eq!(
+            filter.filter(&config, ms(40), small_step).pose.position,
+            small_step.pose.position
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let config = PoseFilterConfig {
+            outlier_rejection: Switch::Disabled,
+            smoothing: Switch::Enabled(PoseSmoothingConfig::Exponential { smoothing: 0.5 }),
+        };
+        let mut filter = PoseFilter::default();
+        let ms = Duration::from_millis;
+
+        filter.filter(&config, ms(0), motion_at(Vec3::ZERO));
+        let filtered = filter.filter(&config, ms(10), motion_at(Vec3::X));
+        assert!(filtered.pose.position.x > 0.0 && filtered.pose.position.x < 1.0);
+
+        // A duplicate sample does not move the filtered pose
+        let duplicate = filter.filter(&config, ms(10), motion_at(Vec3::X));
+        assert_eq!(duplicate.pose.position, filtered.pose.position);
+
+        // Going back in time resets the filter
+        let reset = filter.filter(&config, ms(5), motion_at(Vec3::Y));
+        assert_eq!(reset.pose.position, Vec3::Y);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        for smoothing in [
+            PoseSmoothingConfig::OneEuro(OneEuroFilterConfig {
+                min_cutoff_hz: 1.0,
+                beta: 1.0,
+                derivative_cutoff_hz: 1.0,
+            }),
+            PoseSmoothingConfig::Exponential { smoothing: 0.5 },
+        ] {
+            let config = PoseFilterConfig {
+                outlier_rejection: Switch::Disabled,
+                smoothing: Switch::Enabled(smoothing),
+            };
+            let mut filter = PoseFilter::default();
+
+            filter.filter(&config, Duration::ZERO, motion_at(Vec3::ZERO));
+
+            // Jitter is attenuated
+            let jittery = motion_at(Vec3::new(0.01, 0.0, 0.0));
+            let filtered = filter.filter(&config, Duration::from_millis(10), jittery);
+            assert!(filtered.pose.position.x > 0.0 && filtered.pose.position.x < 0.01);
+
+            // A still pose converges
+            let mut filtered = filtered;
+            for i in 2..200 {
+                filtered = filter.filter(&config, Duration::from_millis(i * 10), jittery);
+            }
+            assert!(
+                filtered
+                    .pose
+                    .position
+                    .abs_diff_eq(jittery.pose.position, 1e-5)
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod body;
 mod face;
+mod filters;
 mod vmc;
 
 pub use body::*;
@@ -11,6 +12,7 @@ use crate::{
     connection::STREAMING_RECV_TIMEOUT,
     hand_gestures::{self, HAND_GESTURE_BUTTON_SET, HandGestureManager},
     input_mapping::ButtonMappingManager,
+    session_recording::SessionRecordEntry,
 };
 use alvr_common::{
     ConnectionError, DEVICE_ID_TO_PATH, DeviceMotion, Pose, ViewParams,
@@ -25,7 +27,7 @@ use alvr_session::{
 };
 use alvr_sockets::StreamReceiver;
 use std::{
-    cmp::Ordering,
+    array,
     collections::{HashMap, VecDeque},
     f32::consts::PI,
     time::Duration,
@@ -33,12 +35,103 @@ use std::{
 
 const DEG_TO_RAD: f32 = PI / 180.0;
 
+// Maximum time a pose is predicted past the newest sample. Later timestamps are treated as if the
+// device is not tracked anymore.
+const MAX_EXTRAPOLATION: Duration = Duration::from_millis(100);
+
 #[derive(Debug)]
 pub enum HandType {
     Left = 0,
     Right = 1,
 }
 
+// Samples of a history around a timestamp. Histories are sorted from oldest to newest.
+enum HistorySamples<'a, T> {
+    // Timestamp matches a sample or is older than the whole history
+    Single(&'a T),
+    Between {
+        older: &'a (Duration, T),
+        newer: &'a (Duration, T),
+    },
+    // Timestamp is more recent than the whole history
+    After {
+        previous: Option<&'a (Duration, T)>,
+        newest: &'a (Duration, T),
+    },
+}
+
+// Poll timestamps can go back in time, the samples are kept sorted. A sample with the same timestamp
+// as an existing one replaces it.
+fn insert_history_sample<T>(
+    history: &mut VecDeque<(Duration, T)>,
+    max_size: usize,
+    timestamp: Duration,
+    sample: T,
+) {
+    let index = history.partition_point(|(ts, _)| *ts < timestamp);
+
+    if let Some((ts, old_sample)) = history.get_mut(index)
+        && *ts == timestamp
+    {
+        *old_sample = sample;
+    } else {
+        history.insert(index, (timestamp, sample));
+    }
+
+    if history.len() > max_size {
+        history.pop_front();
+    }
+}
+
+fn find_history_samples<T>(
+    history: &VecDeque<(Duration, T)>,
+    timestamp: Duration,
+) -> Option<HistorySamples<'_, T>> {
+    let index = history.partition_point(|(ts, _)| *ts < timestamp);
+
+    if index == history.len() {
+        let newest = history.back()?;
+        let previous = index.checked_sub(2).map(|i| &history[i]);
+
+        Some(HistorySamples::After { previous, newest })
+    } else if index == 0 || history[index].0 == timestamp {
+        Some(HistorySamples::Single(&history[index].1))
+    } else {
+        Some(HistorySamples::Between {
+            older: &history[index - 1],
+            newer: &history[index],
+        })
+    }
+}
+
+fn interpolation_factor(from: Duration, to: Duration, timestamp: Duration) -> f32 {
+    (timestamp - from).as_secs_f32() / (to - from).as_secs_f32()
+}
+
+fn interpolate_pose(a: Pose, b: Pose, factor: f32) -> Pose {
+    Pose {
+        orientation: a.orientation.slerp(b.orientation, factor),
+        position: a.position.lerp(b.position, factor),
+    }
+}
+
+// Used for hand joints, which are reported without velocities
+fn motion_from_poses(previous: Pose, current: Pose, delta_time: Duration) -> DeviceMotion {
+    let delta_time_s = delta_time.as_secs_f32();
+
+    // Take the shortest path
+    let mut delta_orientation = current.orientation * previous.orientation.inverse();
+    if delta_orientation.w < 0.0 {
+        delta_orientation = -delta_orientation;
+    }
+
+    DeviceMotion {
+        pose: current,
+        linear_velocity: (current.position - previous.position) / delta_time_s,
+        angular_velocity: delta_orientation.to_scaled_axis() / delta_time_s,
+    }
+}
+
 // todo: Move this struct to Settings and use it for every tracked device
 #[derive(Default)]
 struct MotionConfig {
@@ -54,6 +147,7 @@ pub struct TrackingManager {
     device_motions_history: HashMap<u64, VecDeque<(Duration, DeviceMotion)>>,
     hand_skeletons_history: [VecDeque<(Duration, [Pose; 26])>; 2],
     max_history_size: usize,
+    pose_filters: HashMap<u64, filters::PoseFilter>,
 }
 
 impl TrackingManager {
@@ -64,6 +158,7 @@ impl TrackingManager {
             device_motions_history: HashMap::new(),
             hand_skeletons_history: [VecDeque::new(), VecDeque::new()],
             max_history_size,
+            pose_filters: HashMap::new(),
         }
     }
 
@@ -174,54 +269,55 @@ impl TrackingManager {
                     cutoff(motion.angular_velocity, config.angular_velocity_cutoff);
             }
 
-            if let Some(motions) = self.device_motions_history.get_mut(&device_id) {
-                motions.push_front((timestamp, motion));
-
-                if motions.len() > self.max_history_size {
-                    motions.pop_back();
-                }
-            } else {
-                self.device_motions_history
-                    .insert(device_id, VecDeque::from(vec![(timestamp, motion)]));
+            if let Switch::Enabled(filters_config) = &headset_config.pose_filters
+                && let Some(config) = filters::device_filter_config(filters_config, device_id)
+            {
+                motion = self
+                    .pose_filters
+                    .entry(device_id)
+                    .or_default()
+                    .filter(config, timestamp, motion);
             }
+
+            insert_history_sample(
+                self.device_motions_history.entry(device_id).or_default(),
+                self.max_history_size,
+                timestamp,
+                motion,
+            );
         }
     }
 
-    // If the exact sample_timestamp is not found, use the closest one if it's not older. This makes
-    // sure that we return None if there is no newer sample and always return Some otherwise.
+    // Between two samples, the pose and velocities are interpolated. Past the newest sample, the
+    // motion is extrapolated using its velocities, for at most MAX_EXTRAPOLATION. Timestamps older
+    // than the whole history get the oldest sample.
     pub fn get_device_motion(
         &self,
         device_id: u64,
         sample_timestamp: Duration,
     ) -> Option<DeviceMotion> {
-        self.device_motions_history
-            .get(&device_id)
-            .and_then(|motions| {
-                // Get first element to initialize a valid motion reference
-                if let Some((_, motion)) = motions.front() {
-                    let mut best_timestamp_diff = Duration::MAX;
-                    let mut best_motion_ref = motion;
-
-                    // Note: we are iterating from most recent to oldest
-                    for (ts, m) in motions {
-                        match ts.cmp(&sample_timestamp) {
-                            Ordering::Equal => return Some(*m),
-                            Ordering::Greater => {
-                                let diff = ts.saturating_sub(sample_timestamp);
-                                if diff < best_timestamp_diff {
-                                    best_timestamp_diff = diff;
-                                    best_motion_ref = m;
-                                }
-                            }
-                            Ordering::Less => continue,
-                        }
-                    }
-
-                    (best_timestamp_diff != Duration::MAX).then_some(*best_motion_ref)
-                } else {
-                    None
-                }
-            })
+        let history = self.device_motions_history.get(&device_id)?;
+
+        match find_history_samples(history, sample_timestamp)? {
+            HistorySamples::Single(motion) => Some(*motion),
+            HistorySamples::Between {
+                older: (older_ts, older),
+                newer: (newer_ts, newer),
+            } => {
+                let factor = interpolation_factor(*older_ts, *newer_ts, sample_timestamp);
+
+                Some(DeviceMotion {
+                    pose: interpolate_pose(older.pose, newer.pose, factor),
+                    linear_velocity: older.linear_velocity.lerp(newer.linear_velocity, factor),
+                    angular_velocity: older.angular_velocity.lerp(newer.angular_velocity, factor),
+                })
+            }
+            HistorySamples::After {
+                newest: (newest_ts, newest),
+                ..
+            } => (sample_timestamp - *newest_ts <= MAX_EXTRAPOLATION)
+                .then(|| newest.predict(*newest_ts, sample_timestamp)),
+        }
     }
 
     pub fn report_hand_skeleton(
@@ -234,24 +330,55 @@ impl TrackingManager {
             *pose = self.recenter_pose(*pose);
         }
 
-        let skeleton_history = &mut self.hand_skeletons_history[hand_type as usize];
-
-        skeleton_history.push_back((timestamp, skeleton));
-
-        if skeleton_history.len() > self.max_history_size {
-            skeleton_history.pop_front();
-        }
+        insert_history_sample(
+            &mut self.hand_skeletons_history[hand_type as usize],
+            self.max_history_size,
+            timestamp,
+            skeleton,
+        );
     }
 
+    // Same as get_device_motion(), applied to each joint. Joint velocities are estimated from the
+    // two newest samples.
     pub fn get_hand_skeleton(
         &self,
         hand_type: HandType,
         sample_timestamp: Duration,
-    ) -> Option<&[Pose; 26]> {
-        self.hand_skeletons_history[hand_type as usize]
-            .iter()
-            .find(|(timestamp, _)| *timestamp == sample_timestamp)
-            .map(|(_, skeleton)| skeleton)
+    ) -> Option<[Pose; 26]> {
+        let history = &self.hand_skeletons_history[hand_type as usize];
+
+        match find_history_samples(history, sample_timestamp)? {
+            HistorySamples::Single(skeleton) => Some(*skeleton),
+            HistorySamples::Between {
+                older: (older_ts, older),
+                newer: (newer_ts, newer),
+            } => {
+                let factor = interpolation_factor(*older_ts, *newer_ts, sample_timestamp);
+
+                Some(array::from_fn(|i| {
+                    interpolate_pose(older[i], newer[i], factor)
+                }))
+            }
+            HistorySamples::After {
+                previous,
+                newest: (newest_ts, newest),
+            } => {
+                if sample_timestamp - *newest_ts > MAX_EXTRAPOLATION {
+                    return None;
+                }
+
+                let Some((previous_ts, previous)) = previous.filter(|(ts, _)| ts < newest_ts)
+                else {
+                    return Some(*newest);
+                };
+
+                Some(array::from_fn(|i| {
+                    motion_from_poses(previous[i], newest[i], *newest_ts - *previous_ts)
+                        .predict(*newest_ts, sample_timestamp)
+                        .pose
+                }))
+            }
+        }
     }
 
     pub fn unrecenter_view_params(&self, view_params: &mut [ViewParams; 2]) {
@@ -261,65 +388,65 @@ impl TrackingManager {
     }
 }
 
-pub fn tracking_loop(
-    ctx: &ConnectionContext,
-    initial_settings: Settings,
-    mut tracking_receiver: StreamReceiver<TrackingData>,
-    is_streaming: impl Fn() -> bool,
-) {
-    let mut hand_gesture_manager = HandGestureManager::new();
+// Applies the tracking received from the client (or from a session replay) to the tracking
+// manager, the hand gestures and the external sinks
+pub struct TrackingProcessor {
+    hand_gesture_manager: HandGestureManager,
+    gestures_button_mapping_manager: Option<ButtonMappingManager>,
+    face_tracking_sink: Option<FaceTrackingSink>,
+    body_tracking_sink: Option<BodyTrackingSink>,
+    vmc_sink: Option<VMCSink>,
+}
+
+impl TrackingProcessor {
+    pub fn new(initial_settings: Settings) -> Self {
+        let gestures_button_mapping_manager =
+            initial_settings
+                .headset
+                .controllers
+                .as_option()
+                .map(|config| {
+                    ButtonMappingManager::new_automatic(
+                        &HAND_GESTURE_BUTTON_SET,
+                        &config.emulation_mode,
+                        &config.button_mapping_config,
+                    )
+                });
 
-    let mut gestures_button_mapping_manager =
-        initial_settings
+        let face_tracking_sink = initial_settings
             .headset
-            .controllers
-            .as_option()
-            .map(|config| {
-                ButtonMappingManager::new_automatic(
-                    &HAND_GESTURE_BUTTON_SET,
-                    &config.emulation_mode,
-                    &config.button_mapping_config,
-                )
+            .face_tracking
+            .into_option()
+            .and_then(|config| {
+                FaceTrackingSink::new(config.sink, initial_settings.connection.osc_local_port).ok()
             });
 
-    let mut face_tracking_sink = initial_settings
-        .headset
-        .face_tracking
-        .into_option()
-        .and_then(|config| {
-            FaceTrackingSink::new(config.sink, initial_settings.connection.osc_local_port).ok()
-        });
-
-    let mut body_tracking_sink = initial_settings
-        .headset
-        .body_tracking
-        .into_option()
-        .and_then(|config| {
-            BodyTrackingSink::new(config.sink, initial_settings.connection.osc_local_port).ok()
-        });
-
-    let mut vmc_sink = initial_settings
-        .headset
-        .vmc
-        .into_option()
-        .and_then(|config| VMCSink::new(config).ok());
+        let body_tracking_sink = initial_settings
+            .headset
+            .body_tracking
+            .into_option()
+            .and_then(|config| {
+                BodyTrackingSink::new(config.sink, initial_settings.connection.osc_local_port).ok()
+            });
 
-    while is_streaming() {
-        let data = match tracking_receiver.recv(STREAMING_RECV_TIMEOUT) {
-            Ok(tracking) => tracking,
-            Err(ConnectionError::TryAgain(_)) => continue,
-            Err(ConnectionError::Other(_)) => return,
-        };
-        let Ok(mut tracking) = data.get_header() else {
-            return;
-        };
+        let vmc_sink = initial_settings
+            .headset
+            .vmc
+            .into_option()
+            .and_then(|config| VMCSink::new(config).ok());
+
+        Self {
+            hand_gesture_manager: HandGestureManager::new(),
+            gestures_button_mapping_manager,
+            face_tracking_sink,
+            body_tracking_sink,
+            vmc_sink,
+        }
+    }
 
+    pub fn process(&mut self, ctx: &ConnectionContext, mut tracking: TrackingData) {
         let timestamp = tracking.poll_timestamp;
 
-        if let Some(stats) = &mut *ctx.statistics_manager.write() {
-            stats.report_tracking_received(timestamp);
-        }
-
         let controllers_config = {
             let data_lock = SESSION_MANAGER.read();
             data_lock
@@ -375,7 +502,7 @@ pub fn tracking_loop(
                 tracking_manager_lock.report_hand_skeleton(HandType::Right, timestamp, skeleton);
             }
 
-            if let Some(sink) = &mut face_tracking_sink {
+            if let Some(sink) = &mut self.face_tracking_sink {
                 sink.send_tracking(&tracking.face);
             }
 
@@ -405,42 +532,52 @@ pub fn tracking_loop(
             controllers_config
                 .as_ref()
                 .and_then(|c| c.hand_tracking_interaction.as_option()),
-            &mut gestures_button_mapping_manager,
+            &mut self.gestures_button_mapping_manager,
         ) {
             if !device_motion_keys.contains(&*inp::HAND_LEFT_ID)
                 && let Some(hand_skeleton) = tracking.hand_skeletons[0]
             {
+                let mut entries = hand_gestures::trigger_hand_gesture_actions(
+                    gestures_button_mapping_manager,
+                    *inp::HAND_LEFT_ID,
+                    &self.hand_gesture_manager.get_active_gestures(
+                        &hand_skeleton,
+                        gestures_config,
+                        *inp::HAND_LEFT_ID,
+                    ),
+                    gestures_config.only_touch,
+                );
+                entries.extend(self.hand_gesture_manager.get_custom_gesture_entries(
+                    &hand_skeleton,
+                    gestures_config,
+                    *inp::HAND_LEFT_ID,
+                ));
+
                 ctx.events_sender
-                    .send(ServerCoreEvent::Buttons(
-                        hand_gestures::trigger_hand_gesture_actions(
-                            gestures_button_mapping_manager,
-                            *inp::HAND_LEFT_ID,
-                            &hand_gesture_manager.get_active_gestures(
-                                &hand_skeleton,
-                                gestures_config,
-                                *inp::HAND_LEFT_ID,
-                            ),
-                            gestures_config.only_touch,
-                        ),
-                    ))
+                    .send(ServerCoreEvent::Buttons(entries))
                     .ok();
             }
             if !device_motion_keys.contains(&*inp::HAND_RIGHT_ID)
                 && let Some(hand_skeleton) = tracking.hand_skeletons[1]
             {
+                let mut entries = hand_gestures::trigger_hand_gesture_actions(
+                    gestures_button_mapping_manager,
+                    *inp::HAND_RIGHT_ID,
+                    &self.hand_gesture_manager.get_active_gestures(
+                        &hand_skeleton,
+                        gestures_config,
+                        *inp::HAND_RIGHT_ID,
+                    ),
+                    gestures_config.only_touch,
+                );
+                entries.extend(self.hand_gesture_manager.get_custom_gesture_entries(
+                    &hand_skeleton,
+                    gestures_config,
+                    *inp::HAND_RIGHT_ID,
+                ));
+
                 ctx.events_sender
-                    .send(ServerCoreEvent::Buttons(
-                        hand_gestures::trigger_hand_gesture_actions(
-                            gestures_button_mapping_manager,
-                            *inp::HAND_RIGHT_ID,
-                            &hand_gesture_manager.get_active_gestures(
-                                &hand_skeleton,
-                                gestures_config,
-                                *inp::HAND_RIGHT_ID,
-                            ),
-                            gestures_config.only_touch,
-                        ),
-                    ))
+                    .send(ServerCoreEvent::Buttons(entries))
                     .ok();
             }
         }
@@ -464,7 +601,7 @@ pub fn tracking_loop(
                 })
             );
 
-            if let Some(sink) = &mut vmc_sink {
+            if let Some(sink) = &mut self.vmc_sink {
                 let tracking_manager_lock = ctx.tracking_manager.read();
                 let device_motions = device_motion_keys
                     .iter()
@@ -492,7 +629,7 @@ pub fn tracking_loop(
             SESSION_MANAGER.read().settings().headset.body_tracking,
             Switch::Enabled(BodyTrackingConfig { tracked: true, .. })
         );
-        if track_body && let Some(sink) = &mut body_tracking_sink {
+        if track_body && let Some(sink) = &mut self.body_tracking_sink {
             let tracking_manager_lock = ctx.tracking_manager.read();
             let device_motions = device_motion_keys
                 .iter()
@@ -509,3 +646,190 @@ pub fn tracking_loop(
         }
     }
 }
+
+pub fn tracking_loop(
+    ctx: &ConnectionContext,
+    initial_settings: Settings,
+    mut tracking_receiver: StreamReceiver<TrackingData>,
+    is_streaming: impl Fn() -> bool,
+) {
+    let mut tracking_processor = TrackingProcessor::new(initial_settings);
+
+    while is_streaming() {
+        let data = match tracking_receiver.recv(STREAMING_RECV_TIMEOUT) {
+            Ok(tracking) => tracking,
+            Err(ConnectionError::TryAgain(_)) => continue,
+            Err(ConnectionError::Other(_)) => return,
+        };
+        let Ok(tracking) = data.get_header() else {
+            return;
+        };
+
+        if let Some(stats) = &mut *ctx.statistics_manager.write() {
+            stats.report_tracking_received(tracking.poll_timestamp);
+        }
+
+        if let Some(recorder) = &mut *ctx.session_recorder.lock() {
+            recorder.record(SessionRecordEntry::Tracking(Box::new(tracking.clone())));
+        }
+
+        tracking_processor.process(ctx, tracking);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINEAR_VELOCITY: Vec3 = Vec3::new(1.0, 0.5, -2.0);
+    const ANGULAR_VELOCITY: Vec3 = Vec3::new(0.0, 3.0, 0.0);
+
+    // Constant linear and angular velocity, starting from the identity pose
+    fn synthetic_motion(timestamp: Duration) -> DeviceMotion {
+        DeviceMotion {
+            linear_velocity: LINEAR_VELOCITY,
+            angular_velocity: ANGULAR_VELOCITY,
+            ..DeviceMotion::IDENTITY
+        }
+        .predict(Duration::ZERO, timestamp)
+    }
+
+    // Joints follow the same motion, spread along the X axis
+    fn synthetic_skeleton(timestamp: Duration) -> [Pose; 26] {
+        let pose = synthetic_motion(timestamp).pose;
+
+        array::from_fn(|i| Pose {
+            orientation: pose.orientation,
+            position: pose.position + Vec3::X * i as f32 * 0.01,
+        })
+    }
+
+    fn ms(value: u64) -> Duration {
+        Duration::from_millis(value)
+    }
+
+    fn assert_pose_eq(a: Pose, b: Pose) {
+        assert!(a.position.abs_diff_eq(b.position, 1e-4), "{a:?} != {b:?}");
+        assert!(
+            a.orientation.abs_diff_eq(b.orientation, 1e-4)
+                || a.orientation.abs_diff_eq(-b.orientation, 1e-4),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    fn manager_with_samples(timestamps: &[Duration]) -> TrackingManager {
+        let mut manager = TrackingManager::new(16);
+
+        for &timestamp in timestamps {
+            insert_history_sample(
+                manager
+                    .device_motions_history
+                    .entry(*inp::HEAD_ID)
+                    .or_default(),
+                manager.max_history_size,
+                timestamp,
+                synthetic_motion(timestamp),
+            );
+            manager.report_hand_skeleton(HandType::Left, timestamp, synthetic_skeleton(timestamp));
+        }
+
+        manager
+    }
+
+    #[test]
+    fn test_exact_sample() {
+        let manager = manager_with_samples(&[ms(0), ms(10), ms(20)]);
+
+        let motion = manager.get_device_motion(*inp::HEAD_ID, ms(10)).unwrap();
+        assert_pose_eq(motion.pose, synthetic_motion(ms(10)).pose);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let manager = manager_with_samples(&[ms(0), ms(10), ms(20)]);
+
+        for timestamp in [ms(3), ms(15), ms(19)] {
+            let motion = manager.get_device_motion(*inp::HEAD_ID, timestamp).unwrap();
+            assert_pose_eq(motion.pose, synthetic_motion(timestamp).pose);
+            assert!(motion.linear_velocity.abs_diff_eq(LINEAR_VELOCITY, 1e-4));
+            assert!(motion.angular_velocity.abs_diff_eq(ANGULAR_VELOCITY, 1e-4));
+
+            let skeleton = manager
+                .get_hand_skeleton(HandType::Left, timestamp)
+                .unwrap();
+            for (joint, expected) in skeleton.iter().zip(synthetic_skeleton(timestamp)) {
+                assert_pose_eq(*joint, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extrapolation() {
+        let manager = manager_with_samples(&[ms(0), ms(10), ms(20)]);
+
+        for timestamp in [ms(25), ms(20) + MAX_EXTRAPOLATION] {
+            let motion = manager.get_device_motion(*inp::HEAD_ID, timestamp).unwrap();
+            assert_pose_eq(motion.pose, synthetic_motion(timestamp).pose);
+
+            let skeleton = manager
+                .get_hand_skeleton(HandType::Left, timestamp)
+                .unwrap();
+            for (joint, expected) in skeleton.iter().zip(synthetic_skeleton(timestamp)) {
+                assert_pose_eq(*joint, expected);
+            }
+        }
+
+        let stale_timestamp = ms(21) + MAX_EXTRAPOLATION;
+        assert!(
+            manager
+                .get_device_motion(*inp::HEAD_ID, stale_timestamp)
+                .is_none()
+        );
+        assert!(
+            manager
+                .get_hand_skeleton(HandType::Left, stale_timestamp)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_out_of_history() {
+        let manager = manager_with_samples(&[ms(10), ms(20)]);
+
+        let motion = manager.get_device_motion(*inp::HEAD_ID, ms(5)).unwrap();
+        assert_pose_eq(motion.pose, synthetic_motion(ms(10)).pose);
+
+        assert!(
+            manager
+                .get_device_motion(*inp::HAND_LEFT_ID, ms(10))
+                .is_none()
+        );
+        assert!(manager.get_hand_skeleton(HandType::Right, ms(10)).is_none());
+
+        // Without velocities, a single skeleton sample is held
+        let manager = manager_with_samples(&[ms(10)]);
+        let skeleton = manager.get_hand_skeleton(HandType::Left, ms(30)).unwrap();
+        assert_pose_eq(skeleton[5], synthetic_skeleton(ms(10))[5]);
+    }
+
+    #[test]
+    fn test_out_of_order_samples() {
+        let manager = manager_with_samples(&[ms(0), ms(20), ms(10), ms(30), ms(20)]);
+
+        let history = &manager.device_motions_history[&*inp::HEAD_ID];
+        assert_eq!(
+            history.iter().map(|(ts, _)| *ts).collect::<Vec<_>>(),
+            [ms(0), ms(10), ms(20), ms(30)]
+        );
+
+        for timestamp in [ms(5), ms(15), ms(25)] {
+            let motion = manager.get_device_motion(*inp::HEAD_ID, timestamp).unwrap();
+            assert_pose_eq(motion.pose, synthetic_motion(timestamp).pose);
+
+            let skeleton = manager
+                .get_hand_skeleton(HandType::Left, timestamp)
+                .unwrap();
+            assert_pose_eq(skeleton[5], synthetic_skeleton(timestamp)[5]);
+        }
+    }
+}
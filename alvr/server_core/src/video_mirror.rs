@@ -0,0 +1,129 @@
+// Live mirror of the encoded video stream over a WebSocket, for spectating and debugging. The web
+// server token must be passed with the "token" query parameter.
+//
+// Each binary message contains one frame:
+// * byte 0: codec (0: h264, 1: HEVC, 2: AV1)
+// * byte 1: flags (bit 0: keyframe)
+// * bytes 2-9: frame timestamp in microseconds, little endian
+// * the rest: the frame in Annex B format (h264, HEVC) or as a sequence of OBUs (AV1)
+// Keyframes are prefixed with the codec configuration, so they can be fed directly to a decoder
+// such as WebCodecs.
+
+use crate::{ConnectionContext, SESSION_MANAGER, spectators};
+use alvr_common::{info, settings_schema::Switch};
+use axum::{
+    body::Bytes,
+    extract::{State, WebSocketUpgrade, ws::Message},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Frames buffered for each subscriber before it starts lagging behind
+const CHANNEL_CAPACITY: usize = 64;
+
+const HEADER_SIZE: usize = 10;
+const KEYFRAME_FLAG: u8 = 1;
+
+pub fn publish_frame(
+    ctx: &ConnectionContext,
+    timestamp: Duration,
+    is_idr: bool,
+    nal_buffer: &[u8],
+) {
+    let sender_lock = ctx.video_mirror_sender.lock();
+    let Some(sender) = sender_lock
+        .as_ref()
+        .filter(|sender| sender.receiver_count() > 0)
+    else {
+        return;
+    };
+    let Some(decoder_config) = &*ctx.decoder_config.lock() else {
+        return;
+    };
+
+    let config_buffer: &[u8] = if is_idr {
+        &decoder_config.config_buffer
+    } else {
+        &[]
+    };
+
+    let mut message = Vec::with_capacity(HEADER_SIZE + config_buffer.len() + nal_buffer.len());
+    message.push(decoder_config.codec as u8);
+    message.push(if is_idr { KEYFRAME_FLAG } else { 0 });
+    message.extend_from_slice(&(timestamp.as_micros() as u64).to_le_bytes());
+    message.extend_from_slice(config_buffer);
+    message.extend_from_slice(nal_buffer);
+
+    sender.send(Bytes::from(message)).ok();
+}
+
+pub async fn video_mirror_websocket(
+    State(ctx): State<Arc<ConnectionContext>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let Switch::Enabled(config) = SESSION_MANAGER
+        .read()
+        .settings()
+        .extra
+        .capture
+        .video_mirror
+        .clone()
+    else {
+        return (StatusCode::FORBIDDEN, "video mirror is disabled").into_response();
+    };
+
+    let mut receiver = {
+        let mut sender_lock = ctx.video_mirror_sender.lock();
+        let sender = sender_lock.get_or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+        if sender.receiver_count() >= config.max_subscribers {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "too many video mirror subscribers",
+            )
+                .into_response();
+        }
+
+        sender.subscribe()
+    };
+
+    ws.on_upgrade(async move |mut ws| {
+        // Frames can be decoded only starting from a keyframe. The requests are coalesced with the
+        // ones of the other subscribers and spectators
+        let mut waiting_for_idr = true;
+        spectators::request_secondary_idr(&ctx);
+
+        loop {
+            tokio::select! {
+                res = receiver.recv() => match res {
+                    Ok(frame) => {
+                        if frame[1] & KEYFRAME_FLAG != 0 {
+                            waiting_for_idr = false;
+                        } else if waiting_for_idr {
+                            continue;
+                        }
+
+                        if let Err(e) = ws.send(Message::Binary(frame)).await {
+                            info!("Failed to send video with websocket: {e}");
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        // The next frames reference the skipped ones
+                        waiting_for_idr = true;
+                        spectators::request_secondary_idr(&ctx);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                message = ws.recv() => {
+                    // Incoming messages are ignored, wait only for the socket to close
+                    if !matches!(message, Some(Ok(_))) {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,397 @@
+// Versioned web server API. Unlike the legacy endpoints, which report results through events,
+// requests are answered with JSON and meaningful status codes.
+
+use crate::{ConnectionContext, SESSION_MANAGER, ServerCoreEvent};
+use alvr_common::settings_schema::Switch;
+use alvr_events::StatisticsSnapshot;
+use alvr_packets::{
+    ApiError, ClientListEntry, InputProfileSelectionRequest, InputProfileStatus, PathValuePair,
+};
+use alvr_session::{
+    SessionConfig, Settings,
+    settings_schema::{NumberType, SchemaNode},
+};
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing,
+};
+use std::{borrow::Cow, fmt::Display, io, sync::Arc};
+use utoipa::{
+    OpenApi, PartialSchema, ToSchema,
+    openapi::{
+        RefOr,
+        schema::{ArrayBuilder, ObjectBuilder, Schema, Type},
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ALVR web server API",
+        description = "All requests must set the X-ALVR header and a bearer token in the Authorization header. The tokens are stored in web_server_tokens.json next to session.json. The read-only token allows only GET requests."
+    ),
+    paths(
+        get_session,
+        patch_session,
+        get_clients,
+        get_statistics,
+        get_drivers,
+        get_input_profile,
+        select_input_profile,
+        restart_steamvr,
+        shutdown_steamvr,
+    )
+)]
+struct ApiDoc;
+
+pub fn router() -> Router<Arc<ConnectionContext>> {
+    Router::new()
+        .route("/openapi.json", routing::get(get_openapi))
+        .route("/session", routing::get(get_session).patch(patch_session))
+        .route("/clients", routing::get(get_clients))
+        .route("/statistics", routing::get(get_statistics))
+        .route("/drivers", routing::get(get_drivers))
+        .route(
+            "/input-profile",
+            routing::get(get_input_profile).put(select_input_profile),
+        )
+        .route("/steamvr/restart", routing::post(restart_steamvr))
+        .route("/steamvr/shutdown", routing::post(shutdown_steamvr))
+}
+
+fn error_response(status: StatusCode, message: impl Display) -> Response {
+    (
+        status,
+        Json(ApiError {
+            message: message.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+fn object_schema(properties: Vec<(&str, Schema)>) -> Schema {
+    let mut builder = ObjectBuilder::new().schema_type(Type::Object);
+    for (name, schema) in properties {
+        builder = builder.property(name, schema).required(name);
+    }
+
+    builder.into()
+}
+
+fn typed_schema(ty: Type) -> Schema {
+    ObjectBuilder::new().schema_type(ty).into()
+}
+
+fn array_schema(items: Schema) -> Schema {
+    ArrayBuilder::new().items(RefOr::T(items)).into()
+}
+
+fn any_schema() -> Schema {
+    ObjectBuilder::new().into()
+}
+
+// Follows the session representation of the settings, which contains every branch, like
+// extrapolate_session_settings_from_session_settings() in alvr_session
+fn session_settings_schema(schema: &SchemaNode) -> Schema {
+    let gui_collapsed = || ("gui_collapsed", typed_schema(Type::Boolean));
+
+    match schema {
+        SchemaNode::Section {
+            entries,
+            gui_collapsible,
+        } => {
+            let mut properties = entries
+                .iter()
+                .map(|entry| (entry.name.as_str(), session_settings_schema(&entry.content)))
+                .collect::<Vec<_>>();
+            if *gui_collapsible {
+                properties.push(gui_collapsed());
+            }
+
+            object_schema(properties)
+        }
+        SchemaNode::Choice { variants, .. } => {
+            let mut properties = vec![(
+                "variant",
+                ObjectBuilder::new()
+                    .schema_type(Type::String)
+                    .enum_values(Some(variants.iter().map(|entry| entry.name.clone())))
+                    .into(),
+            )];
+            for entry in variants {
+                if let Some(content) = &entry.content {
+                    properties.push((entry.name.as_str(), session_settings_schema(content)));
+                }
+            }
+
+            object_schema(properties)
+        }
+        SchemaNode::Optional { content, .. } => object_schema(vec![
+            ("set", typed_schema(Type::Boolean)),
+            ("content", session_settings_schema(content)),
+        ]),
+        SchemaNode::Switch { content, .. } => object_schema(vec![
+            ("enabled", typed_schema(Type::Boolean)),
+            ("content", session_settings_schema(content)),
+        ]),
+        SchemaNode::Boolean { .. } => typed_schema(Type::Boolean),
+        SchemaNode::Number { ty, .. } => match ty {
+            NumberType::UnsignedInteger | NumberType::SignedInteger => typed_schema(Type::Integer),
+            NumberType::Float => typed_schema(Type::Number),
+        },
+        SchemaNode::Text { .. } => typed_schema(Type::String),
+        // The elements of fixed size arrays have the same type
+        SchemaNode::Array(elements) => object_schema(vec![
+            gui_collapsed(),
+            (
+                "content",
+                array_schema(
+                    elements
+                        .first()
+                        .map_or_else(any_schema, session_settings_schema),
+                ),
+            ),
+        ]),
+        SchemaNode::Vector {
+            default_element, ..
+        } => {
+            let element = session_settings_schema(default_element);
+
+            object_schema(vec![
+                gui_collapsed(),
+                ("element", element.clone()),
+                ("content", array_schema(element)),
+            ])
+        }
+        // The content is a list of key-value pairs
+        SchemaNode::Dictionary { default_value, .. } => {
+            let value = session_settings_schema(default_value);
+
+            object_schema(vec![
+                gui_collapsed(),
+                ("key", typed_schema(Type::String)),
+                ("value", value),
+                ("content", array_schema(array_schema(any_schema()))),
+            ])
+        }
+        _ => any_schema(),
+    }
+}
+
+// The session types are generated by settings-schema and don't implement ToSchema. Their schema is
+// generated from the settings schema instead.
+struct SessionSchema;
+
+impl PartialSchema for SessionSchema {
+    fn schema() -> RefOr<Schema> {
+        let settings_schema = Settings::schema(alvr_session::session_settings_default());
+
+        object_schema(vec![
+            ("server_version", typed_schema(Type::String)),
+            // Indexed by hostname
+            ("client_connections", typed_schema(Type::Object)),
+            (
+                "session_settings",
+                session_settings_schema(&settings_schema),
+            ),
+            ("restart_settings_hash", typed_schema(Type::Integer)),
+            ("steamvr_hmd_init_config", typed_schema(Type::Object)),
+        ])
+        .into()
+    }
+}
+
+impl ToSchema for SessionSchema {
+    fn name() -> Cow<'static, str> {
+        "SessionConfig".into()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/session",
+    responses((status = 200, description = "Current session", body = SessionSchema))
+)]
+async fn get_session() -> Json<SessionConfig> {
+    Json(SESSION_MANAGER.read().session().clone())
+}
+
+// Each value replaces the session subtree at the given path
+#[utoipa::path(
+    patch,
+    path = "/api/v1/session",
+    request_body = Vec<PathValuePair>,
+    responses(
+        (status = 200, description = "Updated session", body = SessionSchema),
+        (status = 400, description = "Invalid path or value", body = ApiError),
+        (status = 500, description = "Failed to save the session", body = ApiError),
+    )
+)]
+async fn patch_session(Json(descs): Json<Vec<PathValuePair>>) -> Response {
+    let mut session_manager = SESSION_MANAGER.write();
+
+    match session_manager.set_session_values(descs) {
+        Ok(()) => Json(session_manager.session().clone()).into_response(),
+        // Only saving session.json can fail with an IO error, the values are valid
+        Err(e) if e.downcast_ref::<io::Error>().is_some() => {
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+        Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/clients",
+    responses((status = 200, description = "Known clients", body = Vec<ClientListEntry>))
+)]
+async fn get_clients() -> Json<Vec<ClientListEntry>> {
+    let mut clients = SESSION_MANAGER
+        .read()
+        .client_list()
+        .iter()
+        .map(|(hostname, config)| ClientListEntry {
+            hostname: hostname.clone(),
+            display_name: config.display_name.clone(),
+            current_ip: config.current_ip,
+            manual_ips: config.manual_ips.iter().copied().collect(),
+            trusted: config.trusted,
+            connection_state: config.connection_state.clone(),
+            spectator: config.spectator,
+        })
+        .collect::<Vec<_>>();
+    clients.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+
+    Json(clients)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/statistics",
+    responses((status = 200, description = "Latest statistics", body = StatisticsSnapshot))
+)]
+async fn get_statistics(State(ctx): State<Arc<ConnectionContext>>) -> Json<StatisticsSnapshot> {
+    Json(
+        ctx.statistics_manager
+            .read()
+            .as_ref()
+            .map(|manager| manager.snapshot())
+            .unwrap_or_default(),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/drivers",
+    responses(
+        (
+            status = 200,
+            description = "Paths of the registered SteamVR drivers",
+            body = Vec<String>
+        ),
+        (
+            status = 500,
+            description = "Failed to read the SteamVR configuration",
+            body = ApiError
+        ),
+    )
+)]
+async fn get_drivers() -> Response {
+    match alvr_server_io::get_registered_drivers() {
+        Ok(list) => Json(list).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+fn input_profile_status(ctx: &ConnectionContext) -> InputProfileStatus {
+    let selection = ctx.input_profile_selection.read();
+    let active_profile = match &SESSION_MANAGER.read().settings().headset.controllers {
+        Switch::Enabled(config) => selection
+            .active_profile(config)
+            .map(|(name, _)| name.clone()),
+        Switch::Disabled => None,
+    };
+
+    InputProfileStatus {
+        foreground_application: selection.foreground_application().cloned(),
+        selected_profile: selection.selected_profile().cloned(),
+        active_profile,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/input-profile",
+    responses((status = 200, description = "Input mapping profile in use", body = InputProfileStatus))
+)]
+async fn get_input_profile(State(ctx): State<Arc<ConnectionContext>>) -> Json<InputProfileStatus> {
+    Json(input_profile_status(&ctx))
+}
+
+// The profile is applied to the next button inputs, without reconnecting
+#[utoipa::path(
+    put,
+    path = "/api/v1/input-profile",
+    request_body = InputProfileSelectionRequest,
+    responses(
+        (status = 200, description = "Input mapping profile in use", body = InputProfileStatus),
+        (status = 404, description = "Unknown profile", body = ApiError),
+    )
+)]
+async fn select_input_profile(
+    State(ctx): State<Arc<ConnectionContext>>,
+    Json(request): Json<InputProfileSelectionRequest>,
+) -> Response {
+    if let Some(name) = &request.profile {
+        let exists = SESSION_MANAGER
+            .read()
+            .settings()
+            .headset
+            .controllers
+            .as_option()
+            .is_some_and(|config| config.input_profiles.iter().any(|(n, _)| n == name));
+        if !exists {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("input profile \"{name}\" not found"),
+            );
+        }
+    }
+
+    ctx.input_profile_selection
+        .write()
+        .select_profile(request.profile);
+
+    Json(input_profile_status(&ctx)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/steamvr/restart",
+    responses((status = 202, description = "SteamVR is restarting"))
+)]
+async fn restart_steamvr(State(ctx): State<Arc<ConnectionContext>>) -> StatusCode {
+    ctx.events_sender.send(ServerCoreEvent::RestartPending).ok();
+
+    StatusCode::ACCEPTED
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/steamvr/shutdown",
+    responses((status = 202, description = "SteamVR is shutting down"))
+)]
+async fn shutdown_steamvr(State(ctx): State<Arc<ConnectionContext>>) -> StatusCode {
+    ctx.events_sender
+        .send(ServerCoreEvent::ShutdownPending)
+        .ok();
+
+    StatusCode::ACCEPTED
+}
@@ -1,24 +1,35 @@
 use crate::{
     ConnectionContext, FILESYSTEM_LAYOUT, SESSION_MANAGER, ServerCoreEvent,
+    hand_gesture_evaluation::{self, GestureEvaluation, GestureLabel},
     logging_backend::EVENTS_SENDER,
+    metrics, session_recording, video_mirror, web_api,
 };
 use alvr_common::{ConnectionState, LogEntry, anyhow::Result, error, info, log};
-use alvr_events::{ButtonEvent, EventType};
+use alvr_events::{ButtonEvent, EventSubscription, EventType, EventsLagged};
 use alvr_packets::{ButtonEntry, ClientConnectionsAction, FirewallRulesAction, PathValuePair};
+use alvr_server_io::{WebServerScope, WebServerTokens};
 use alvr_session::SessionConfig;
 use axum::{
     Json, Router,
     extract::{Request, State, WebSocketUpgrade, ws::Message},
     http::{
         HeaderValue, Method, StatusCode,
-        header::{CACHE_CONTROL, CONTENT_TYPE},
+        header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, UPGRADE},
     },
     middleware,
     response::Response,
     routing,
 };
+use serde::Deserialize;
 use serde_json as json;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 use tokio::{net::TcpListener, sync::broadcast::error::RecvError};
 use tower_http::{
     cors::{self, CorsLayer},
@@ -44,6 +55,72 @@ async fn ensure_preflight(request: Request, next: middleware::Next) -> Response
     }
 }
 
+fn is_websocket_upgrade(request: &Request) -> bool {
+    request
+        .headers()
+        .get(UPGRADE)
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"websocket"))
+}
+
+// Query parameters end up in logs and browser history, so they are accepted only where the
+// Authorization header cannot be set
+fn request_token(request: &Request, allow_query_token: bool) -> Option<&str> {
+    if let Some(value) = request.headers().get(AUTHORIZATION) {
+        return value.to_str().ok()?.strip_prefix("Bearer ");
+    }
+
+    if !allow_query_token {
+        return None;
+    }
+
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|param| param.strip_prefix("token="))
+}
+
+// Browsers cannot set the authorization header on WebSocket connections
+async fn authenticate(
+    State(tokens): State<Arc<WebServerTokens>>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    let allow_query_token = is_websocket_upgrade(&request);
+    authorize(&tokens, request, next, allow_query_token).await
+}
+
+// Used for routes whose clients usually cannot set headers, like metrics scrapers
+async fn authenticate_allowing_query_token(
+    State(tokens): State<Arc<WebServerTokens>>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    authorize(&tokens, request, next, true).await
+}
+
+async fn authorize(
+    tokens: &WebServerTokens,
+    request: Request,
+    next: middleware::Next,
+    allow_query_token: bool,
+) -> Response {
+    let scope = request_token(&request, allow_query_token).and_then(|token| tokens.scope(token));
+    let (status, message) = match scope {
+        Some(WebServerScope::Admin) => return next.run(request).await,
+        Some(WebServerScope::ReadOnly) if request.method() == Method::GET => {
+            return next.run(request).await;
+        }
+        Some(WebServerScope::ReadOnly) => (StatusCode::FORBIDDEN, "read-only token"),
+        None => (StatusCode::UNAUTHORIZED, "missing or invalid token"),
+    };
+
+    Response::builder()
+        .status(status)
+        .body(message.into())
+        .unwrap()
+}
+
 pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()> {
     let allow_untrusted_http;
     let web_server_port;
@@ -54,9 +131,13 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
         web_server_port = session_manager.settings().connection.web_server_port;
     }
 
+    let tokens = Arc::new(alvr_server_io::load_or_create_web_server_tokens(
+        &FILESYSTEM_LAYOUT.get().unwrap().web_server_tokens(),
+    )?);
+
     let mut cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([CONTENT_TYPE, X_ALVR.parse().unwrap()]);
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, X_ALVR.parse().unwrap()]);
     if allow_untrusted_http {
         cors = cors.allow_origin(cors::Any);
     }
@@ -65,6 +146,7 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
         .nest(
             "/api",
             Router::new()
+                .nest("/v1", web_api::router())
                 .route("/events", routing::get(events_websocket))
                 .route("/log", routing::post(set_log))
                 .nest(
@@ -86,6 +168,18 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
                         .route("/start", routing::post(start_recording))
                         .route("/stop", routing::post(stop_recording)),
                 )
+                .nest(
+                    "/session-recording",
+                    Router::new()
+                        .route("/start", routing::post(start_session_recording))
+                        .route("/stop", routing::post(stop_session_recording))
+                        .route("/replay", routing::post(replay_session))
+                        .route("/stop-replay", routing::post(stop_session_replay))
+                        .route(
+                            "/evaluate-hand-gestures",
+                            routing::post(evaluate_hand_gestures),
+                        ),
+                )
                 .nest(
                     "/firewall-rules",
                     Router::new()
@@ -105,6 +199,11 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
                         .route("/restart", routing::post(restart_steamvr))
                         .route("/shutdown", routing::post(shutdown_steamvr)),
                 )
+                .layer(middleware::from_fn_with_state(
+                    Arc::clone(&tokens),
+                    authenticate,
+                ))
+                // Used to check if the server is running, no token required
                 .route(
                     "/version",
                     routing::get(async || alvr_common::ALVR_VERSION.to_string()),
@@ -117,6 +216,22 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
             HeaderValue::from_static("no-cache, no-store, must-revalidate"),
         ))
         .layer(middleware::from_fn(ensure_preflight))
+        // Browsers cannot set custom headers on WebSocket connections, so the video mirror is
+        // served without the preflight check. It must be enabled in the settings.
+        .route(
+            "/api/video-mirror",
+            routing::get(video_mirror::video_mirror_websocket).layer(
+                middleware::from_fn_with_state(Arc::clone(&tokens), authenticate),
+            ),
+        )
+        // Scrapers usually cannot set custom headers either
+        .route(
+            "/metrics",
+            routing::get(get_metrics).layer(middleware::from_fn_with_state(
+                tokens,
+                authenticate_allowing_query_token,
+            )),
+        )
         .with_state(connection_context);
 
     axum::serve(
@@ -130,28 +245,103 @@ pub async fn web_server(connection_context: Arc<ConnectionContext>) -> Result<()
     Ok(())
 }
 
+#[derive(Default)]
+struct EventFilter {
+    subscription: EventSubscription,
+    last_sent: HashMap<&'static str, Instant>,
+}
+
+impl EventFilter {
+    fn accept(&mut self, event_type: &EventType) -> bool {
+        let id = event_type.id();
+
+        if let Some(event_types) = &self.subscription.event_types
+            && !event_types.contains(id)
+        {
+            return false;
+        }
+
+        if let Some(max_rate) = self.subscription.max_rates.get(id) {
+            let min_interval = Duration::try_from_secs_f32(1.0 / max_rate).unwrap_or(Duration::MAX);
+
+            let now = Instant::now();
+            if let Some(last_sent) = self.last_sent.get(id)
+                && now.duration_since(*last_sent) < min_interval
+            {
+                return false;
+            }
+            self.last_sent.insert(id, now);
+        }
+
+        true
+    }
+}
+
+// All events are sent until the client sends an EventSubscription
 async fn events_websocket(ws: WebSocketUpgrade) -> Response {
     ws.on_upgrade(async |mut ws| {
         let mut events_receiver = EVENTS_SENDER.subscribe();
+        let mut filter = EventFilter::default();
 
         loop {
-            match events_receiver.recv().await {
-                Ok(event) => {
-                    if let Err(e) = ws
-                        .send(Message::Text(json::to_string(&event).unwrap().into()))
-                        .await
-                    {
-                        info!("Failed to send event with websocket: {e}");
-                        break;
+            let message = tokio::select! {
+                res = events_receiver.recv() => match res {
+                    Ok(event) => {
+                        if !filter.accept(&event.event_type) {
+                            continue;
+                        }
+
+                        json::to_string(&event).unwrap()
+                    }
+                    Err(RecvError::Lagged(count)) => json::to_string(&EventsLagged {
+                        lagged_events: count,
+                    })
+                    .unwrap(),
+                    Err(RecvError::Closed) => break,
+                },
+                message = ws.recv() => {
+                    match message {
+                        Some(Ok(Message::Text(text))) => {
+                            match json::from_str::<EventSubscription>(text.as_str()) {
+                                Ok(subscription) => {
+                                    filter = EventFilter {
+                                        subscription,
+                                        last_sent: HashMap::new(),
+                                    };
+                                }
+                                Err(e) => info!("Invalid event subscription: {e}"),
+                            }
+                        }
+                        Some(Ok(_)) => (),
+                        _ => break,
                     }
+
+                    continue;
                 }
-                Err(RecvError::Lagged(_)) => (),
-                Err(RecvError::Closed) => break,
+            };
+
+            if let Err(e) = ws.send(Message::Text(message.into())).await {
+                info!("Failed to send event with websocket: {e}");
+                break;
             }
         }
     })
 }
 
+async fn get_metrics(State(ctx): State<Arc<ConnectionContext>>) -> Response {
+    let snapshot = ctx
+        .statistics_manager
+        .read()
+        .as_ref()
+        .map(|manager| manager.snapshot())
+        .unwrap_or_default();
+
+    Response::builder()
+        .header(CONTENT_TYPE, metrics::CONTENT_TYPE)
+        .body(metrics::METRICS.lock().encode(&snapshot).into())
+        .unwrap()
+}
+
 async fn set_log(Json(entry): Json<LogEntry>) {
     let level = entry.severity.into_log_level();
     log::log!(level, "{}", entry.content);
@@ -201,7 +391,101 @@ async fn start_recording(State(ctx): State<Arc<ConnectionContext>>) {
 }
 
 async fn stop_recording(State(ctx): State<Arc<ConnectionContext>>) {
-    *ctx.video_recording_file.lock() = None;
+    *ctx.video_recorder.lock() = None;
+}
+
+async fn start_session_recording(State(ctx): State<Arc<ConnectionContext>>) {
+    session_recording::start_session_recording(&ctx);
+}
+
+async fn stop_session_recording(State(ctx): State<Arc<ConnectionContext>>) {
+    session_recording::stop_session_recording(&ctx);
+}
+
+// Only files inside the log directory can be accessed through the API
+fn resolve_log_path(path: &Path) -> Result<PathBuf, (StatusCode, String)> {
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(FILESYSTEM_LAYOUT.get().unwrap().log_dir.join(path))
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            "the path must be relative to the log directory".into(),
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct SessionReplayRequest {
+    path: PathBuf, // Relative to the log directory
+    real_time: bool,
+}
+
+async fn replay_session(
+    State(ctx): State<Arc<ConnectionContext>>,
+    Json(request): Json<SessionReplayRequest>,
+) -> Result<(), (StatusCode, String)> {
+    let path = resolve_log_path(&request.path)?;
+
+    thread::spawn(move || {
+        if let Err(e) = session_recording::replay_session(&ctx, &path, request.real_time) {
+            error!("Failed to replay session: {e}");
+        }
+    });
+
+    Ok(())
+}
+
+async fn stop_session_replay() {
+    session_recording::stop_session_replay();
+}
+
+#[derive(Deserialize)]
+struct HandGestureEvaluationRequest {
+    path: PathBuf, // Session or hand recording, relative to the log directory
+    #[serde(default)]
+    labels: Vec<GestureLabel>,
+}
+
+// Evaluates the hand gestures of a recording with the current hand tracking interaction settings
+async fn evaluate_hand_gestures(
+    Json(request): Json<HandGestureEvaluationRequest>,
+) -> Result<Json<Vec<GestureEvaluation>>, (StatusCode, String)> {
+    let Some(config) = SESSION_MANAGER
+        .read()
+        .settings()
+        .headset
+        .controllers
+        .as_option()
+        .and_then(|config| config.hand_tracking_interaction.as_option())
+        .cloned()
+    else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "hand tracking interaction is disabled".into(),
+        ));
+    };
+
+    let path = resolve_log_path(&request.path)?;
+
+    let frames =
+        tokio::task::spawn_blocking(move || hand_gesture_evaluation::load_hand_recording(&path))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map_err(|e| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("failed to load recording: {e}"),
+                )
+            })?;
+
+    Ok(Json(hand_gesture_evaluation::evaluate_hand_gestures(
+        &frames,
+        &config,
+        &request.labels,
+    )))
 }
 
 async fn add_firewall_rules() {
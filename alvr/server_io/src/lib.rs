@@ -1,10 +1,12 @@
 mod firewall;
 mod openvr_drivers;
 mod openvrpaths;
+mod web_server_tokens;
 
 pub use firewall::*;
 pub use openvr_drivers::*;
 pub use openvrpaths::*;
+pub use web_server_tokens::*;
 
 use alvr_common::{
     ConnectionState,
@@ -21,6 +23,10 @@ use std::{
     fs,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 fn save_session(session: &SessionConfig, path: &Path) -> Result<()> {
@@ -34,6 +40,7 @@ pub struct SessionLock<'a> {
     session_desc: &'a mut SessionConfig,
     session_path: Option<&'a Path>,
     settings: &'a mut Settings,
+    settings_revision: &'a AtomicUsize,
 }
 
 impl Deref for SessionLock<'_> {
@@ -56,6 +63,7 @@ impl Drop for SessionLock<'_> {
         }
 
         *self.settings = self.session_desc.to_settings();
+        self.settings_revision.fetch_add(1, Ordering::Relaxed);
         alvr_events::send_event(EventType::Session(Box::new(self.session_desc.clone())));
     }
 }
@@ -68,6 +76,8 @@ impl Drop for SessionLock<'_> {
 pub struct ServerSessionManager {
     session_config: SessionConfig,
     settings: Settings,
+    // Incremented every time the settings are replaced
+    settings_revision: Arc<AtomicUsize>,
     session_path: Option<PathBuf>,
 }
 
@@ -84,6 +94,7 @@ impl ServerSessionManager {
         Self {
             session_config: session_config.clone(),
             settings: session_config.to_settings(),
+            settings_revision: Arc::new(AtomicUsize::new(0)),
             session_path,
         }
     }
@@ -145,6 +156,7 @@ impl ServerSessionManager {
             session_desc: &mut self.session_config,
             session_path: self.session_path.as_deref(),
             settings: &mut self.settings,
+            settings_revision: &self.settings_revision,
         }
     }
 
@@ -152,6 +164,11 @@ impl ServerSessionManager {
         &self.settings
     }
 
+    // Can be polled without locking the session manager, to detect settings changes
+    pub fn settings_revision(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.settings_revision)
+    }
+
     // Note: "value" can be any session subtree, in json format.
     pub fn set_session_values(&mut self, descs: Vec<PathValuePair>) -> Result<()> {
         let mut session_json = serde_json::to_value(self.session_config.clone()).unwrap();
@@ -182,6 +199,7 @@ impl ServerSessionManager {
         // session_json has been updated
         self.session_config = serde_json::from_value(session_json)?;
         self.settings = self.session_config.to_settings();
+        self.settings_revision.fetch_add(1, Ordering::Relaxed);
 
         if let Some(session_path) = &self.session_path {
             save_session(&self.session_config, session_path)?;
@@ -214,6 +232,9 @@ impl ServerSessionManager {
                         manual_ips: manual_ips.into_iter().collect(),
                         trusted,
                         connection_state: ConnectionState::Disconnected,
+                        pairing_key: None,
+                        announced_pairing_key: None,
+                        spectator: false,
                     };
                     new_entry.insert(client_connection_desc);
 
@@ -228,8 +249,12 @@ impl ServerSessionManager {
                 }
             }
             ClientConnectionsAction::Trust => {
-                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                if let Entry::Occupied(mut entry) = maybe_client_entry
+                    && !entry.get().trusted
+                {
+                    // A newly trusted client must be paired again
                     entry.get_mut().trusted = true;
+                    entry.get_mut().pairing_key = None;
 
                     updated = true;
                 }
@@ -263,6 +288,31 @@ impl ServerSessionManager {
                 {
                     entry.get_mut().connection_state = state;
 
+                    updated = true;
+                }
+            }
+            ClientConnectionsAction::SetPairingKey(key) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    entry.get_mut().pairing_key = Some(key);
+
+                    updated = true;
+                }
+            }
+            ClientConnectionsAction::SetAnnouncedPairingKey(key) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry
+                    && entry.get().announced_pairing_key.as_ref() != Some(&key)
+                {
+                    entry.get_mut().announced_pairing_key = Some(key);
+
+                    updated = true;
+                }
+            }
+            ClientConnectionsAction::SetSpectator(spectator) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry
+                    && entry.get().spectator != spectator
+                {
+                    entry.get_mut().spectator = spectator;
+
                     updated = true;
                 }
             }
@@ -0,0 +1,120 @@
+use alvr_common::anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use std::{fs, io::Write, path::Path};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WebServerScope {
+    // Allows only requests that don't modify the server state
+    ReadOnly,
+    Admin,
+}
+
+// Tokens required by the web server. They are stored outside of the session, which can be read
+// through the web server itself.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WebServerTokens {
+    pub admin: String,
+    pub read_only: String,
+}
+
+impl WebServerTokens {
+    pub fn scope(&self, token: &str) -> Option<WebServerScope> {
+        // Both tokens are always compared, to not leak which one matched through timing
+        let is_admin = constant_time_eq(token.as_bytes(), self.admin.as_bytes());
+        let is_read_only = constant_time_eq(token.as_bytes(), self.read_only.as_bytes());
+
+        if is_admin {
+            Some(WebServerScope::Admin)
+        } else if is_read_only {
+            Some(WebServerScope::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+// The duration depends only on the length, which is the same for all generated tokens
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a
+        .iter()
+        .zip(b)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+
+    std::hint::black_box(difference) == 0
+}
+
+fn generate_token() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// Files containing secrets must be readable only by the current user
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+pub fn write_private_file(path: &Path, text: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    // The mode is applied only when the file is created
+    options.open(path)?.write_all(text.as_bytes())?;
+    restrict_permissions(path)
+}
+
+pub fn load_or_create_web_server_tokens(path: &Path) -> Result<WebServerTokens> {
+    if let Ok(text) = fs::read_to_string(path)
+        && let Ok(tokens) = json::from_str(&text)
+    {
+        // Files created by older versions are world readable
+        restrict_permissions(path)?;
+
+        return Ok(tokens);
+    }
+
+    let tokens = WebServerTokens {
+        admin: generate_token(),
+        read_only: generate_token(),
+    };
+    write_private_file(path, &json::to_string_pretty(&tokens)?)?;
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope() {
+        let tokens = WebServerTokens {
+            admin: "admin".into(),
+            read_only: "reader".into(),
+        };
+
+        assert_eq!(tokens.scope("admin"), Some(WebServerScope::Admin));
+        assert_eq!(tokens.scope("reader"), Some(WebServerScope::ReadOnly));
+        assert_eq!(tokens.scope("admin2"), None);
+        assert_eq!(tokens.scope("admix"), None);
+        assert_eq!(tokens.scope(""), None);
+    }
+}
@@ -127,6 +127,10 @@ public:
     virtual void RunFrame() override {
         vr::VREvent_t event;
         while (vr::VRServerDriverHost()->PollNextEvent(&event, sizeof(vr::VREvent_t))) {
+            if (event.eventType == vr::VREvent_SceneApplicationChanged) {
+                SceneApplicationChanged(event.data.process.pid);
+            }
+
             if (event.eventType == vr::VREvent_Input_HapticVibration) {
                 Debug("DriverProvider: Received HapticVibration event");
 
@@ -367,6 +371,12 @@ void RequestIDR() {
     }
 }
 
+void InvalidateReferenceFrames(unsigned long long lastValidTimestampNs) {
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_encoder) {
+        g_driver_provider.hmd->m_encoder->InvalidateReferenceFrames(lastValidTimestampNs);
+    }
+}
+
 void SetTracking(
     unsigned long long targetTimestampNs,
     float controllerPoseTimeOffsetS,
@@ -204,7 +204,10 @@ VideoSend(unsigned long long targetTimestampNs, unsigned char* buf, int len, boo
 extern "C" void
 HapticsSend(unsigned long long path, float duration_s, float frequency, float amplitude);
 extern "C" void ShutdownRuntime();
+extern "C" void SceneApplicationChanged(unsigned int processId);
 extern "C" unsigned long long PathStringToHash(const char* path);
+extern "C" void
+ReportRecoveryFrame(unsigned long long targetTimestampNs, unsigned long long lastValidTimestampNs);
 extern "C" void ReportPresent(unsigned long long timestamp_ns, unsigned long long offset_ns);
 extern "C" void ReportComposed(unsigned long long timestamp_ns, unsigned long long offset_ns);
 extern "C" FfiDynamicEncoderParams GetDynamicEncoderParams();
@@ -220,6 +223,7 @@ extern "C" bool InitializeStreaming(Settings settings);
 extern "C" void DeinitializeStreaming();
 extern "C" void SendVSync();
 extern "C" void RequestIDR();
+extern "C" void InvalidateReferenceFrames(unsigned long long lastValidTimestampNs);
 extern "C" void SetTracking(
     unsigned long long targetTimestampNs,
     float controllerPoseTimeOffsetS,
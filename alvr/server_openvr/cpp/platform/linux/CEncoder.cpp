@@ -314,4 +314,7 @@ void CEncoder::OnStreamStart() { m_scheduler.OnStreamStart(); }
 
 void CEncoder::InsertIDR() { m_scheduler.InsertIDR(); }
 
+// Reference frame invalidation is not supported by the Linux encode pipelines
+void CEncoder::InvalidateReferenceFrames(uint64_t) { m_scheduler.InsertIDR(); }
+
 void CEncoder::CaptureFrame() { m_captureFrame = true; }
@@ -20,6 +20,7 @@ public:
     void Stop();
     void OnStreamStart();
     void InsertIDR();
+    void InvalidateReferenceFrames(uint64_t lastValidTimestampNs);
     bool IsConnected() { return m_connected; }
     void CaptureFrame();
 
@@ -12,4 +12,5 @@ public:
     void Stop() { }
     void OnStreamStart() { }
     void InsertIDR() { }
+    void InvalidateReferenceFrames(uint64_t) { }
 };
@@ -159,4 +159,10 @@ void CEncoder::OnStreamStart() { m_scheduler.OnStreamStart(); }
 
 void CEncoder::InsertIDR() { m_scheduler.InsertIDR(); }
 
+void CEncoder::InvalidateReferenceFrames(uint64_t lastValidTimestampNs) {
+    if (!m_videoEncoder || !m_videoEncoder->InvalidateReferenceFrames(lastValidTimestampNs)) {
+        m_scheduler.InsertIDR();
+    }
+}
+
 void CEncoder::CaptureFrame() { }
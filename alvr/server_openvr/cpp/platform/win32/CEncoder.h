@@ -65,6 +65,8 @@ public:
 
     void InsertIDR();
 
+    void InvalidateReferenceFrames(uint64_t lastValidTimestampNs);
+
     void CaptureFrame();
 
 private:
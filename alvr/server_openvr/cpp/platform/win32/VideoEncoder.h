@@ -16,4 +16,8 @@ public:
         uint64_t targetTimestampNs,
         bool insertIDR
     ) = 0;
+
+    // Stop referencing the frames encoded after the given one. Can be called from any thread.
+    // Returns false if not supported, in which case an IDR frame should be inserted instead.
+    virtual bool InvalidateReferenceFrames(uint64_t lastValidTimestampNs) { return false; }
 };
@@ -4,6 +4,10 @@
 #include "alvr_server/Logger.h"
 #include "alvr_server/Utils.h"
 #include "alvr_server/bindings.h"
+#include <algorithm>
+
+// Frames older than this are assumed to be evicted from the DPB
+const size_t MAX_REFERENCE_FRAMES = 16;
 
 VideoEncoderNVENC::VideoEncoderNVENC(std::shared_ptr<CD3DRender> pD3DRender, int width, int height)
     : m_pD3DRender(pD3DRender)
@@ -121,13 +125,37 @@ void VideoEncoderNVENC::Transmit(
         = reinterpret_cast<ID3D11Texture2D*>(encoderInputFrame->inputPtr);
     m_pD3DRender->GetContext()->CopyResource(pInputTexture, pTexture);
 
+    std::optional<uint64_t> lastValidTimestampNs;
+    {
+        std::lock_guard<std::mutex> lock(m_invalidationMutex);
+        std::swap(lastValidTimestampNs, m_pendingInvalidation);
+    }
+    bool isRecoveryFrame = false;
+    if (!insertIDR && lastValidTimestampNs) {
+        isRecoveryFrame = InvalidateFramesAfter(*lastValidTimestampNs);
+        insertIDR = !isRecoveryFrame;
+    }
+
     NV_ENC_PIC_PARAMS picParams = {};
     if (insertIDR) {
         Debug("Inserting IDR frame.\n");
         picParams.encodePicFlags = NV_ENC_PIC_FLAG_FORCEIDR;
+        m_referenceTimestamps.clear();
     }
+    // The input timestamp identifies the frame when invalidating it
+    picParams.inputTimeStamp = targetTimestampNs;
     m_NvNecoder->EncodeFrame(vPacket, &picParams);
 
+    m_referenceTimestamps.push_back(targetTimestampNs);
+    if (m_referenceTimestamps.size() > MAX_REFERENCE_FRAMES) {
+        m_referenceTimestamps.pop_front();
+    }
+
+    // This is the first frame that doesn't reference the invalidated frames
+    if (isRecoveryFrame) {
+        ReportRecoveryFrame(targetTimestampNs, *lastValidTimestampNs);
+    }
+
     for (std::vector<uint8_t>& packet : vPacket) {
         uint8_t* buf = packet.data();
         int len = (int)packet.size();
@@ -159,6 +187,36 @@ void VideoEncoderNVENC::Transmit(
     }
 }
 
+bool VideoEncoderNVENC::InvalidateReferenceFrames(uint64_t lastValidTimestampNs) {
+    std::lock_guard<std::mutex> lock(m_invalidationMutex);
+    m_pendingInvalidation = lastValidTimestampNs;
+
+    return true;
+}
+
+bool VideoEncoderNVENC::InvalidateFramesAfter(uint64_t lastValidTimestampNs) {
+    auto lastValidFrame = std::find(
+        m_referenceTimestamps.begin(), m_referenceTimestamps.end(), lastValidTimestampNs
+    );
+    if (lastValidFrame == m_referenceTimestamps.end()) {
+        return false;
+    }
+
+    try {
+        for (auto it = lastValidFrame + 1; it != m_referenceTimestamps.end(); it++) {
+            m_NvNecoder->InvalidateRefFrames(*it);
+        }
+    } catch (NVENCException e) {
+        Warn("NvEnc reference frame invalidation failed. Code=%d %hs\n", e.getErrorCode(), e.what());
+        return false;
+    }
+
+    Debug("Invalidated reference frames after %llu.\n", lastValidTimestampNs);
+    m_referenceTimestamps.erase(lastValidFrame + 1, m_referenceTimestamps.end());
+
+    return true;
+}
+
 void VideoEncoderNVENC::FillEncodeConfig(
     NV_ENC_INITIALIZE_PARAMS& initializeParams,
     int refreshRate,
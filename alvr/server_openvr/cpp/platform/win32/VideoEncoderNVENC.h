@@ -3,7 +3,10 @@
 #include "NvEncoderD3D11.h"
 #include "VideoEncoder.h"
 #include "shared/d3drender.h"
+#include <deque>
 #include <memory>
+#include <mutex>
+#include <optional>
 
 enum AdaptiveQuantizationMode { SpatialAQ = 1, TemporalAQ = 2 };
 
@@ -23,6 +26,8 @@ public:
         bool insertIDR
     );
 
+    bool InvalidateReferenceFrames(uint64_t lastValidTimestampNs);
+
 private:
     void FillEncodeConfig(
         NV_ENC_INITIALIZE_PARAMS& initializeParams,
@@ -32,6 +37,8 @@ private:
         uint64_t bitrate_bps
     );
 
+    bool InvalidateFramesAfter(uint64_t lastValidTimestampNs);
+
     std::ofstream fpOut;
     std::shared_ptr<NvEncoder> m_NvNecoder;
 
@@ -42,4 +49,9 @@ private:
     int m_renderWidth;
     int m_renderHeight;
     int m_bitrateInMBits;
+
+    std::mutex m_invalidationMutex;
+    std::optional<uint64_t> m_pendingInvalidation;
+    // Timestamps of the frames that can still be used as references, oldest first
+    std::deque<uint64_t> m_referenceTimestamps;
 };
@@ -480,6 +480,11 @@ fn spawn_event_loop(events_receiver: mpsc::Receiver<ServerCoreEvent>) {
                     }
                 }
                 ServerCoreEvent::RequestIDR => unsafe { RequestIDR() },
+                ServerCoreEvent::InvalidateReferenceFrames {
+                    last_valid_frame_timestamp,
+                } => unsafe {
+                    InvalidateReferenceFrames(last_valid_frame_timestamp.as_nanos() as u64)
+                },
                 ServerCoreEvent::CaptureFrame => unsafe { CaptureFrame() },
                 ServerCoreEvent::GameRenderLatencyFeedback(game_latency) => {
                     if cfg!(target_os = "linux") && game_latency.as_secs_f32() > 0.25 {
@@ -563,6 +568,27 @@ extern "C" fn send_haptics(device_id: u64, duration_s: f32, frequency: f32, ampl
     }
 }
 
+#[unsafe(export_name = "SceneApplicationChanged")]
+extern "C" fn scene_application_changed(process_id: u32) {
+    // A process ID of 0 means that there is no scene application
+    let application = if process_id != 0 {
+        let pid = sysinfo::Pid::from_u32(process_id);
+
+        let mut system = sysinfo::System::new();
+        system.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+
+        system
+            .process(pid)
+            .map(|process| process.name().to_string_lossy().into_owned())
+    } else {
+        None
+    };
+
+    if let Some(context) = &*SERVER_CORE_CONTEXT.read() {
+        context.set_foreground_application(application);
+    }
+}
+
 #[unsafe(export_name = "SetVideoConfigNals")]
 extern "C" fn set_video_config_nals(buffer_ptr: *const u8, len: i32, codec: i32) {
     let codec = if codec == 0 {
@@ -629,6 +655,16 @@ extern "C" fn get_dynamic_encoder_params() -> FfiDynamicEncoderParams {
     }
 }
 
+#[unsafe(export_name = "ReportRecoveryFrame")]
+extern "C" fn report_recovery_frame(timestamp_ns: u64, last_valid_timestamp_ns: u64) {
+    if let Some(context) = &*SERVER_CORE_CONTEXT.read() {
+        context.report_recovery_frame(
+            Duration::from_nanos(timestamp_ns),
+            Duration::from_nanos(last_valid_timestamp_ns),
+        );
+    }
+}
+
 #[unsafe(export_name = "ReportComposed")]
 extern "C" fn report_composed(timestamp_ns: u64, offset_ns: u64) {
     if let Some(context) = &*SERVER_CORE_CONTEXT.read() {
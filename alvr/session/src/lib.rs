@@ -39,6 +39,15 @@ pub struct ClientConnectionConfig {
     pub manual_ips: HashSet<IpAddr>,
     pub trusted: bool,
     pub connection_state: ConnectionState,
+    // Public key used to authenticate the client when encryption is enabled. It is set when the
+    // user trusts the client or confirms its announced key in the dashboard.
+    pub pairing_key: Option<String>,
+    // Public key announced by the client, waiting to be confirmed by the user
+    #[serde(default)]
+    pub announced_pairing_key: Option<String>,
+    // Spectators receive the video and audio of the main client, their tracking is ignored
+    #[serde(default)]
+    pub spectator: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -273,6 +273,12 @@ CABAC produces better compression but it's significantly slower and may lead to
     #[schema(flag = "steamvr-restart")]
     pub filler_data: bool,
 
+    #[schema(strings(
+        help = r#"On packet loss, the client reports the last correctly decoded frame and the encoder stops referencing the frames sent after it, instead of encoding a new IDR frame.
+Only supported by NVENC on Windows. Other encoders fall back to IDR frames. Has no effect if "Avoid video glitching" is enabled."#
+    ))]
+    pub reference_frame_invalidation: bool,
+
     #[schema(strings(
         display_name = "10-bit encoding",
         help = "Sets the encoder to use 10 bits per channel instead of 8, if the client has no preference. Does not work on Linux with Nvidia"
@@ -395,6 +401,55 @@ pub enum BitrateMode {
         #[schema(flag = "real-time")]
         decoder_latency_limiter: Switch<DecoderLatencyLimiter>,
     },
+
+    #[schema(collapsible)]
+    #[schema(strings(
+        display_name = "Delay gradient",
+        help = "Congestion controller that watches the one-way delay of the video frames. The bitrate is reduced as soon as the network queues start to grow, and slowly increased otherwise"
+    ))]
+    DelayGradient {
+        #[schema(strings(display_name = "Initial bitrate"))]
+        #[schema(gui(slider(min = 1, max = 1000, logarithmic)), suffix = "Mbps")]
+        initial_bitrate_mbps: u64,
+
+        #[schema(strings(display_name = "Maximum bitrate"))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 1, max = 1000, logarithmic)), suffix = "Mbps")]
+        max_bitrate_mbps: u64,
+
+        #[schema(strings(display_name = "Minimum bitrate"))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 1, max = 100, logarithmic)), suffix = "Mbps")]
+        min_bitrate_mbps: u64,
+
+        #[schema(strings(
+            help = "Initial value of the delay trend above which the network is considered congested. The threshold then adapts to the network jitter"
+        ))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 1.0, max = 50.0, step = 0.5)), suffix = "ms")]
+        initial_overuse_threshold_ms: f32,
+
+        #[schema(strings(
+            help = "The bitrate is set to this fraction of the receive rate when the network is congested"
+        ))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 0.5, max = 0.95, step = 0.01)))]
+        decrease_multiplier: f32,
+
+        #[schema(strings(
+            help = "Bitrate increase per second while the network is not congested"
+        ))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 0.01, max = 0.5, step = 0.01)))]
+        increase_rate_per_s: f32,
+
+        #[schema(strings(
+            help = "Minimum interval between encoder bitrate updates. Decreases caused by congestion are applied immediately"
+        ))]
+        #[schema(flag = "real-time")]
+        #[schema(gui(slider(min = 10, max = 1000, logarithmic)), suffix = "ms")]
+        update_interval_ms: u64,
+    },
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone, PartialEq)]
@@ -1044,12 +1099,126 @@ pub struct Range {
     pub max: f32,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct CurvePoint {
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)))]
+    pub input: f32,
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)))]
+    pub output: f32,
+}
+
+// Applied to the magnitude of the axis, after the deadzone
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub enum ResponseCurve {
+    Linear,
+    #[schema(strings(help = "Values above 1 give more precision for small movements"))]
+    Exponent(#[schema(gui(slider(min = 0.2, max = 5.0, step = 0.1)))] f32),
+    #[schema(strings(
+        help = "Points of a piecewise linear curve. The curve always passes through (0, 0) and (1, 1)."
+    ))]
+    Piecewise(Vec<CurvePoint>),
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct AxisTransform {
+    #[schema(strings(
+        help = "Smaller values are ignored. The rest of the range is stretched to start from 0."
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 0.9, step = 0.01)))]
+    pub deadzone: f32,
+
+    pub curve: ResponseCurve,
+
+    pub inverted: bool,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct ThumbstickAxisTransform {
+    #[schema(strings(help = "Path of the other axis of the same thumbstick"))]
+    pub paired_axis: String,
+
+    #[schema(strings(
+        help = "Deadzone applied to the distance of the thumbstick from the center, before the deadzone of each axis"
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 0.9, step = 0.01)))]
+    pub radial_deadzone: f32,
+
+    pub transform: AxisTransform,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+#[schema(gui = "button_group")]
+pub enum ThumbstickDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct ThumbstickDirectionConfig {
+    #[schema(strings(
+        help = "Path of the Y axis of the same thumbstick. The source must be the X axis."
+    ))]
+    pub y_axis: String,
+
+    pub direction: ThumbstickDirection,
+
+    #[schema(strings(help = "Minimum distance of the thumbstick from the center"))]
+    #[schema(gui(slider(min = 0.1, max = 1.0, step = 0.01)))]
+    pub threshold: f32,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub enum ButtonMappingType {
     Passthrough,
     HysteresisThreshold(HysteresisThreshold),
     BinaryToScalar(BinaryToScalarStates),
     Remap(Range),
+
+    // The following mappings apply to binary sources and destinations
+    #[schema(strings(help = "Each press switches the destination on or off"))]
+    Toggle,
+
+    #[schema(strings(help = "The destination is activated after holding the button"))]
+    LongPress(#[schema(gui(slider(min = 100, max = 2000, step = 50)), suffix = "ms")] u64),
+
+    #[schema(strings(
+        help = "The destination is activated by a second press within this interval, until released"
+    ))]
+    DoubleTap(#[schema(gui(slider(min = 100, max = 1000, step = 10)), suffix = "ms")] u64),
+
+    #[schema(strings(
+        help = "While the button is held, the destination is pressed and released repeatedly with this interval"
+    ))]
+    Turbo(#[schema(gui(slider(min = 20, max = 1000, step = 10)), suffix = "ms")] u64),
+
+    #[schema(strings(
+        help = "The destination is activated while the button and all these buttons are held, pressed in any order"
+    ))]
+    Chord(Vec<String>),
+
+    // The following mappings apply to scalar sources
+    #[schema(strings(
+        help = "Deadzone, response curve and inversion for triggers and single axes"
+    ))]
+    Axis(AxisTransform),
+
+    #[schema(strings(
+        help = "Axis transform for thumbsticks, which also takes into account the other axis"
+    ))]
+    ThumbstickAxis(ThumbstickAxisTransform),
+
+    #[schema(strings(
+        display_name = "Thumbstick to d-pad",
+        help = "Binary destination pressed while the thumbstick points in a direction"
+    ))]
+    Dpad(ThumbstickDirectionConfig),
+
+    #[schema(strings(
+        help = "Binary destination pressed briefly each time the thumbstick is pushed in a direction"
+    ))]
+    SnapTurn(ThumbstickDirectionConfig),
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -1059,6 +1228,17 @@ pub struct ButtonBindingTarget {
     pub binary_conditions: Vec<String>,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct InputMappingProfile {
+    #[schema(strings(
+        help = "Process names of the applications using this profile, for example vrchat.exe. SteamVR app keys are not supported."
+    ))]
+    pub applications: Vec<String>,
+
+    #[schema(strings(help = "List of OpenXR-syle paths"))]
+    pub button_mappings: Vec<(String, Vec<ButtonBindingTarget>)>,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[schema(collapsible)]
 pub struct AutomaticButtonMappingConfig {
@@ -1067,6 +1247,83 @@ pub struct AutomaticButtonMappingConfig {
     pub force_threshold: f32,
 }
 
+// Same order as the joints of the hand skeleton
+#[repr(u8)]
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum HandJoint {
+    Palm,
+    Wrist,
+    ThumbMetacarpal,
+    ThumbProximal,
+    ThumbDistal,
+    ThumbTip,
+    IndexMetacarpal,
+    IndexProximal,
+    IndexIntermediate,
+    IndexDistal,
+    IndexTip,
+    MiddleMetacarpal,
+    MiddleProximal,
+    MiddleIntermediate,
+    MiddleDistal,
+    MiddleTip,
+    RingMetacarpal,
+    RingProximal,
+    RingIntermediate,
+    RingDistal,
+    RingTip,
+    LittleMetacarpal,
+    LittleProximal,
+    LittleIntermediate,
+    LittleDistal,
+    LittleTip,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ThresholdComparison {
+    Below,
+    Above,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub enum HandJointPredicate {
+    #[schema(strings(help = "Distance between the centers of two joints"))]
+    Distance {
+        first_joint: HandJoint,
+        second_joint: HandJoint,
+        comparison: ThresholdComparison,
+        #[schema(gui(slider(min = 0.0, max = 20.0, step = 0.1)), suffix = "cm")]
+        threshold: f32,
+    },
+    #[schema(strings(
+        help = "Angle at the middle joint between the directions to the other two joints. A straight finger measures 180°."
+    ))]
+    Angle {
+        first_joint: HandJoint,
+        middle_joint: HandJoint,
+        last_joint: HandJoint,
+        comparison: ThresholdComparison,
+        #[schema(gui(slider(min = 0.0, max = 180.0, step = 1.0)), suffix = "°")]
+        threshold: f32,
+    },
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct CustomHandGesture {
+    #[schema(strings(help = "All predicates must hold for the gesture to be active"))]
+    pub predicates: Vec<HandJointPredicate>,
+
+    #[schema(strings(
+        help = "Button path driven when the gesture is performed with the left hand, e.g. /user/hand/left/input/menu/click"
+    ))]
+    pub left_hand_button: Switch<String>,
+
+    #[schema(strings(
+        help = "Button path driven when the gesture is performed with the right hand, e.g. /user/hand/right/input/a/click"
+    ))]
+    pub right_hand_button: Switch<String>,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct HandTrackingInteractionConfig {
     #[schema(flag = "real-time")]
@@ -1139,6 +1396,12 @@ pub struct HandTrackingInteractionConfig {
     ))]
     #[schema(gui(slider(min = 0, max = 1000)), suffix = "ms")]
     pub repeat_delay: u32,
+
+    #[schema(flag = "real-time")]
+    #[schema(strings(
+        help = "Gestures defined by the positions of the hand joints, evaluated in addition to the built-in ones. They use the same activation, deactivation and repeat delays."
+    ))]
+    pub custom_gestures: Vec<(String, CustomHandGesture)>,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -1247,6 +1510,11 @@ Currently this cannot be reliably estimated automatically. The correct value sho
     #[schema(strings(help = "List of OpenXR-syle paths"))]
     pub button_mappings: Option<Vec<(String, Vec<ButtonBindingTarget>)>>,
 
+    #[schema(strings(
+        help = "Named button mappings used in place of the ones above while one of their applications is in the foreground. A profile can also be selected manually with the web server API."
+    ))]
+    pub input_profiles: Vec<(String, InputMappingProfile)>,
+
     pub button_mapping_config: AutomaticButtonMappingConfig,
 }
 
@@ -1277,6 +1545,81 @@ This will be configurable in the future."
     pub detached_controllers_steamvr_sink: bool,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct OutlierRejectionConfig {
+    #[schema(strings(help = "Samples implying a faster movement are discarded"))]
+    #[schema(gui(slider(min = 1.0, max = 50.0, step = 0.5)), suffix = "m/s")]
+    pub max_linear_speed: f32,
+
+    #[schema(strings(help = "Samples implying a faster rotation are discarded"))]
+    #[schema(gui(slider(min = 90.0, max = 7200.0, step = 90.0)), suffix = "°/s")]
+    pub max_angular_speed_deg: f32,
+
+    #[schema(strings(
+        help = "After this many consecutive discarded samples, the next sample is accepted. This allows recovering from a real jump of the tracked position."
+    ))]
+    #[schema(gui(slider(min = 1, max = 30)))]
+    pub max_consecutive_rejections: usize,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct OneEuroFilterConfig {
+    #[schema(strings(
+        help = "Cutoff frequency when the device is still. Lower values remove more jitter but increase lag."
+    ))]
+    #[schema(gui(slider(min = 0.1, max = 10.0, step = 0.1)), suffix = "Hz")]
+    pub min_cutoff_hz: f32,
+
+    #[schema(strings(
+        help = "How much the cutoff frequency increases with speed. Higher values reduce lag during fast movements."
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 5.0, step = 0.05)))]
+    pub beta: f32,
+
+    #[schema(strings(help = "Cutoff frequency used to smooth the speed"))]
+    #[schema(gui(slider(min = 0.1, max = 10.0, step = 0.1)), suffix = "Hz")]
+    pub derivative_cutoff_hz: f32,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub enum PoseSmoothingConfig {
+    #[schema(strings(
+        display_name = "One Euro filter",
+        help = "Smooths strongly when the device is still and less during fast movements"
+    ))]
+    OneEuro(OneEuroFilterConfig),
+
+    #[schema(strings(help = "Each pose is blended with the previous smoothed pose"))]
+    Exponential {
+        #[schema(strings(
+            help = "Weight of the previous pose. Higher values remove more jitter but increase lag."
+        ))]
+        #[schema(gui(slider(min = 0.0, max = 0.95, step = 0.01)))]
+        smoothing: f32,
+    },
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct PoseFilterConfig {
+    pub outlier_rejection: Switch<OutlierRejectionConfig>,
+    pub smoothing: Switch<PoseSmoothingConfig>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+#[schema(collapsible)]
+pub struct PoseFiltersConfig {
+    pub head: PoseFilterConfig,
+
+    #[schema(strings(help = "Includes detached controllers"))]
+    pub controllers: PoseFilterConfig,
+
+    #[schema(strings(help = "Trackers obtained from body tracking"))]
+    pub body_trackers: PoseFilterConfig,
+
+    #[schema(strings(help = "Motion trackers, as exposed before assigning them to body parts"))]
+    pub generic_trackers: PoseFilterConfig,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct HeadsetConfig {
     #[schema(strings(
@@ -1328,6 +1671,12 @@ Because of runtime limitations, this option is ignored when body tracking is act
     #[schema(strings(display_name = "VMC"))]
     pub vmc: Switch<VMCConfig>,
 
+    #[schema(strings(
+        help = "Filter the poses of tracked devices to reduce jitter. Filters are applied before prediction."
+    ))]
+    #[schema(flag = "real-time")]
+    pub pose_filters: Switch<PoseFiltersConfig>,
+
     #[schema(strings(
         help = "Maximum prediction for head and controllers. Used to avoid too much jitter during loading."
     ))]
@@ -1342,6 +1691,8 @@ pub enum SocketProtocol {
     Udp,
     #[schema(strings(display_name = "TCP"))]
     Tcp,
+    #[schema(strings(display_name = "QUIC"))]
+    Quic,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -1369,6 +1720,103 @@ pub struct SocketBufferConfig {
     pub recv_size_bytes: SocketBufferSize,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct ForwardErrorCorrectionConfig {
+    #[schema(strings(
+        help = "Number of consecutive shards of a packet protected by the same group of parity shards."
+    ))]
+    #[schema(gui(slider(min = 1, max = 128)), suffix = " shards")]
+    pub data_shards_per_block: u32,
+
+    #[schema(strings(
+        help = "Number of shards that can be lost in each block and still be recovered. Each parity shard costs as much bandwidth as a data shard."
+    ))]
+    #[schema(gui(slider(min = 1, max = 32)), suffix = " shards")]
+    pub parity_shards_per_block: u32,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct VideoRetransmissionConfig {
+    #[schema(strings(
+        help = "Lost shards are sent again only if they can arrive within this time from the first transmission of the frame. Frames that are not recovered in time are dropped and a IDR frame is requested."
+    ))]
+    #[schema(gui(slider(min = 5, max = 200, step = 5)), suffix = "ms")]
+    pub deadline_ms: u64,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct GilbertElliottLossConfig {
+    #[schema(strings(
+        help = "Probability for each packet that the link switches from the good state to the bad state."
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.001)))]
+    pub good_to_bad_probability: f32,
+
+    #[schema(strings(
+        help = "Probability for each packet that the link switches from the bad state back to the good state. The average length of a loss burst is the inverse of this value."
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)))]
+    pub bad_to_good_probability: f32,
+
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.001)))]
+    pub good_state_loss_probability: f32,
+
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)))]
+    pub bad_state_loss_probability: f32,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct LinkImpairmentConfig {
+    #[schema(gui(slider(min = 0, max = 500)), suffix = "ms")]
+    pub latency_ms: u64,
+
+    #[schema(strings(
+        help = "Maximum random deviation from the latency. Datagrams are not reordered by jitter."
+    ))]
+    #[schema(gui(slider(min = 0, max = 100)), suffix = "ms")]
+    pub jitter_ms: u64,
+
+    #[schema(strings(display_name = "Bandwidth cap"))]
+    #[schema(gui(slider(min = 1, max = 1000, logarithmic)), suffix = "Mbps")]
+    pub bandwidth_cap_mbps: Switch<u64>,
+
+    #[schema(strings(
+        help = "Datagrams that would wait longer than this in the queue of the bandwidth cap are dropped."
+    ))]
+    #[schema(gui(slider(min = 1, max = 1000, logarithmic)), suffix = "ms")]
+    pub max_queue_delay_ms: u64,
+
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)))]
+    pub reorder_probability: f32,
+
+    #[schema(strings(help = "Additional delay of reordered datagrams."))]
+    #[schema(gui(slider(min = 1, max = 100)), suffix = "ms")]
+    pub reorder_delay_ms: u64,
+
+    #[schema(strings(
+        help = "Bursty packet loss, modeled with a good and a bad state (Gilbert-Elliott model)."
+    ))]
+    pub loss: Switch<GilbertElliottLossConfig>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub struct NetworkImpairmentConfig {
+    pub server_to_client: LinkImpairmentConfig,
+    pub client_to_server: LinkImpairmentConfig,
+
+    #[schema(strings(help = "The same seed reproduces the same sequence of random events."))]
+    pub random_seed: u64,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct ConnectionDebugConfig {
+    #[schema(strings(
+        help = r#"Simulate a bad network on the stream socket. Applied by the streamer for both directions, to every UDP datagram, so lost datagrams are recovered by forward error correction and retransmission like on a real network. Supported only with the UDP protocol.
+Use for testing only."#
+    ))]
+    pub network_impairment: Switch<NetworkImpairmentConfig>,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct WiredClientAutoLaunchConfig {
     #[schema(strings(
@@ -1381,7 +1829,8 @@ pub struct WiredClientAutoLaunchConfig {
 pub struct ConnectionConfig {
     #[schema(strings(
         help = r#"UDP: Faster, but less stable than TCP. Try this if your network is well optimized and free of interference.
-TCP: Slower than UDP, but more stable. Pick this if you experience video or audio stutters with UDP."#
+TCP: Slower than UDP, but more stable. Pick this if you experience video or audio stutters with UDP.
+QUIC: Video, audio and tracking are sent as unreliable datagrams like UDP, haptics and statistics use retransmitted QUIC streams. Packets are limited to 1152 bytes."#
     ))]
     pub stream_protocol: SocketProtocol,
 
@@ -1440,6 +1889,23 @@ TCP: Slower than UDP, but more stable. Pick this if you experience video or audi
     #[schema(gui(slider(min = 1024, max = 65507, logarithmic)), suffix = "B")]
     pub packet_size: i32,
 
+    #[schema(strings(
+        help = r#"Send redundant shards alongside each packet so that lost shards can be rebuilt by the receiver, instead of waiting for a new IDR frame. Only used with UDP."#
+    ))]
+    pub forward_error_correction: Switch<ForwardErrorCorrectionConfig>,
+
+    #[schema(strings(
+        help = r#"Request the specific video shards lost by the network, instead of requesting a new IDR frame. Only used with UDP and QUIC."#
+    ))]
+    pub video_retransmission: Switch<VideoRetransmissionConfig>,
+
+    #[schema(strings(
+        help = r#"Authenticate the client and encrypt the control and stream sockets. The client key is pinned when it is trusted or confirmed in the Devices tab: check that the pairing key shown there matches the one shown by the client. Wired clients and clients trusted automatically are paired on their first connection. The client pins the key of the first streamer it pairs with.
+A paired client refuses unencrypted connections and connections from other streamers. To pair the client again, clear the app data of the client.
+Clients that are not paired accept unencrypted connections, unless they are configured to require encryption."#
+    ))]
+    pub encryption: bool,
+
     pub stream_port: u16,
     pub web_server_port: u16,
 
@@ -1465,6 +1931,8 @@ This could happen on TCP. A IDR frame is requested in this case."#
 
     #[schema(strings(display_name = "DSCP (packet prio hints)"))]
     pub dscp: Option<DscpTos>,
+
+    pub debug: ConnectionDebugConfig,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
@@ -1559,13 +2027,51 @@ pub struct RollingVideoFilesConfig {
     pub duration_s: u64,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct StatisticsTimelineConfig {
+    #[schema(strings(display_name = "Maximum file size"))]
+    #[schema(gui(slider(min = 1, max = 1000, logarithmic)), suffix = "MB")]
+    pub max_file_size_mb: u64,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct VideoMirrorConfig {
+    #[schema(gui(slider(min = 1, max = 10)))]
+    pub max_subscribers: usize,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct CaptureConfig {
     #[schema(strings(display_name = "Start video recording at client connection"))]
     pub startup_video_recording: bool,
 
+    #[schema(strings(
+        display_name = "Start session recording at client connection",
+        help = "Records tracking, buttons and headset state to a file in the log directory. The file can be replayed without a headset from the debug tab of the dashboard."
+    ))]
+    pub startup_session_recording: bool,
+
+    #[schema(strings(
+        help = "Rolling video files are split at IDR frames, so each file can be played independently"
+    ))]
     pub rolling_video_files: Switch<RollingVideoFilesConfig>,
 
+    #[schema(strings(
+        display_name = "Record view params",
+        help = "Adds a subtitle track to video recordings containing the JSON encoded view parameters of each frame"
+    ))]
+    pub record_view_params: bool,
+
+    #[schema(strings(
+        help = "Streams the encoded video through a WebSocket at /api/video-mirror on the web server port. The read-only or admin web server token must be passed with the token query parameter."
+    ))]
+    pub video_mirror: Switch<VideoMirrorConfig>,
+
+    #[schema(strings(
+        help = "Writes the statistics of every frame to CSV files in the log directory. Use `cargo xtask summarize-stats --file <path>` to get latency percentiles of a file."
+    ))]
+    pub statistics_timeline: Switch<StatisticsTimelineConfig>,
+
     #[schema(flag = "steamvr-restart")]
     pub capture_frame_dir: String,
 }
@@ -1644,6 +2150,145 @@ pub fn session_settings_default() -> SettingsDefault {
         },
         content: vec![],
     };
+    let axis_transform = AxisTransformDefault {
+        deadzone: 0.1,
+        curve: ResponseCurveDefault {
+            Exponent: 2.0,
+            Piecewise: VectorDefault {
+                gui_collapsed: false,
+                element: CurvePointDefault {
+                    input: 0.5,
+                    output: 0.5,
+                },
+                content: vec![],
+            },
+            variant: ResponseCurveDefaultVariant::Linear,
+        },
+        inverted: false,
+    };
+    let default_button_mappings = DictionaryDefault {
+        gui_collapsed: false,
+        key: "/user/hand/left/input/a/click".into(),
+        value: VectorDefault {
+            gui_collapsed: false,
+            element: ButtonBindingTargetDefault {
+                destination: "/user/hand/left/input/a/click".into(),
+                mapping_type: ButtonMappingTypeDefault {
+                    HysteresisThreshold: HysteresisThresholdDefault {
+                        value: 0.5,
+                        deviation: 0.05,
+                    },
+                    BinaryToScalar: BinaryToScalarStatesDefault { off: 0.0, on: 1.0 },
+                    Remap: RangeDefault { min: 0.0, max: 1.0 },
+                    LongPress: 500,
+                    DoubleTap: 300,
+                    Turbo: 100,
+                    Chord: VectorDefault {
+                        gui_collapsed: false,
+                        element: "/user/hand/left/input/trigger/click".into(),
+                        content: vec![],
+                    },
+                    Axis: axis_transform.clone(),
+                    ThumbstickAxis: ThumbstickAxisTransformDefault {
+                        paired_axis: "/user/hand/left/input/thumbstick/y".into(),
+                        radial_deadzone: 0.1,
+                        transform: AxisTransformDefault {
+                            deadzone: 0.0,
+                            ..axis_transform
+                        },
+                    },
+                    Dpad: ThumbstickDirectionConfigDefault {
+                        y_axis: "/user/hand/left/input/thumbstick/y".into(),
+                        direction: ThumbstickDirectionDefault {
+                            variant: ThumbstickDirectionDefaultVariant::Up,
+                        },
+                        threshold: 0.5,
+                    },
+                    SnapTurn: ThumbstickDirectionConfigDefault {
+                        y_axis: "/user/hand/left/input/thumbstick/y".into(),
+                        direction: ThumbstickDirectionDefault {
+                            variant: ThumbstickDirectionDefaultVariant::Right,
+                        },
+                        threshold: 0.7,
+                    },
+                    variant: ButtonMappingTypeDefaultVariant::Passthrough,
+                },
+                binary_conditions: VectorDefault {
+                    gui_collapsed: true,
+                    element: "/user/hand/left/input/trigger/touch".into(),
+                    content: vec![],
+                },
+            },
+            content: vec![],
+        },
+        content: vec![],
+    };
+    let pose_filter = |filter_enabled| PoseFilterConfigDefault {
+        outlier_rejection: SwitchDefault {
+            enabled: filter_enabled,
+            content: OutlierRejectionConfigDefault {
+                max_linear_speed: 10.0,
+                max_angular_speed_deg: 1800.0,
+                max_consecutive_rejections: 5,
+            },
+        },
+        smoothing: SwitchDefault {
+            enabled: filter_enabled,
+            content: PoseSmoothingConfigDefault {
+                OneEuro: OneEuroFilterConfigDefault {
+                    min_cutoff_hz: 1.0,
+                    beta: 1.0,
+                    derivative_cutoff_hz: 1.0,
+                },
+                Exponential: PoseSmoothingConfigExponentialDefault { smoothing: 0.5 },
+                variant: PoseSmoothingConfigDefaultVariant::OneEuro,
+            },
+        },
+    };
+    let custom_hand_gesture = CustomHandGestureDefault {
+        predicates: VectorDefault {
+            gui_collapsed: false,
+            element: HandJointPredicateDefault {
+                Distance: HandJointPredicateDistanceDefault {
+                    first_joint: HandJointDefault {
+                        variant: HandJointDefaultVariant::ThumbTip,
+                    },
+                    second_joint: HandJointDefault {
+                        variant: HandJointDefaultVariant::IndexTip,
+                    },
+                    comparison: ThresholdComparisonDefault {
+                        variant: ThresholdComparisonDefaultVariant::Below,
+                    },
+                    threshold: 2.0,
+                },
+                Angle: HandJointPredicateAngleDefault {
+                    first_joint: HandJointDefault {
+                        variant: HandJointDefaultVariant::IndexProximal,
+                    },
+                    middle_joint: HandJointDefault {
+                        variant: HandJointDefaultVariant::IndexIntermediate,
+                    },
+                    last_joint: HandJointDefault {
+                        variant: HandJointDefaultVariant::IndexDistal,
+                    },
+                    comparison: ThresholdComparisonDefault {
+                        variant: ThresholdComparisonDefaultVariant::Above,
+                    },
+                    threshold: 150.0,
+                },
+                variant: HandJointPredicateDefaultVariant::Distance,
+            },
+            content: vec![],
+        },
+        left_hand_button: SwitchDefault {
+            enabled: true,
+            content: "/user/hand/left/input/menu/click".into(),
+        },
+        right_hand_button: SwitchDefault {
+            enabled: false,
+            content: "/user/hand/right/input/a/click".into(),
+        },
+    };
     let socket_buffer = SocketBufferSizeDefault {
         Custom: 100000,
         variant: SocketBufferSizeDefaultVariant::Maximum,
@@ -1748,6 +2393,16 @@ pub fn session_settings_default() -> SettingsDefault {
                             },
                         },
                     },
+                    DelayGradient: BitrateModeDelayGradientDefault {
+                        gui_collapsed: true,
+                        initial_bitrate_mbps: 30,
+                        max_bitrate_mbps: 100,
+                        min_bitrate_mbps: 5,
+                        initial_overuse_threshold_ms: 12.5,
+                        decrease_multiplier: 0.85,
+                        increase_rate_per_s: 0.08,
+                        update_interval_ms: 100,
+                    },
                     variant: BitrateModeDefaultVariant::ConstantMbps,
                 },
                 adapt_to_framerate: SwitchDefault {
@@ -1768,6 +2423,7 @@ pub fn session_settings_default() -> SettingsDefault {
                     variant: RateControlModeDefaultVariant::Cbr,
                 },
                 filler_data: false,
+                reference_frame_invalidation: false,
                 h264_profile: H264ProfileDefault {
                     variant: H264ProfileDefaultVariant::High,
                 },
@@ -2022,6 +2678,16 @@ pub fn session_settings_default() -> SettingsDefault {
                     orientation_correction: true,
                 },
             },
+            pose_filters: SwitchDefault {
+                enabled: false,
+                content: PoseFiltersConfigDefault {
+                    gui_collapsed: true,
+                    head: pose_filter(false),
+                    controllers: pose_filter(false),
+                    body_trackers: pose_filter(true),
+                    generic_trackers: pose_filter(true),
+                },
+            },
             controllers: SwitchDefault {
                 enabled: true,
                 content: ControllersConfigDefault {
@@ -2048,35 +2714,20 @@ pub fn session_settings_default() -> SettingsDefault {
                     extra_openvr_props: default_custom_openvr_props,
                     button_mappings: OptionalDefault {
                         set: false,
-                        content: DictionaryDefault {
-                            gui_collapsed: false,
-                            key: "/user/hand/left/input/a/click".into(),
-                            value: VectorDefault {
+                        content: default_button_mappings.clone(),
+                    },
+                    input_profiles: DictionaryDefault {
+                        gui_collapsed: true,
+                        key: "Profile".into(),
+                        value: InputMappingProfileDefault {
+                            applications: VectorDefault {
                                 gui_collapsed: false,
-                                element: ButtonBindingTargetDefault {
-                                    destination: "/user/hand/left/input/a/click".into(),
-                                    mapping_type: ButtonMappingTypeDefault {
-                                        HysteresisThreshold: HysteresisThresholdDefault {
-                                            value: 0.5,
-                                            deviation: 0.05,
-                                        },
-                                        BinaryToScalar: BinaryToScalarStatesDefault {
-                                            off: 0.0,
-                                            on: 1.0,
-                                        },
-                                        Remap: RangeDefault { min: 0.0, max: 1.0 },
-                                        variant: ButtonMappingTypeDefaultVariant::Passthrough,
-                                    },
-                                    binary_conditions: VectorDefault {
-                                        gui_collapsed: true,
-                                        element: "/user/hand/left/input/trigger/touch".into(),
-                                        content: vec![],
-                                    },
-                                },
+                                element: "".into(),
                                 content: vec![],
                             },
-                            content: vec![],
+                            button_mappings: default_button_mappings,
                         },
+                        content: vec![],
                     },
                     button_mapping_config: AutomaticButtonMappingConfigDefault {
                         gui_collapsed: true,
@@ -2105,6 +2756,12 @@ pub fn session_settings_default() -> SettingsDefault {
                             repeat_delay: 100,
                             activation_delay: 50,
                             deactivation_delay: 100,
+                            custom_gestures: DictionaryDefault {
+                                gui_collapsed: true,
+                                key: "Gesture".into(),
+                                value: custom_hand_gesture,
+                                content: vec![],
+                            },
                         },
                     },
                     steamvr_pipeline_frames: 2.1,
@@ -2191,7 +2848,67 @@ pub fn session_settings_default() -> SettingsDefault {
             enable_on_disconnect_script: false,
             allow_untrusted_http: false,
             packet_size: 1400,
+            forward_error_correction: SwitchDefault {
+                enabled: false,
+                content: ForwardErrorCorrectionConfigDefault {
+                    data_shards_per_block: 20,
+                    parity_shards_per_block: 2,
+                },
+            },
+            video_retransmission: SwitchDefault {
+                enabled: false,
+                content: VideoRetransmissionConfigDefault { deadline_ms: 40 },
+            },
+            encryption: false,
             statistics_history_size: 256,
+            debug: ConnectionDebugConfigDefault {
+                network_impairment: SwitchDefault {
+                    enabled: false,
+                    content: NetworkImpairmentConfigDefault {
+                        server_to_client: LinkImpairmentConfigDefault {
+                            latency_ms: 20,
+                            jitter_ms: 2,
+                            bandwidth_cap_mbps: SwitchDefault {
+                                enabled: false,
+                                content: 100,
+                            },
+                            max_queue_delay_ms: 100,
+                            reorder_probability: 0.0,
+                            reorder_delay_ms: 5,
+                            loss: SwitchDefault {
+                                enabled: true,
+                                content: GilbertElliottLossConfigDefault {
+                                    good_to_bad_probability: 0.005,
+                                    bad_to_good_probability: 0.3,
+                                    good_state_loss_probability: 0.0,
+                                    bad_state_loss_probability: 0.5,
+                                },
+                            },
+                        },
+                        client_to_server: LinkImpairmentConfigDefault {
+                            latency_ms: 20,
+                            jitter_ms: 2,
+                            bandwidth_cap_mbps: SwitchDefault {
+                                enabled: false,
+                                content: 100,
+                            },
+                            max_queue_delay_ms: 100,
+                            reorder_probability: 0.0,
+                            reorder_delay_ms: 5,
+                            loss: SwitchDefault {
+                                enabled: true,
+                                content: GilbertElliottLossConfigDefault {
+                                    good_to_bad_probability: 0.005,
+                                    bad_to_good_probability: 0.3,
+                                    good_state_loss_probability: 0.0,
+                                    bad_state_loss_probability: 0.5,
+                                },
+                            },
+                        },
+                        random_seed: 0,
+                    },
+                },
+            },
         },
         extra: ExtraConfigDefault {
             logging: LoggingConfigDefault {
@@ -2239,6 +2956,18 @@ pub fn session_settings_default() -> SettingsDefault {
             },
             capture: CaptureConfigDefault {
                 startup_video_recording: false,
+                startup_session_recording: false,
+                record_view_params: false,
+                statistics_timeline: SwitchDefault {
+                    enabled: false,
+                    content: StatisticsTimelineConfigDefault {
+                        max_file_size_mb: 100,
+                    },
+                },
+                video_mirror: SwitchDefault {
+                    enabled: false,
+                    content: VideoMirrorConfigDefault { max_subscribers: 2 },
+                },
                 rolling_video_files: SwitchDefault {
                     enabled: false,
                     content: RollingVideoFilesConfigDefault { duration_s: 5 },
@@ -1,13 +1,19 @@
-use crate::{CONTROL_PORT, LOCAL_IP};
-use alvr_common::{ConResult, HandleTryAgain, ToCon, anyhow::Result, con_bail};
+use crate::{
+    CONTROL_PORT, LOCAL_IP,
+    secure_channel::{self, SecureChannel, SecureStreamReader},
+};
+use alvr_common::{
+    AnyhowToCon, ConResult, HandleTryAgain, ToCon, anyhow::Result, con_bail, parking_lot::Mutex,
+};
 use alvr_session::{DscpTos, SocketBufferConfig};
 use bincode::config;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
     io::{Read, Write},
     marker::PhantomData,
     mem,
     net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -83,9 +89,32 @@ pub fn connect_to_client(
     Ok((socket.try_clone().to_con()?, socket))
 }
 
+// Packets exchanged before the stream configuration, to set up encryption
+#[derive(Serialize, Deserialize)]
+enum SecureHandshakePacket {
+    EncryptionDisabled,
+    NoiseMessage(Vec<u8>),
+}
+
+// Used when the connection is encrypted. The buffer holds the encrypted records.
+pub(crate) struct SecureStreamWriter {
+    pub(crate) channel: SecureChannel,
+    pub(crate) buffer: Vec<u8>,
+}
+
+impl SecureStreamWriter {
+    pub(crate) fn new(channel: SecureChannel) -> Self {
+        Self {
+            channel,
+            buffer: vec![],
+        }
+    }
+}
+
 fn framed_send<S: Serialize>(
     socket: &mut TcpStream,
     buffer: &mut Vec<u8>,
+    encryptor: Option<&mut SecureStreamWriter>,
     packet: &S,
 ) -> Result<()> {
     buffer.resize(FRAMED_PREFIX_LENGTH, 0);
@@ -94,7 +123,15 @@ fn framed_send<S: Serialize>(
 
     buffer[0..FRAMED_PREFIX_LENGTH].copy_from_slice(&(encoded_size as u32).to_le_bytes());
 
-    socket.write_all(buffer)?;
+    if let Some(encryptor) = encryptor {
+        encryptor
+            .channel
+            .seal_records(buffer, &mut encryptor.buffer)?;
+
+        socket.write_all(&encryptor.buffer)?;
+    } else {
+        socket.write_all(buffer)?;
+    }
 
     Ok(())
 }
@@ -103,6 +140,7 @@ fn framed_recv<R: DeserializeOwned>(
     socket: &mut TcpStream,
     buffer: &mut Vec<u8>,
     recv_cursor: &mut Option<usize>,
+    mut decryptor: Option<&mut SecureStreamReader>,
     timeout: Duration,
 ) -> ConResult<R> {
     let deadline = Instant::now() + timeout;
@@ -113,7 +151,11 @@ fn framed_recv<R: DeserializeOwned>(
         let mut payload_size_bytes = [0; FRAMED_PREFIX_LENGTH];
 
         loop {
-            let count = socket.peek(&mut payload_size_bytes).handle_try_again()?;
+            let count = if let Some(decryptor) = &mut decryptor {
+                decryptor.peek(socket, &mut payload_size_bytes)?
+            } else {
+                socket.peek(&mut payload_size_bytes).handle_try_again()?
+            };
             if count == FRAMED_PREFIX_LENGTH {
                 break;
             } else if Instant::now() > deadline {
@@ -128,9 +170,16 @@ fn framed_recv<R: DeserializeOwned>(
     };
 
     loop {
-        *recv_cursor_ref += socket
-            .read(&mut buffer[*recv_cursor_ref..])
-            .handle_try_again()?;
+        *recv_cursor_ref += if let Some(decryptor) = &mut decryptor {
+            decryptor.read(
+                socket,
+                crate::as_uninit_slice(&mut buffer[*recv_cursor_ref..]),
+            )?
+        } else {
+            socket
+                .read(&mut buffer[*recv_cursor_ref..])
+                .handle_try_again()?
+        };
 
         if *recv_cursor_ref == buffer.len() {
             break;
@@ -151,12 +200,18 @@ fn framed_recv<R: DeserializeOwned>(
 pub struct ControlSocketSender<T> {
     pub(crate) inner: TcpStream,
     pub(crate) buffer: Vec<u8>,
+    pub(crate) encryptor: Option<SecureStreamWriter>,
     pub(crate) _phantom: PhantomData<T>,
 }
 
 impl<S: Serialize> ControlSocketSender<S> {
     pub fn send(&mut self, packet: &S) -> Result<()> {
-        framed_send(&mut self.inner, &mut self.buffer, packet)
+        framed_send(
+            &mut self.inner,
+            &mut self.buffer,
+            self.encryptor.as_mut(),
+            packet,
+        )
     }
 }
 
@@ -164,15 +219,19 @@ pub struct ControlSocketReceiver<T> {
     pub(crate) inner: TcpStream,
     pub(crate) buffer: Vec<u8>,
     pub(crate) recv_cursor: Option<usize>,
+    pub(crate) decryptor: Option<Arc<Mutex<SecureStreamReader>>>,
     pub(crate) _phantom: PhantomData<T>,
 }
 
 impl<R: DeserializeOwned> ControlSocketReceiver<R> {
     pub fn recv(&mut self, timeout: Duration) -> ConResult<R> {
+        let mut decryptor = self.decryptor.as_ref().map(|decryptor| decryptor.lock());
+
         framed_recv(
             &mut self.inner,
             &mut self.buffer,
             &mut self.recv_cursor,
+            decryptor.as_deref_mut(),
             timeout,
         )
     }
@@ -188,6 +247,13 @@ pub fn get_server_listener(timeout: Duration) -> Result<TcpListener> {
 // the specified types can be exchanged
 pub struct ProtoControlSocket {
     pub(crate) inner: TcpStream,
+    pub(crate) secure_channel: Option<SecureChannel>,
+    // The receive state is kept across calls, a packet or record can be read partially before a
+    // timeout. The decryptor is shared with the receivers created from this socket, since records
+    // already read from the socket are buffered inside it
+    recv_buffer: Vec<u8>,
+    recv_cursor: Option<usize>,
+    pub(crate) decryptor: Option<Arc<Mutex<SecureStreamReader>>>,
 }
 
 pub enum PeerType<'a> {
@@ -206,15 +272,129 @@ impl ProtoControlSocket {
 
         let peer_ip = socket.peer_addr().to_con()?.ip();
 
-        Ok((Self { inner: socket }, peer_ip))
+        Ok((
+            Self {
+                inner: socket,
+                secure_channel: None,
+                recv_buffer: vec![],
+                recv_cursor: None,
+                decryptor: None,
+            },
+            peer_ip,
+        ))
     }
 
     pub fn send<S: Serialize>(&mut self, packet: &S) -> Result<()> {
-        framed_send(&mut self.inner, &mut vec![], packet)
+        framed_send(
+            &mut self.inner,
+            &mut vec![],
+            self.secure_channel
+                .clone()
+                .map(SecureStreamWriter::new)
+                .as_mut(),
+            packet,
+        )
     }
 
     pub fn recv<R: DeserializeOwned>(&mut self, timeout: Duration) -> ConResult<R> {
-        framed_recv(&mut self.inner, &mut vec![], &mut None, timeout)
+        let mut decryptor = self.decryptor.as_ref().map(|decryptor| decryptor.lock());
+
+        framed_recv(
+            &mut self.inner,
+            &mut self.recv_buffer,
+            &mut self.recv_cursor,
+            decryptor.as_deref_mut(),
+            timeout,
+        )
+    }
+
+    fn set_secure_channel(&mut self, channel: SecureChannel) {
+        self.decryptor = Some(Arc::new(Mutex::new(SecureStreamReader::new(
+            channel.clone(),
+        ))));
+        self.secure_channel = Some(channel);
+    }
+
+    // Server side of the encryption handshake. If `private_key` is None, the client is notified
+    // that the connection will not be encrypted.
+    // Returns the public pairing key of the client, which must be verified by the caller.
+    pub fn negotiate_encryption_as_server(
+        &mut self,
+        private_key: Option<&str>,
+        timeout: Duration,
+    ) -> ConResult<Option<String>> {
+        let Some(private_key) = private_key else {
+            self.send(&SecureHandshakePacket::EncryptionDisabled)
+                .to_con()?;
+
+            return Ok(None);
+        };
+
+        let mut handshake = secure_channel::handshake_state(private_key, true).to_con()?;
+        let mut message = secure_channel::noise_message_buffer();
+
+        // -> e
+        let size = handshake.write_message(&[], &mut message).to_con()?;
+        self.send(&SecureHandshakePacket::NoiseMessage(
+            message[..size].to_vec(),
+        ))
+        .to_con()?;
+
+        // <- e, ee, s, es
+        let SecureHandshakePacket::NoiseMessage(response) = self.recv(timeout)? else {
+            con_bail!("Unexpected packet during encryption handshake");
+        };
+        handshake.read_message(&response, &mut message).to_con()?;
+
+        // -> s, se
+        let size = handshake.write_message(&[], &mut message).to_con()?;
+        self.send(&SecureHandshakePacket::NoiseMessage(
+            message[..size].to_vec(),
+        ))
+        .to_con()?;
+
+        let (channel, remote_key) = secure_channel::finish_handshake(handshake).to_con()?;
+        self.set_secure_channel(channel);
+
+        Ok(Some(remote_key))
+    }
+
+    // Client side of the encryption handshake.
+    // Returns the public pairing key of the server, which must be verified by the caller, or None
+    // if the server disabled encryption.
+    pub fn negotiate_encryption_as_client(
+        &mut self,
+        private_key: &str,
+        timeout: Duration,
+    ) -> ConResult<Option<String>> {
+        let SecureHandshakePacket::NoiseMessage(request) = self.recv(timeout)? else {
+            return Ok(None);
+        };
+
+        let mut handshake = secure_channel::handshake_state(private_key, false).to_con()?;
+        let mut message = secure_channel::noise_message_buffer();
+
+        handshake.read_message(&request, &mut message).to_con()?;
+
+        let size = handshake.write_message(&[], &mut message).to_con()?;
+        self.send(&SecureHandshakePacket::NoiseMessage(
+            message[..size].to_vec(),
+        ))
+        .to_con()?;
+
+        let SecureHandshakePacket::NoiseMessage(response) = self.recv(timeout)? else {
+            con_bail!("Unexpected packet during encryption handshake");
+        };
+        handshake.read_message(&response, &mut message).to_con()?;
+
+        let (channel, remote_key) = secure_channel::finish_handshake(handshake).to_con()?;
+        self.set_secure_channel(channel);
+
+        Ok(Some(remote_key))
+    }
+
+    pub fn secure_channel(&self) -> Option<SecureChannel> {
+        self.secure_channel.clone()
     }
 
     pub fn split<S: Serialize, R: DeserializeOwned>(
@@ -227,12 +407,14 @@ impl ProtoControlSocket {
             ControlSocketSender {
                 inner: self.inner.try_clone()?,
                 buffer: vec![0; FRAMED_PREFIX_LENGTH],
+                encryptor: self.secure_channel.clone().map(SecureStreamWriter::new),
                 _phantom: PhantomData,
             },
             ControlSocketReceiver {
                 inner: self.inner,
-                buffer: vec![0; FRAMED_PREFIX_LENGTH],
-                recv_cursor: None,
+                buffer: self.recv_buffer,
+                recv_cursor: self.recv_cursor,
+                decryptor: self.decryptor,
                 _phantom: PhantomData,
             },
         ))
@@ -1,18 +1,28 @@
 mod control_socket;
+mod secure_channel;
 mod stream_socket;
 
 use alvr_common::{AnyhowToCon, ConResult, ToCon, anyhow::Result, con_bail, info};
 use alvr_packets::{ClientControlPacket, ServerControlPacket};
-use alvr_session::{DscpTos, SocketBufferConfig, SocketBufferSize, SocketProtocol};
+use alvr_session::{
+    DscpTos, ForwardErrorCorrectionConfig, NetworkImpairmentConfig, SocketBufferConfig,
+    SocketBufferSize, SocketProtocol,
+};
 use serde::{Serialize, de::DeserializeOwned};
 use socket2::Socket;
 use std::{
     marker::PhantomData,
+    mem::MaybeUninit,
     net::{IpAddr, Ipv4Addr, TcpListener},
+    ptr,
     time::Duration,
 };
 
 pub use control_socket::*;
+pub use secure_channel::{
+    EncryptionStatistics, SecureChannel, generate_pairing_key, pairing_key_fingerprint,
+    pairing_public_key,
+};
 pub use stream_socket::*;
 
 pub const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
@@ -24,9 +34,14 @@ pub const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(2);
 pub const MDNS_SERVICE_TYPE: &str = "_alvr._tcp.local.";
 pub const MDNS_PROTOCOL_KEY: &str = "protocol";
 pub const MDNS_DEVICE_ID_KEY: &str = "device_id";
+pub const MDNS_PAIRING_KEY_KEY: &str = "pairing_key";
 
 pub const WIRED_CLIENT_HOSTNAME: &str = "client.wired";
 
+fn as_uninit_slice(buffer: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    unsafe { &mut *(ptr::from_mut(buffer) as *mut [MaybeUninit<u8>]) }
+}
+
 fn set_socket_buffers(socket: &socket2::Socket, buffer_config: SocketBufferConfig) -> Result<()> {
     info!(
         "Initial socket buffer size: send: {}B, recv: {}B",
@@ -141,6 +156,9 @@ pub struct StreamSocketConfig {
     pub buffer_config: SocketBufferConfig,
     pub max_packet_size: usize,
     pub dscp: Option<DscpTos>,
+    pub fec_config: Option<ForwardErrorCorrectionConfig>,
+    // Only used by the server
+    pub network_impairment: Option<NetworkImpairmentConfig>,
 }
 
 pub enum ServerConnectionResult {
@@ -182,6 +200,9 @@ impl SocketConnection {
             socket_config.dscp,
             socket_config.buffer_config,
             socket_config.max_packet_size,
+            socket_config.fec_config,
+            control_socket.secure_channel(),
+            socket_config.network_impairment,
         )?;
 
         Ok(Self {
@@ -221,6 +242,8 @@ impl SocketConnection {
             server_ip,
             socket_config.port,
             socket_config.max_packet_size,
+            socket_config.fec_config,
+            control_socket.secure_channel(),
             timeout,
         )?;
 
@@ -234,6 +257,10 @@ impl SocketConnection {
         Ok(ControlSocketSender {
             inner: self.control_socket.inner.try_clone().to_con()?,
             buffer: vec![],
+            encryptor: self
+                .control_socket
+                .secure_channel()
+                .map(SecureStreamWriter::new),
             _phantom: PhantomData,
         })
     }
@@ -243,24 +270,41 @@ impl SocketConnection {
             inner: self.control_socket.inner.try_clone().to_con()?,
             buffer: vec![],
             recv_cursor: None,
+            decryptor: self.control_socket.decryptor.clone(),
             _phantom: PhantomData,
         })
     }
 
-    pub fn request_unreliable_stream<T>(&self, stream_id: u16) -> StreamSender<T> {
-        self.stream_socket.request_stream(stream_id)
+    // Used to measure the cost of encryption. None if the connection is not encrypted
+    pub fn secure_channel(&self) -> Option<SecureChannel> {
+        self.control_socket.secure_channel()
+    }
+
+    pub fn request_stream<T>(
+        &self,
+        stream_id: u16,
+        class: StreamClass,
+        priority: StreamPriority,
+    ) -> StreamSender<T> {
+        self.stream_socket
+            .request_stream(stream_id, class, priority)
     }
 
-    pub fn subscribe_to_unreliable_stream<T>(
+    pub fn subscribe_to_stream<T>(
         &mut self,
         stream_id: u16,
+        class: StreamClass,
         max_concurrent_buffers: usize,
     ) -> StreamReceiver<T> {
         self.stream_socket
-            .subscribe_to_stream(stream_id, max_concurrent_buffers)
+            .subscribe_to_stream(stream_id, class, max_concurrent_buffers)
     }
 
     pub fn recv_poll(&mut self) -> ConResult<()> {
         self.stream_socket.recv()
     }
+
+    pub fn take_statistics(&mut self) -> SocketStatistics {
+        self.stream_socket.take_statistics()
+    }
 }
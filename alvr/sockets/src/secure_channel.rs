@@ -0,0 +1,514 @@
+// Authenticated encryption for the control and stream sockets.
+//
+// Server and client run a Noise XX handshake over the control socket, right after the client sent
+// its connection result. Each peer owns a static X25519 key (the pairing key). The client announces
+// its public key and displays its fingerprint, the server pins it when the user trusts the client in
+// the dashboard. The client pins the public key of the first server it pairs with. Verification of
+// the remote key is left to the caller.
+// After the handshake the same cipher state is shared by the control socket and the stream socket.
+// Every message carries its nonce explicitly: the control socket is written from multiple threads
+// and UDP shards can be lost or reordered, so the receiver cannot infer it. The nonce space is
+// split in two halves, one per channel, and each channel rejects replayed nonces with a sliding
+// window.
+
+use alvr_common::{
+    AnyhowToCon, ConResult, HandleTryAgain,
+    anyhow::{Result, anyhow, bail},
+    con_bail,
+    parking_lot::Mutex,
+};
+use snow::{
+    Builder, HandshakeState, StatelessTransportState,
+    params::NoiseParams,
+    resolvers::{CryptoResolver, DefaultResolver},
+};
+use std::{
+    io::Read,
+    mem::{self, MaybeUninit},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+const NOISE_PATTERN: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+const NOISE_PROLOGUE: &[u8] = b"ALVR";
+const NOISE_MAX_MESSAGE_SIZE: usize = 65535;
+const FINGERPRINT_SIZE: usize = 8;
+
+const NONCE_SIZE: usize = mem::size_of::<u64>();
+const TAG_SIZE: usize = 16;
+
+// Extra bytes added to each encrypted datagram or record
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+// Stream sockets carry records of at most this size, each prefixed by its length
+const RECORD_PREFIX_SIZE: usize = mem::size_of::<u32>();
+const MAX_RECORD_PLAINTEXT_SIZE: usize = NOISE_MAX_MESSAGE_SIZE - TAG_SIZE;
+
+const STREAM_CHANNEL_NONCE_FLAG: u64 = 1 << 63;
+const REPLAY_WINDOW_SIZE: u64 = u64::BITS as u64;
+
+fn noise_params() -> NoiseParams {
+    NOISE_PATTERN.parse().unwrap()
+}
+
+fn encode_key(key: &[u8]) -> String {
+    key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_key(key: &str) -> Result<Vec<u8>> {
+    if !key.len().is_multiple_of(2) || !key.is_ascii() {
+        bail!("Invalid pairing key");
+    }
+
+    (0..key.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&key[idx..idx + 2], 16).map_err(|e| anyhow!(e)))
+        .collect()
+}
+
+// Returns a new private pairing key, hex encoded
+pub fn generate_pairing_key() -> Result<String> {
+    let keypair = Builder::new(noise_params()).generate_keypair()?;
+
+    Ok(encode_key(&keypair.private))
+}
+
+// Returns the hex encoded public key of a private pairing key
+pub fn pairing_public_key(private_key: &str) -> Result<String> {
+    let private_key = decode_key(private_key)?;
+    let mut dh = DefaultResolver
+        .resolve_dh(&noise_params().dh)
+        .ok_or_else(|| anyhow!("Unsupported key exchange"))?;
+    if private_key.len() != dh.priv_len() {
+        bail!("Invalid pairing key");
+    }
+    dh.set(&private_key);
+
+    Ok(encode_key(dh.pubkey()))
+}
+
+// Short form of a public pairing key, displayed by the client and the dashboard so that the user
+// can compare them before trusting the client
+pub fn pairing_key_fingerprint(public_key: &str) -> String {
+    public_key
+        .chars()
+        .take(FINGERPRINT_SIZE * 2)
+        .collect::<Vec<_>>()
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>().to_uppercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn handshake_state(private_key: &str, initiator: bool) -> Result<HandshakeState> {
+    let private_key = decode_key(private_key)?;
+    let builder = Builder::new(noise_params())
+        .local_private_key(&private_key)
+        .prologue(NOISE_PROLOGUE);
+
+    Ok(if initiator {
+        builder.build_initiator()?
+    } else {
+        builder.build_responder()?
+    })
+}
+
+pub(crate) fn noise_message_buffer() -> Vec<u8> {
+    vec![0; NOISE_MAX_MESSAGE_SIZE]
+}
+
+// Returns the secure channel for the control socket and the hex encoded public key of the peer
+pub(crate) fn finish_handshake(handshake: HandshakeState) -> Result<(SecureChannel, String)> {
+    let remote_key = encode_key(
+        handshake
+            .get_remote_static()
+            .ok_or_else(|| anyhow!("Missing remote pairing key"))?,
+    );
+
+    let shared = SharedState {
+        transport: handshake.into_stateless_transport_mode()?,
+        next_nonces: [AtomicU64::new(0), AtomicU64::new(0)],
+        replay_windows: [
+            Mutex::new(ReplayWindow::default()),
+            Mutex::new(ReplayWindow::default()),
+        ],
+        processing_time_ns: AtomicU64::new(0),
+        overhead_bytes: AtomicU64::new(0),
+    };
+
+    Ok((
+        SecureChannel {
+            shared: Arc::new(shared),
+            channel: Channel::Control,
+        },
+        remote_key,
+    ))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Control = 0,
+    Stream = 1,
+}
+
+#[derive(Default)]
+struct ReplayWindow {
+    highest_nonce: Option<u64>,
+    // bit N is set if the nonce `highest_nonce - N` has been received
+    received_mask: u64,
+}
+
+impl ReplayWindow {
+    // Returns false if the nonce was already received or is too old. Otherwise marks it as
+    // received, in the same step so that concurrent receivers cannot both accept it. Must be called
+    // only after the message was authenticated.
+    fn check_and_mark(&mut self, nonce: u64) -> bool {
+        match self.highest_nonce {
+            Some(highest) if nonce <= highest => {
+                let offset = highest - nonce;
+                if offset >= REPLAY_WINDOW_SIZE || self.received_mask & (1 << offset) != 0 {
+                    return false;
+                }
+
+                self.received_mask |= 1 << offset;
+            }
+            Some(highest) => {
+                let shift = nonce - highest;
+                self.received_mask = if shift < REPLAY_WINDOW_SIZE {
+                    (self.received_mask << shift) | 1
+                } else {
+                    1
+                };
+                self.highest_nonce = Some(nonce);
+            }
+            None => {
+                self.received_mask = 1;
+                self.highest_nonce = Some(nonce);
+            }
+        }
+
+        true
+    }
+}
+
+struct SharedState {
+    transport: StatelessTransportState,
+    next_nonces: [AtomicU64; 2],
+    replay_windows: [Mutex<ReplayWindow>; 2],
+    processing_time_ns: AtomicU64,
+    overhead_bytes: AtomicU64,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EncryptionStatistics {
+    pub processing_time: Duration,
+    pub overhead_bytes: usize,
+}
+
+// Handle to the cipher state negotiated during the handshake. Clones share nonces and statistics.
+#[derive(Clone)]
+pub struct SecureChannel {
+    shared: Arc<SharedState>,
+    channel: Channel,
+}
+
+impl SecureChannel {
+    pub(crate) fn into_stream_channel(self) -> Self {
+        Self {
+            channel: Channel::Stream,
+            ..self
+        }
+    }
+
+    // Returns the time spent encrypting and decrypting and the bytes added to the sent data since
+    // the last call
+    pub fn take_statistics(&self) -> EncryptionStatistics {
+        EncryptionStatistics {
+            processing_time: Duration::from_nanos(
+                self.shared.processing_time_ns.swap(0, Ordering::Relaxed),
+            ),
+            overhead_bytes: self.shared.overhead_bytes.swap(0, Ordering::Relaxed) as usize,
+        }
+    }
+
+    fn report_cost(&self, start: Instant, overhead_bytes: usize) {
+        self.shared
+            .processing_time_ns
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.shared
+            .overhead_bytes
+            .fetch_add(overhead_bytes as u64, Ordering::Relaxed);
+    }
+
+    // Append nonce and ciphertext of `plaintext` to `output`
+    fn seal_into(&self, plaintext: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let counter =
+            self.shared.next_nonces[self.channel as usize].fetch_add(1, Ordering::Relaxed);
+        if counter >= STREAM_CHANNEL_NONCE_FLAG {
+            bail!("Nonces exhausted");
+        }
+        let nonce = match self.channel {
+            Channel::Control => counter,
+            Channel::Stream => counter | STREAM_CHANNEL_NONCE_FLAG,
+        };
+
+        let message_start = output.len();
+        output.extend_from_slice(&nonce.to_le_bytes());
+        output.resize(message_start + ENCRYPTION_OVERHEAD + plaintext.len(), 0);
+
+        self.shared.transport.write_message(
+            nonce,
+            plaintext,
+            &mut output[message_start + NONCE_SIZE..],
+        )?;
+
+        Ok(())
+    }
+
+    // Decrypt a message produced by seal_into(), appending the plaintext to `output`
+    fn open_into(&self, message: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        if message.len() < ENCRYPTION_OVERHEAD {
+            bail!("Encrypted message too short");
+        }
+
+        let nonce = u64::from_le_bytes(message[..NONCE_SIZE].try_into().unwrap());
+        let expected_flag = match self.channel {
+            Channel::Control => 0,
+            Channel::Stream => STREAM_CHANNEL_NONCE_FLAG,
+        };
+        if nonce & STREAM_CHANNEL_NONCE_FLAG != expected_flag {
+            bail!("Encrypted message from the wrong channel");
+        }
+
+        let plaintext_start = output.len();
+        output.resize(plaintext_start + message.len() - ENCRYPTION_OVERHEAD, 0);
+        if let Err(e) = self.shared.transport.read_message(
+            nonce,
+            &message[NONCE_SIZE..],
+            &mut output[plaintext_start..],
+        ) {
+            output.truncate(plaintext_start);
+            bail!("Failed to decrypt message: {e}");
+        }
+
+        if !self.shared.replay_windows[self.channel as usize]
+            .lock()
+            .check_and_mark(nonce)
+        {
+            output.truncate(plaintext_start);
+            bail!("Replayed or too old encrypted message");
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn seal_datagram(&self, plaintext: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let start = Instant::now();
+
+        output.clear();
+        self.seal_into(plaintext, output)?;
+
+        self.report_cost(start, ENCRYPTION_OVERHEAD);
+
+        Ok(())
+    }
+
+    pub(crate) fn open_datagram(&self, message: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let start = Instant::now();
+
+        output.clear();
+        let res = self.open_into(message, output);
+
+        self.report_cost(start, 0);
+
+        res
+    }
+
+    // Split `plaintext` into length-prefixed records, to be sent over a stream socket
+    pub(crate) fn seal_records(&self, plaintext: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let start = Instant::now();
+
+        output.clear();
+        let mut records_count = 0;
+        for chunk in plaintext.chunks(MAX_RECORD_PLAINTEXT_SIZE) {
+            let prefix_start = output.len();
+            output.extend_from_slice(&[0; RECORD_PREFIX_SIZE]);
+
+            self.seal_into(chunk, output)?;
+
+            let record_size = (output.len() - prefix_start - RECORD_PREFIX_SIZE) as u32;
+            output[prefix_start..][..RECORD_PREFIX_SIZE]
+                .copy_from_slice(&record_size.to_le_bytes());
+
+            records_count += 1;
+        }
+
+        self.report_cost(
+            start,
+            records_count * (RECORD_PREFIX_SIZE + ENCRYPTION_OVERHEAD),
+        );
+
+        Ok(())
+    }
+}
+
+// Reads records from a stream socket and exposes the decrypted bytes with a peek/read interface
+// similar to the one of the socket. State is kept between calls, so reads can be resumed after a
+// timeout.
+pub(crate) struct SecureStreamReader {
+    channel: SecureChannel,
+    record_buffer: Vec<u8>,
+    record_cursor: usize,
+    plaintext: Vec<u8>,
+    plaintext_cursor: usize,
+}
+
+impl SecureStreamReader {
+    pub fn new(channel: SecureChannel) -> Self {
+        Self {
+            channel,
+            record_buffer: vec![0; RECORD_PREFIX_SIZE],
+            record_cursor: 0,
+            plaintext: vec![],
+            plaintext_cursor: 0,
+        }
+    }
+
+    fn read_some(socket: &mut impl Read, buffer: &mut [u8]) -> ConResult<usize> {
+        let count = socket.read(buffer).handle_try_again()?;
+        if count == 0 {
+            // Either the socket has been closed or there is no data yet. Let the caller decide
+            return alvr_common::try_again();
+        }
+
+        Ok(count)
+    }
+
+    fn read_record(&mut self, socket: &mut impl Read) -> ConResult {
+        while self.record_cursor < RECORD_PREFIX_SIZE {
+            self.record_cursor += Self::read_some(
+                socket,
+                &mut self.record_buffer[self.record_cursor..RECORD_PREFIX_SIZE],
+            )?;
+        }
+
+        let record_size =
+            u32::from_le_bytes(self.record_buffer[..RECORD_PREFIX_SIZE].try_into().unwrap())
+                as usize;
+        if !(ENCRYPTION_OVERHEAD..=NONCE_SIZE + NOISE_MAX_MESSAGE_SIZE).contains(&record_size) {
+            con_bail!("Invalid encrypted record size: {record_size}");
+        }
+        self.record_buffer
+            .resize(RECORD_PREFIX_SIZE + record_size, 0);
+
+        while self.record_cursor < self.record_buffer.len() {
+            self.record_cursor +=
+                Self::read_some(socket, &mut self.record_buffer[self.record_cursor..])?;
+        }
+
+        if self.plaintext_cursor == self.plaintext.len() {
+            self.plaintext.clear();
+            self.plaintext_cursor = 0;
+        }
+
+        let start = Instant::now();
+        // A stream cannot be resynchronized after a bad record, the connection must be closed
+        let res = self
+            .channel
+            .open_into(
+                &self.record_buffer[RECORD_PREFIX_SIZE..],
+                &mut self.plaintext,
+            )
+            .to_con();
+        self.channel.report_cost(start, 0);
+
+        self.record_cursor = 0;
+        self.record_buffer.truncate(RECORD_PREFIX_SIZE);
+
+        res
+    }
+
+    // Returns the number of bytes copied to `buffer`, which is always the full length of `buffer`
+    pub fn peek(&mut self, socket: &mut impl Read, buffer: &mut [u8]) -> ConResult<usize> {
+        while self.plaintext.len() - self.plaintext_cursor < buffer.len() {
+            self.read_record(socket)?;
+        }
+
+        buffer.copy_from_slice(&self.plaintext[self.plaintext_cursor..][..buffer.len()]);
+
+        Ok(buffer.len())
+    }
+
+    pub fn read(
+        &mut self,
+        socket: &mut impl Read,
+        buffer: &mut [MaybeUninit<u8>],
+    ) -> ConResult<usize> {
+        if self.plaintext_cursor == self.plaintext.len() {
+            self.read_record(socket)?;
+        }
+
+        let available = &self.plaintext[self.plaintext_cursor..];
+        let count = usize::min(available.len(), buffer.len());
+        for (dst, src) in buffer[..count].iter_mut().zip(available) {
+            dst.write(*src);
+        }
+        self.plaintext_cursor += count;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_window() {
+        let mut window = ReplayWindow::default();
+
+        assert!(window.check_and_mark(5));
+        assert!(!window.check_and_mark(5));
+
+        // Reordered nonces are accepted once
+        assert!(window.check_and_mark(3));
+        assert!(!window.check_and_mark(3));
+        assert!(window.check_and_mark(100));
+        assert!(window.check_and_mark(99));
+
+        // Too old to be tracked
+        assert!(!window.check_and_mark(100 - REPLAY_WINDOW_SIZE));
+        assert!(window.check_and_mark(100 - REPLAY_WINDOW_SIZE + 1));
+    }
+
+    #[test]
+    fn test_pairing_public_key() {
+        let private_key = generate_pairing_key().unwrap();
+        let public_key = pairing_public_key(&private_key).unwrap();
+
+        // The public key is the one the remote peer sees after the handshake
+        let mut initiator = handshake_state(&private_key, true).unwrap();
+        let mut responder = handshake_state(&generate_pairing_key().unwrap(), false).unwrap();
+        let mut message = noise_message_buffer();
+        let mut payload = noise_message_buffer();
+        for _ in 0..3 {
+            let (writer, reader) = if initiator.is_my_turn() {
+                (&mut initiator, &mut responder)
+            } else {
+                (&mut responder, &mut initiator)
+            };
+            let size = writer.write_message(&[], &mut message).unwrap();
+            reader.read_message(&message[..size], &mut payload).unwrap();
+        }
+        assert_eq!(
+            encode_key(responder.get_remote_static().unwrap()),
+            public_key
+        );
+
+        assert_eq!(pairing_key_fingerprint(&public_key).len(), 19);
+        assert!(pairing_public_key("1234").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+// Systematic Reed-Solomon erasure code over GF(2^8), used to protect UDP shards.
+//
+// Data shards are sent unmodified, followed by a number of parity shards per block. The parity
+// shards are computed with a Cauchy matrix, which guarantees that any square submatrix is
+// invertible: a block with K data shards and M parity shards can be rebuilt as long as any K of
+// its K + M shards are received.
+// Data shard i of a block is associated to the field element i, parity shard j is associated to
+// the field element 255 - j. Since blocks have at most 128 data shards and 32 parity shards, these
+// elements never overlap.
+
+pub const MAX_DATA_SHARDS_PER_BLOCK: usize = 128;
+pub const MAX_PARITY_SHARDS_PER_BLOCK: usize = 32;
+
+// Primitive polynomial x^8 + x^4 + x^3 + x^2 + 1
+const GF_POLYNOMIAL: u16 = 0x11d;
+
+struct GfTables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const GF_TABLES: GfTables = {
+    let mut exp = [0; 512];
+    let mut log = [0; 256];
+
+    let mut value: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = value as u8;
+        exp[i + 255] = value as u8;
+        log[value as usize] = i as u8;
+
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= GF_POLYNOMIAL;
+        }
+
+        i += 1;
+    }
+
+    GfTables { exp, log }
+};
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        0
+    } else {
+        GF_TABLES.exp[GF_TABLES.log[a as usize] as usize + GF_TABLES.log[b as usize] as usize]
+    }
+}
+
+fn gf_inv(a: u8) -> u8 {
+    debug_assert!(a != 0);
+
+    GF_TABLES.exp[255 - GF_TABLES.log[a as usize] as usize]
+}
+
+// dst += coefficient * src. src can be shorter than dst, the missing bytes are treated as zeros.
+fn gf_mul_add_slice(dst: &mut [u8], coefficient: u8, src: &[u8]) {
+    if coefficient == 0 {
+        return;
+    }
+
+    let log_coefficient = GF_TABLES.log[coefficient as usize] as usize;
+    for (d, s) in dst.iter_mut().zip(src) {
+        if *s != 0 {
+            *d ^= GF_TABLES.exp[log_coefficient + GF_TABLES.log[*s as usize] as usize];
+        }
+    }
+}
+
+fn cauchy_coefficient(parity_index: usize, data_index: usize) -> u8 {
+    gf_inv((255 - parity_index as u8) ^ data_index as u8)
+}
+
+// Compute the parity shard `parity_index` for the given block of data shards. All data shards
+// except the last one must be as long as `parity`, the last one can be shorter.
+pub fn encode_parity<'a>(
+    data_shards: impl Iterator<Item = &'a [u8]>,
+    parity_index: usize,
+    parity: &mut [u8],
+) {
+    parity.fill(0);
+
+    for (data_index, shard) in data_shards.enumerate() {
+        gf_mul_add_slice(parity, cauchy_coefficient(parity_index, data_index), shard);
+    }
+}
+
+// Invert a square matrix in place with Gauss-Jordan elimination. Cauchy submatrices are always
+// invertible, so this cannot fail unless the inputs are inconsistent.
+fn invert_matrix(matrix: &mut [Vec<u8>]) -> Option<Vec<Vec<u8>>> {
+    let size = matrix.len();
+
+    let mut inverse = (0..size)
+        .map(|row| {
+            let mut row_values = vec![0; size];
+            row_values[row] = 1;
+            row_values
+        })
+        .collect::<Vec<_>>();
+
+    for column in 0..size {
+        let pivot_row = (column..size).find(|row| matrix[*row][column] != 0)?;
+        matrix.swap(column, pivot_row);
+        inverse.swap(column, pivot_row);
+
+        let pivot_inv = gf_inv(matrix[column][column]);
+        for value in &mut matrix[column] {
+            *value = gf_mul(*value, pivot_inv);
+        }
+        for value in &mut inverse[column] {
+            *value = gf_mul(*value, pivot_inv);
+        }
+
+        let pivot_matrix_row = matrix[column].clone();
+        let pivot_inverse_row = inverse[column].clone();
+        for (row, (matrix_row, inverse_row)) in matrix.iter_mut().zip(&mut inverse).enumerate() {
+            let factor = matrix_row[column];
+            if row != column && factor != 0 {
+                gf_mul_add_slice(matrix_row, factor, &pivot_matrix_row);
+                gf_mul_add_slice(inverse_row, factor, &pivot_inverse_row);
+            }
+        }
+    }
+
+    Some(inverse)
+}
+
+// Rebuild the missing data shards of a block.
+// `data_shards` contains one entry per data shard of the block, None for the missing ones.
+// `parity_shards` contains the received parity shards as (parity index, shard) pairs. There must
+// be at least as many parity shards as missing data shards.
+// Returns the rebuilt shards as (data index, shard) pairs, each one `shard_size` long, or None if
+// there are not enough parity shards.
+pub fn reconstruct_data(
+    data_shards: &[Option<&[u8]>],
+    parity_shards: &[(usize, &[u8])],
+    shard_size: usize,
+) -> Option<Vec<(usize, Vec<u8>)>> {
+    let missing_indices = data_shards
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, shard)| shard.is_none().then_some(idx))
+        .collect::<Vec<_>>();
+
+    if missing_indices.is_empty() {
+        return Some(vec![]);
+    }
+    if parity_shards.len() < missing_indices.len() {
+        return None;
+    }
+
+    let parity_shards = &parity_shards[..missing_indices.len()];
+
+    // Remove the contribution of the received data shards from the parity shards. What is left is
+    // the contribution of the missing shards only.
+    let syndromes = parity_shards
+        .iter()
+        .map(|(parity_index, parity)| {
+            let mut syndrome = parity[..shard_size].to_vec();
+            for (data_index, shard) in data_shards.iter().enumerate() {
+                if let Some(shard) = shard {
+                    gf_mul_add_slice(
+                        &mut syndrome,
+                        cauchy_coefficient(*parity_index, data_index),
+                        shard,
+                    );
+                }
+            }
+
+            syndrome
+        })
+        .collect::<Vec<_>>();
+
+    let mut matrix = parity_shards
+        .iter()
+        .map(|(parity_index, _)| {
+            missing_indices
+                .iter()
+                .map(|data_index| cauchy_coefficient(*parity_index, *data_index))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    let inverse = invert_matrix(&mut matrix)?;
+
+    Some(
+        missing_indices
+            .iter()
+            .zip(inverse)
+            .map(|(data_index, inverse_row)| {
+                let mut shard = vec![0; shard_size];
+                for (coefficient, syndrome) in inverse_row.iter().zip(&syndromes) {
+                    gf_mul_add_slice(&mut shard, *coefficient, syndrome);
+                }
+
+                (*data_index, shard)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHARD_SIZE: usize = 100;
+
+    // Deterministic pseudo-random content. The last data shard is shorter than the others
+    fn data_shards(count: usize) -> Vec<Vec<u8>> {
+        let mut state = 12345_u32;
+        (0..count)
+            .map(|index| {
+                let size = if index == count - 1 { 60 } else { SHARD_SIZE };
+                (0..size)
+                    .map(|_| {
+                        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                        (state >> 16) as u8
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn parity_shards(data_shards: &[Vec<u8>], count: usize) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|parity_index| {
+                let mut parity = vec![0; SHARD_SIZE];
+                encode_parity(
+                    data_shards.iter().map(Vec::as_slice),
+                    parity_index,
+                    &mut parity,
+                );
+                parity
+            })
+            .collect()
+    }
+
+    // Returns the rebuilt block, with the missing shards replaced
+    fn reconstruct(
+        data_shards: &[Vec<u8>],
+        parity_shards: &[Vec<u8>],
+        lost_data: &[usize],
+        lost_parity: &[usize],
+    ) -> Option<Vec<Vec<u8>>> {
+        let received_data = data_shards
+            .iter()
+            .enumerate()
+            .map(|(index, shard)| (!lost_data.contains(&index)).then_some(shard.as_slice()))
+            .collect::<Vec<_>>();
+        let received_parity = parity_shards
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !lost_parity.contains(index))
+            .map(|(index, shard)| (index, shard.as_slice()))
+            .collect::<Vec<_>>();
+
+        let rebuilt_shards = reconstruct_data(&received_data, &received_parity, SHARD_SIZE)?;
+
+        let mut block = data_shards.to_vec();
+        for (index, shard) in rebuilt_shards {
+            assert!(lost_data.contains(&index));
+            block[index] = shard;
+        }
+
+        Some(block)
+    }
+
+    // The shorter last shard is rebuilt padded with zeros
+    fn assert_same_content(rebuilt: &[Vec<u8>], original: &[Vec<u8>]) {
+        for (rebuilt, original) in rebuilt.iter().zip(original) {
+            assert_eq!(&rebuilt[..original.len()], original.as_slice());
+            assert!(rebuilt[original.len()..].iter().all(|value| *value == 0));
+        }
+    }
+
+    #[test]
+    fn test_field_inverse() {
+        for value in 1..=255 {
+            assert_eq!(gf_mul(value, gf_inv(value)), 1);
+        }
+    }
+
+    #[test]
+    fn test_no_erasures() {
+        let data = data_shards(10);
+        let parity = parity_shards(&data, 4);
+
+        assert_eq!(reconstruct(&data, &parity, &[], &[0, 1, 2, 3]), Some(data));
+    }
+
+    #[test]
+    fn test_erasures_up_to_parity_count() {
+        let data = data_shards(10);
+        let parity = parity_shards(&data, 4);
+
+        let cases: [(&[usize], &[usize]); 6] = [
+            (&[0], &[]),
+            (&[9], &[1, 2, 3]),
+            (&[2, 5], &[0]),
+            (&[0, 4, 9], &[]),
+            (&[1, 3, 6, 8], &[]),
+            (&[6, 7, 8, 9], &[]),
+        ];
+        for (lost_data, lost_parity) in cases {
+            let rebuilt = reconstruct(&data, &parity, lost_data, lost_parity).unwrap();
+            assert_same_content(&rebuilt, &data);
+        }
+    }
+
+    #[test]
+    fn test_too_many_erasures() {
+        let data = data_shards(10);
+        let parity = parity_shards(&data, 4);
+
+        assert!(reconstruct(&data, &parity, &[0, 1, 2, 3, 4], &[]).is_none());
+        assert!(reconstruct(&data, &parity, &[0, 1], &[0, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn test_largest_block() {
+        let data = data_shards(MAX_DATA_SHARDS_PER_BLOCK);
+        let parity = parity_shards(&data, MAX_PARITY_SHARDS_PER_BLOCK);
+
+        let lost_data = (0..MAX_DATA_SHARDS_PER_BLOCK)
+            .step_by(4)
+            .collect::<Vec<_>>();
+        let rebuilt = reconstruct(&data, &parity, &lost_data, &[]).unwrap();
+        assert_same_content(&rebuilt, &data);
+    }
+}
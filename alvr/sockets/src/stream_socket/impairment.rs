@@ -0,0 +1,547 @@
+// Network impairment simulator. The wrappers apply a simulated link to the datagrams of the UDP
+// socket: bandwidth cap with a drop-tail queue, bursty loss (Gilbert-Elliott model), latency,
+// jitter and reordering.
+// The simulation works on single datagrams (shards), below forward error correction and
+// retransmission, so that they can recover the lost shards like on a real network.
+
+use super::{
+    MultiplexedSocketReader, MultiplexedSocketWriter,
+    udp::{self, DatagramReceiver, DatagramSender},
+};
+use crate::{SecureChannel, as_uninit_slice};
+use alvr_common::{
+    ConResult, ConnectionError,
+    anyhow::{Result, anyhow, bail},
+    con_bail,
+    parking_lot::Mutex,
+};
+use alvr_session::{ForwardErrorCorrectionConfig, LinkImpairmentConfig, NetworkImpairmentConfig};
+use rand::{RngExt, SeedableRng, rngs::StdRng};
+use socket2::Socket;
+use std::{
+    collections::VecDeque,
+    mem::MaybeUninit,
+    net::UdpSocket,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+struct LinkModel {
+    config: LinkImpairmentConfig,
+    rng: StdRng,
+    loss_bad_state: bool,
+    // Time at which the bottleneck link finishes sending the queued datagrams
+    link_free_time: Instant,
+    last_delivery_time: Instant,
+}
+
+impl LinkModel {
+    fn new(config: LinkImpairmentConfig, random_seed: u64) -> Self {
+        let now = Instant::now();
+
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(random_seed),
+            loss_bad_state: false,
+            link_free_time: now,
+            last_delivery_time: now,
+        }
+    }
+
+    // Returns the time at which the datagram reaches the other end of the link, or None if the
+    // datagram is lost
+    fn transmit(&mut self, now: Instant, datagram_size: usize) -> Option<Instant> {
+        let departure_time =
+            if let Some(&bandwidth_mbps) = self.config.bandwidth_cap_mbps.as_option() {
+                let start_time = self.link_free_time.max(now);
+                if start_time - now > Duration::from_millis(self.config.max_queue_delay_ms) {
+                    return None;
+                }
+
+                let transmission_time =
+                    datagram_size as f64 * 8.0 / (bandwidth_mbps.max(1) as f64 * 1e6);
+                self.link_free_time = start_time + Duration::from_secs_f64(transmission_time);
+
+                self.link_free_time
+            } else {
+                now
+            };
+
+        // The random values are drawn for every datagram, so the sequence of events depends only on
+        // the seed and the sequence of datagrams
+        let lost = if let Some(loss) = self.config.loss.as_option() {
+            let transition_probability = if self.loss_bad_state {
+                loss.bad_to_good_probability
+            } else {
+                loss.good_to_bad_probability
+            };
+            if self.rng.random_bool(probability(transition_probability)) {
+                self.loss_bad_state = !self.loss_bad_state;
+            }
+
+            let loss_probability = if self.loss_bad_state {
+                loss.bad_state_loss_probability
+            } else {
+                loss.good_state_loss_probability
+            };
+            self.rng.random_bool(probability(loss_probability))
+        } else {
+            false
+        };
+        let reordered = self
+            .rng
+            .random_bool(probability(self.config.reorder_probability));
+
+        let jitter_us = self.config.jitter_ms as i64 * 1000;
+        let delay_us = (self.config.latency_ms as i64 * 1000
+            + self.rng.random_range(-jitter_us..=jitter_us))
+        .max(0);
+        let delivery_time = departure_time + Duration::from_micros(delay_us as u64);
+
+        if lost {
+            None
+        } else if reordered {
+            // The following datagrams are not held back and overtake this one
+            Some(delivery_time + Duration::from_millis(self.config.reorder_delay_ms))
+        } else {
+            self.last_delivery_time = self.last_delivery_time.max(delivery_time);
+
+            Some(self.last_delivery_time)
+        }
+    }
+}
+
+fn probability(value: f32) -> f64 {
+    (value as f64).clamp(0.0, 1.0)
+}
+
+// Holds the datagrams on a dedicated thread until their delivery time
+struct DelayLine<T> {
+    sender: mpsc::Sender<(Instant, T)>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl<T: Send + 'static> DelayLine<T> {
+    fn new(mut deliver: impl FnMut(T) -> Result<()> + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel::<(Instant, T)>();
+
+        let thread = thread::spawn(move || {
+            let mut queue = VecDeque::<(Instant, T)>::new();
+
+            loop {
+                while let Some((time, _)) = queue.front()
+                    && *time <= Instant::now()
+                {
+                    let (_, item) = queue.pop_front().unwrap();
+                    deliver(item)?;
+                }
+
+                let res = if let Some((time, _)) = queue.front() {
+                    receiver.recv_timeout(time.saturating_duration_since(Instant::now()))
+                } else {
+                    receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                };
+                let entry = match res {
+                    Ok(entry) => entry,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                };
+
+                // Datagrams are mostly pushed in order of delivery time
+                let position = queue.partition_point(|(time, _)| *time <= entry.0);
+                queue.insert(position, entry);
+            }
+        });
+
+        Self {
+            sender,
+            thread: Some(thread),
+        }
+    }
+
+    fn push(&mut self, time: Instant, item: T) -> Result<()> {
+        if self.sender.send((time, item)).is_err() {
+            // The thread stops early only if the delivery failed
+            if let Some(thread) = self.thread.take() {
+                thread
+                    .join()
+                    .map_err(|_| anyhow!("Network impairment thread panicked"))??;
+            }
+            bail!("Network impairment thread stopped");
+        }
+
+        Ok(())
+    }
+}
+
+// The datagrams are handed to the inner sender by the delay line thread
+pub struct ImpairedSender {
+    link: Mutex<LinkModel>,
+    delay_line: Mutex<DelayLine<Vec<u8>>>,
+}
+
+impl ImpairedSender {
+    pub fn new(
+        inner: impl DatagramSender + Send + 'static,
+        config: LinkImpairmentConfig,
+        random_seed: u64,
+    ) -> Self {
+        Self {
+            link: Mutex::new(LinkModel::new(config, random_seed)),
+            delay_line: Mutex::new(DelayLine::new(move |datagram: Vec<u8>| {
+                inner.send_datagram(&datagram)
+            })),
+        }
+    }
+}
+
+impl DatagramSender for ImpairedSender {
+    fn send_datagram(&self, datagram: &[u8]) -> Result<()> {
+        if let Some(delivery_time) = self.link.lock().transmit(Instant::now(), datagram.len()) {
+            self.delay_line
+                .lock()
+                .push(delivery_time, datagram.to_vec())?;
+        }
+
+        Ok(())
+    }
+}
+
+// The datagrams are received by a dedicated thread, which also keeps the time of arrival at the
+// simulated link accurate while the reader is busy
+pub struct ImpairedReceiver {
+    datagram_receiver: mpsc::Receiver<Vec<u8>>,
+    // Datagram returned by the last peek
+    pending: Option<Vec<u8>>,
+    timeout: Duration,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl ImpairedReceiver {
+    // `timeout` should match the read timeout of the inner receiver
+    pub fn new(
+        mut inner: impl DatagramReceiver + Send + 'static,
+        timeout: Duration,
+        config: LinkImpairmentConfig,
+        random_seed: u64,
+    ) -> Self {
+        let (datagram_sender, datagram_receiver) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = thread::spawn({
+            let running = Arc::clone(&running);
+            move || {
+                let mut link = LinkModel::new(config, random_seed);
+                let mut delay_line = DelayLine::new(move |datagram: Vec<u8>| {
+                    datagram_sender
+                        .send(datagram)
+                        .map_err(|_| anyhow!("Impaired receiver dropped"))
+                });
+
+                let mut buffer = vec![];
+                while running.load(Ordering::Relaxed) {
+                    let size = match inner.peek_datagram(&mut []) {
+                        Ok(size) => size,
+                        Err(ConnectionError::TryAgain(_)) => continue,
+                        Err(ConnectionError::Other(e)) => return Err(e),
+                    };
+
+                    buffer.resize(size, 0);
+                    let size = inner.recv_datagram(as_uninit_slice(&mut buffer))?;
+
+                    if let Some(delivery_time) = link.transmit(Instant::now(), size) {
+                        delay_line.push(delivery_time, buffer[..size].to_vec())?;
+                    }
+                }
+
+                Ok(())
+            }
+        });
+
+        Self {
+            datagram_receiver,
+            pending: None,
+            timeout,
+            running,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl DatagramReceiver for ImpairedReceiver {
+    fn peek_datagram(&mut self, buffer: &mut [u8]) -> ConResult<usize> {
+        if self.pending.is_none() {
+            match self.datagram_receiver.recv_timeout(self.timeout) {
+                Ok(datagram) => self.pending = Some(datagram),
+                Err(RecvTimeoutError::Timeout) => return alvr_common::try_again(),
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Some(Ok(Err(e))) = self.thread.take().map(JoinHandle::join) {
+                        con_bail!("Network impairment thread failed: {e}");
+                    }
+                    con_bail!("Network impairment thread stopped");
+                }
+            }
+        }
+
+        let datagram = self.pending.as_ref().unwrap();
+        let count = usize::min(buffer.len(), datagram.len());
+        buffer[..count].copy_from_slice(&datagram[..count]);
+
+        Ok(datagram.len())
+    }
+
+    fn recv_datagram(&mut self, buffer: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        let Some(datagram) = self.pending.take() else {
+            bail!("No datagram peeked");
+        };
+
+        let count = usize::min(buffer.len(), datagram.len());
+        for (dst, src) in buffer[..count].iter_mut().zip(&datagram) {
+            dst.write(*src);
+        }
+
+        Ok(count)
+    }
+
+    fn discard_datagram(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Drop for ImpairedReceiver {
+    fn drop(&mut self) {
+        // The thread notices within the read timeout of the inner receiver
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+// Should be used on the server side: the outgoing datagrams go through the server to client link
+// and the incoming datagrams through the client to server link
+pub fn split_multiplexed(
+    socket: UdpSocket,
+    max_packet_size: usize,
+    fec_config: Option<ForwardErrorCorrectionConfig>,
+    secure_channel: Option<SecureChannel>,
+    config: NetworkImpairmentConfig,
+) -> Result<(
+    Box<dyn MultiplexedSocketWriter + Send>,
+    Box<dyn MultiplexedSocketReader + Send>,
+)> {
+    let timeout = socket.read_timeout()?.unwrap_or(Duration::from_millis(100));
+
+    let sender = ImpairedSender::new(
+        socket.try_clone()?,
+        config.server_to_client,
+        config.random_seed,
+    );
+    let receiver = ImpairedReceiver::new(
+        Socket::from(socket),
+        timeout,
+        config.client_to_server,
+        config.random_seed.wrapping_add(1),
+    );
+
+    let (writer, reader) = udp::split_datagrams(
+        sender,
+        receiver,
+        max_packet_size,
+        fec_config,
+        secure_channel,
+    );
+
+    Ok((Box::new(writer), Box::new(reader)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StreamClass, StreamPriority, StreamSocket};
+    use alvr_session::{GilbertElliottLossConfig, settings_schema::Switch};
+    use std::net::Ipv4Addr;
+
+    const TEST_STREAM: u16 = 0;
+
+    fn ideal_link() -> LinkImpairmentConfig {
+        LinkImpairmentConfig {
+            latency_ms: 0,
+            jitter_ms: 0,
+            bandwidth_cap_mbps: Switch::Disabled,
+            max_queue_delay_ms: 100,
+            reorder_probability: 0.0,
+            reorder_delay_ms: 5,
+            loss: Switch::Disabled,
+        }
+    }
+
+    // Connected UDP sockets on localhost. The datagrams sent by the first socket go through the
+    // simulated link
+    fn loopback_pair(config: LinkImpairmentConfig) -> (StreamSocket, StreamSocket) {
+        let first = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let second = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let timeout = Duration::from_millis(10);
+        udp::connect(
+            &first,
+            Ipv4Addr::LOCALHOST.into(),
+            second.local_addr().unwrap().port(),
+            timeout,
+        )
+        .unwrap();
+        udp::connect(
+            &second,
+            Ipv4Addr::LOCALHOST.into(),
+            first.local_addr().unwrap().port(),
+            timeout,
+        )
+        .unwrap();
+
+        let (writer, reader) = split_multiplexed(
+            first,
+            1400,
+            None,
+            None,
+            NetworkImpairmentConfig {
+                server_to_client: config,
+                client_to_server: ideal_link(),
+                random_seed: 0,
+            },
+        )
+        .unwrap();
+        let first = StreamSocket::new(writer, reader);
+
+        let (writer, reader) = udp::split_multiplexed(second, 1400, None, None).unwrap();
+        let second = StreamSocket::new(writer, reader);
+
+        (first, second)
+    }
+
+    #[test]
+    fn test_latency() {
+        let (sender_socket, mut receiver_socket) = loopback_pair(LinkImpairmentConfig {
+            latency_ms: 50,
+            ..ideal_link()
+        });
+        let mut sender = sender_socket.request_stream::<u32>(
+            TEST_STREAM,
+            StreamClass::Droppable,
+            StreamPriority::Low,
+        );
+        let mut receiver =
+            receiver_socket.subscribe_to_stream::<u32>(TEST_STREAM, StreamClass::Droppable, 4);
+
+        let send_time = Instant::now();
+        sender.send_header(&42).unwrap();
+
+        let data = loop {
+            receiver_socket.recv().ok();
+            if let Ok(data) = receiver.recv(Duration::ZERO) {
+                break data;
+            }
+            assert!(send_time.elapsed() < Duration::from_secs(1));
+        };
+
+        assert_eq!(data.get_header().unwrap(), 42);
+        assert!(send_time.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_bandwidth_cap() {
+        let mut link = LinkModel::new(
+            LinkImpairmentConfig {
+                bandwidth_cap_mbps: Switch::Enabled(1),
+                max_queue_delay_ms: 35,
+                ..ideal_link()
+            },
+            0,
+        );
+
+        // 1250 bytes take 10ms at 1Mbps. The queue fills up after 4 datagrams
+        let now = Instant::now();
+        let delivery_times = (0..6).map(|_| link.transmit(now, 1250)).collect::<Vec<_>>();
+
+        for (index, time) in delivery_times[..4].iter().enumerate() {
+            assert_eq!(
+                time.unwrap() - now,
+                Duration::from_millis(10 * (index as u64 + 1))
+            );
+        }
+        assert!(delivery_times[4..].iter().all(Option::is_none));
+
+        // The queue drains over time
+        assert!(
+            link.transmit(now + Duration::from_millis(20), 1250)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_bursty_loss() {
+        let config = LinkImpairmentConfig {
+            loss: Switch::Enabled(GilbertElliottLossConfig {
+                good_to_bad_probability: 0.01,
+                bad_to_good_probability: 0.25,
+                good_state_loss_probability: 0.0,
+                bad_state_loss_probability: 1.0,
+            }),
+            ..ideal_link()
+        };
+
+        let now = Instant::now();
+        let run = || {
+            let mut link = LinkModel::new(config, 7);
+            (0..100_000)
+                .map(|_| link.transmit(now, 100).is_none())
+                .collect::<Vec<_>>()
+        };
+
+        // Same seed, same losses
+        let losses = run();
+        assert_eq!(losses, run());
+
+        // The stationary probability of the bad state is 0.01 / (0.01 + 0.25) ~= 3.8%, and the
+        // average burst length is 1 / 0.25 = 4 datagrams
+        let lost_count = losses.iter().filter(|lost| **lost).count();
+        let bursts_count = losses.windows(2).filter(|w| !w[0] && w[1]).count();
+        assert!((3000..4700).contains(&lost_count));
+        assert!((3.0..5.0).contains(&(lost_count as f64 / bursts_count as f64)));
+    }
+
+    #[test]
+    fn test_reordering() {
+        let mut link = LinkModel::new(
+            LinkImpairmentConfig {
+                latency_ms: 10,
+                reorder_probability: 0.5,
+                reorder_delay_ms: 5,
+                ..ideal_link()
+            },
+            0,
+        );
+
+        // One datagram per millisecond
+        let now = Instant::now();
+        let delays = (0..100)
+            .map(|index| {
+                let send_time = now + Duration::from_millis(index);
+                link.transmit(send_time, 100).unwrap() - send_time
+            })
+            .collect::<Vec<_>>();
+
+        // Reordered datagrams are overtaken by the following ones
+        assert!(
+            delays
+                .windows(2)
+                .any(|w| w[1] + Duration::from_millis(1) < w[0])
+        );
+        assert!(
+            delays
+                .iter()
+                .all(|delay| (10..=15).contains(&delay.as_millis()))
+        );
+    }
+}
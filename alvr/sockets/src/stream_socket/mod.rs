@@ -6,25 +6,35 @@
 
 // Performance analysis:
 // We want to minimize the transmission time for various sizes of packets.
-// The current code locks the write socket *per shard* and not *per packet*. This leds to the best
-// performance outcome given that the possible packets can be either very small (one shard) or very
-// large (hundreds/thousands of shards, for video). if we don't allow interleaving shards, a very
-// small packet will need to wait a long time before getting received if there was an ongoing
+// The current code locks the write socket *per burst* of shards and not *per packet*. This leds to
+// the best performance outcome given that the possible packets can be either very small (one shard)
+// or very large (hundreds/thousands of shards, for video). if we don't allow interleaving shards, a
+// very small packet will need to wait a long time before getting received if there was an ongoing
 // transmission of a big packet before. If we allow interleaving shards, small packets can be
 // transmitted quicker, with only minimal latency increase for the ongoing transmission of the big
-// packet.
+// packet. Between bursts, the socket is given to the waiting sender with the highest priority.
 // Note: We can't clone the underlying socket for each StreamSender and the mutex around the socket
 // cannot be removed. This is because we need to make sure at least shards are written whole.
 
+mod fec;
+mod impairment;
+mod quic;
 mod tcp;
 mod udp;
 
+use crate::SecureChannel;
 use alvr_common::{
-    AnyhowToCon, ConResult, HandleTryAgain, ToCon, anyhow::Result, parking_lot::Mutex,
+    AnyhowToCon, ConResult, HandleTryAgain, ToCon,
+    anyhow::Result,
+    parking_lot::{Condvar, Mutex, MutexGuard},
+    warn,
+};
+use alvr_session::{
+    DscpTos, ForwardErrorCorrectionConfig, NetworkImpairmentConfig, Settings, SocketBufferConfig,
+    SocketProtocol, settings_schema::Switch,
 };
-use alvr_session::{DscpTos, SocketBufferConfig, SocketProtocol};
 use bincode::config;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
     cmp::Ordering,
     collections::HashMap,
@@ -33,22 +43,112 @@ use std::{
     net::{IpAddr, TcpListener, UdpSocket},
     ops::{Deref, DerefMut},
     sync::{Arc, mpsc},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+// Reserved stream used to exchange the retransmission feedback of reliable streams
+const FEEDBACK_STREAM_ID: u16 = u16::MAX;
+const FEEDBACK_MAX_CONCURRENT_BUFFERS: usize = 16;
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(5);
+
+const PRIORITY_LEVELS: usize = 3;
+
+// Delivery semantics of a stream. Both peers must use the same class for a stream ID.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StreamClass {
+    // Packets can be lost. Every received packet is delivered
+    Droppable,
+    // Packets can be lost. When packets queue up, the receiver gets only the most recent one
+    LatestOnly,
+    // Lost packets are retransmitted and packets are delivered in order. Over UDP retransmission is
+    // limited: a packet that cannot be recovered in time is skipped
+    Reliable,
+    // Lost shards are requested again and retransmitted as long as they can arrive before the
+    // deadline, counted from the first transmission. Packets are delivered in order, a packet that
+    // cannot be recovered in time is skipped and reported as lost. Over TCP, the same as Reliable
+    Recoverable { deadline: Duration },
+}
+
+// Used by both server and client, which receive the same settings
+pub fn video_stream_class(settings: &Settings) -> StreamClass {
+    if let Switch::Enabled(config) = &settings.connection.video_retransmission {
+        StreamClass::Recoverable {
+            deadline: Duration::from_millis(config.deadline_ms),
+        }
+    } else {
+        StreamClass::Droppable
+    }
+}
+
+// Used to schedule the shards of packets sent concurrently on different streams
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum StreamPriority {
+    Low = 0,
+    Medium = 1,
+    High = 2,
+}
+
+#[derive(Serialize, Deserialize)]
+enum RetransmissionFeedback {
+    // The packet has been fully received
+    Ack {
+        stream_id: u16,
+        packet_index: u32,
+    },
+    // Some data shards of the packet are missing. An empty list means the whole packet is missing
+    Nack {
+        stream_id: u16,
+        packet_index: u32,
+        shard_indices: Vec<u32>,
+    },
+}
+
+// Retransmission counters of the sent streams. Always zero when the transport retransmits by itself
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SocketStatistics {
+    // Shards reported as lost by the peer
+    pub nacked_shards: usize,
+    // Shards sent again after a NACK or a timeout
+    pub retransmitted_shards: usize,
+}
+
 trait MultiplexedSocketWriter {
     // Note: consts are not trait-safe, we require a method
     fn payload_offset(&self) -> usize;
 
-    fn send(&mut self, stream_id: u16, packet_index: u32, buffer: &mut Vec<u8>) -> Result<()>;
+    // Send the next burst of shards of a packet. `cursor` tracks the progress and must be 0 for a
+    // new packet. Returns true once the whole packet has been sent.
+    fn send_burst(
+        &mut self,
+        stream_id: u16,
+        class: StreamClass,
+        packet_index: u32,
+        buffer: &mut Vec<u8>,
+        cursor: &mut usize,
+    ) -> Result<bool>;
+
+    // Called periodically with the feedback received from the peer, even if empty. Used to
+    // retransmit lost packets of reliable streams, when the transport does not already do it
+    fn process_feedback(&mut self, _feedback: &[RetransmissionFeedback]) -> Result<()> {
+        Ok(())
+    }
+
+    // Statistics accumulated since the last call
+    fn take_statistics(&mut self) -> SocketStatistics {
+        SocketStatistics::default()
+    }
 }
 
 struct ReconstructedPacket {
     index: u32,
     buffer: Vec<u8>,
+    recovered_shards_count: usize,
+    first_shard_arrival: Instant,
+    last_shard_arrival: Instant,
 }
 
 struct StreamRecvQueues {
+    class: StreamClass,
     used_buffer_sender: mpsc::Sender<Vec<u8>>,
     used_buffer_receiver: mpsc::Receiver<Vec<u8>>,
     packet_queue: mpsc::Sender<ReconstructedPacket>,
@@ -58,6 +158,84 @@ trait MultiplexedSocketReader {
     fn payload_offset(&self) -> usize;
 
     fn recv(&mut self, stream_queues: &HashMap<u16, StreamRecvQueues>) -> ConResult;
+
+    // Collect the feedback to be sent to the peer
+    fn take_feedback(&mut self, _feedback: &mut Vec<RetransmissionFeedback>) {}
+}
+
+struct SchedulerState {
+    busy: bool,
+    waiting_count: [usize; PRIORITY_LEVELS],
+}
+
+// Gives access to the writer in priority order. Each sender holds the writer for a single burst,
+// so the transmission of a big packet can be interrupted by packets of higher priority streams.
+struct WriterScheduler {
+    writer: Mutex<Box<dyn MultiplexedSocketWriter + Send>>,
+    payload_offset: usize,
+    state: Mutex<SchedulerState>,
+    condvar: Condvar,
+}
+
+impl WriterScheduler {
+    fn new(writer: Box<dyn MultiplexedSocketWriter + Send>) -> Self {
+        Self {
+            payload_offset: writer.payload_offset(),
+            writer: Mutex::new(writer),
+            state: Mutex::new(SchedulerState {
+                busy: false,
+                waiting_count: [0; PRIORITY_LEVELS],
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn lock(&self, priority: StreamPriority) -> ScheduledWriter<'_> {
+        let priority = priority as usize;
+
+        let mut state = self.state.lock();
+        state.waiting_count[priority] += 1;
+        while state.busy
+            || state.waiting_count[priority + 1..]
+                .iter()
+                .any(|count| *count > 0)
+        {
+            self.condvar.wait(&mut state);
+        }
+        state.waiting_count[priority] -= 1;
+        state.busy = true;
+
+        ScheduledWriter {
+            scheduler: self,
+            writer: self.writer.lock(),
+        }
+    }
+}
+
+struct ScheduledWriter<'a> {
+    scheduler: &'a WriterScheduler,
+    writer: MutexGuard<'a, Box<dyn MultiplexedSocketWriter + Send>>,
+}
+
+impl Deref for ScheduledWriter<'_> {
+    type Target = dyn MultiplexedSocketWriter + Send;
+
+    fn deref(&self) -> &Self::Target {
+        &**self.writer
+    }
+}
+
+impl DerefMut for ScheduledWriter<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut **self.writer
+    }
+}
+
+impl Drop for ScheduledWriter<'_> {
+    fn drop(&mut self) {
+        self.scheduler.state.lock().busy = false;
+        self.scheduler.condvar.notify_all();
+    }
 }
 
 /// Memory buffer that contains a hidden prefix
@@ -84,11 +262,14 @@ impl<H> DerefMut for Buffer<H> {
 
 #[derive(Clone)]
 pub struct StreamSender<H> {
-    inner: Arc<Mutex<Box<dyn MultiplexedSocketWriter + Send>>>,
+    scheduler: Arc<WriterScheduler>,
     stream_id: u16,
+    class: StreamClass,
+    priority: StreamPriority,
     payload_offset: usize,
     next_packet_index: u32,
     used_buffers: Vec<Vec<u8>>,
+    last_send_instants: Option<(Instant, Instant)>,
     _phantom: PhantomData<H>,
 }
 
@@ -96,9 +277,25 @@ impl<H> StreamSender<H> {
     /// Shard and send a buffer with zero copies and zero allocations.
     /// The prefix of each shard is written over the previously sent shard to avoid reallocations.
     pub fn send(&mut self, mut buffer: Buffer<H>) -> Result<()> {
-        self.inner
-            .lock()
-            .send(self.stream_id, self.next_packet_index, &mut buffer.inner)?;
+        let mut cursor = 0;
+        let mut first_shard_instant = None;
+        loop {
+            let mut writer = self.scheduler.lock(self.priority);
+            first_shard_instant.get_or_insert_with(Instant::now);
+
+            if writer.send_burst(
+                self.stream_id,
+                self.class,
+                self.next_packet_index,
+                &mut buffer.inner,
+                &mut cursor,
+            )? {
+                break;
+            }
+        }
+
+        // Safety: the loop runs at least once
+        self.last_send_instants = Some((first_shard_instant.unwrap(), Instant::now()));
 
         self.used_buffers.push(buffer.inner);
 
@@ -137,6 +334,11 @@ impl<H: Serialize> StreamSender<H> {
     pub fn send_header(&mut self, header: &H) -> Result<()> {
         self.send_header_with_payload(header, &[])
     }
+
+    // Instants when the first and the last shard of the last packet were handed to the socket
+    pub fn last_send_instants(&self) -> Option<(Instant, Instant)> {
+        self.last_send_instants
+    }
 }
 
 pub struct ReceiverData<H> {
@@ -144,6 +346,9 @@ pub struct ReceiverData<H> {
     payload_offset: usize,
     used_buffer_queue: mpsc::Sender<Vec<u8>>,
     had_packet_loss: bool,
+    recovered_shards_count: usize,
+    first_shard_arrival: Instant,
+    last_shard_arrival: Instant,
     _phantom: PhantomData<H>,
 }
 
@@ -151,6 +356,20 @@ impl<H> ReceiverData<H> {
     pub fn had_packet_loss(&self) -> bool {
         self.had_packet_loss
     }
+
+    // Number of shards of this packet that were lost by the network and rebuilt using forward
+    // error correction
+    pub fn recovered_shards_count(&self) -> usize {
+        self.recovered_shards_count
+    }
+
+    pub fn first_shard_arrival(&self) -> Instant {
+        self.first_shard_arrival
+    }
+
+    pub fn last_shard_arrival(&self) -> Instant {
+        self.last_shard_arrival
+    }
 }
 
 impl<H: DeserializeOwned> ReceiverData<H> {
@@ -177,6 +396,7 @@ impl<H> Drop for ReceiverData<H> {
 }
 
 pub struct StreamReceiver<H> {
+    class: StreamClass,
     payload_offset: usize,
     packet_receiver: mpsc::Receiver<ReconstructedPacket>,
     used_buffer_queue: mpsc::Sender<Vec<u8>>,
@@ -206,7 +426,7 @@ fn wrapping_cmp(lhs: u32, rhs: u32) -> Ordering {
 
 impl<H: DeserializeOwned + Serialize> StreamReceiver<H> {
     pub fn recv(&mut self, timeout: Duration) -> ConResult<ReceiverData<H>> {
-        let packet = self
+        let mut packet = self
             .packet_receiver
             .recv_timeout(timeout)
             .handle_try_again()?;
@@ -228,6 +448,20 @@ impl<H: DeserializeOwned + Serialize> StreamReceiver<H> {
                 }
             }
         }
+
+        if self.class == StreamClass::LatestOnly {
+            // Skipping queued packets on purpose is not reported as packet loss
+            while let Ok(newer_packet) = self.packet_receiver.try_recv() {
+                let older_packet =
+                    if wrapping_cmp(newer_packet.index, packet.index) == Ordering::Greater {
+                        mem::replace(&mut packet, newer_packet)
+                    } else {
+                        newer_packet
+                    };
+                self.used_buffer_queue.send(older_packet.buffer).to_con()?;
+            }
+        }
+
         self.last_packet_index = Some(packet.index);
 
         Ok(ReceiverData {
@@ -235,6 +469,9 @@ impl<H: DeserializeOwned + Serialize> StreamReceiver<H> {
             payload_offset: self.payload_offset,
             used_buffer_queue: self.used_buffer_queue.clone(),
             had_packet_loss,
+            recovered_shards_count: packet.recovered_shards_count,
+            first_shard_arrival: packet.first_shard_arrival,
+            last_shard_arrival: packet.last_shard_arrival,
             _phantom: PhantomData,
         })
     }
@@ -243,6 +480,7 @@ impl<H: DeserializeOwned + Serialize> StreamReceiver<H> {
 pub enum StreamSocketBuilder {
     Tcp(TcpListener),
     Udp(UdpSocket),
+    Quic(quic::QuicListener),
 }
 
 impl StreamSocketBuilder {
@@ -263,6 +501,9 @@ impl StreamSocketBuilder {
                 stream_tos_config,
                 buffer_config,
             )?),
+            SocketProtocol::Quic => {
+                StreamSocketBuilder::Quic(quic::bind(port, stream_tos_config, buffer_config)?)
+            }
         })
     }
 
@@ -271,24 +512,32 @@ impl StreamSocketBuilder {
         server_ip: IpAddr,
         port: u16,
         max_packet_size: usize,
+        fec_config: Option<ForwardErrorCorrectionConfig>,
+        secure_channel: Option<SecureChannel>,
         timeout: Duration,
     ) -> ConResult<StreamSocket> {
+        let secure_channel = secure_channel.map(SecureChannel::into_stream_channel);
+
         let (send_socket, receive_socket) = match self {
             StreamSocketBuilder::Udp(socket) => {
                 udp::connect(&socket, server_ip, port, timeout).to_con()?;
-                udp::split_multiplexed(socket, max_packet_size).to_con()?
+                udp::split_multiplexed(socket, max_packet_size, fec_config, secure_channel)
+                    .to_con()?
             }
             StreamSocketBuilder::Tcp(listener) => {
                 let socket = tcp::accept_from_server(&listener, Some(server_ip), timeout)?;
-                tcp::split_multiplexed(socket, timeout).to_con()?
+                tcp::split_multiplexed(socket, timeout, secure_channel).to_con()?
+            }
+            StreamSocketBuilder::Quic(listener) => {
+                let socket = quic::accept_from_server(listener, server_ip, timeout)?;
+                if let Some(channel) = &secure_channel {
+                    socket.bind_to_secure_channel(channel, timeout)?;
+                }
+                quic::split_multiplexed(socket, max_packet_size, fec_config, timeout).to_con()?
             }
         };
 
-        Ok(StreamSocket {
-            send_socket: Arc::new(Mutex::new(send_socket)),
-            receive_socket,
-            queues: HashMap::new(),
-        })
+        Ok(StreamSocket::new(send_socket, receive_socket))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -300,51 +549,129 @@ impl StreamSocketBuilder {
         dscp: Option<DscpTos>,
         buffer_config: SocketBufferConfig,
         max_packet_size: usize,
+        fec_config: Option<ForwardErrorCorrectionConfig>,
+        secure_channel: Option<SecureChannel>,
+        network_impairment: Option<NetworkImpairmentConfig>,
     ) -> ConResult<StreamSocket> {
+        let secure_channel = secure_channel.map(SecureChannel::into_stream_channel);
+
+        if network_impairment.is_some() && !matches!(protocol, SocketProtocol::Udp) {
+            warn!("Network impairment is supported only with the UDP stream protocol");
+        }
+
         let (send_socket, receive_socket) = match protocol {
             SocketProtocol::Udp => {
                 let socket = udp::bind(port, dscp, buffer_config).to_con()?;
                 udp::connect(&socket, client_ip, port, timeout).to_con()?;
-                udp::split_multiplexed(socket, max_packet_size).to_con()?
+
+                // The simulated link is applied to single datagrams, below retransmission and
+                // forward error correction
+                if let Some(config) = network_impairment {
+                    impairment::split_multiplexed(
+                        socket,
+                        max_packet_size,
+                        fec_config,
+                        secure_channel,
+                        config,
+                    )
+                    .to_con()?
+                } else {
+                    udp::split_multiplexed(socket, max_packet_size, fec_config, secure_channel)
+                        .to_con()?
+                }
             }
             SocketProtocol::Tcp => {
                 let socket = tcp::connect_to_client(timeout, &[client_ip], port, buffer_config)?;
-                tcp::split_multiplexed(socket, timeout).to_con()?
+                tcp::split_multiplexed(socket, timeout, secure_channel).to_con()?
+            }
+            SocketProtocol::Quic => {
+                let socket =
+                    quic::connect_to_client(timeout, client_ip, port, dscp, buffer_config)?;
+                if let Some(channel) = &secure_channel {
+                    socket.bind_to_secure_channel(channel, timeout)?;
+                }
+                quic::split_multiplexed(socket, max_packet_size, fec_config, timeout).to_con()?
             }
         };
 
-        Ok(StreamSocket {
-            send_socket: Arc::new(Mutex::new(send_socket)),
-            receive_socket,
-            queues: HashMap::new(),
-        })
+        Ok(StreamSocket::new(send_socket, receive_socket))
     }
 }
 
+struct FeedbackChannel {
+    sender: StreamSender<Vec<RetransmissionFeedback>>,
+    receiver: StreamReceiver<Vec<RetransmissionFeedback>>,
+    outgoing_feedback: Vec<RetransmissionFeedback>,
+    last_exchange_time: Instant,
+}
+
 pub struct StreamSocket {
-    send_socket: Arc<Mutex<Box<dyn MultiplexedSocketWriter + Send>>>,
+    scheduler: Arc<WriterScheduler>,
     receive_socket: Box<dyn MultiplexedSocketReader + Send>,
     queues: HashMap<u16, StreamRecvQueues>,
+    feedback: Box<FeedbackChannel>,
+    statistics: SocketStatistics,
 }
 
 impl StreamSocket {
-    pub fn request_stream<T>(&self, stream_id: u16) -> StreamSender<T> {
+    fn new(
+        send_socket: Box<dyn MultiplexedSocketWriter + Send>,
+        receive_socket: Box<dyn MultiplexedSocketReader + Send>,
+    ) -> Self {
+        let scheduler = Arc::new(WriterScheduler::new(send_socket));
+
+        let mut queues = HashMap::new();
+        let feedback = Box::new(FeedbackChannel {
+            sender: Self::request(
+                &scheduler,
+                FEEDBACK_STREAM_ID,
+                StreamClass::Droppable,
+                StreamPriority::High,
+            ),
+            receiver: Self::subscribe(
+                &mut queues,
+                receive_socket.payload_offset(),
+                FEEDBACK_STREAM_ID,
+                StreamClass::Droppable,
+                FEEDBACK_MAX_CONCURRENT_BUFFERS,
+            ),
+            outgoing_feedback: vec![],
+            last_exchange_time: Instant::now(),
+        });
+
+        Self {
+            scheduler,
+            receive_socket,
+            queues,
+            feedback,
+            statistics: SocketStatistics::default(),
+        }
+    }
+
+    fn request<T>(
+        scheduler: &Arc<WriterScheduler>,
+        stream_id: u16,
+        class: StreamClass,
+        priority: StreamPriority,
+    ) -> StreamSender<T> {
         StreamSender {
-            inner: Arc::clone(&self.send_socket),
+            scheduler: Arc::clone(scheduler),
             stream_id,
-            payload_offset: self.send_socket.lock().payload_offset(),
+            class,
+            priority,
+            payload_offset: scheduler.payload_offset,
             next_packet_index: 0,
             used_buffers: vec![],
+            last_send_instants: None,
             _phantom: PhantomData,
         }
     }
 
-    // max_concurrent_buffers: number of buffers allocated by this call which will be reused to
-    // receive packets for this stream ID. If packets are not read fast enough, the shards received
-    // for this particular stream will be discarded
-    pub fn subscribe_to_stream<T>(
-        &mut self,
+    fn subscribe<T>(
+        queues: &mut HashMap<u16, StreamRecvQueues>,
+        payload_offset: usize,
         stream_id: u16,
+        class: StreamClass,
         max_concurrent_buffers: usize,
     ) -> StreamReceiver<T> {
         let (packet_sender, packet_receiver) = mpsc::channel();
@@ -354,9 +681,10 @@ impl StreamSocket {
             used_buffer_sender.send(vec![]).ok();
         }
 
-        self.queues.insert(
+        queues.insert(
             stream_id,
             StreamRecvQueues {
+                class,
                 used_buffer_sender: used_buffer_sender.clone(),
                 used_buffer_receiver,
                 packet_queue: packet_sender,
@@ -364,7 +692,8 @@ impl StreamSocket {
         );
 
         StreamReceiver {
-            payload_offset: self.receive_socket.payload_offset(),
+            class,
+            payload_offset,
             packet_receiver,
             used_buffer_queue: used_buffer_sender,
             last_packet_index: None,
@@ -372,7 +701,73 @@ impl StreamSocket {
         }
     }
 
+    // The priority is used to schedule the packets of concurrent senders
+    pub fn request_stream<T>(
+        &self,
+        stream_id: u16,
+        class: StreamClass,
+        priority: StreamPriority,
+    ) -> StreamSender<T> {
+        Self::request(&self.scheduler, stream_id, class, priority)
+    }
+
+    // max_concurrent_buffers: number of buffers allocated by this call which will be reused to
+    // receive packets for this stream ID. If packets are not read fast enough, the shards received
+    // for this particular stream will be discarded
+    pub fn subscribe_to_stream<T>(
+        &mut self,
+        stream_id: u16,
+        class: StreamClass,
+        max_concurrent_buffers: usize,
+    ) -> StreamReceiver<T> {
+        Self::subscribe(
+            &mut self.queues,
+            self.receive_socket.payload_offset(),
+            stream_id,
+            class,
+            max_concurrent_buffers,
+        )
+    }
+
+    fn exchange_feedback(&mut self) -> Result<()> {
+        let feedback = &mut *self.feedback;
+
+        let mut incoming_feedback = vec![];
+        while let Ok(data) = feedback.receiver.recv(Duration::ZERO) {
+            incoming_feedback.extend(data.get_header()?);
+        }
+
+        let mut writer = self.scheduler.lock(StreamPriority::High);
+        writer.process_feedback(&incoming_feedback)?;
+        let statistics = writer.take_statistics();
+        drop(writer);
+
+        self.statistics.nacked_shards += statistics.nacked_shards;
+        self.statistics.retransmitted_shards += statistics.retransmitted_shards;
+
+        self.receive_socket
+            .take_feedback(&mut feedback.outgoing_feedback);
+        if !feedback.outgoing_feedback.is_empty() {
+            feedback.sender.send_header(&feedback.outgoing_feedback)?;
+            feedback.outgoing_feedback.clear();
+        }
+
+        Ok(())
+    }
+
+    // Retransmission statistics accumulated since the last call
+    pub fn take_statistics(&mut self) -> SocketStatistics {
+        mem::take(&mut self.statistics)
+    }
+
     pub fn recv(&mut self) -> ConResult {
-        self.receive_socket.recv(&self.queues)
+        let res = self.receive_socket.recv(&self.queues);
+
+        if self.feedback.last_exchange_time.elapsed() >= FEEDBACK_INTERVAL {
+            self.feedback.last_exchange_time = Instant::now();
+            self.exchange_feedback().to_con()?;
+        }
+
+        res
     }
 }
@@ -0,0 +1,656 @@
+// QUIC transport. All streams share a single connection on the stream port.
+// Streams that tolerate loss (droppable, latest-only and recoverable classes) are sharded into
+// unreliable datagrams, using the same shard format, forward error correction and retransmission
+// of lost shards as the UDP socket. Each
+// reliable stream gets a dedicated unidirectional QUIC stream, so a retransmission on one of them
+// does not block the others.
+//
+// The client acts as the QUIC server (see the note in mod.rs) and presents a self-signed
+// certificate generated for each connection, which is not verified. QUIC always encrypts the
+// traffic, when the control socket is encrypted too the QUIC connection is bound to the paired keys
+// by exchanging its exported keying material sealed with the secure channel.
+
+use super::{
+    MultiplexedSocketReader, MultiplexedSocketWriter, ReconstructedPacket, RetransmissionFeedback,
+    StreamClass, StreamRecvQueues, tcp,
+    udp::{self, DatagramReceiver, DatagramSender, MultiplexedUdpReader, MultiplexedUdpWriter},
+};
+use crate::SecureChannel;
+use alvr_common::{
+    AnyhowToCon, ConResult, HandleTryAgain, ToCon,
+    anyhow::{Result, anyhow, bail},
+    con_bail,
+};
+use alvr_session::{DscpTos, ForwardErrorCorrectionConfig, SocketBufferConfig};
+use quinn::{
+    ClientConfig, Connection, Endpoint, EndpointConfig, RecvStream, SendStream, ServerConfig,
+    TokioRuntime, TransportConfig, VarInt,
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+};
+use rustls::{
+    DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    mem::MaybeUninit,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+const ALPN_PROTOCOL: &[u8] = b"alvr";
+const SERVER_NAME: &str = "alvr";
+const KEYING_MATERIAL_LABEL: &[u8] = b"EXPORTER-ALVR-channel-binding";
+const KEYING_MATERIAL_SIZE: usize = 32;
+const DATAGRAM_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+// QUIC never lowers the MTU below 1200 bytes. Shards are sized to always fit this minimum, so the
+// layout is the same on both peers even if path MTU discovery gives them different limits
+const MIN_MTU: usize = 1200;
+const DATAGRAM_FRAME_OVERHEAD: usize = 48;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+// Sizes are sent by the peer, larger packets are rejected instead of being allocated
+const MAX_RELIABLE_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
+// Packets received but not yet processed. When full, datagrams are dropped like the OS does for UDP
+// and reliable packets wait, which stalls the QUIC flow control of their stream
+const INCOMING_QUEUE_SIZE: usize = DATAGRAM_BUFFER_SIZE / MIN_MTU;
+const RELIABLE_QUEUE_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn transport_config() -> Arc<TransportConfig> {
+    let mut config = TransportConfig::default();
+    config
+        .keep_alive_interval(Some(KEEPALIVE_INTERVAL))
+        .max_idle_timeout(Some(IDLE_TIMEOUT.try_into().unwrap()))
+        .datagram_receive_buffer_size(Some(DATAGRAM_BUFFER_SIZE))
+        .datagram_send_buffer_size(DATAGRAM_BUFFER_SIZE);
+
+    Arc::new(config)
+}
+
+// The certificate is not checked, only the handshake signature made with it
+#[derive(Debug)]
+struct UnverifiedCertificate {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for UnverifiedCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer,
+        _: &[CertificateDer],
+        _: &ServerName,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+fn server_config() -> Result<ServerConfig> {
+    let certificate = rcgen::generate_simple_self_signed(vec![SERVER_NAME.into()])?;
+
+    let mut tls_config = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certificate.cert.der().clone()],
+            PrivatePkcs8KeyDer::from(certificate.key_pair.serialize_der()).into(),
+        )?;
+    tls_config.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+    let mut config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls_config)?));
+    config.transport_config(transport_config());
+
+    Ok(config)
+}
+
+fn client_config() -> Result<ClientConfig> {
+    let provider = crypto_provider();
+
+    let mut tls_config = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(UnverifiedCertificate {
+            algorithms: provider.signature_verification_algorithms,
+        }))
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+    let mut config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls_config)?));
+    config.transport_config(transport_config());
+
+    Ok(config)
+}
+
+// The async QUIC implementation is driven by a runtime owned by the socket. The socket interface
+// stays blocking, the runtime threads only do the network IO.
+fn new_runtime() -> Result<Arc<Runtime>> {
+    Ok(Arc::new(
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("alvr-quic")
+            .enable_all()
+            .build()?,
+    ))
+}
+
+fn block_on_timeout<T>(
+    runtime: &Runtime,
+    timeout: Duration,
+    future: impl Future<Output = T>,
+) -> ConResult<T> {
+    match runtime.block_on(async { tokio::time::timeout(timeout, future).await }) {
+        Ok(output) => Ok(output),
+        Err(_) => alvr_common::try_again(),
+    }
+}
+
+fn new_endpoint(
+    runtime: &Runtime,
+    port: u16,
+    dscp: Option<DscpTos>,
+    buffer_config: SocketBufferConfig,
+    server_config: Option<ServerConfig>,
+) -> Result<Endpoint> {
+    let socket = udp::bind(port, dscp, buffer_config)?;
+
+    // The endpoint must be created inside the runtime context
+    let _guard = runtime.enter();
+    Ok(Endpoint::new(
+        EndpointConfig::default(),
+        server_config,
+        socket,
+        Arc::new(TokioRuntime),
+    )?)
+}
+
+pub struct QuicListener {
+    runtime: Arc<Runtime>,
+    endpoint: Endpoint,
+}
+
+pub fn bind(
+    port: u16,
+    dscp: Option<DscpTos>,
+    buffer_config: SocketBufferConfig,
+) -> Result<QuicListener> {
+    let runtime = new_runtime()?;
+    let endpoint = new_endpoint(&runtime, port, dscp, buffer_config, Some(server_config()?))?;
+
+    Ok(QuicListener { runtime, endpoint })
+}
+
+pub fn accept_from_server(
+    listener: QuicListener,
+    server_ip: IpAddr,
+    timeout: Duration,
+) -> ConResult<QuicConnection> {
+    let QuicListener { runtime, endpoint } = listener;
+
+    let Some(incoming) = block_on_timeout(&runtime, timeout, endpoint.accept())? else {
+        con_bail!("QUIC endpoint closed");
+    };
+
+    if incoming.remote_address().ip() != server_ip {
+        con_bail!(
+            "Connected to wrong server: Expected: {server_ip}, Found {}",
+            incoming.remote_address().ip()
+        );
+    }
+
+    // Connections must be created inside the runtime context
+    let connection =
+        block_on_timeout(&runtime, timeout, async { incoming.accept()?.await })?.to_con()?;
+
+    Ok(QuicConnection {
+        runtime,
+        endpoint,
+        connection,
+    })
+}
+
+pub fn connect_to_client(
+    timeout: Duration,
+    client_ip: IpAddr,
+    port: u16,
+    dscp: Option<DscpTos>,
+    buffer_config: SocketBufferConfig,
+) -> ConResult<QuicConnection> {
+    let runtime = new_runtime().to_con()?;
+    let mut endpoint = new_endpoint(&runtime, port, dscp, buffer_config, None).to_con()?;
+    endpoint.set_default_client_config(client_config().to_con()?);
+
+    let connection = block_on_timeout(&runtime, timeout, async {
+        Ok::<_, alvr_common::anyhow::Error>(
+            endpoint
+                .connect(SocketAddr::new(client_ip, port), SERVER_NAME)?
+                .await?,
+        )
+    })?
+    .to_con()?;
+
+    Ok(QuicConnection {
+        runtime,
+        endpoint,
+        connection,
+    })
+}
+
+pub struct QuicConnection {
+    runtime: Arc<Runtime>,
+    endpoint: Endpoint,
+    connection: Connection,
+}
+
+impl QuicConnection {
+    // Each peer sends its copy of the exported keying material sealed with the secure channel. The
+    // material matches only if both peers are part of the same QUIC connection, and only the paired
+    // peer can seal it.
+    pub fn bind_to_secure_channel(
+        &self,
+        secure_channel: &SecureChannel,
+        timeout: Duration,
+    ) -> ConResult {
+        let mut keying_material = [0; KEYING_MATERIAL_SIZE];
+        self.connection
+            .export_keying_material(&mut keying_material, KEYING_MATERIAL_LABEL, &[])
+            .map_err(|_| anyhow!("Failed to export QUIC keying material"))
+            .to_con()?;
+
+        let mut sealed = vec![];
+        secure_channel
+            .seal_datagram(&keying_material, &mut sealed)
+            .to_con()?;
+
+        let received = block_on_timeout(&self.runtime, timeout, async {
+            let mut stream = self.connection.open_uni().await?;
+            stream.write_all(&sealed).await?;
+            stream.finish()?;
+
+            // This is always the first stream opened by the peer
+            let mut stream = self.connection.accept_uni().await?;
+            Ok::<_, alvr_common::anyhow::Error>(stream.read_to_end(sealed.len()).await?)
+        })?
+        .to_con()?;
+
+        let mut peer_keying_material = vec![];
+        secure_channel
+            .open_datagram(&received, &mut peer_keying_material)
+            .to_con()?;
+
+        if peer_keying_material != keying_material {
+            con_bail!("QUIC connection is not bound to the paired peer");
+        }
+
+        Ok(())
+    }
+}
+
+// Datagrams are sent directly from the caller thread, without waiting for the runtime
+struct DatagramSocket {
+    connection: Connection,
+}
+
+impl DatagramSender for DatagramSocket {
+    fn send_datagram(&self, datagram: &[u8]) -> Result<()> {
+        self.connection
+            .send_datagram(datagram.to_vec().into())
+            .map_err(|e| anyhow!("Failed to send QUIC datagram: {e}"))
+    }
+}
+
+pub struct MultiplexedQuicWriter {
+    runtime: Arc<Runtime>,
+    _endpoint: Endpoint,
+    connection: Connection,
+    datagram_writer: MultiplexedUdpWriter<DatagramSocket>,
+    reliable_streams: HashMap<u16, SendStream>,
+    timeout: Duration,
+}
+
+impl MultiplexedSocketWriter for MultiplexedQuicWriter {
+    // Reliable packets use the TCP prefix, which is smaller than the shard prefix and is placed
+    // right before the payload
+    fn payload_offset(&self) -> usize {
+        self.datagram_writer.payload_offset()
+    }
+
+    fn send_burst(
+        &mut self,
+        stream_id: u16,
+        class: StreamClass,
+        packet_index: u32,
+        buffer: &mut Vec<u8>,
+        cursor: &mut usize,
+    ) -> Result<bool> {
+        if class != StreamClass::Reliable {
+            return self
+                .datagram_writer
+                .send_burst(stream_id, class, packet_index, buffer, cursor);
+        }
+
+        let payload_offset = self.payload_offset();
+        let payload_size = buffer.len() - payload_offset;
+        let packet = &mut buffer[payload_offset - tcp::PACKET_PREFIX_SIZE..];
+
+        packet[0..2].copy_from_slice(&stream_id.to_le_bytes());
+        packet[2..6].copy_from_slice(&packet_index.to_le_bytes());
+        packet[6..10].copy_from_slice(&(payload_size as u32).to_le_bytes());
+
+        // A packet that is partially written cannot be skipped, so a stream that makes no progress
+        // within the timeout (flow control never releasing) is treated as a lost connection
+        let stream = if let Some(stream) = self.reliable_streams.get_mut(&stream_id) {
+            stream
+        } else {
+            let Ok(stream) =
+                block_on_timeout(&self.runtime, self.timeout, self.connection.open_uni())
+            else {
+                bail!("Timed out opening QUIC stream");
+            };
+            self.reliable_streams.entry(stream_id).or_insert(stream?)
+        };
+
+        let Ok(written) = block_on_timeout(
+            &self.runtime,
+            self.timeout,
+            stream.write(&packet[*cursor..]),
+        ) else {
+            bail!("QUIC stream stalled");
+        };
+        *cursor += written?;
+
+        Ok(*cursor == packet.len())
+    }
+
+    fn process_feedback(&mut self, feedback: &[RetransmissionFeedback]) -> Result<()> {
+        self.datagram_writer.process_feedback(feedback)
+    }
+}
+
+impl Drop for MultiplexedQuicWriter {
+    fn drop(&mut self) {
+        self.connection.close(VarInt::from_u32(0), b"");
+    }
+}
+
+enum IncomingPacket {
+    Datagram(Vec<u8>),
+    Reliable {
+        stream_id: u16,
+        packet_index: u32,
+        payload: Vec<u8>,
+    },
+}
+
+// Holds the datagram to be processed by the shard reader. The reader never waits here, datagrams
+// are fetched by MultiplexedQuicReader
+struct PendingDatagram {
+    datagram: Option<Vec<u8>>,
+}
+
+impl DatagramReceiver for PendingDatagram {
+    fn peek_datagram(&mut self, buffer: &mut [u8]) -> ConResult<usize> {
+        let Some(datagram) = &self.datagram else {
+            return alvr_common::try_again();
+        };
+
+        let count = usize::min(buffer.len(), datagram.len());
+        buffer[..count].copy_from_slice(&datagram[..count]);
+
+        Ok(datagram.len())
+    }
+
+    fn recv_datagram(&mut self, buffer: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        let Some(datagram) = self.datagram.take() else {
+            bail!("No pending QUIC datagram");
+        };
+
+        let count = usize::min(buffer.len(), datagram.len());
+        for (dst, src) in buffer[..count].iter_mut().zip(&datagram) {
+            dst.write(*src);
+        }
+
+        Ok(count)
+    }
+
+    fn discard_datagram(&mut self) {
+        self.datagram = None;
+    }
+}
+
+async fn receive_datagrams(connection: Connection, sender: mpsc::SyncSender<IncomingPacket>) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        if let Err(mpsc::TrySendError::Disconnected(_)) =
+            sender.try_send(IncomingPacket::Datagram(datagram.into()))
+        {
+            break;
+        }
+    }
+}
+
+// Reliable packets cannot be dropped. The task must not block the runtime thread while waiting
+async fn send_reliable_packet(
+    sender: &mpsc::SyncSender<IncomingPacket>,
+    mut packet: IncomingPacket,
+) -> Result<()> {
+    loop {
+        match sender.try_send(packet) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::TrySendError::Full(returned_packet)) => {
+                packet = returned_packet;
+                tokio::time::sleep(RELIABLE_QUEUE_RETRY_INTERVAL).await;
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => bail!("Socket closed"),
+        }
+    }
+}
+
+async fn receive_reliable_packets(
+    mut stream: RecvStream,
+    sender: mpsc::SyncSender<IncomingPacket>,
+) {
+    let mut prefix_bytes = [0; tcp::PACKET_PREFIX_SIZE];
+
+    while stream.read_exact(&mut prefix_bytes).await.is_ok() {
+        let stream_id = u16::from_le_bytes(prefix_bytes[0..2].try_into().unwrap());
+        let packet_index = u32::from_le_bytes(prefix_bytes[2..6].try_into().unwrap());
+        let payload_size = u32::from_le_bytes(prefix_bytes[6..10].try_into().unwrap()) as usize;
+
+        if payload_size > MAX_RELIABLE_PAYLOAD_SIZE {
+            stream.stop(VarInt::from_u32(0)).ok();
+            break;
+        }
+
+        let mut payload = vec![0; payload_size];
+        if stream.read_exact(&mut payload).await.is_err() {
+            break;
+        }
+
+        let packet = IncomingPacket::Reliable {
+            stream_id,
+            packet_index,
+            payload,
+        };
+        if send_reliable_packet(&sender, packet).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn accept_reliable_streams(connection: Connection, sender: mpsc::SyncSender<IncomingPacket>) {
+    while let Ok(stream) = connection.accept_uni().await {
+        tokio::spawn(receive_reliable_packets(stream, sender.clone()));
+    }
+}
+
+pub struct MultiplexedQuicReader {
+    _runtime: Arc<Runtime>,
+    incoming_receiver: mpsc::Receiver<IncomingPacket>,
+    // Reliable packet waiting for a free buffer of its stream, it cannot be dropped
+    pending_reliable_packet: Option<IncomingPacket>,
+    datagram_reader: MultiplexedUdpReader<PendingDatagram>,
+    timeout: Duration,
+}
+
+impl MultiplexedSocketReader for MultiplexedQuicReader {
+    fn payload_offset(&self) -> usize {
+        self.datagram_reader.payload_offset()
+    }
+
+    fn recv(&mut self, stream_queues: &HashMap<u16, StreamRecvQueues>) -> ConResult {
+        let packet = if let Some(packet) = self.pending_reliable_packet.take() {
+            packet
+        } else {
+            // The channel is closed only when the connection is lost
+            self.incoming_receiver
+                .recv_timeout(self.timeout)
+                .handle_try_again()?
+        };
+
+        match packet {
+            IncomingPacket::Datagram(datagram) => {
+                self.datagram_reader.datagram_receiver().datagram = Some(datagram);
+
+                self.datagram_reader.recv(stream_queues)
+            }
+            IncomingPacket::Reliable {
+                stream_id,
+                packet_index,
+                payload,
+            } => {
+                let Some(queues) = stream_queues.get(&stream_id) else {
+                    // Invalid stream ID
+                    return Ok(());
+                };
+
+                let mut buffer = match queues
+                    .used_buffer_receiver
+                    .recv_timeout(self.timeout)
+                    .handle_try_again()
+                {
+                    Ok(buffer) => buffer,
+                    Err(e) => {
+                        self.pending_reliable_packet = Some(IncomingPacket::Reliable {
+                            stream_id,
+                            packet_index,
+                            payload,
+                        });
+
+                        return Err(e);
+                    }
+                };
+
+                let payload_offset = self.payload_offset();
+                buffer.clear();
+                buffer.resize(payload_offset, 0);
+                buffer.extend_from_slice(&payload);
+
+                let now = Instant::now();
+                queues
+                    .packet_queue
+                    .send(ReconstructedPacket {
+                        index: packet_index,
+                        buffer,
+                        recovered_shards_count: 0,
+                        first_shard_arrival: now,
+                        last_shard_arrival: now,
+                    })
+                    .to_con()
+            }
+        }
+    }
+
+    fn take_feedback(&mut self, feedback: &mut Vec<RetransmissionFeedback>) {
+        self.datagram_reader.take_feedback(feedback);
+    }
+}
+
+pub fn split_multiplexed(
+    socket: QuicConnection,
+    max_packet_size: usize,
+    fec_config: Option<ForwardErrorCorrectionConfig>,
+    timeout: Duration,
+) -> Result<(
+    Box<dyn MultiplexedSocketWriter + Send>,
+    Box<dyn MultiplexedSocketReader + Send>,
+)> {
+    let QuicConnection {
+        runtime,
+        endpoint,
+        connection,
+    } = socket;
+
+    if connection.max_datagram_size().is_none() {
+        bail!("The peer does not support QUIC datagrams");
+    }
+    // Datagrams cannot be fragmented
+    let max_packet_size = usize::min(max_packet_size, MIN_MTU - DATAGRAM_FRAME_OVERHEAD);
+
+    // The shards are not encrypted a second time, QUIC already does it
+    let (datagram_writer, datagram_reader) = udp::split_datagrams(
+        DatagramSocket {
+            connection: connection.clone(),
+        },
+        PendingDatagram { datagram: None },
+        max_packet_size,
+        fec_config,
+        None,
+    );
+
+    let (incoming_sender, incoming_receiver) = mpsc::sync_channel(INCOMING_QUEUE_SIZE);
+    runtime.spawn(receive_datagrams(
+        connection.clone(),
+        incoming_sender.clone(),
+    ));
+    runtime.spawn(accept_reliable_streams(connection.clone(), incoming_sender));
+
+    let writer = MultiplexedQuicWriter {
+        runtime: Arc::clone(&runtime),
+        _endpoint: endpoint,
+        connection,
+        datagram_writer,
+        reliable_streams: HashMap::new(),
+        timeout,
+    };
+
+    let reader = MultiplexedQuicReader {
+        _runtime: runtime,
+        incoming_receiver,
+        pending_reliable_packet: None,
+        datagram_reader,
+        timeout,
+    };
+
+    Ok((Box::new(writer), Box::new(reader)))
+}
@@ -1,7 +1,8 @@
 use super::{
-    MultiplexedSocketReader, MultiplexedSocketWriter, ReconstructedPacket, StreamRecvQueues,
+    MultiplexedSocketReader, MultiplexedSocketWriter, ReconstructedPacket, StreamClass,
+    StreamRecvQueues,
 };
-use crate::LOCAL_IP;
+use crate::{LOCAL_IP, SecureChannel, SecureStreamWriter, secure_channel::SecureStreamReader};
 use alvr_common::{ConResult, HandleTryAgain, ToCon, anyhow::Result, con_bail};
 use alvr_session::{DscpTos, SocketBufferConfig};
 use socket2::Socket;
@@ -10,7 +11,7 @@ use std::{
     io::Write,
     mem::{self, MaybeUninit},
     net::{IpAddr, SocketAddr, TcpListener, TcpStream},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 pub const PACKET_PREFIX_SIZE: usize = mem::size_of::<u16>() // stream ID
@@ -88,6 +89,7 @@ pub fn connect_to_client(
 
 pub struct MultiplexedTcpWriter {
     inner: TcpStream,
+    encryptor: Option<SecureStreamWriter>,
 }
 
 impl MultiplexedSocketWriter for MultiplexedTcpWriter {
@@ -96,16 +98,32 @@ impl MultiplexedSocketWriter for MultiplexedTcpWriter {
     }
 
     // `buffer` contains the payload offset by `payload_offset()`
-    fn send(&mut self, stream_id: u16, packet_index: u32, buffer: &mut Vec<u8>) -> Result<()> {
+    // Packets cannot be interleaved on the TCP stream, they are always sent in a single burst
+    fn send_burst(
+        &mut self,
+        stream_id: u16,
+        _: StreamClass,
+        packet_index: u32,
+        buffer: &mut Vec<u8>,
+        _: &mut usize,
+    ) -> Result<bool> {
         let payload_size = buffer.len() - PACKET_PREFIX_SIZE;
 
         buffer[0..2].copy_from_slice(&stream_id.to_le_bytes());
         buffer[2..6].copy_from_slice(&packet_index.to_le_bytes());
         buffer[6..10].copy_from_slice(&(payload_size as u32).to_le_bytes());
 
-        self.inner.write_all(buffer)?;
+        if let Some(encryptor) = &mut self.encryptor {
+            encryptor
+                .channel
+                .seal_records(buffer, &mut encryptor.buffer)?;
 
-        Ok(())
+            self.inner.write_all(&encryptor.buffer)?;
+        } else {
+            self.inner.write_all(buffer)?;
+        }
+
+        Ok(true)
     }
 }
 
@@ -115,10 +133,12 @@ struct InProgressPacket {
     buffer: Vec<u8>,
     buffer_size: usize,
     cursor: usize,
+    first_chunk_arrival: Instant,
 }
 
 pub struct MultiplexedTcpReader {
     inner: Socket,
+    decryptor: Option<SecureStreamReader>,
     in_progress_packet: Option<InProgressPacket>,
     used_buffers_poll_timeout: Duration,
 }
@@ -135,12 +155,15 @@ impl MultiplexedSocketReader for MultiplexedTcpReader {
             let mut prefix_bytes = [0u8; PACKET_PREFIX_SIZE];
 
             loop {
-                let count = self
-                    .inner
-                    .peek(unsafe {
-                        &mut *(&mut prefix_bytes as *mut [u8] as *mut [MaybeUninit<u8>])
-                    })
-                    .handle_try_again()?;
+                let count = if let Some(decryptor) = &mut self.decryptor {
+                    decryptor.peek(&mut self.inner, &mut prefix_bytes)?
+                } else {
+                    self.inner
+                        .peek(unsafe {
+                            &mut *(&mut prefix_bytes as *mut [u8] as *mut [MaybeUninit<u8>])
+                        })
+                        .handle_try_again()?
+                };
                 if count == PACKET_PREFIX_SIZE {
                     break;
                 }
@@ -171,6 +194,7 @@ impl MultiplexedSocketReader for MultiplexedTcpReader {
                 buffer,
                 buffer_size: PACKET_PREFIX_SIZE + payload_size,
                 cursor: 0,
+                first_chunk_arrival: Instant::now(),
             })
         };
 
@@ -178,7 +202,11 @@ impl MultiplexedSocketReader for MultiplexedTcpReader {
             let sub_buffer = &mut in_progress_packet.buffer.spare_capacity_mut()
                 [in_progress_packet.cursor..in_progress_packet.buffer_size];
 
-            in_progress_packet.cursor += self.inner.recv(sub_buffer).handle_try_again()?;
+            in_progress_packet.cursor += if let Some(decryptor) = &mut self.decryptor {
+                decryptor.read(&mut self.inner, sub_buffer)?
+            } else {
+                self.inner.recv(sub_buffer).handle_try_again()?
+            };
         }
 
         // All writing was done to uninit capacity, here we set the final buffer length
@@ -194,7 +222,10 @@ impl MultiplexedSocketReader for MultiplexedTcpReader {
                 .packet_queue
                 .send(ReconstructedPacket {
                     index: in_progress_packet.packet_index,
+                    first_shard_arrival: in_progress_packet.first_chunk_arrival,
+                    last_shard_arrival: Instant::now(),
                     buffer: self.in_progress_packet.take().unwrap().buffer,
+                    recovered_shards_count: 0,
                 })
                 .to_con()?;
         } else {
@@ -209,16 +240,19 @@ impl MultiplexedSocketReader for MultiplexedTcpReader {
 pub fn split_multiplexed(
     socket: TcpStream,
     used_buffers_poll_timeout: Duration,
+    secure_channel: Option<SecureChannel>,
 ) -> Result<(
     Box<dyn MultiplexedSocketWriter + Send>,
     Box<dyn MultiplexedSocketReader + Send>,
 )> {
     let writer = MultiplexedTcpWriter {
         inner: socket.try_clone()?,
+        encryptor: secure_channel.clone().map(SecureStreamWriter::new),
     };
 
     let reader = MultiplexedTcpReader {
         inner: socket.into(),
+        decryptor: secure_channel.map(SecureStreamReader::new),
         in_progress_packet: None,
         used_buffers_poll_timeout,
     };
@@ -1,18 +1,19 @@
 use super::{
-    MultiplexedSocketReader, MultiplexedSocketWriter, ReconstructedPacket, StreamRecvQueues,
+    MultiplexedSocketReader, MultiplexedSocketWriter, ReconstructedPacket, RetransmissionFeedback,
+    SocketStatistics, StreamClass, StreamRecvQueues, fec,
 };
-use crate::LOCAL_IP;
-use alvr_common::{ConResult, HandleTryAgain, ToCon, anyhow::Result};
-use alvr_session::{DscpTos, SocketBufferConfig};
+use crate::{LOCAL_IP, SecureChannel, as_uninit_slice, secure_channel::ENCRYPTION_OVERHEAD};
+use alvr_common::{AnyhowToCon, ConResult, HandleTryAgain, anyhow::Result};
+use alvr_session::{DscpTos, ForwardErrorCorrectionConfig, SocketBufferConfig};
 use socket2::{MaybeUninitSlice, Socket};
 use std::ffi::c_int;
 use std::{
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     mem::{self, MaybeUninit},
     net::{IpAddr, UdpSocket},
-    ptr,
-    time::Duration,
+    slice,
+    time::{Duration, Instant},
 };
 
 pub const SHARD_PREFIX_SIZE: usize = mem::size_of::<u16>() // stream ID
@@ -20,14 +21,72 @@ pub const SHARD_PREFIX_SIZE: usize = mem::size_of::<u16>() // stream ID
     + mem::size_of::<u32>() // shards count
     + mem::size_of::<u32>(); // shards index
 
+// Without forward error correction, packets are sent in bursts of this many shards. With it, a burst
+// is a block of data shards and its parity shards.
+const MAX_SHARDS_PER_BURST: usize = 16;
+
+// Retransmission of reliable and recoverable streams. The sender keeps the recently sent packets
+// of each stream. The receiver requests the missing shards of incomplete packets, and skips the
+// missing packets after waiting too long for them. The sender gives up at the same time. For
+// reliable streams, the sender also resends a whole packet if it was not acknowledged in time, in
+// case all its shards went missing.
+const RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(50);
+const RELIABLE_SKIP_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_RETAINED_PACKETS_PER_STREAM: usize = 128;
+// Capped to a fraction of the deadline, so short deadlines leave time for more than one request
+const NACK_DELAY: Duration = Duration::from_millis(10);
+const NACK_INTERVAL: Duration = Duration::from_millis(30);
+const MAX_MISSING_PACKETS_PER_NACK: u32 = 16;
+const RETRANSMISSION_CHECK_INTERVAL: Duration = Duration::from_millis(5);
+
+// Time after which the packets of a stream are not retransmitted anymore. None if the stream has no
+// retransmission
+fn retransmission_deadline(class: StreamClass) -> Option<Duration> {
+    match class {
+        StreamClass::Droppable | StreamClass::LatestOnly => None,
+        StreamClass::Reliable => Some(RELIABLE_SKIP_TIMEOUT),
+        StreamClass::Recoverable { deadline } => Some(deadline),
+    }
+}
+
+// With forward error correction, the prefix also contains the payload size. This is needed to
+// restore the exact size of the packet when the last shard is lost and rebuilt.
+pub const FEC_SHARD_PREFIX_SIZE: usize = SHARD_PREFIX_SIZE + mem::size_of::<u32>(); // payload size
+
+// Clamp the settings values to what the Reed-Solomon code supports.
+// Returns (data shards per block, parity shards per block)
+fn fec_block_layout(config: ForwardErrorCorrectionConfig) -> (usize, usize) {
+    (
+        (config.data_shards_per_block as usize).clamp(1, fec::MAX_DATA_SHARDS_PER_BLOCK),
+        (config.parity_shards_per_block as usize).clamp(1, fec::MAX_PARITY_SHARDS_PER_BLOCK),
+    )
+}
+
+fn write_shard_prefix(
+    shard: &mut [u8],
+    stream_id: u16,
+    packet_index: u32,
+    shards_count: usize,
+    shard_index: usize,
+    fec_payload_size: Option<usize>,
+) {
+    shard[0..2].copy_from_slice(&stream_id.to_le_bytes());
+    shard[2..6].copy_from_slice(&packet_index.to_le_bytes());
+    shard[6..10].copy_from_slice(&(shards_count as u32).to_le_bytes());
+    shard[10..14].copy_from_slice(&(shard_index as u32).to_le_bytes());
+
+    if let Some(payload_size) = fec_payload_size {
+        shard[14..18].copy_from_slice(&(payload_size as u32).to_le_bytes());
+    }
+}
+
 fn socket_peek(socket: &mut Socket, buffer: &mut [u8]) -> ConResult<usize> {
     #[cfg(windows)]
     const FLAGS: c_int = 0x02 | 0x8000; // MSG_PEEK | MSG_PARTIAL
     #[cfg(not(windows))]
     const FLAGS: c_int = 0x02 | 0x20; // MSG_PEEK | MSG_TRUNC
 
-    let buffer =
-        MaybeUninitSlice::new(unsafe { &mut *(ptr::from_mut(buffer) as *mut [MaybeUninit<u8>]) });
+    let buffer = MaybeUninitSlice::new(as_uninit_slice(buffer));
     // NB: Using the non vectored call doesn't seem to work
     Ok(socket
         .recv_vectored_with_flags(&mut [buffer], FLAGS)
@@ -35,6 +94,118 @@ fn socket_peek(socket: &mut Socket, buffer: &mut [u8]) -> ConResult<usize> {
         .0)
 }
 
+// Transport used to carry the shards. Implemented by the UDP socket and by QUIC datagrams.
+pub(super) trait DatagramSender {
+    fn send_datagram(&self, datagram: &[u8]) -> Result<()>;
+}
+
+impl DatagramSender for UdpSocket {
+    fn send_datagram(&self, datagram: &[u8]) -> Result<()> {
+        self.send(datagram)?;
+
+        Ok(())
+    }
+}
+
+pub(super) trait DatagramReceiver {
+    // Copy the start of the next datagram without consuming it. Returns the size of the whole
+    // datagram
+    fn peek_datagram(&mut self, buffer: &mut [u8]) -> ConResult<usize>;
+
+    // Consume the datagram previously peeked
+    fn recv_datagram(&mut self, buffer: &mut [MaybeUninit<u8>]) -> Result<usize>;
+
+    fn discard_datagram(&mut self);
+}
+
+impl DatagramReceiver for Socket {
+    fn peek_datagram(&mut self, buffer: &mut [u8]) -> ConResult<usize> {
+        socket_peek(self, buffer)
+    }
+
+    fn recv_datagram(&mut self, buffer: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        Ok(self.recv(buffer)?)
+    }
+
+    fn discard_datagram(&mut self) {
+        // Reading with any sized buffer (even 0) will consume the whole datagram
+        self.recv(&mut []).ok();
+    }
+}
+
+// Used when the connection is encrypted. Each datagram is received and decrypted whole, then the
+// plaintext is served with the same peek/recv interface as the socket.
+struct DatagramDecryptor {
+    channel: SecureChannel,
+    datagram_buffer: Vec<u8>,
+    plaintext: Vec<u8>,
+    pending: bool,
+}
+
+struct ShardSource<R> {
+    socket: R,
+    decryptor: Option<DatagramDecryptor>,
+}
+
+impl<R: DatagramReceiver> ShardSource<R> {
+    fn peek(&mut self, buffer: &mut [u8]) -> ConResult<usize> {
+        let Some(decryptor) = &mut self.decryptor else {
+            return self.socket.peek_datagram(buffer);
+        };
+
+        if !decryptor.pending {
+            // Wait for a datagram, timeouts are reported by the peek call
+            self.socket.peek_datagram(&mut [])?;
+
+            let size = self
+                .socket
+                .recv_datagram(as_uninit_slice(&mut decryptor.datagram_buffer))
+                .to_con()?;
+
+            // Corrupted or forged datagrams are dropped
+            if decryptor
+                .channel
+                .open_datagram(&decryptor.datagram_buffer[..size], &mut decryptor.plaintext)
+                .is_err()
+            {
+                return alvr_common::try_again();
+            }
+
+            decryptor.pending = true;
+        }
+
+        let count = usize::min(buffer.len(), decryptor.plaintext.len());
+        buffer[..count].copy_from_slice(&decryptor.plaintext[..count]);
+
+        // Like the socket peek, return the size of the whole datagram
+        Ok(decryptor.plaintext.len())
+    }
+
+    fn recv(&mut self, buffer: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        let Some(decryptor) = &mut self.decryptor else {
+            return self.socket.recv_datagram(buffer);
+        };
+
+        let count = usize::min(buffer.len(), decryptor.plaintext.len());
+        for (dst, src) in buffer[..count].iter_mut().zip(&decryptor.plaintext) {
+            dst.write(*src);
+        }
+        decryptor.pending = false;
+
+        Ok(count)
+    }
+
+    fn discard_and_try_again(&mut self) -> ConResult {
+        if let Some(decryptor) = &mut self.decryptor {
+            decryptor.pending = false;
+        } else {
+            self.socket.discard_datagram();
+        }
+
+        alvr_common::try_again()
+    }
+}
+
 // Create tokio socket, convert to socket2, apply settings, convert back to tokio. This is done to
 // let tokio set all the internal parameters it needs from the start.
 pub fn bind(
@@ -57,39 +228,312 @@ pub fn connect(socket: &UdpSocket, peer_ip: IpAddr, port: u16, timeout: Duration
     Ok(())
 }
 
-pub struct MultiplexedUdpWriter {
-    inner: UdpSocket,
+struct FecEncoder {
+    data_shards_per_block: usize,
+    parity_buffers: Vec<Vec<u8>>, // these contain the prefix
+}
+
+// The buffer contains the last encrypted shard
+struct DatagramEncryptor {
+    channel: SecureChannel,
+    buffer: Vec<u8>,
+}
+
+fn send_shard(
+    socket: &impl DatagramSender,
+    encryptor: &mut Option<DatagramEncryptor>,
+    shard: &[u8],
+) -> Result<()> {
+    if let Some(encryptor) = encryptor {
+        encryptor
+            .channel
+            .seal_datagram(shard, &mut encryptor.buffer)?;
+        socket.send_datagram(&encryptor.buffer)?;
+    } else {
+        socket.send_datagram(shard)?;
+    }
+
+    Ok(())
+}
+
+// Copy of the data shards of a packet of a stream with retransmission, kept until acknowledged or
+// until the deadline
+struct RetainedPacket {
+    index: u32,
+    shards: Vec<Vec<u8>>, // these contain the prefix
+    first_send_time: Instant,
+    last_send_time: Instant,
+    deadline: Duration,
+    resend_on_timeout: bool,
+    retransmitted: bool,
+}
+
+// Resend the requested data shards, or all of them if `shard_indices` is empty. Returns the number
+// of shards sent
+fn retransmit(
+    socket: &impl DatagramSender,
+    encryptor: &mut Option<DatagramEncryptor>,
+    packet: &mut RetainedPacket,
+    shard_indices: &[u32],
+) -> Result<usize> {
+    let mut sent_shards_count = 0;
+    if shard_indices.is_empty() {
+        for shard in &packet.shards {
+            send_shard(socket, encryptor, shard)?;
+            sent_shards_count += 1;
+        }
+    } else {
+        for shard_index in shard_indices {
+            if let Some(shard) = packet.shards.get(*shard_index as usize) {
+                send_shard(socket, encryptor, shard)?;
+                sent_shards_count += 1;
+            }
+        }
+    }
+
+    packet.last_send_time = Instant::now();
+    packet.retransmitted = true;
+
+    Ok(sent_shards_count)
+}
+
+pub struct MultiplexedUdpWriter<S = UdpSocket> {
+    inner: S,
     max_packet_size: usize,
+    fec_encoder: Option<FecEncoder>,
+    encryptor: Option<DatagramEncryptor>,
+    // Ring buffers of the recently sent packets, oldest first
+    retained_streams: HashMap<u16, VecDeque<RetainedPacket>>,
+    shard_buffers_pool: Vec<Vec<u8>>,
+    // Estimated from the acknowledgements. Used to skip retransmissions that would arrive late
+    round_trip_time: Option<Duration>,
+    statistics: SocketStatistics,
 }
 
-impl MultiplexedSocketWriter for MultiplexedUdpWriter {
+impl<S: DatagramSender> MultiplexedSocketWriter for MultiplexedUdpWriter<S> {
     fn payload_offset(&self) -> usize {
-        SHARD_PREFIX_SIZE
+        if self.fec_encoder.is_some() {
+            FEC_SHARD_PREFIX_SIZE
+        } else {
+            SHARD_PREFIX_SIZE
+        }
     }
 
-    fn send(&mut self, stream_id: u16, packet_index: u32, buffer: &mut Vec<u8>) -> Result<()> {
-        let max_shard_size = self.max_packet_size - SHARD_PREFIX_SIZE;
-        let payload_size = buffer.len() - SHARD_PREFIX_SIZE;
+    // `cursor` is the index of the first data shard of the burst
+    fn send_burst(
+        &mut self,
+        stream_id: u16,
+        class: StreamClass,
+        packet_index: u32,
+        buffer: &mut Vec<u8>,
+        cursor: &mut usize,
+    ) -> Result<bool> {
+        let prefix_size = self.payload_offset();
+        let max_shard_size = self.max_packet_size - prefix_size;
+        let payload_size = buffer.len() - prefix_size;
         // rounding up:
         let shards_count = payload_size.div_ceil(max_shard_size);
 
-        for shard_idx in 0..shards_count {
+        let fec_payload_size = self.fec_encoder.is_some().then_some(payload_size);
+        let burst_shards_count = self
+            .fec_encoder
+            .as_ref()
+            .map_or(MAX_SHARDS_PER_BURST, |encoder| {
+                encoder.data_shards_per_block
+            });
+
+        let block_start = *cursor;
+        let block_end = usize::min(block_start + burst_shards_count, shards_count);
+        let block_idx = block_start / burst_shards_count;
+
+        let deadline = retransmission_deadline(class);
+        if let Some(deadline) = deadline
+            && block_start == 0
+        {
+            let packets = self.retained_streams.entry(stream_id).or_default();
+            if packets.len() >= MAX_RETAINED_PACKETS_PER_STREAM
+                && let Some(packet) = packets.pop_front()
+            {
+                self.shard_buffers_pool.extend(packet.shards);
+            }
+
+            packets.push_back(RetainedPacket {
+                index: packet_index,
+                shards: Vec::with_capacity(shards_count),
+                first_send_time: Instant::now(),
+                last_send_time: Instant::now(),
+                deadline,
+                resend_on_timeout: class == StreamClass::Reliable,
+                retransmitted: false,
+            });
+        }
+
+        // Parity shards must be computed before sending the data shards of the block, because
+        // the shard prefixes are written over the data of the previous shard
+        if let Some(encoder) = &mut self.fec_encoder {
+            let packet_data = buffer.as_slice();
+            let parity_count = encoder.parity_buffers.len();
+            for (parity_idx, parity_buffer) in encoder.parity_buffers.iter_mut().enumerate() {
+                parity_buffer.resize(prefix_size + max_shard_size, 0);
+
+                fec::encode_parity(
+                    (block_start..block_end).map(|shard_idx| {
+                        let shard_start_position = prefix_size + shard_idx * max_shard_size;
+                        let shard_size =
+                            usize::min(max_shard_size, payload_size - shard_idx * max_shard_size);
+
+                        &packet_data[shard_start_position..][..shard_size]
+                    }),
+                    parity_idx,
+                    &mut parity_buffer[prefix_size..],
+                );
+
+                write_shard_prefix(
+                    parity_buffer,
+                    stream_id,
+                    packet_index,
+                    shards_count,
+                    shards_count + block_idx * parity_count + parity_idx,
+                    fec_payload_size,
+                );
+            }
+        }
+
+        for shard_idx in block_start..block_end {
             // this overlaps with the previous shard, this is intended behavior and allows to
             // reduce allocations
             let shard_start_position = shard_idx * max_shard_size;
             let shard_size = usize::min(max_shard_size, payload_size - shard_start_position);
 
-            let shard_view = &mut buffer[shard_start_position..][..SHARD_PREFIX_SIZE + shard_size];
+            let shard_view = &mut buffer[shard_start_position..][..prefix_size + shard_size];
 
-            shard_view[0..2].copy_from_slice(&stream_id.to_le_bytes());
-            shard_view[2..6].copy_from_slice(&packet_index.to_le_bytes());
-            shard_view[6..10].copy_from_slice(&(shards_count as u32).to_le_bytes());
-            shard_view[10..14].copy_from_slice(&(shard_idx as u32).to_le_bytes());
+            write_shard_prefix(
+                shard_view,
+                stream_id,
+                packet_index,
+                shards_count,
+                shard_idx,
+                fec_payload_size,
+            );
 
-            self.inner.send(shard_view)?;
+            send_shard(&self.inner, &mut self.encryptor, shard_view)?;
+
+            if deadline.is_some()
+                && let Some(packet) = self
+                    .retained_streams
+                    .get_mut(&stream_id)
+                    .and_then(|packets| packets.back_mut())
+                && packet.index == packet_index
+            {
+                let mut shard = self.shard_buffers_pool.pop().unwrap_or_default();
+                shard.clear();
+                shard.extend_from_slice(shard_view);
+                packet.shards.push(shard);
+            }
         }
 
-        Ok(())
+        if let Some(encoder) = &self.fec_encoder
+            && block_end > block_start
+        {
+            for parity_buffer in &encoder.parity_buffers {
+                send_shard(&self.inner, &mut self.encryptor, parity_buffer)?;
+            }
+        }
+
+        *cursor = block_end;
+
+        Ok(block_end == shards_count)
+    }
+
+    fn process_feedback(&mut self, feedback: &[RetransmissionFeedback]) -> Result<()> {
+        let now = Instant::now();
+
+        for message in feedback {
+            match message {
+                RetransmissionFeedback::Ack {
+                    stream_id,
+                    packet_index,
+                } => {
+                    if let Some(packets) = self.retained_streams.get_mut(stream_id)
+                        && let Some(position) = packets
+                            .iter()
+                            .position(|packet| packet.index == *packet_index)
+                    {
+                        let packet = packets.remove(position).unwrap();
+
+                        // The acknowledgement of a retransmitted packet could refer to any of the
+                        // transmissions
+                        if !packet.retransmitted {
+                            let sample = now.saturating_duration_since(packet.last_send_time);
+                            self.round_trip_time = Some(
+                                self.round_trip_time
+                                    .map_or(sample, |time| (time * 7 + sample) / 8),
+                            );
+                        }
+
+                        self.shard_buffers_pool.extend(packet.shards);
+                    }
+                }
+                RetransmissionFeedback::Nack {
+                    stream_id,
+                    packet_index,
+                    shard_indices,
+                } => {
+                    let one_way_delay = self.round_trip_time.unwrap_or_default() / 2;
+
+                    let packet = self
+                        .retained_streams
+                        .get_mut(stream_id)
+                        .and_then(|packets| {
+                            packets
+                                .iter_mut()
+                                .find(|packet| packet.index == *packet_index)
+                        });
+
+                    // An empty NACK requests the whole packet
+                    self.statistics.nacked_shards += if shard_indices.is_empty() {
+                        packet.as_ref().map_or(0, |packet| packet.shards.len())
+                    } else {
+                        shard_indices.len()
+                    };
+
+                    if let Some(packet) = packet
+                        && now + one_way_delay < packet.first_send_time + packet.deadline
+                    {
+                        self.statistics.retransmitted_shards +=
+                            retransmit(&self.inner, &mut self.encryptor, packet, shard_indices)?;
+                    }
+                }
+            }
+        }
+
+        let mut res = Ok(());
+        for packets in self.retained_streams.values_mut() {
+            packets.retain_mut(|packet| {
+                if now - packet.first_send_time > packet.deadline {
+                    // Give up, the receiver skips this packet
+                    self.shard_buffers_pool.append(&mut packet.shards);
+
+                    false
+                } else {
+                    if packet.resend_on_timeout
+                        && now - packet.last_send_time > RETRANSMISSION_TIMEOUT
+                        && res.is_ok()
+                    {
+                        res = retransmit(&self.inner, &mut self.encryptor, packet, &[])
+                            .map(|count| self.statistics.retransmitted_shards += count);
+                    }
+
+                    true
+                }
+            });
+        }
+
+        res
+    }
+
+    fn take_statistics(&mut self) -> SocketStatistics {
+        mem::take(&mut self.statistics)
     }
 }
 
@@ -103,54 +547,367 @@ struct InProgressPacket {
     buffer_size: usize, // size of the packet counting prefix
     shards_count: usize,
     received_shard_indices: HashSet<usize>,
+    // The following fields are used only with forward error correction
+    payload_size: usize,
+    parity_shards: HashMap<usize, Vec<u8>>, // indexed by shard index, they contain the prefix
+    recovered_shards_count: usize,
+    first_shard_time: Instant,
+    last_shard_time: Instant,
+    // The following field is used only for streams with retransmission
+    last_nack_time: Option<Instant>,
+}
+
+// Streams with retransmission deliver packets in order. Complete packets are held until the
+// previous ones are delivered or skipped.
+struct RetransmittedStreamState {
+    deadline: Duration,
+    next_index: u32,
+    held_packets: HashMap<u32, ReconstructedPacket>,
+    waiting_since: Option<Instant>,
+    last_missing_packets_nack_time: Option<Instant>,
+}
+
+impl RetransmittedStreamState {
+    fn new(deadline: Duration) -> Self {
+        Self {
+            deadline,
+            next_index: 0,
+            held_packets: HashMap::new(),
+            waiting_since: None,
+            last_missing_packets_nack_time: None,
+        }
+    }
+
+    fn is_delivered(&self, packet_index: u32) -> bool {
+        super::wrapping_cmp(packet_index, self.next_index) == Ordering::Less
+            || self.held_packets.contains_key(&packet_index)
+    }
+
+    fn deliver(&mut self, queues: &StreamRecvQueues) {
+        while let Some(packet) = self.held_packets.remove(&self.next_index) {
+            queues.packet_queue.send(packet).ok();
+            self.next_index = self.next_index.wrapping_add(1);
+        }
+
+        self.waiting_since = if self.held_packets.is_empty() {
+            None
+        } else {
+            Some(self.waiting_since.unwrap_or_else(Instant::now))
+        };
+    }
+
+    fn newest_held_index(&self) -> Option<u32> {
+        self.held_packets
+            .keys()
+            .copied()
+            .max_by(|lhs, rhs| super::wrapping_cmp(*lhs, *rhs))
+    }
+}
+
+fn push_ack(feedback: &mut Vec<RetransmissionFeedback>, stream_id: u16, packet_index: u32) {
+    let already_queued = feedback.iter().any(|message| {
+        matches!(
+            message,
+            RetransmissionFeedback::Ack { stream_id: id, packet_index: index }
+                if *id == stream_id && *index == packet_index
+        )
+    });
+
+    if !already_queued {
+        feedback.push(RetransmissionFeedback::Ack {
+            stream_id,
+            packet_index,
+        });
+    }
+}
+
+struct FecDecoder {
+    data_shards_per_block: usize,
+    parity_shards_per_block: usize,
+    parity_buffers_pool: Vec<Vec<u8>>,
+}
+
+// Rebuild the missing data shards of a block, if enough parity shards have been received.
+fn recover_block(
+    packet: &mut InProgressPacket,
+    decoder: &FecDecoder,
+    block_idx: usize,
+    prefix_size: usize,
+    max_shard_data_size: usize,
+) {
+    let block_start = block_idx * decoder.data_shards_per_block;
+    let block_end = usize::min(
+        block_start + decoder.data_shards_per_block,
+        packet.shards_count,
+    );
+    let parity_start = packet.shards_count + block_idx * decoder.parity_shards_per_block;
+
+    let payload_size = packet.payload_size;
+    let shard_size = move |shard_idx: usize| {
+        usize::min(
+            max_shard_data_size,
+            payload_size - shard_idx * max_shard_data_size,
+        )
+    };
+
+    let data_shards = (block_start..block_end)
+        .map(|shard_idx| {
+            packet.received_shard_indices.contains(&shard_idx).then(|| {
+                // Safety: the data section of received shards has been initialized by recv()
+                unsafe {
+                    slice::from_raw_parts(
+                        packet
+                            .buffer
+                            .as_ptr()
+                            .add(prefix_size + shard_idx * max_shard_data_size),
+                        shard_size(shard_idx),
+                    )
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let missing_count = data_shards.iter().filter(|shard| shard.is_none()).count();
+
+    let parity_shards = (0..decoder.parity_shards_per_block)
+        .filter_map(|parity_idx| {
+            packet
+                .parity_shards
+                .get(&(parity_start + parity_idx))
+                .map(|shard| (parity_idx, &shard[prefix_size..]))
+        })
+        .collect::<Vec<_>>();
+
+    if missing_count == 0 || parity_shards.len() < missing_count {
+        return;
+    }
+
+    let Some(recovered_shards) =
+        fec::reconstruct_data(&data_shards, &parity_shards, max_shard_data_size)
+    else {
+        return;
+    };
+
+    for (block_shard_idx, shard) in recovered_shards {
+        let shard_idx = block_start + block_shard_idx;
+        let shard_start_position = prefix_size + shard_idx * max_shard_data_size;
+        let shard_size = shard_size(shard_idx);
+
+        for (dst, src) in packet.buffer.spare_capacity_mut()[shard_start_position..][..shard_size]
+            .iter_mut()
+            .zip(&shard)
+        {
+            dst.write(*src);
+        }
+
+        packet.buffer_size = usize::max(packet.buffer_size, shard_start_position + shard_size);
+        packet.received_shard_indices.insert(shard_idx);
+        packet.recovered_shards_count += 1;
+    }
 }
 
-pub struct MultiplexedUdpReader {
-    inner: Socket,
+pub struct MultiplexedUdpReader<R = Socket> {
+    source: ShardSource<R>,
     max_packet_size: usize,
+    fec_decoder: Option<FecDecoder>,
     in_progress_packets: HashMap<u16, HashMap<u32, InProgressPacket>>,
+    retransmitted_streams: HashMap<u16, RetransmittedStreamState>,
+    feedback: Vec<RetransmissionFeedback>,
+    last_retransmission_check_time: Instant,
+}
+
+impl<R> MultiplexedUdpReader<R> {
+    pub(super) fn datagram_receiver(&mut self) -> &mut R {
+        &mut self.source.socket
+    }
+
+    // Request the missing shards and packets of streams with retransmission, and skip the packets
+    // that could not be recovered in time
+    fn check_retransmitted_streams(&mut self, stream_queues: &HashMap<u16, StreamRecvQueues>) {
+        let now = Instant::now();
+
+        for (stream_id, state) in &mut self.retransmitted_streams {
+            let Some(queues) = stream_queues.get(stream_id) else {
+                continue;
+            };
+            let in_progress_packets = self.in_progress_packets.entry(*stream_id).or_default();
+            let nack_delay = Duration::min(NACK_DELAY, state.deadline / 8);
+            let nack_interval = Duration::min(NACK_INTERVAL, state.deadline / 4);
+
+            // A missing packet is skipped once its deadline has passed, counted from its first
+            // shard, or from when the following packets started waiting if none of its shards
+            // arrived
+            while !state.held_packets.is_empty()
+                && in_progress_packets
+                    .get(&state.next_index)
+                    .map(|packet| packet.first_shard_time)
+                    .or(state.waiting_since)
+                    .is_some_and(|time| now - time > state.deadline)
+            {
+                if let Some(packet) = in_progress_packets.remove(&state.next_index) {
+                    queues.used_buffer_sender.send(packet.buffer).ok();
+                    if let Some(decoder) = &mut self.fec_decoder {
+                        decoder
+                            .parity_buffers_pool
+                            .extend(packet.parity_shards.into_values());
+                    }
+                }
+
+                state.next_index = state.next_index.wrapping_add(1);
+                state.deliver(queues);
+            }
+
+            // Incomplete packets are given up after the deadline even if no following packet is
+            // complete, otherwise their buffers would never be returned. The sender stops
+            // retransmitting them at the same time.
+            let mut next_packet_expired = false;
+            in_progress_packets.retain(|packet_index, packet| {
+                if now - packet.first_shard_time <= state.deadline {
+                    return true;
+                }
+
+                queues
+                    .used_buffer_sender
+                    .send(mem::take(&mut packet.buffer))
+                    .ok();
+                if let Some(decoder) = &mut self.fec_decoder {
+                    decoder
+                        .parity_buffers_pool
+                        .extend(packet.parity_shards.drain().map(|(_, shard)| shard));
+                }
+                next_packet_expired |= *packet_index == state.next_index;
+
+                false
+            });
+            if next_packet_expired {
+                state.next_index = state.next_index.wrapping_add(1);
+                state.deliver(queues);
+            }
+
+            for (packet_index, packet) in in_progress_packets.iter_mut() {
+                if now - packet.first_shard_time < state.deadline
+                    && now - packet.last_shard_time > nack_delay
+                    && packet
+                        .last_nack_time
+                        .is_none_or(|time| now - time > nack_interval)
+                {
+                    self.feedback.push(RetransmissionFeedback::Nack {
+                        stream_id: *stream_id,
+                        packet_index: *packet_index,
+                        shard_indices: (0..packet.shards_count)
+                            .filter(|index| !packet.received_shard_indices.contains(index))
+                            .map(|index| index as u32)
+                            .collect(),
+                    });
+                    packet.last_nack_time = Some(now);
+                }
+            }
+
+            // Packets of which no shard has been received
+            if let Some(newest_index) = state.newest_held_index()
+                && state
+                    .waiting_since
+                    .is_some_and(|time| now - time < state.deadline)
+                && state
+                    .last_missing_packets_nack_time
+                    .is_none_or(|time| now - time > nack_interval)
+            {
+                let mut packet_index = state.next_index;
+                for _ in 0..MAX_MISSING_PACKETS_PER_NACK {
+                    if packet_index == newest_index {
+                        break;
+                    }
+
+                    if !in_progress_packets.contains_key(&packet_index)
+                        && !state.held_packets.contains_key(&packet_index)
+                    {
+                        self.feedback.push(RetransmissionFeedback::Nack {
+                            stream_id: *stream_id,
+                            packet_index,
+                            shard_indices: vec![],
+                        });
+                    }
+
+                    packet_index = packet_index.wrapping_add(1);
+                }
+
+                state.last_missing_packets_nack_time = Some(now);
+            }
+        }
+    }
 }
 
-impl MultiplexedSocketReader for MultiplexedUdpReader {
+impl<R: DatagramReceiver> MultiplexedSocketReader for MultiplexedUdpReader<R> {
     fn payload_offset(&self) -> usize {
-        SHARD_PREFIX_SIZE
+        if self.fec_decoder.is_some() {
+            FEC_SHARD_PREFIX_SIZE
+        } else {
+            SHARD_PREFIX_SIZE
+        }
     }
 
     fn recv(&mut self, stream_queues: &HashMap<u16, StreamRecvQueues>) -> ConResult {
-        let max_shard_data_size = self.max_packet_size - SHARD_PREFIX_SIZE;
+        if !self.retransmitted_streams.is_empty()
+            && self.last_retransmission_check_time.elapsed() >= RETRANSMISSION_CHECK_INTERVAL
+        {
+            self.last_retransmission_check_time = Instant::now();
+            self.check_retransmitted_streams(stream_queues);
+        }
 
-        let discard_and_try_again = move |socket: &Socket| {
-            // Reading with any sized buffer (even 0) will consume the whole datagram
-            socket.recv(&mut []).ok();
-            alvr_common::try_again()
-        };
+        let prefix_size = self.payload_offset();
+        let max_shard_data_size = self.max_packet_size - prefix_size;
 
-        let mut prefix_bytes = [0; SHARD_PREFIX_SIZE];
-        let peek_size = socket_peek(&mut self.inner, &mut prefix_bytes)?;
-        if peek_size < SHARD_PREFIX_SIZE {
-            return discard_and_try_again(&self.inner);
+        let mut prefix_bytes = [0; FEC_SHARD_PREFIX_SIZE];
+        let peek_size = self.source.peek(&mut prefix_bytes[..prefix_size])?;
+        if peek_size < prefix_size {
+            return self.source.discard_and_try_again();
         }
 
-        // The values obtained from the prefix (stream ID, packet index, shards count, shard index)
-        // could be corrupted somehow. This method has safety checks against corrupted values and
-        // the relative packet would be discarded.
+        // The values obtained from the prefix (stream ID, packet index, shards count, shard index,
+        // payload size) could be corrupted somehow. This method has safety checks against
+        // corrupted values and the relative packet would be discarded.
         let stream_id = u16::from_le_bytes(prefix_bytes[0..2].try_into().unwrap());
         let packet_index = u32::from_le_bytes(prefix_bytes[2..6].try_into().unwrap());
         let maybe_shards_count =
             u32::from_le_bytes(prefix_bytes[6..10].try_into().unwrap()) as usize;
         let shard_index = u32::from_le_bytes(prefix_bytes[10..14].try_into().unwrap()) as usize;
+        let maybe_payload_size =
+            u32::from_le_bytes(prefix_bytes[14..18].try_into().unwrap()) as usize;
 
         if maybe_shards_count == 0 {
-            return discard_and_try_again(&self.inner);
+            return self.source.discard_and_try_again();
         }
 
         let Some(queues) = stream_queues.get(&stream_id) else {
-            return discard_and_try_again(&self.inner);
+            return self.source.discard_and_try_again();
         };
+
+        let retransmitted = if let Some(deadline) = retransmission_deadline(queues.class) {
+            let state = self
+                .retransmitted_streams
+                .entry(stream_id)
+                .or_insert_with(|| RetransmittedStreamState::new(deadline));
+
+            if state.is_delivered(packet_index) {
+                // This is a retransmission, the acknowledgement was probably lost
+                push_ack(&mut self.feedback, stream_id, packet_index);
+
+                return self.source.discard_and_try_again();
+            }
+
+            true
+        } else {
+            false
+        };
+
         let in_progress_packets = self.in_progress_packets.entry(stream_id).or_default();
 
         let in_progress_packet = if let Some(packet) = in_progress_packets.get_mut(&packet_index) {
             packet
+        } else if self.fec_decoder.is_some()
+            && (maybe_payload_size > max_shard_data_size * maybe_shards_count
+                || maybe_payload_size <= max_shard_data_size * (maybe_shards_count - 1))
+        {
+            return self.source.discard_and_try_again();
         } else if let Some(mut buffer) = queues.used_buffer_receiver.try_recv().ok().or_else(|| {
             // By default, try to dequeue a used buffer. In case none were found, recycle one of the
             // in progress packets, chances are these buffers are "dead" because one of their shards
@@ -162,7 +919,7 @@ impl MultiplexedSocketReader for MultiplexedUdpReader {
             // reserved capacity is an upper bound: we don't know yet the exact size, the last
             // shard could be smaller than max_shard_data_size
             buffer.clear();
-            buffer.reserve(SHARD_PREFIX_SIZE + max_shard_data_size * maybe_shards_count);
+            buffer.reserve(prefix_size + max_shard_data_size * maybe_shards_count);
 
             in_progress_packets
                 .entry(packet_index)
@@ -172,47 +929,104 @@ impl MultiplexedSocketReader for MultiplexedUdpReader {
                     shards_count: maybe_shards_count,
                     // todo: find a way to skipping this allocation
                     received_shard_indices: HashSet::with_capacity(maybe_shards_count),
+                    payload_size: maybe_payload_size,
+                    parity_shards: HashMap::new(),
+                    recovered_shards_count: 0,
+                    first_shard_time: Instant::now(),
+                    last_shard_time: Instant::now(),
+                    last_nack_time: None,
                 })
         } else {
             // This branch may be hit in case the thread related to the stream hangs for some reason
-            return discard_and_try_again(&self.inner);
+            return self.source.discard_and_try_again();
         };
 
-        if shard_index >= in_progress_packet.shards_count
+        let total_shards_count = if let Some(decoder) = &self.fec_decoder {
+            let blocks_count = in_progress_packet
+                .shards_count
+                .div_ceil(decoder.data_shards_per_block);
+
+            in_progress_packet.shards_count + blocks_count * decoder.parity_shards_per_block
+        } else {
+            in_progress_packet.shards_count
+        };
+
+        if shard_index >= total_shards_count
             || in_progress_packet
                 .received_shard_indices
                 .contains(&shard_index)
+            || in_progress_packet.parity_shards.contains_key(&shard_index)
         {
-            return discard_and_try_again(&self.inner);
+            return self.source.discard_and_try_again();
         }
 
-        // Note: there is no prefix offset, since we want to write the prefix too.
-        let packet_start_index = shard_index * max_shard_data_size;
+        in_progress_packet.last_shard_time = Instant::now();
+
+        let block_idx = if let Some(decoder) = &mut self.fec_decoder
+            && shard_index >= in_progress_packet.shards_count
+        {
+            // This is a parity shard. Parity shards are kept in separate buffers, they are needed
+            // only if some data shard of the same block goes missing
+            let mut parity_buffer = decoder.parity_buffers_pool.pop().unwrap_or_default();
+            parity_buffer.resize(prefix_size + max_shard_data_size, 0);
+
+            // See note below about the usage of .to_con()
+            self.source
+                .recv(as_uninit_slice(&mut parity_buffer))
+                .to_con()?;
+
+            in_progress_packet
+                .parity_shards
+                .insert(shard_index, parity_buffer);
+
+            (shard_index - in_progress_packet.shards_count) / decoder.parity_shards_per_block
+        } else {
+            // Note: there is no prefix offset, since we want to write the prefix too.
+            let packet_start_index = shard_index * max_shard_data_size;
+
+            // Note: this is a MaybeUninit slice
+            let sub_buffer =
+                &mut in_progress_packet.buffer.spare_capacity_mut()[packet_start_index..];
 
-        // Note: this is a MaybeUninit slice
-        let sub_buffer = &mut in_progress_packet.buffer.spare_capacity_mut()[packet_start_index..];
+            // Safety: bound checks lead from the previous code
+            let mut overwritten_data_backup = [MaybeUninit::uninit(); FEC_SHARD_PREFIX_SIZE];
+            overwritten_data_backup[..prefix_size].copy_from_slice(&sub_buffer[..prefix_size]);
 
-        // Safety: bound checks lead from the previous code
-        let overwritten_data_backup: [_; SHARD_PREFIX_SIZE] =
-            sub_buffer[..SHARD_PREFIX_SIZE].try_into().unwrap();
+            // This call should never fail because the peek call succeded before.
+            // Note: in unexpected circumstances, here .to_con() is used not to emit TryAgain, which
+            // would mess with the state of the code. The connection would need to be closed
+            // instead.
+            // NB: the received_size contains the prefix
+            let received_size = self.source.recv(sub_buffer).to_con()?;
 
-        // This call should never fail because the peek call succeded before.
-        // Note: in unexpected circumstances, here .to_con() is used not to emit TryAgain, which
-        // would mess with the state of the code. The connection would need to be closed instead.
-        // NB: the received_size contains the prefix
-        let received_size = self.inner.recv(sub_buffer).to_con()?;
+            // Restore backed up bytes
+            sub_buffer[..prefix_size].copy_from_slice(&overwritten_data_backup[..prefix_size]);
 
-        // Restore backed up bytes
-        sub_buffer[..SHARD_PREFIX_SIZE].copy_from_slice(&overwritten_data_backup);
+            in_progress_packet.buffer_size = usize::max(
+                in_progress_packet.buffer_size,
+                packet_start_index + received_size,
+            );
 
-        in_progress_packet.buffer_size = usize::max(
-            in_progress_packet.buffer_size,
-            packet_start_index + received_size,
-        );
+            in_progress_packet
+                .received_shard_indices
+                .insert(shard_index);
 
-        in_progress_packet
-            .received_shard_indices
-            .insert(shard_index);
+            self.fec_decoder
+                .as_ref()
+                .map_or(0, |decoder| shard_index / decoder.data_shards_per_block)
+        };
+
+        if let Some(decoder) = &self.fec_decoder
+            && in_progress_packet.received_shard_indices.len() < in_progress_packet.shards_count
+        {
+            recover_block(
+                in_progress_packet,
+                decoder,
+                block_idx,
+                prefix_size,
+                max_shard_data_size,
+            );
+        }
 
         // Check if packet is complete (and not dummy) and send
         if in_progress_packet.received_shard_indices.len() == in_progress_packet.shards_count {
@@ -220,49 +1034,138 @@ impl MultiplexedSocketReader for MultiplexedUdpReader {
                 // All writing was done to uninit capacity, here we set the final buffer length
                 unsafe { packet.buffer.set_len(packet.buffer_size) };
 
-                queues
-                    .packet_queue
-                    .send(ReconstructedPacket {
-                        index: packet_index,
-                        buffer: packet.buffer,
-                    })
-                    .ok();
+                if let Some(decoder) = &mut self.fec_decoder {
+                    decoder
+                        .parity_buffers_pool
+                        .extend(packet.parity_shards.into_values());
+                }
+
+                let packet = ReconstructedPacket {
+                    index: packet_index,
+                    buffer: packet.buffer,
+                    recovered_shards_count: packet.recovered_shards_count,
+                    first_shard_arrival: packet.first_shard_time,
+                    last_shard_arrival: packet.last_shard_time,
+                };
+
+                if retransmitted {
+                    push_ack(&mut self.feedback, stream_id, packet_index);
+
+                    // Safety: the state is created when the first shard is received
+                    let state = self.retransmitted_streams.get_mut(&stream_id).unwrap();
+                    state.held_packets.insert(packet_index, packet);
+                    state.deliver(queues);
+                } else {
+                    queues.packet_queue.send(packet).ok();
+                }
             }
 
-            // Discard older in-progress packets
-            while let Some((idx, _)) = in_progress_packets
-                .iter()
-                .find(|(idx, _)| super::wrapping_cmp(**idx, packet_index) == Ordering::Less)
+            // Discard older in-progress packets. Those of streams with retransmission can still be
+            // completed
+            while !retransmitted
+                && let Some((idx, _)) = in_progress_packets
+                    .iter()
+                    .find(|(idx, _)| super::wrapping_cmp(**idx, packet_index) == Ordering::Less)
             {
                 let idx = *idx; // fix borrow rule
                 let packet = in_progress_packets.remove(&idx).unwrap();
 
-                // Recycle buffer
+                // Recycle buffers
                 queues.used_buffer_sender.send(packet.buffer).ok();
+                if let Some(decoder) = &mut self.fec_decoder {
+                    decoder
+                        .parity_buffers_pool
+                        .extend(packet.parity_shards.into_values());
+                }
             }
         }
 
         Ok(())
     }
+
+    fn take_feedback(&mut self, feedback: &mut Vec<RetransmissionFeedback>) {
+        feedback.append(&mut self.feedback);
+    }
 }
 
 pub fn split_multiplexed(
     socket: UdpSocket,
     max_packet_size: usize,
+    fec_config: Option<ForwardErrorCorrectionConfig>,
+    secure_channel: Option<SecureChannel>,
 ) -> Result<(
     Box<dyn MultiplexedSocketWriter + Send>,
     Box<dyn MultiplexedSocketReader + Send>,
 )> {
+    let (writer, reader) = split_datagrams(
+        socket.try_clone()?,
+        Socket::from(socket),
+        max_packet_size,
+        fec_config,
+        secure_channel,
+    );
+
+    Ok((Box::new(writer), Box::new(reader)))
+}
+
+pub(super) fn split_datagrams<S, R>(
+    sender: S,
+    receiver: R,
+    max_packet_size: usize,
+    fec_config: Option<ForwardErrorCorrectionConfig>,
+    secure_channel: Option<SecureChannel>,
+) -> (MultiplexedUdpWriter<S>, MultiplexedUdpReader<R>) {
+    let fec_layout = fec_config.map(fec_block_layout);
+
+    // Encryption adds a nonce and a tag to each datagram, the shards must be smaller to compensate
+    let max_packet_size = if secure_channel.is_some() {
+        max_packet_size - ENCRYPTION_OVERHEAD
+    } else {
+        max_packet_size
+    };
+
     let writer = MultiplexedUdpWriter {
-        inner: socket.try_clone()?,
+        inner: sender,
         max_packet_size,
+        fec_encoder: fec_layout.map(|(data_shards_per_block, parity_shards_per_block)| {
+            FecEncoder {
+                data_shards_per_block,
+                parity_buffers: vec![vec![]; parity_shards_per_block],
+            }
+        }),
+        encryptor: secure_channel.clone().map(|channel| DatagramEncryptor {
+            channel,
+            buffer: vec![],
+        }),
+        retained_streams: HashMap::new(),
+        shard_buffers_pool: vec![],
+        round_trip_time: None,
+        statistics: SocketStatistics::default(),
     };
 
     let reader = MultiplexedUdpReader {
-        inner: socket.into(),
+        source: ShardSource {
+            socket: receiver,
+            decryptor: secure_channel.map(|channel| DatagramDecryptor {
+                channel,
+                datagram_buffer: vec![0; max_packet_size + ENCRYPTION_OVERHEAD],
+                plaintext: vec![],
+                pending: false,
+            }),
+        },
         max_packet_size,
+        fec_decoder: fec_layout.map(|(data_shards_per_block, parity_shards_per_block)| {
+            FecDecoder {
+                data_shards_per_block,
+                parity_shards_per_block,
+                parity_buffers_pool: vec![],
+            }
+        }),
         in_progress_packets: HashMap::new(),
+        retransmitted_streams: HashMap::new(),
+        feedback: vec![],
+        last_retransmission_check_time: Instant::now(),
     };
 
-    Ok((Box::new(writer), Box::new(reader)))
+    (writer, reader)
 }
@@ -4,6 +4,7 @@ mod command;
 mod dependencies;
 mod format;
 mod packaging;
+mod stats;
 mod version;
 
 use crate::build::Profile;
@@ -12,7 +13,7 @@ use alvr_filesystem as afs;
 use dependencies::OpenXRLoadersSelection;
 use packaging::ReleaseFlavor;
 use pico_args::Arguments;
-use std::{fs, process, time::Instant};
+use std::{fs, path::PathBuf, process, time::Instant};
 use xshell::{Shell, cmd};
 
 const HELP_STR: &str = r#"
@@ -42,6 +43,7 @@ SUBCOMMANDS:
     bump                Bump streamer and client package versions
     clippy              Show warnings for selected clippy lints
     kill-oculus         Kill all Oculus processes
+    summarize-stats     Print the latency percentiles of a statistics timeline file
 
 FLAGS:
     --help              Print this text
@@ -63,6 +65,7 @@ ARGS:
     --version <VERSION> Specify version to set with the bump-versions subcommand
     --root <PATH>       Installation root. By default no root is set and paths are calculated using
                         relative paths, which requires conforming to FHS on Linux
+    --file <PATH>       Statistics timeline file, for the summarize-stats subcommand
 "#;
 
 enum BuildPlatform {
@@ -196,6 +199,7 @@ fn main() {
 
         let version: Option<String> = args.opt_value_from_str("--version").unwrap();
         let root: Option<String> = args.opt_value_from_str("--root").unwrap();
+        let file: Option<PathBuf> = args.opt_value_from_str("--file").unwrap();
 
         let package_flavor = if args.contains("--meta-store") {
             ReleaseFlavor::MetaStore
@@ -274,6 +278,13 @@ fn main() {
                     packaging::check_licenses();
                 }
                 "kill-oculus" => kill_oculus_processes(),
+                "summarize-stats" => {
+                    if let Some(file) = file {
+                        stats::summarize_stats(&file);
+                    } else {
+                        print_help_and_exit("Missing --file argument");
+                    }
+                }
                 _ => print_help_and_exit("Unrecognized subcommand."),
             }
         } else {
@@ -0,0 +1,71 @@
+use std::{fs, path::Path};
+
+// Columns of the statistics timeline files containing the latency of each pipeline stage
+const STAGE_COLUMNS: &[&str] = &[
+    "total_pipeline_latency_s",
+    "game_time_s",
+    "server_compositor_s",
+    "encoder_s",
+    "network_s",
+    "uplink_s",
+    "downlink_s",
+    "decoder_s",
+    "decoder_queue_s",
+    "client_compositor_s",
+    "vsync_queue_s",
+];
+
+// Nearest-rank percentile of a sorted list
+fn percentile(sorted_values: &[f32], percent: f32) -> f32 {
+    let rank = (percent / 100.0 * sorted_values.len() as f32).ceil() as usize;
+
+    sorted_values[rank.clamp(1, sorted_values.len()) - 1]
+}
+
+// Prints the latency percentiles of a statistics timeline file written by the streamer
+pub fn summarize_stats(path: &Path) {
+    let text = fs::read_to_string(path).unwrap();
+    let mut lines = text.lines();
+
+    let mut columns = vec![];
+    for line in lines.by_ref() {
+        if let Some(comment) = line.strip_prefix('#') {
+            println!("{}", comment.trim());
+        } else {
+            columns = line.split(',').collect();
+            break;
+        }
+    }
+
+    let mut values = vec![vec![]; columns.len()];
+    let mut frame_count = 0;
+    for line in lines {
+        for (column_values, field) in values.iter_mut().zip(line.split(',')) {
+            if let Ok(value) = field.parse::<f32>() {
+                column_values.push(value);
+            }
+        }
+        frame_count += 1;
+    }
+
+    println!("\n{frame_count} frames\n");
+    println!(
+        "{:<26}{:>10}{:>10}{:>10}",
+        "Stage latency", "p50", "p95", "p99"
+    );
+
+    for stage in STAGE_COLUMNS {
+        let Some(index) = columns.iter().position(|column| column == stage) else {
+            continue;
+        };
+        let stage_values = &mut values[index];
+        if stage_values.is_empty() {
+            continue;
+        }
+        stage_values.sort_by(f32::total_cmp);
+
+        let name = stage.trim_end_matches("_s");
+        let [p50, p95, p99] = [50.0, 95.0, 99.0].map(|p| percentile(stage_values, p) * 1000.0);
+        println!("{name:<26}{p50:>8.2}ms{p95:>8.2}ms{p99:>8.2}ms");
+    }
+}